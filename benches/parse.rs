@@ -0,0 +1,127 @@
+//! Benchmark parsing of large partition arrays.
+//!
+//! The array is read from memory, so that only parsing is measured, with
+//! both the previous per-entry parser and `read_partitions_from()`.
+//!
+//! Run with `cargo bench --bench parse`.
+
+use gpt::disk;
+use gpt::header::Header;
+use gpt::partition::{read_partitions_from, Partition, PartitionType};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::time::{Duration, Instant};
+
+const NUM_PARTS: u32 = 1024;
+const ITERATIONS: u32 = 200;
+
+/// Build a 1024-entry partition array, with `used` non-empty entries.
+fn entries(used: u32) -> Vec<u8> {
+    let mut buf = vec![0u8; NUM_PARTS as usize * 128];
+    for (i, entry) in buf.chunks_mut(128).take(used as usize).enumerate() {
+        // Linux filesystem data type GUID, mixed-endian.
+        entry[0..16].copy_from_slice(&[
+            0xAF, 0x3D, 0xC6, 0x0F, 0x83, 0x84, 0x72, 0x47, 0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47,
+            0x7D, 0xE4,
+        ]);
+        entry[16..32].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+        let first = 1024 + (i as u64) * 8;
+        entry[32..40].copy_from_slice(&first.to_le_bytes());
+        entry[40..48].copy_from_slice(&(first + 7).to_le_bytes());
+        for (j, c) in format!("part-{}", i).encode_utf16().enumerate() {
+            entry[56 + 2 * j..58 + 2 * j].copy_from_slice(&c.to_le_bytes());
+        }
+    }
+    buf
+}
+
+/// The previous parser: one pair of reads per entry, parsing all of them,
+/// and a second read of the whole array for its CRC32.
+fn read_per_entry(device: &mut Cursor<Vec<u8>>, hdr: &Header) -> BTreeMap<u32, Partition> {
+    let mut parts = BTreeMap::new();
+    device.seek(SeekFrom::Start(hdr.part_start * 512)).unwrap();
+    for i in 0..hdr.num_parts {
+        let mut bytes = [0u8; 56];
+        let mut nameraw = [0u8; 72];
+        device.read_exact(&mut bytes).unwrap();
+        device.read_exact(&mut nameraw).unwrap();
+        let part_guid = uuid::Uuid::from_slice(&bytes[16..32]).unwrap();
+        if part_guid.to_simple().to_string() == "00000000000000000000000000000000" {
+            continue;
+        }
+        let mut p = Partition::zero();
+        let type_guid = uuid::Uuid::from_slice(&bytes[0..16]).unwrap();
+        p.part_type_guid = PartitionType::from_guid(type_guid);
+        p.part_guid = part_guid;
+        p.first_lba = u64::from_le_bytes(bytes[32..40].try_into().unwrap());
+        p.last_lba = u64::from_le_bytes(bytes[40..48].try_into().unwrap());
+        p.flags = u64::from_le_bytes(bytes[48..56].try_into().unwrap());
+        let units: Vec<u16> = nameraw
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .filter(|u| *u != 0)
+            .collect();
+        p.name = String::from_utf16_lossy(&units);
+        parts.insert(i + 1, p);
+    }
+
+    device.seek(SeekFrom::Start(hdr.part_start * 512)).unwrap();
+    let mut table = vec![0; hdr.num_parts as usize * hdr.part_size as usize];
+    device.read_exact(&mut table).unwrap();
+    assert_eq!(crc32fast::hash(&table), hdr.crc32_parts);
+    parts
+}
+
+/// Time `ITERATIONS` runs of `f`.
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn bench(used: u32) {
+    let table = entries(used);
+    let mut image = vec![0u8; 16 * 1024 * 1024];
+    image[2 * 512..2 * 512 + table.len()].copy_from_slice(&table);
+    let mut device = Cursor::new(image);
+
+    let hdr = Header {
+        signature: "EFI PART".to_string(),
+        revision: 65536,
+        header_size_le: 92,
+        crc32: 0,
+        reserved: 0,
+        current_lba: 1,
+        backup_lba: 32767,
+        first_usable: 258,
+        last_usable: 32510,
+        disk_guid: uuid::Uuid::new_v4(),
+        part_start: 2,
+        num_parts: NUM_PARTS,
+        part_size: 128,
+        crc32_parts: crc32fast::hash(&table),
+        crc32_stale: false,
+    };
+
+    let before = time(|| {
+        let pp = read_per_entry(&mut device, &hdr);
+        assert_eq!(pp.len(), used as usize);
+    });
+    let after = time(|| {
+        let pp = read_partitions_from(&mut device, &hdr, disk::DEFAULT_SECTOR_SIZE).unwrap();
+        assert_eq!(pp.len(), used as usize);
+    });
+    println!(
+        "{} entries ({} used): per-entry {:?}/iter, single buffer {:?}/iter",
+        NUM_PARTS, used, before, after
+    );
+}
+
+fn main() {
+    bench(0);
+    bench(16);
+    bench(NUM_PARTS);
+}
@@ -1,7 +1,3 @@
-use gpt;
-
-
-
 use simplelog::{Config, LevelFilter, SimpleLogger};
 use std::io;
 
@@ -26,6 +22,13 @@ fn run() -> io::Result<()> {
     let cfg = gpt::GptConfig::new().writable(false);
     let disk = cfg.open(diskpath)?;
 
+    // Identify the physical device, if any.
+    #[cfg(feature = "device-info")]
+    match disk.device_identity() {
+        Some(id) => println!("Device: {}", id),
+        None => println!("Device: not a block device"),
+    }
+
     // Print GPT layout.
     println!("Disk (primary) header: {:#?}", disk.primary_header());
     println!("Partition layout: {:#?}", disk.partitions());
@@ -0,0 +1,284 @@
+//! Asynchronous access to GPT disks, over `tokio` I/O traits.
+//!
+//! The partition table structures (MBR, headers and partition arrays)
+//! are read up-front into a `StagedDevice`, an in-memory copy of the disk
+//! which records what gets written to it. The whole sync API then
+//! applies to the staged copy without blocking, and `AsyncGptDisk::write()`
+//! writes the recorded changes back to the real device.
+//!
+//! ```rust
+//! # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+//! let image = std::fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+//! let mut disk = gpt::GptConfig::new()
+//!     .writable(true)
+//!     .open_async(std::io::Cursor::new(image))
+//!     .await
+//!     .unwrap();
+//! disk.table_mut().remove_partition(1, false).unwrap();
+//! disk.write().await.unwrap();
+//! # });
+//! ```
+
+use log::*;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use crate::disk::{self, DiskDevice, MemoryDevice};
+use crate::header;
+use crate::{GptDisk, Result};
+
+/// Bytes read at both ends of a disk, covering LBA0 and LBA1 (and the last
+/// LBA) for all supported logical block sizes.
+const EDGE_LEN: u64 = 2 * 4096;
+/// Largest partition array loaded from a device.
+const MAX_ARRAY_LEN: u64 = 4 * 1024 * 1024;
+
+/// In-memory copy of the table structures of a disk, recording writes.
+///
+/// Only the table structures are loaded, the rest of the disk reads as
+/// zeros: operations on partition contents (e.g. `GptDisk::open_partition()`)
+/// are not meaningful on it.
+#[derive(Clone, Debug)]
+pub struct StagedDevice {
+    mem: MemoryDevice,
+    /// Written byte ranges, as start offset to end offset, merged.
+    dirty: BTreeMap<u64, u64>,
+}
+
+impl StagedDevice {
+    /// Record that `len` bytes were written at `start`, merging adjacent ranges.
+    fn mark_dirty(&mut self, start: u64, len: u64) {
+        let (mut start, mut end) = (start, start + len);
+        let overlapping: Vec<_> = self
+            .dirty
+            .range(..=end)
+            .filter(|(_, e)| **e >= start)
+            .map(|(s, e)| (*s, *e))
+            .collect();
+        for (s, e) in overlapping {
+            self.dirty.remove(&s);
+            start = start.min(s);
+            end = end.max(e);
+        }
+        self.dirty.insert(start, end);
+    }
+}
+
+impl Read for StagedDevice {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.mem.read(buf)
+    }
+}
+
+impl Write for StagedDevice {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let start = self.mem.stream_position()?;
+        let n = self.mem.write(buf)?;
+        self.mark_dirty(start, n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.mem.flush()
+    }
+}
+
+impl Seek for StagedDevice {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.mem.seek(pos)
+    }
+}
+
+impl DiskDevice for StagedDevice {
+    fn size(&self) -> io::Result<u64> {
+        self.mem.size()
+    }
+}
+
+/// GPT disk over an asynchronous device, see `GptConfig::open_async()`.
+#[derive(Debug)]
+pub struct AsyncGptDisk<D> {
+    device: D,
+    table: GptDisk<StagedDevice>,
+}
+
+impl<D: AsyncRead + AsyncWrite + AsyncSeek + Unpin> AsyncGptDisk<D> {
+    /// Wrap a disk opened on the staged copy of `device`.
+    pub(crate) fn new(device: D, table: GptDisk<StagedDevice>) -> Self {
+        Self { device, table }
+    }
+
+    /// Return the partition table, staged in memory.
+    pub fn table(&self) -> &GptDisk<StagedDevice> {
+        &self.table
+    }
+
+    /// Return the partition table, staged in memory, for changes.
+    ///
+    /// Changes are applied to the real device by `write()`.
+    pub fn table_mut(&mut self) -> &mut GptDisk<StagedDevice> {
+        &mut self.table
+    }
+
+    /// Write the in-memory partition table, and all staged writes, to the device.
+    ///
+    /// This is `GptDisk::write_inplace()` on the staged copy, followed by
+    /// writing every byte range written to it so far (e.g. by `zap()` or
+    /// `write_protective_mbr()` too) to the device, and flushing it.
+    pub async fn write(&mut self) -> Result<()> {
+        self.table.write_inplace()?;
+        self.write_staged().await
+    }
+
+    /// Write the byte ranges written to the staged copy to the device, and flush it.
+    pub async fn write_staged(&mut self) -> Result<()> {
+        let staged = &mut self.table.file;
+        let dirty = std::mem::take(&mut staged.dirty);
+        let mut buf = vec![];
+        for (start, end) in dirty {
+            buf.resize((end - start) as usize, 0);
+            staged.mem.seek(SeekFrom::Start(start))?;
+            staged.mem.read_exact(&mut buf)?;
+            debug!("writing {} staged bytes at {:#x}", buf.len(), start);
+            self.device.seek(SeekFrom::Start(start)).await?;
+            self.device.write_all(&buf).await?;
+        }
+        self.device.flush().await?;
+        Ok(())
+    }
+
+    /// Return the device, discarding staged writes not written yet.
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+}
+
+/// Read the table structures of `device` into a staged copy.
+///
+/// Both ends of the disk are loaded, then the partition arrays and
+/// backup header recorded in any GPT header found there.
+pub(crate) async fn load<D: AsyncRead + AsyncSeek + Unpin>(
+    device: &mut D,
+) -> io::Result<StagedDevice> {
+    let size = device.seek(SeekFrom::End(0)).await?;
+    let mut mem = MemoryDevice::new(size);
+    let edge = EDGE_LEN.min(size);
+    copy_range(device, &mut mem, 0, edge).await?;
+    copy_range(device, &mut mem, size - edge, edge).await?;
+
+    let mut headers = vec![];
+    for lb_size in &[
+        disk::LogicalBlockSize::Lb512,
+        disk::LogicalBlockSize::Lb4096,
+    ] {
+        let lb = lb_size.bytes();
+        headers.push((lb, lb));
+        headers.push((size.saturating_sub(lb) / lb * lb, lb));
+    }
+    let mut loaded_backup = false;
+    while let Some((offset, lb)) = headers.pop() {
+        if offset.checked_add(lb).is_none_or(|end| end > size) {
+            continue;
+        }
+        copy_range(device, &mut mem, offset, lb).await?;
+        let h = match header::file_read_header_unchecked(&mut mem, offset) {
+            Ok((h, _)) if h.signature == "EFI PART" => h,
+            _ => continue,
+        };
+        let len = u64::from(h.num_parts) * u64::from(h.part_size);
+        let start = h.part_start.saturating_mul(lb);
+        if len <= MAX_ARRAY_LEN && start.saturating_add(len) <= size {
+            copy_range(device, &mut mem, start, len).await?;
+        }
+        // Follow the primary header to a misplaced backup one, once.
+        if h.current_lba == 1 && !loaded_backup {
+            loaded_backup = true;
+            if let Some(bak) = h.backup_lba.checked_mul(lb) {
+                headers.push((bak, lb));
+            }
+        }
+    }
+    mem.seek(SeekFrom::Start(0))?;
+    Ok(StagedDevice {
+        mem,
+        dirty: BTreeMap::new(),
+    })
+}
+
+/// Copy `len` bytes at `offset` from `device` to `mem`.
+async fn copy_range<D: AsyncRead + AsyncSeek + Unpin>(
+    device: &mut D,
+    mem: &mut MemoryDevice,
+    offset: u64,
+    len: u64,
+) -> io::Result<()> {
+    let mut buf = vec![0u8; len as usize];
+    device.seek(SeekFrom::Start(offset)).await?;
+    device.read_exact(&mut buf).await?;
+    mem.seek(SeekFrom::Start(offset))?;
+    mem.write_all(&buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    fn block_on<F: std::future::Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    #[test]
+    fn test_open_async() {
+        let image = std::fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+        let mut disk = block_on(
+            crate::GptConfig::new()
+                .writable(true)
+                .open_async(Cursor::new(image.clone())),
+        )
+        .unwrap();
+        assert_eq!(disk.table().partitions()[&1].name, "primary");
+        assert!(disk.table().file.dirty.is_empty());
+
+        disk.table_mut().partitions_mut().for_each(|p| {
+            p.name = "renamed".to_string();
+        });
+        block_on(disk.write()).unwrap();
+        let written = disk.into_inner().into_inner();
+        assert_eq!(written.len(), image.len());
+        // Partition contents are left alone.
+        assert_eq!(written[34 * 512..63 * 512], image[34 * 512..63 * 512]);
+
+        let disk = crate::GptConfig::new()
+            .open_from_device(Cursor::new(written))
+            .unwrap();
+        assert_eq!(disk.partitions()[&1].name, "renamed");
+    }
+
+    #[test]
+    fn test_open_async_bogus_backup_lba() {
+        let mut image = std::fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+        // Primary header backup_lba, whose CRC32 is checked only after loading.
+        image[512 + 32..512 + 40].copy_from_slice(&(u64::MAX / 512).to_le_bytes());
+        let res = block_on(crate::GptConfig::new().open_async(Cursor::new(image)));
+        assert!(matches!(res, Err(crate::Error::HeaderCrcMismatch { .. })));
+    }
+
+    #[test]
+    fn test_staged_dirty_ranges() {
+        let mut staged = super::StagedDevice {
+            mem: crate::disk::MemoryDevice::new(4096),
+            dirty: Default::default(),
+        };
+        staged.mark_dirty(100, 10);
+        staged.mark_dirty(200, 10);
+        staged.mark_dirty(105, 10);
+        assert_eq!(staged.dirty.len(), 2);
+        staged.mark_dirty(110, 90);
+        assert_eq!(staged.dirty.into_iter().collect::<Vec<_>>(), [(100, 210)]);
+    }
+}
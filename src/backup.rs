@@ -0,0 +1,91 @@
+//! Compressed backups of partition contents.
+//!
+//! A backup starts with a small header recording the length of the
+//! partition data, followed by the gzip-compressed data itself and a
+//! trailing CRC32 of the uncompressed data, checked on restore.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{BufReader, Error, Read, Result, Seek, SeekFrom, Write};
+
+/// Magic signature at the start of a partition backup.
+const MAGIC: &[u8; 8] = b"GPTPBAK1";
+/// Size of chunks moved between disk and (de)compressor.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Compress `len` bytes of `file`, from offset `start`, into `out`.
+pub(crate) fn save<D: Read + Seek, W: Write>(
+    file: &mut D,
+    start: u64,
+    len: u64,
+    out: W,
+) -> Result<u64> {
+    let mut out = out;
+    out.write_all(MAGIC)?;
+    out.write_u64::<LittleEndian>(len)?;
+
+    file.seek(SeekFrom::Start(start))?;
+    let mut encoder = GzEncoder::new(out, Compression::default());
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = remaining.min(CHUNK_SIZE as u64) as usize;
+        file.read_exact(&mut buf[..n])?;
+        hasher.update(&buf[..n]);
+        encoder.write_all(&buf[..n])?;
+        remaining -= n as u64;
+    }
+    let mut out = encoder.finish()?;
+    out.write_u32::<LittleEndian>(hasher.finalize())?;
+    out.flush()?;
+    Ok(len)
+}
+
+/// Restore a backup from `input` into `file` at offset `start`.
+///
+/// The backup must hold at most `max_len` bytes. Data is written
+/// while decompressing, so a corrupted backup is only detected (by
+/// its length or checksum) after having been partially restored.
+pub(crate) fn restore<D: Write + Seek, R: Read>(
+    file: &mut D,
+    start: u64,
+    max_len: u64,
+    input: R,
+) -> Result<u64> {
+    let mut input = input;
+    let mut magic = [0u8; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(Error::other("invalid partition backup signature"));
+    }
+    let len = input.read_u64::<LittleEndian>()?;
+    if len > max_len {
+        return Err(Error::other("partition backup larger than partition"));
+    }
+
+    file.seek(SeekFrom::Start(start))?;
+    // Keep buffered input around, as the checksum follows compressed data.
+    let mut decoder = GzDecoder::new(BufReader::new(input));
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = remaining.min(CHUNK_SIZE as u64) as usize;
+        decoder.read_exact(&mut buf[..n])?;
+        hasher.update(&buf[..n]);
+        file.write_all(&buf[..n])?;
+        remaining -= n as u64;
+    }
+    if decoder.read(&mut buf[..1])? != 0 {
+        return Err(Error::other("partition backup longer than recorded"));
+    }
+    let mut input = decoder.into_inner();
+    if input.read_u32::<LittleEndian>()? != hasher.finalize() {
+        return Err(Error::other("partition backup checksum mismatch"));
+    }
+    file.flush()?;
+    Ok(len)
+}
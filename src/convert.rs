@@ -0,0 +1,198 @@
+//! Conversion between MBR and GPT partition tables.
+//!
+//! Partition types are mapped through a table of DOS partition types
+//! and their GPT equivalents, see `gpt_type_for_mbr()` and
+//! `mbr_type_for_gpt()`.
+//!
+//! ```rust,no_run
+//! let mut disk = gpt::GptConfig::new()
+//!     .writable(true)
+//!     .initialized(false)
+//!     .overwrite(true)
+//!     .open(std::path::Path::new("/dev/sdz"))
+//!     .unwrap();
+//! gpt::convert::mbr_to_gpt(&mut disk).unwrap();
+//! println!("{:#?}", disk.partitions());
+//! disk.write_initial_layout().unwrap();
+//! ```
+
+use lazy_static::*;
+use log::*;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use crate::disk::DiskDevice;
+use crate::mbr::{self, PartRecord};
+use crate::partition::{self, Partition, PartitionAttributes, PartitionType};
+use crate::{Error, GptDisk, Result};
+
+/// DOS partition types with a GPT equivalent, as (MBR type, type-GUID).
+///
+/// The first MBR type listed for a type-GUID is the preferred one.
+const TYPE_MAP: &[(u8, &str)] = &[
+    (0x07, partition::MICROSOFT_BASIC_DATA),
+    (0x0C, partition::MICROSOFT_BASIC_DATA),
+    (0x0B, partition::MICROSOFT_BASIC_DATA),
+    (0x0E, partition::MICROSOFT_BASIC_DATA),
+    (0x06, partition::MICROSOFT_BASIC_DATA),
+    (0x04, partition::MICROSOFT_BASIC_DATA),
+    (0x01, partition::MICROSOFT_BASIC_DATA),
+    // Hidden variants of the above.
+    (0x17, partition::MICROSOFT_BASIC_DATA),
+    (0x1C, partition::MICROSOFT_BASIC_DATA),
+    (0x1B, partition::MICROSOFT_BASIC_DATA),
+    (0x1E, partition::MICROSOFT_BASIC_DATA),
+    (0x16, partition::MICROSOFT_BASIC_DATA),
+    (0x14, partition::MICROSOFT_BASIC_DATA),
+    (0x11, partition::MICROSOFT_BASIC_DATA),
+    (0x27, partition::WINDOWS_RECOVERY),
+    (0x82, partition::LINUX_SWAP),
+    (0x83, partition::LINUX_FILESYSTEM),
+    (0x8E, partition::LINUX_LVM),
+    (0xFD, partition::LINUX_RAID),
+    (0xA5, partition::FREEBSD_DATA),
+    (0xAF, partition::APPLE_HFS_PLUS),
+    (0xEF, partition::EFI_SYSTEM),
+];
+
+lazy_static! {
+    /// `TYPE_MAP`, with type-GUIDs parsed once.
+    static ref GUID_MAP: Vec<(u8, uuid::Uuid)> = TYPE_MAP
+        .iter()
+        .map(|(t, guid)| (*t, uuid::Uuid::parse_str(guid).expect("invalid type-GUID in map")))
+        .collect();
+}
+
+/// Return the GPT partition type equivalent to DOS partition type `os_type`, if known.
+pub fn gpt_type_for_mbr(os_type: u8) -> Option<PartitionType> {
+    GUID_MAP
+        .iter()
+        .find(|(t, _)| *t == os_type)
+        .map(|(_, guid)| PartitionType::from_guid(*guid))
+}
+
+/// Return the preferred DOS partition type equivalent to GPT type `part_type`, if known.
+///
+/// Linux types without a DOS equivalent of their own (e.g. root
+/// partitions) map to 0x83, like Linux data.
+pub fn mbr_type_for_gpt(part_type: &PartitionType) -> Option<u8> {
+    GUID_MAP
+        .iter()
+        .find(|(_, guid)| *guid == part_type.guid)
+        .map(|(t, _)| *t)
+        .or_else(|| Some(0x83).filter(|_| part_type.os == "Linux"))
+}
+
+/// Replace the in-memory partitions of `disk` with those of the MBR in its LBA0.
+///
+/// Primary partitions keep their slot number (1 to 4), and logical
+/// partitions of the extended one are numbered from 5, as in Linux
+/// device names. Types are mapped with `gpt_type_for_mbr()`, falling
+/// back to Microsoft basic data for unknown ones, and bootable records
+/// get the legacy-BIOS bootable attribute. New partition GUIDs are generated.
+///
+/// Nothing is written: the result can be reviewed with
+/// `GptDisk::partitions()` first, then written with
+/// `GptDisk::write_initial_layout()`, which also replaces the MBR with a
+/// protective one. The disk is usually opened with `initialized(false)`,
+/// and `overwrite(true)` is needed to replace the MBR.
+/// This fails if LBA0 holds a protective MBR, or if a partition overlaps
+/// the areas needed by the GPT structures, e.g. the last 33 LBAs.
+pub fn mbr_to_gpt<D: DiskDevice>(disk: &mut GptDisk<D>) -> Result<()> {
+    let lb_size = disk.config.lb_size;
+    let table = mbr::MbrTable::from_disk(&mut disk.file, lb_size)?;
+    if table.partitions().iter().any(|p| p.os_type == 0xEE) {
+        return Err(Error::Invalid("disk already has a protective MBR".into()));
+    }
+
+    let mut records: Vec<(u32, u64, &PartRecord)> = vec![];
+    for (slot, p) in table.partitions().iter().enumerate() {
+        if !p.is_empty() && !p.is_extended() {
+            records.push((slot as u32 + 1, u64::from(p.lb_start), p));
+        }
+    }
+    let logical = mbr::read_logical_partitions(&mut disk.file, lb_size)?;
+    for (n, p) in logical.iter().enumerate() {
+        records.push((n as u32 + 5, p.first_lba(), &p.record));
+    }
+
+    let mut pp = BTreeMap::new();
+    for (id, first_lba, record) in records {
+        let part_type_guid = gpt_type_for_mbr(record.os_type).unwrap_or_else(|| {
+            warn!(
+                "unknown MBR type {:#04x} for partition {}, using basic data",
+                record.os_type, id
+            );
+            gpt_type_for_mbr(0x07).expect("basic data in type map")
+        });
+        let flags = if record.is_bootable() {
+            PartitionAttributes::BOOTABLE.bits()
+        } else {
+            0
+        };
+        pp.insert(
+            id,
+            Partition {
+                part_type_guid,
+                part_guid: uuid::Uuid::new_v4(),
+                first_lba,
+                last_lba: first_lba + u64::from(record.lb_size) - 1,
+                flags,
+                name: String::new(),
+                raw_name: None,
+                number: id,
+            },
+        );
+    }
+    debug!("converted {} MBR partitions", pp.len());
+    disk.update_partitions(pp)?;
+    Ok(())
+}
+
+/// Build an MBR table equivalent to the partitions of `disk`.
+///
+/// At most four partitions are supported, as primary records in order
+/// of partition number, and all of them must end below the 2 TiB limit
+/// of MBR records (on 512 bytes sectors). Types are mapped with
+/// `mbr_type_for_gpt()`, failing for unknown ones, and the legacy-BIOS
+/// bootable attribute sets the bootable flag. CHS addresses are
+/// synthesized, see `mbr::ChsMode`.
+///
+/// The table is only returned, see `mbr::MbrTable::overwrite_lba0()` to
+/// write it. Note that the GPT structures are left on disk, and may need
+/// to be wiped with `GptDisk::zap()` first.
+pub fn gpt_to_mbr<D: DiskDevice>(disk: &GptDisk<D>) -> Result<mbr::MbrTable> {
+    if disk.partitions.len() > 4 {
+        return Err(Error::Invalid(format!(
+            "{} partitions do not fit in an MBR, at most 4 are supported",
+            disk.partitions.len()
+        )));
+    }
+    let mut table = mbr::MbrTable::new();
+    for (slot, (id, p)) in disk.partitions.iter().enumerate() {
+        let os_type = mbr_type_for_gpt(&p.part_type_guid).ok_or_else(|| {
+            Error::Invalid(format!(
+                "partition {} has type {} without MBR equivalent",
+                id, p.part_type_guid.guid
+            ))
+        })?;
+        let start = u32::try_from(p.first_lba).ok();
+        let size = u32::try_from(p.last_lba - p.first_lba + 1).ok();
+        let (start, size) = match (start, size) {
+            (Some(start), Some(size)) if start.checked_add(size).is_some() => (start, size),
+            _ => {
+                return Err(Error::Invalid(format!(
+                    "partition {} beyond MBR addressing limit",
+                    id
+                )))
+            }
+        };
+        let mut record = PartRecord::new(os_type, start, size);
+        record.set_chs(mbr::ChsMode::Synthesized);
+        if PartitionAttributes::from_bits_retain(p.flags).contains(PartitionAttributes::BOOTABLE) {
+            record.boot_indicator = 0x80;
+        }
+        table.set_partition(slot, record)?;
+    }
+    Ok(table)
+}
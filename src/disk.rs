@@ -1,29 +1,933 @@
 //! Disk-related types and helper functions.
 
 use super::{GptConfig, GptDisk};
-use std::{io, path};
+use crate::partition::Partition;
+use crate::quirks::QuirkTracker;
+use crate::{header, mbr};
+use log::*;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::Duration;
+use std::{fmt, fs, io, path, thread};
 
 /// Default size of a logical sector (bytes).
 pub const DEFAULT_SECTOR_SIZE: LogicalBlockSize = LogicalBlockSize::Lb512;
 
+/// Smallest supported logical block size (bytes), holding a full MBR.
+pub const MIN_SECTOR_SIZE: u64 = 512;
+
 /// Logical block/sector size of a GPT disk.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+///
+/// Sizes are compared by value. Other sizes are only built through
+/// `TryFrom<u64>`, which always picks the named variants when possible.
+#[derive(Clone, Copy, Debug)]
 pub enum LogicalBlockSize {
     /// 512 bytes.
     Lb512,
     /// 4096 bytes.
     Lb4096,
+    /// Any other size, at least 512 bytes (e.g. 2048 for optical media,
+    /// or 520 and 528 for some SAN arrays).
+    Other(OtherBlockSize),
 }
 
-impl Into<u64> for LogicalBlockSize {
-    fn into(self) -> u64 {
-        match self {
+/// Size (in bytes) of a `LogicalBlockSize::Other` block, checked on creation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OtherBlockSize(u32);
+
+impl OtherBlockSize {
+    /// Return the size of a logical block (in bytes).
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl PartialEq for LogicalBlockSize {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes() == other.bytes()
+    }
+}
+
+impl Eq for LogicalBlockSize {}
+
+impl From<LogicalBlockSize> for u64 {
+    fn from(lb_size: LogicalBlockSize) -> u64 {
+        match lb_size {
             LogicalBlockSize::Lb512 => 512,
             LogicalBlockSize::Lb4096 => 4096,
+            LogicalBlockSize::Other(bytes) => bytes.0.into(),
+        }
+    }
+}
+
+impl TryFrom<u64> for LogicalBlockSize {
+    type Error = crate::Error;
+
+    fn try_from(bytes: u64) -> crate::Result<Self> {
+        match bytes {
+            512 => Ok(LogicalBlockSize::Lb512),
+            4096 => Ok(LogicalBlockSize::Lb4096),
+            _ => u32::try_from(bytes)
+                .ok()
+                .filter(|_| bytes >= MIN_SECTOR_SIZE)
+                .map(|bytes| LogicalBlockSize::Other(OtherBlockSize(bytes)))
+                .ok_or_else(|| crate::Error::Invalid("unsupported logical block size".into())),
+        }
+    }
+}
+
+impl LogicalBlockSize {
+    /// Return the size of a logical block (in bytes).
+    pub fn bytes(self) -> u64 {
+        self.into()
+    }
+
+    /// Convert an LBA to its offset (in bytes), failing on overflow.
+    pub fn lba_to_bytes(self, lba: u64) -> crate::Result<u64> {
+        lba.checked_mul(self.bytes())
+            .ok_or(crate::Error::Overflow("LBA in bytes"))
+    }
+
+    /// Convert an offset (in bytes) to an LBA.
+    ///
+    /// This fails if the offset is not aligned to a logical block boundary.
+    pub fn bytes_to_lba(self, bytes: u64) -> crate::Result<u64> {
+        if bytes % self.bytes() != 0 {
+            return Err(crate::Error::Unaligned {
+                value: bytes,
+                alignment: self.bytes(),
+            });
+        }
+        Ok(bytes / self.bytes())
+    }
+}
+
+/// A block device or disk image holding a partition table.
+///
+/// This is implemented for files and in-memory buffers, and can be
+/// implemented for other backends (e.g. network block devices) to open
+/// them with `GptConfig::open_from_device()`.
+pub trait DiskDevice: Read + Write + Seek + std::fmt::Debug {
+    /// Return the size of the device (in bytes).
+    fn size(&self) -> io::Result<u64>;
+
+    /// Persist written data to the underlying storage.
+    fn sync_data(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+
+    /// Notify the operating system that partitions changed from `old` to `new`.
+    ///
+    /// This is called once a new table has been written, and does nothing
+    /// by default.
+    fn partitions_changed(
+        &mut self,
+        old: &BTreeMap<u32, Partition>,
+        new: &BTreeMap<u32, Partition>,
+        lb_size: LogicalBlockSize,
+    ) -> io::Result<()> {
+        let _ = (old, new, lb_size);
+        Ok(())
+    }
+
+    /// Discard `len` bytes from `offset`, letting the storage reclaim them.
+    ///
+    /// Discarded ranges may read back as zeros or as stale data. This
+    /// fails with `ErrorKind::Unsupported` by default.
+    fn discard(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        let _ = (offset, len);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "discard not supported by device",
+        ))
+    }
+}
+
+impl DiskDevice for fs::File {
+    fn size(&self) -> io::Result<u64> {
+        #[cfg(all(feature = "linux", target_os = "linux"))]
+        {
+            if crate::linux::is_block_device(self) {
+                return crate::linux::device_size(self);
+            }
+        }
+        Ok(self.metadata()?.len())
+    }
+
+    fn sync_data(&mut self) -> io::Result<()> {
+        fs::File::sync_data(self)
+    }
+
+    /// With the `linux` feature, block devices get their partition table
+    /// re-read by the kernel, falling back to updating changed partitions
+    /// one by one if some are in use.
+    fn partitions_changed(
+        &mut self,
+        old: &BTreeMap<u32, Partition>,
+        new: &BTreeMap<u32, Partition>,
+        lb_size: LogicalBlockSize,
+    ) -> io::Result<()> {
+        #[cfg(all(feature = "linux", target_os = "linux"))]
+        {
+            use crate::linux;
+
+            if !linux::is_block_device(self) {
+                return Ok(());
+            }
+            fs::File::sync_data(self)?;
+            match linux::reread_partitions(self) {
+                Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {
+                    debug!("partition table busy, updating partitions one by one");
+                    linux::update_partitions(self, old, new, lb_size)
+                }
+                res => res,
+            }
+        }
+        #[cfg(not(all(feature = "linux", target_os = "linux")))]
+        {
+            let _ = (old, new, lb_size);
+            Ok(())
+        }
+    }
+
+    /// With the `linux` feature, block devices are discarded with `BLKDISCARD`.
+    fn discard(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        #[cfg(all(feature = "linux", target_os = "linux"))]
+        {
+            if crate::linux::is_block_device(self) {
+                return crate::linux::discard(self, offset, len);
+            }
+        }
+        let _ = (offset, len);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "discard only supported on Linux block devices",
+        ))
+    }
+}
+
+impl DiskDevice for io::Cursor<Vec<u8>> {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.get_ref().len() as u64)
+    }
+}
+
+impl DiskDevice for io::Cursor<&mut Vec<u8>> {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.get_ref().len() as u64)
+    }
+}
+
+impl DiskDevice for io::Cursor<&mut [u8]> {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.get_ref().len() as u64)
+    }
+}
+
+impl<D: DiskDevice + ?Sized> DiskDevice for Box<D> {
+    fn size(&self) -> io::Result<u64> {
+        (**self).size()
+    }
+
+    fn sync_data(&mut self) -> io::Result<()> {
+        (**self).sync_data()
+    }
+
+    fn partitions_changed(
+        &mut self,
+        old: &BTreeMap<u32, Partition>,
+        new: &BTreeMap<u32, Partition>,
+        lb_size: LogicalBlockSize,
+    ) -> io::Result<()> {
+        (**self).partitions_changed(old, new, lb_size)
+    }
+
+    fn discard(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        (**self).discard(offset, len)
+    }
+}
+
+/// Adapter for devices which only transfer whole sectors at aligned offsets.
+///
+/// Raw disks on some platforms (e.g. `\\.\PhysicalDrive0` on Windows)
+/// reject unaligned reads and writes, and cannot report their size by
+/// seeking. Every transfer through this adapter is widened to whole
+/// `sector_size` blocks, with partial sectors read back and merged before
+/// being written, and the device size is given upfront.
+#[derive(Debug)]
+pub struct AlignedDevice<D> {
+    inner: D,
+    sector_size: u64,
+    size: u64,
+    pos: u64,
+}
+
+impl<D: Read + Write + Seek> AlignedDevice<D> {
+    /// Wrap `inner`, a device of `size` bytes transferring `sector_size` blocks.
+    pub fn new(inner: D, sector_size: LogicalBlockSize, size: u64) -> Self {
+        Self {
+            inner,
+            sector_size: sector_size.bytes(),
+            size,
+            pos: 0,
+        }
+    }
+
+    /// Return the wrapped device.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Aligned span covering `len` bytes from the current position.
+    fn span(&self, len: usize) -> (u64, usize) {
+        let start = self.pos - self.pos % self.sector_size;
+        let end = (self.pos + len as u64).div_ceil(self.sector_size) * self.sector_size;
+        (start, (end - start) as usize)
+    }
+
+    fn read_span(&mut self, start: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.inner.seek(SeekFrom::Start(start))?;
+        self.inner.read_exact(buf)
+    }
+}
+
+impl<D: Read + Write + Seek> Read for AlignedDevice<D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.size.saturating_sub(self.pos) as usize);
+        if len == 0 {
+            return Ok(0);
+        }
+        let (start, span_len) = self.span(len);
+        let mut span = vec![0u8; span_len];
+        self.read_span(start, &mut span)?;
+        let skip = (self.pos - start) as usize;
+        buf[..len].copy_from_slice(&span[skip..skip + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<D: Read + Write + Seek> Write for AlignedDevice<D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pos + buf.len() as u64 > self.size {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "write past the end of device",
+            ));
+        }
+        let (start, span_len) = self.span(buf.len());
+        let skip = (self.pos - start) as usize;
+        let mut span = vec![0u8; span_len];
+        if skip != 0 || span_len != buf.len() {
+            self.read_span(start, &mut span)?;
+        }
+        span[skip..skip + buf.len()].copy_from_slice(buf);
+        self.inner.seek(SeekFrom::Start(start))?;
+        self.inner.write_all(&span)?;
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<D: Read + Write + Seek> Seek for AlignedDevice<D> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = seek_position(self.pos, self.size, pos)?;
+        Ok(self.pos)
+    }
+}
+
+impl<D: DiskDevice> DiskDevice for AlignedDevice<D> {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.size)
+    }
+
+    fn sync_data(&mut self) -> io::Result<()> {
+        self.inner.sync_data()
+    }
+
+    fn partitions_changed(
+        &mut self,
+        old: &BTreeMap<u32, Partition>,
+        new: &BTreeMap<u32, Partition>,
+        lb_size: LogicalBlockSize,
+    ) -> io::Result<()> {
+        self.inner.partitions_changed(old, new, lb_size)
+    }
+
+    fn discard(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        self.inner.discard(offset, len)
+    }
+}
+
+/// Size of the chunks allocated by a `MemoryDevice`.
+const MEMORY_CHUNK: u64 = 64 * 1024;
+
+/// In-memory device of a fixed size, only allocating the chunks written to.
+///
+/// Unwritten ranges read as zeros, so that tables for large disks can be
+/// composed without any backing storage, see `GptDisk::new_in_memory()`.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryDevice {
+    size: u64,
+    pos: u64,
+    chunks: BTreeMap<u64, Vec<u8>>,
+}
+
+impl MemoryDevice {
+    /// Create a zeroed device of `size` bytes.
+    pub fn new(size: u64) -> Self {
+        Self {
+            size,
+            ..Self::default()
+        }
+    }
+
+    /// Return the amount of memory (in bytes) allocated for written data.
+    pub fn allocated(&self) -> u64 {
+        self.chunks.len() as u64 * MEMORY_CHUNK
+    }
+}
+
+impl Read for MemoryDevice {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.size.saturating_sub(self.pos) as usize);
+        let mut done = 0;
+        while done < len {
+            let (idx, off) = (self.pos / MEMORY_CHUNK, (self.pos % MEMORY_CHUNK) as usize);
+            let n = (len - done).min(MEMORY_CHUNK as usize - off);
+            let dst = &mut buf[done..done + n];
+            match self.chunks.get(&idx) {
+                Some(chunk) => dst.copy_from_slice(&chunk[off..off + n]),
+                None => dst.iter_mut().for_each(|b| *b = 0),
+            }
+            done += n;
+            self.pos += n as u64;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for MemoryDevice {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.pos + buf.len() as u64 > self.size {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "write past the end of device",
+            ));
+        }
+        let mut done = 0;
+        while done < buf.len() {
+            let (idx, off) = (self.pos / MEMORY_CHUNK, (self.pos % MEMORY_CHUNK) as usize);
+            let n = (buf.len() - done).min(MEMORY_CHUNK as usize - off);
+            let chunk = self
+                .chunks
+                .entry(idx)
+                .or_insert_with(|| vec![0; MEMORY_CHUNK as usize]);
+            chunk[off..off + n].copy_from_slice(&buf[done..done + n]);
+            done += n;
+            self.pos += n as u64;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryDevice {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = seek_position(self.pos, self.size, pos)?;
+        Ok(self.pos)
+    }
+}
+
+impl DiskDevice for MemoryDevice {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.size)
+    }
+
+    /// Discarded ranges read back as zeros, and whole chunks are freed.
+    fn discard(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        let end = offset
+            .checked_add(len)
+            .filter(|end| *end <= self.size)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "discard past the end"))?;
+        let mut pos = offset;
+        while pos < end {
+            let (idx, off) = (pos / MEMORY_CHUNK, pos % MEMORY_CHUNK);
+            let n = (end - pos).min(MEMORY_CHUNK - off);
+            if n == MEMORY_CHUNK {
+                self.chunks.remove(&idx);
+            } else if let Some(chunk) = self.chunks.get_mut(&idx) {
+                chunk[off as usize..(off + n) as usize]
+                    .iter_mut()
+                    .for_each(|b| *b = 0);
+            }
+            pos += n;
+        }
+        Ok(())
+    }
+}
+
+/// View into the data region of a partition, see `GptDisk::open_partition()`.
+///
+/// Offsets are relative to the start of the partition, and transfers are
+/// clamped to its end: reads stop there, and writes beyond it fail with
+/// `ErrorKind::WriteZero`. This can be handed to filesystem crates as the
+/// partition's block device.
+#[derive(Debug)]
+pub struct PartitionIo<'a, D> {
+    device: &'a mut D,
+    start: u64,
+    size: u64,
+    pos: u64,
+    writable: bool,
+}
+
+impl<'a, D: Seek> PartitionIo<'a, D> {
+    /// Wrap `device`, for the `size` bytes starting at byte offset `start`.
+    ///
+    /// Writes fail with `ErrorKind::PermissionDenied` unless `writable`.
+    pub fn new(device: &'a mut D, start: u64, size: u64, writable: bool) -> Self {
+        Self {
+            device,
+            start,
+            size,
+            pos: 0,
+            writable,
+        }
+    }
+
+    /// Return the size (in bytes) of the partition.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Position the underlying device, returning how many bytes are left from there.
+    fn remaining(&mut self) -> io::Result<u64> {
+        let left = self.size.saturating_sub(self.pos);
+        if left > 0 {
+            self.device.seek(SeekFrom::Start(self.start + self.pos))?;
+        }
+        Ok(left)
+    }
+}
+
+impl<D: Read + Seek> Read for PartitionIo<'_, D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.remaining()?;
+        let n = buf.len().min(usize::try_from(left).unwrap_or(usize::MAX));
+        if n == 0 {
+            return Ok(0);
+        }
+        let n = self.device.read(&mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<D: Write + Seek> Write for PartitionIo<'_, D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.writable {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "disk not opened in writable mode",
+            ));
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let left = self.remaining()?;
+        if left == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "write past the end of partition",
+            ));
+        }
+        let n = buf.len().min(usize::try_from(left).unwrap_or(usize::MAX));
+        let n = self.device.write(&buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.device.flush()
+    }
+}
+
+impl<D> Seek for PartitionIo<'_, D> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = seek_position(self.pos, self.size, pos)?;
+        Ok(self.pos)
+    }
+}
+
+/// Resolve `pos` from the current offset `cur`, on a device of `size` bytes.
+pub(crate) fn seek_position(cur: u64, size: u64, pos: SeekFrom) -> io::Result<u64> {
+    let target = match pos {
+        SeekFrom::Start(off) => Some(off),
+        SeekFrom::End(off) => size.checked_add_signed(off),
+        SeekFrom::Current(off) => cur.checked_add_signed(off),
+    };
+    target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))
+}
+
+/// Retry policy for transient I/O failures.
+///
+/// Interrupted, timed-out and would-block operations, as well as generic
+/// I/O errors (`EIO`, common with flaky USB bridges and network targets),
+/// are retried up to `max_retries` times, waiting `backoff` before the
+/// first retry and doubling the delay each time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries for a single operation.
+    pub max_retries: u32,
+    /// Delay before the first retry.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy which never retries.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: Duration::from_millis(0),
+        }
+    }
+}
+
+/// Statistics about retried and partial I/O operations.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct IoStats {
+    /// Number of retries after transient errors.
+    pub retries: u64,
+    /// Number of reads which returned less data than requested.
+    pub short_reads: u64,
+    /// Number of writes which accepted less data than provided.
+    pub short_writes: u64,
+}
+
+/// Whether an I/O error is worth retrying.
+fn is_transient(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        if e.raw_os_error() == Some(libc::EIO) {
+            return true;
+        }
+    }
+    matches!(
+        e.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Errors which may come from a transient I/O error.
+pub(crate) trait IoFailure: fmt::Display {
+    /// The underlying I/O error, if any.
+    fn io_error(&self) -> Option<&io::Error>;
+}
+
+impl IoFailure for io::Error {
+    fn io_error(&self) -> Option<&io::Error> {
+        Some(self)
+    }
+}
+
+impl IoFailure for crate::Error {
+    fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            crate::Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Run `op`, retrying it on transient errors according to `policy`.
+pub(crate) fn with_retries<T, E, F>(
+    policy: &RetryPolicy,
+    stats: &mut IoStats,
+    mut op: F,
+) -> Result<T, E>
+where
+    E: IoFailure,
+    F: FnMut() -> Result<T, E>,
+{
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(ref e)
+                if e.io_error().is_some_and(is_transient) && attempt < policy.max_retries =>
+            {
+                let delay = policy.backoff * 2u32.saturating_pow(attempt);
+                warn!("transient I/O error ({}), retrying in {:?}", e, delay);
+                stats.retries += 1;
+                attempt += 1;
+                thread::sleep(delay);
+            }
+            res => return res,
         }
     }
 }
 
+/// Read exactly `buf.len()` bytes at `offset`, resuming short reads.
+pub(crate) fn read_exact_at<R: Read + Seek>(
+    dev: &mut R,
+    offset: u64,
+    buf: &mut [u8],
+    policy: &RetryPolicy,
+    stats: &mut IoStats,
+) -> io::Result<()> {
+    let mut done = 0;
+    while done < buf.len() {
+        let n = with_retries(policy, stats, || {
+            dev.seek(SeekFrom::Start(offset + done as u64))?;
+            dev.read(&mut buf[done..])
+        })?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "unexpected end of disk",
+            ));
+        }
+        done += n;
+        if done < buf.len() {
+            stats.short_reads += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Write all of `buf` at `offset`, resuming short writes.
+pub(crate) fn write_all_at<W: Write + Seek>(
+    dev: &mut W,
+    offset: u64,
+    buf: &[u8],
+    policy: &RetryPolicy,
+    stats: &mut IoStats,
+) -> io::Result<()> {
+    let mut done = 0;
+    while done < buf.len() {
+        let n = with_retries(policy, stats, || {
+            dev.seek(SeekFrom::Start(offset + done as u64))?;
+            dev.write(&buf[done..])
+        })?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "failed to write to disk",
+            ));
+        }
+        done += n;
+        if done < buf.len() {
+            stats.short_writes += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Look for an existing partition table on a disk.
+///
+/// This returns a short description of the first label found, either
+/// a valid GPT header or an MBR with at least one partition.
+pub(crate) fn probe_label<D: Read + Seek>(
+    file: &mut D,
+    lb_size: LogicalBlockSize,
+) -> Option<&'static str> {
+    if header::read_primary_header(file, lb_size, &mut QuirkTracker::default()).is_ok() {
+        return Some("a GPT partition table");
+    }
+    match mbr::MbrTable::from_disk(file, lb_size) {
+        Ok(m) if m.partitions().iter().any(|p| !p.is_empty()) => Some("an MBR partition table"),
+        _ => None,
+    }
+}
+
+/// Return the preferred I/O alignment (in bytes) of a block device, if any.
+///
+/// On Linux, this is read from the sysfs queue attributes of the device:
+/// `optimal_io_size` (e.g. a RAID stripe width), falling back to
+/// `minimum_io_size` (e.g. a RAID chunk size). Regular files, and devices
+/// which report neither, return `None`.
+pub fn topology_alignment(diskpath: &path::Path) -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        read_queue_alignment(&sysfs_block_dir(diskpath)?.join("queue"))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = diskpath;
+        None
+    }
+}
+
+/// Return the logical block size reported by the kernel for a block device.
+///
+/// On Linux, this is queried with `BLKSSZGET` if the `linux` feature is
+/// enabled, and read from the sysfs `logical_block_size` queue attribute
+/// otherwise. Regular files, and unsupported sizes, return `None`.
+pub fn device_block_size(diskpath: &path::Path) -> Option<LogicalBlockSize> {
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    {
+        let file = fs::File::open(diskpath).ok()?;
+        if !crate::linux::is_block_device(&file) {
+            return None;
+        }
+        crate::linux::logical_block_size(&file).ok()
+    }
+    #[cfg(all(target_os = "linux", not(feature = "linux")))]
+    {
+        let queue = sysfs_block_dir(diskpath)?.join("queue");
+        let raw = fs::read_to_string(queue.join("logical_block_size")).ok()?;
+        LogicalBlockSize::try_from(raw.trim().parse::<u64>().ok()?).ok()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = diskpath;
+        None
+    }
+}
+
+/// Guess the logical block size of a disk from the location of its primary header.
+///
+/// LBA1 is probed for a GPT signature at 512 and 4096 bytes offsets. This
+/// returns `None` if neither holds a header, e.g. for a blank disk.
+pub fn detect_block_size<D: Read + Seek>(file: &mut D) -> Option<LogicalBlockSize> {
+    let candidates = [LogicalBlockSize::Lb512, LogicalBlockSize::Lb4096];
+    candidates.iter().copied().find(|lb_size| {
+        let mut buf = [0u8; 32];
+        let read = file
+            .seek(SeekFrom::Start(lb_size.bytes()))
+            .and_then(|_| file.read_exact(&mut buf));
+        // Signature, and the header's own LBA.
+        read.is_ok() && &buf[..8] == b"EFI PART" && buf[24..32] == 1u64.to_le_bytes()
+    })
+}
+
+/// Return the sysfs directory of a block device, e.g. `/sys/dev/block/8:0`.
+#[cfg(target_os = "linux")]
+fn sysfs_block_dir(diskpath: &path::Path) -> Option<path::PathBuf> {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    let meta = fs::metadata(diskpath).ok()?;
+    if !meta.file_type().is_block_device() {
+        return None;
+    }
+    let (major, minor) = (libc::major(meta.rdev()), libc::minor(meta.rdev()));
+    Some(path::PathBuf::from(format!(
+        "/sys/dev/block/{}:{}",
+        major, minor
+    )))
+}
+
+/// Identity of the physical device behind a disk path.
+#[cfg(feature = "device-info")]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DeviceIdentity {
+    /// Model name, as reported by the device.
+    pub model: Option<String>,
+    /// Serial number, as reported by the device.
+    pub serial: Option<String>,
+    /// Transport the device is attached through, e.g. `nvme` or `usb`.
+    pub transport: Option<String>,
+}
+
+#[cfg(feature = "device-info")]
+impl std::fmt::Display for DeviceIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let show = |v: &Option<String>| v.clone().unwrap_or_else(|| "unknown".to_string());
+        write!(
+            f,
+            "model {}, serial {}, transport {}",
+            show(&self.model),
+            show(&self.serial),
+            show(&self.transport)
+        )
+    }
+}
+
+/// Look up the model, serial and transport of a block device.
+///
+/// On Linux, this is read from sysfs; for partitions, the identity of
+/// the whole disk is returned. Regular files return `None`, as do all
+/// paths on other platforms.
+#[cfg(feature = "device-info")]
+pub fn device_identity(diskpath: &path::Path) -> Option<DeviceIdentity> {
+    #[cfg(target_os = "linux")]
+    {
+        let dir = fs::canonicalize(sysfs_block_dir(diskpath)?).ok()?;
+        // Partitions live below the directory of their disk.
+        let dir = if dir.join("partition").exists() {
+            dir.parent()?.to_path_buf()
+        } else {
+            dir
+        };
+        Some(read_device_identity(&dir))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = diskpath;
+        None
+    }
+}
+
+/// Read the identity of a disk from its (canonical) sysfs directory.
+#[cfg(all(feature = "device-info", target_os = "linux"))]
+fn read_device_identity(dir: &path::Path) -> DeviceIdentity {
+    let read = |name: &str| -> Option<String> {
+        let raw = fs::read(dir.join("device").join(name)).ok()?;
+        let value = String::from_utf8_lossy(&raw)
+            .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+            .to_string();
+        Some(value).filter(|v| !v.is_empty())
+    };
+    // SCSI disks only expose their serial in the unit serial number VPD page.
+    let vpd_serial = || {
+        let raw = fs::read(dir.join("device").join("vpd_pg80")).ok()?;
+        let value = String::from_utf8_lossy(raw.get(4..)?).trim().to_string();
+        Some(value).filter(|v| !v.is_empty())
+    };
+    let transport = ["nvme", "usb", "virtio", "mmc", "ata"]
+        .iter()
+        .find(|name| {
+            dir.components()
+                .any(|c| c.as_os_str().to_string_lossy().starts_with(*name))
+        })
+        .map(|name| name.to_string());
+    DeviceIdentity {
+        model: read("model"),
+        serial: read("serial").or_else(vpd_serial),
+        transport,
+    }
+}
+
+/// Read the preferred alignment from a sysfs `queue` directory.
+#[cfg(target_os = "linux")]
+fn read_queue_alignment(queue: &path::Path) -> Option<u64> {
+    let read = |name: &str| -> Option<u64> {
+        let raw = fs::read_to_string(queue.join(name)).ok()?;
+        raw.trim().parse::<u64>().ok().filter(|v| *v > 0)
+    };
+    read("optimal_io_size").or_else(|| read("minimum_io_size"))
+}
+
 /// Open and read a GPT disk, using default configuration options.
 ///
 /// ## Example
@@ -33,7 +937,308 @@ impl Into<u64> for LogicalBlockSize {
 /// let gpt_disk = gpt::disk::read_disk(diskpath).unwrap();
 /// println!("{:#?}", gpt_disk);
 /// ```
-pub fn read_disk(diskpath: &path::Path) -> io::Result<GptDisk> {
+pub fn read_disk(diskpath: &path::Path) -> crate::Result<GptDisk> {
     let cfg = GptConfig::new();
     cfg.open(diskpath)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::disk::LogicalBlockSize;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_lb_size_conversions() {
+        let lb512 = LogicalBlockSize::Lb512;
+        let lb4096 = LogicalBlockSize::Lb4096;
+        assert_eq!(lb512.bytes(), 512);
+        assert_eq!(lb4096.bytes(), 4096);
+
+        assert_eq!(lb512.lba_to_bytes(0).unwrap(), 0);
+        assert_eq!(lb512.lba_to_bytes(34).unwrap(), 34 * 512);
+        assert_eq!(lb4096.lba_to_bytes(6).unwrap(), 6 * 4096);
+        lb4096.lba_to_bytes(u64::MAX).unwrap_err();
+
+        assert_eq!(lb512.bytes_to_lba(1024).unwrap(), 2);
+        assert_eq!(lb4096.bytes_to_lba(8192).unwrap(), 2);
+        lb4096.bytes_to_lba(512).unwrap_err();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_read_queue_alignment() {
+        let queue = tempfile::tempdir().unwrap();
+        assert_eq!(super::read_queue_alignment(queue.path()), None);
+
+        std::fs::write(queue.path().join("minimum_io_size"), "65536\n").unwrap();
+        std::fs::write(queue.path().join("optimal_io_size"), "0\n").unwrap();
+        assert_eq!(super::read_queue_alignment(queue.path()), Some(65536));
+
+        std::fs::write(queue.path().join("optimal_io_size"), "262144\n").unwrap();
+        assert_eq!(super::read_queue_alignment(queue.path()), Some(262_144));
+    }
+
+    #[test]
+    fn test_detect_block_size() {
+        let image = std::fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+        let mut cur = std::io::Cursor::new(image);
+        assert_eq!(
+            super::detect_block_size(&mut cur),
+            Some(LogicalBlockSize::Lb512)
+        );
+
+        // Move the primary header to LBA1 of a 4096 bytes sectors disk.
+        let mut image = vec![0u8; 16 * 4096];
+        image[4096..4096 + 512].copy_from_slice(&cur.get_ref()[512..1024]);
+        let mut cur = std::io::Cursor::new(image);
+        assert_eq!(
+            super::detect_block_size(&mut cur),
+            Some(LogicalBlockSize::Lb4096)
+        );
+        cur.get_mut()[4096] = 0;
+        assert_eq!(super::detect_block_size(&mut cur), None);
+        assert_eq!(
+            super::detect_block_size(&mut std::io::Cursor::new(vec![])),
+            None
+        );
+
+        let path = std::path::Path::new("tests/fixtures/gpt-linux-disk-01.img");
+        assert_eq!(super::device_block_size(path), None);
+    }
+
+    #[test]
+    fn test_topology_alignment_file() {
+        let path = std::path::Path::new("tests/fixtures/gpt-linux-disk-01.img");
+        assert_eq!(super::topology_alignment(path), None);
+    }
+
+    #[cfg(all(feature = "device-info", target_os = "linux"))]
+    #[test]
+    fn test_read_device_identity() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root
+            .path()
+            .join("pci0000:00/0000:00:14.0/usb2/2-1/block/sdb");
+        std::fs::create_dir_all(dir.join("device")).unwrap();
+        let id = super::read_device_identity(&dir);
+        assert_eq!(id.transport.as_deref(), Some("usb"));
+        assert_eq!(id.model, None);
+        assert_eq!(
+            id.to_string(),
+            "model unknown, serial unknown, transport usb"
+        );
+
+        std::fs::write(dir.join("device/model"), "Flash Disk      \n").unwrap();
+        std::fs::write(dir.join("device/vpd_pg80"), b"\0\x80\0\x08 ABC1234").unwrap();
+        let id = super::read_device_identity(&dir);
+        assert_eq!(id.model.as_deref(), Some("Flash Disk"));
+        assert_eq!(id.serial.as_deref(), Some("ABC1234"));
+
+        std::fs::write(dir.join("device/serial"), "XYZ\n").unwrap();
+        assert_eq!(
+            super::read_device_identity(&dir).serial.as_deref(),
+            Some("XYZ")
+        );
+    }
+
+    /// In-memory device, failing and truncating transfers on demand.
+    struct FlakyDevice {
+        data: std::io::Cursor<Vec<u8>>,
+        failures: u32,
+        max_chunk: usize,
+    }
+
+    impl FlakyDevice {
+        fn fail(&mut self) -> std::io::Result<()> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(std::io::Error::from(std::io::ErrorKind::Interrupted));
+            }
+            Ok(())
+        }
+    }
+
+    impl std::io::Read for FlakyDevice {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.fail()?;
+            let n = buf.len().min(self.max_chunk);
+            self.data.read(&mut buf[..n])
+        }
+    }
+
+    impl std::io::Write for FlakyDevice {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.fail()?;
+            let n = buf.len().min(self.max_chunk);
+            self.data.write(&buf[..n])
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl std::io::Seek for FlakyDevice {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_retrying_io() {
+        use crate::disk::{read_exact_at, write_all_at, IoStats, RetryPolicy};
+
+        let mut dev = FlakyDevice {
+            data: std::io::Cursor::new(vec![0u8; 64]),
+            failures: 2,
+            max_chunk: 10,
+        };
+        let policy = RetryPolicy {
+            max_retries: 2,
+            backoff: std::time::Duration::from_millis(1),
+        };
+        let mut stats = IoStats::default();
+        write_all_at(&mut dev, 8, &[0xAA; 25], &policy, &mut stats).unwrap();
+        assert_eq!(stats.retries, 2);
+        assert_eq!(stats.short_writes, 2);
+
+        let mut buf = [0u8; 27];
+        read_exact_at(&mut dev, 7, &mut buf, &policy, &mut stats).unwrap();
+        assert_eq!(buf[0], 0);
+        assert!(buf[1..26].iter().all(|b| *b == 0xAA));
+        assert_eq!(stats.short_reads, 2);
+        read_exact_at(&mut dev, 60, &mut buf, &policy, &mut stats).unwrap_err();
+
+        // Out of retries.
+        dev.failures = 3;
+        let err = write_all_at(&mut dev, 0, &[1], &policy, &mut stats).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Interrupted);
+        assert_eq!(stats.retries, 4);
+    }
+
+    /// Device refusing unaligned transfers, like raw Windows disks.
+    #[derive(Debug)]
+    struct StrictDevice(std::io::Cursor<Vec<u8>>);
+
+    impl StrictDevice {
+        fn check(&self, len: usize) -> std::io::Result<()> {
+            if self.0.position() % 512 != 0 || len % 512 != 0 {
+                return Err(std::io::Error::other("unaligned transfer"));
+            }
+            Ok(())
+        }
+    }
+
+    impl std::io::Read for StrictDevice {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.check(buf.len())?;
+            self.0.read(buf)
+        }
+    }
+
+    impl std::io::Write for StrictDevice {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.check(buf.len())?;
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl std::io::Seek for StrictDevice {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    impl super::DiskDevice for StrictDevice {
+        fn size(&self) -> std::io::Result<u64> {
+            Err(std::io::Error::other("size not available by seeking"))
+        }
+    }
+
+    #[test]
+    fn test_aligned_device() {
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        let image = std::fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+        let len = image.len() as u64;
+        let strict = StrictDevice(std::io::Cursor::new(image));
+        let mut dev = super::AlignedDevice::new(strict, LogicalBlockSize::Lb512, len);
+        let mut sig = [0u8; 8];
+        dev.seek(SeekFrom::Start(512)).unwrap();
+        dev.read_exact(&mut sig).unwrap();
+        assert_eq!(&sig, b"EFI PART");
+        dev.seek(SeekFrom::Start(20000)).unwrap();
+        dev.write_all(&[0xAA; 100]).unwrap();
+        dev.seek(SeekFrom::Current(-101)).unwrap();
+        let mut buf = [0u8; 102];
+        dev.read_exact(&mut buf).unwrap();
+        assert_eq!((buf[0], buf[1], buf[100], buf[101]), (0, 0xAA, 0xAA, 0));
+        assert_eq!(dev.seek(SeekFrom::End(0)).unwrap(), len);
+        assert_eq!(dev.read(&mut buf).unwrap(), 0);
+        dev.write_all(&[0]).unwrap_err();
+
+        let mut gdisk = crate::GptConfig::new()
+            .writable(true)
+            .alignment(512)
+            .open_from_device(dev)
+            .unwrap();
+        let part_type = gdisk.partitions()[&1].part_type_guid.clone();
+        gdisk.remove_partition(1, false).unwrap();
+        gdisk.add_partition("second", 4096, part_type, 0).unwrap();
+        let dev = gdisk.write().unwrap();
+        let gdisk = crate::GptConfig::new().open_from_device(dev).unwrap();
+        assert_eq!(gdisk.partitions()[&1].name, "second");
+    }
+
+    #[test]
+    fn test_memory_device() {
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        let mut dev = super::MemoryDevice::new(1 << 40);
+        dev.seek(SeekFrom::Start(super::MEMORY_CHUNK - 2)).unwrap();
+        dev.write_all(b"abcd").unwrap();
+        assert_eq!(dev.allocated(), 2 * super::MEMORY_CHUNK);
+        dev.seek(SeekFrom::Current(-6)).unwrap();
+        let mut buf = [0xFF; 8];
+        dev.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"\0\0abcd\0\0");
+
+        dev.seek(SeekFrom::End(-1)).unwrap();
+        dev.write_all(b"z").unwrap();
+        assert_eq!(dev.read(&mut buf).unwrap(), 0);
+        dev.write_all(b"z").unwrap_err();
+        dev.seek(SeekFrom::Current(-(1 << 41))).unwrap_err();
+
+        // Discarding frees whole chunks, and zeroes partial ones.
+        use super::DiskDevice;
+        dev.discard(super::MEMORY_CHUNK - 1, super::MEMORY_CHUNK + 1)
+            .unwrap();
+        assert_eq!(dev.allocated(), 2 * super::MEMORY_CHUNK);
+        dev.seek(SeekFrom::Start(super::MEMORY_CHUNK - 2)).unwrap();
+        dev.read_exact(&mut buf[..4]).unwrap();
+        assert_eq!(&buf[..4], b"a\0\0\0");
+        dev.discard(1 << 40, 1).unwrap_err();
+    }
+
+    #[test]
+    fn test_lb_size_try_from() {
+        assert_eq!(
+            LogicalBlockSize::try_from(512).unwrap(),
+            LogicalBlockSize::Lb512
+        );
+        assert_eq!(
+            LogicalBlockSize::try_from(4096).unwrap(),
+            LogicalBlockSize::Lb4096
+        );
+        let other = LogicalBlockSize::try_from(520).unwrap();
+        assert!(matches!(other, LogicalBlockSize::Other(b) if b.get() == 520));
+        assert_eq!(other.bytes(), 520);
+        LogicalBlockSize::try_from(0).unwrap_err();
+        LogicalBlockSize::try_from(256).unwrap_err();
+        LogicalBlockSize::try_from(1 << 40).unwrap_err();
+    }
+}
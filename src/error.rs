@@ -0,0 +1,224 @@
+//! Error type of the GPT API.
+//!
+//! Public functions return `gpt::Result`, from `GptConfig` and `GptDisk`
+//! down to the `header`, `mbr` and `partition` modules and the `std`-less
+//! `raw` one, so that callers can match on the kind of failure. Only the
+//! device-level helpers (`disk::DiskDevice` and the OS-specific modules)
+//! stick to `io::Result`. I/O errors convert into `Error`, while an
+//! `Error` wrapped in an `io::Error` (e.g. by a `Read` or `Write`
+//! implementation) is recovered as-is by the conversion.
+
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+#[cfg(feature = "std")]
+use crate::{ExternalModification, ValidationFailed, VerificationFailed};
+
+/// Result type of the GPT API.
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Errors of the GPT API.
+///
+/// For checksums, `expected` is the value recorded in the header and
+/// `found` the one computed from the current data.
+#[derive(Debug)]
+pub enum Error {
+    /// I/O error of the underlying device.
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    /// Buffer or disk too short for the structures to parse or write.
+    TooShort,
+    /// GPT header signature is not "EFI PART", or MBR boot signature is missing.
+    InvalidSignature,
+    /// Header CRC32 does not match the header content.
+    HeaderCrcMismatch {
+        /// Header CRC32 field.
+        expected: u32,
+        /// Computed header CRC32.
+        found: u32,
+    },
+    /// Partition array CRC32 does not match the array content.
+    PartitionArrayCrcMismatch {
+        /// Partition array CRC32 field.
+        expected: u32,
+        /// Computed partition array CRC32.
+        found: u32,
+    },
+    /// Header `current_lba` (MyLBA) differs from where the header was read.
+    CurrentLbaMismatch {
+        /// LBA the header was read from.
+        expected: u64,
+        /// Header `current_lba` field.
+        found: u64,
+    },
+    /// Arithmetic overflow computing an offset or size, naming the value.
+    Overflow(&'static str),
+    /// Value (in bytes) not a multiple of the required alignment.
+    Unaligned {
+        /// Value, e.g. a disk size or offset.
+        value: u64,
+        /// Required alignment, e.g. the logical block size.
+        alignment: u64,
+    },
+    /// Partition overlaps an existing one.
+    Overlap,
+    /// Partition outside of the usable LBAs of the table.
+    OutsideUsableArea(u32),
+    /// Not enough free space for the requested partition.
+    NoSpace,
+    /// Disk not opened in writable mode.
+    NotWritable,
+    /// Disk has no partition table yet.
+    NotInitialized,
+    /// No partition with the given number or GUID.
+    UnknownPartition,
+    /// Partition number 0, or beyond the entries of the partition array.
+    InvalidPartitionNumber(u32),
+    /// No free entry left in the partition array.
+    TableFull,
+    /// Partition required by the platform, removed without forcing it.
+    RequiredPartition(u32),
+    /// Disk already holding a label (e.g. "an MBR partition table"), kept as-is.
+    ExistingLabel(&'static str),
+    /// Headers changed on disk since open.
+    #[cfg(feature = "std")]
+    ExternalModification(ExternalModification),
+    /// Data read back after a write differs from what was written.
+    #[cfg(feature = "std")]
+    VerificationFailed(VerificationFailed),
+    /// Table about to be written failed validation.
+    #[cfg(feature = "std")]
+    ValidationFailed(ValidationFailed),
+    /// Invalid argument, layout or on-disk data, with a description.
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            Error::Io(e) => write!(f, "{}", e),
+            Error::TooShort => write!(f, "buffer or disk too short"),
+            Error::InvalidSignature => write!(f, "invalid signature"),
+            Error::HeaderCrcMismatch { expected, found } => write!(
+                f,
+                "header CRC32 mismatch: expected {:#010x}, found {:#010x}",
+                expected, found
+            ),
+            Error::PartitionArrayCrcMismatch { expected, found } => write!(
+                f,
+                "partition array CRC32 mismatch: expected {:#010x}, found {:#010x}",
+                expected, found
+            ),
+            Error::CurrentLbaMismatch { expected, found } => write!(
+                f,
+                "header located at LBA {} but records LBA {}",
+                expected, found
+            ),
+            Error::Overflow(what) => write!(f, "arithmetic overflow computing {}", what),
+            Error::Unaligned { value, alignment } => {
+                write!(f, "{} is not a multiple of {} bytes", value, alignment)
+            }
+            Error::Overlap => write!(f, "partition overlaps an existing one"),
+            Error::OutsideUsableArea(id) => write!(f, "partition {} outside of usable LBAs", id),
+            Error::NoSpace => write!(f, "not enough free space for partition"),
+            Error::NotWritable => write!(f, "disk not opened in writable mode"),
+            Error::NotInitialized => write!(f, "disk not initialized"),
+            Error::UnknownPartition => write!(f, "unknown partition"),
+            Error::InvalidPartitionNumber(id) => write!(f, "invalid partition number {}", id),
+            Error::TableFull => write!(f, "no free partition entry"),
+            Error::RequiredPartition(id) => {
+                write!(f, "partition {} is required by the platform", id)
+            }
+            Error::ExistingLabel(label) => {
+                write!(f, "disk already contains {}, refusing to overwrite", label)
+            }
+            #[cfg(feature = "std")]
+            Error::ExternalModification(e) => write!(f, "{}", e),
+            #[cfg(feature = "std")]
+            Error::VerificationFailed(e) => write!(f, "{}", e),
+            #[cfg(feature = "std")]
+            Error::ValidationFailed(e) => write!(f, "{}", e),
+            Error::Invalid(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        if !e.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            return Error::Io(e);
+        }
+        let kind = e.kind();
+        match e.into_inner().map(|inner| inner.downcast::<Error>()) {
+            Some(Ok(inner)) => *inner,
+            Some(Err(inner)) => Error::Io(io::Error::new(kind, inner)),
+            None => Error::Io(kind.into()),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            Error::TooShort => io::Error::new(io::ErrorKind::UnexpectedEof, e),
+            Error::RequiredPartition(_) => io::Error::new(io::ErrorKind::PermissionDenied, e),
+            Error::ExistingLabel(_) => io::Error::new(io::ErrorKind::AlreadyExists, e),
+            e => io::Error::other(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Error;
+    use std::io;
+
+    #[test]
+    fn test_error_io_roundtrip() {
+        let e: Error = io::Error::other(Error::NoSpace).into();
+        assert!(matches!(e, Error::NoSpace));
+        let e: Error = io::Error::new(io::ErrorKind::UnexpectedEof, "eof").into();
+        let e = io::Error::from(e);
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(
+            io::Error::from(Error::NoSpace).to_string(),
+            Error::NoSpace.to_string()
+        );
+        let crc = Error::HeaderCrcMismatch {
+            expected: 1,
+            found: 2,
+        };
+        assert!(matches!(
+            Error::from(io::Error::from(crc)),
+            Error::HeaderCrcMismatch { found: 2, .. }
+        ));
+        assert_eq!(
+            io::Error::from(Error::RequiredPartition(1)).kind(),
+            io::ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            Error::Unaligned {
+                value: 1000,
+                alignment: 512
+            }
+            .to_string(),
+            "1000 is not a multiple of 512 bytes"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Free-space tracking for partition allocation.
+
+use crate::header::TableGeometry;
+use crate::partition::{Allocation, Partition};
+use std::collections::BTreeMap;
+
+/// Sorted set of free LBA ranges inside the usable area of a disk.
+///
+/// This is kept up to date on allocations, so that they do not need
+/// to rescan the whole partition table.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct FreeSpace {
+    /// Free ranges, as first LBA -> last LBA (inclusive).
+    by_start: BTreeMap<u64, u64>,
+}
+
+impl FreeSpace {
+    /// Compute free ranges in the usable area of `geometry`, not covered by any partition.
+    pub(crate) fn new<'a, I>(geometry: &TableGeometry, pp: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Partition>,
+    {
+        let (first_usable, last_usable) = (geometry.first_usable, geometry.last_usable);
+        let mut used: Vec<(u64, u64)> = pp.into_iter().map(|p| (p.first_lba, p.last_lba)).collect();
+        used.sort();
+
+        let mut free = Self::default();
+        let mut start = first_usable;
+        for (first, last) in used {
+            if first > start {
+                free.insert(start, (first - 1).min(last_usable));
+            }
+            start = start.max(last.saturating_add(1));
+            if start > last_usable {
+                break;
+            }
+        }
+        if start <= last_usable {
+            free.insert(start, last_usable);
+        }
+        free
+    }
+
+    /// Iterate over free ranges, as `(first_lba, last_lba)`, sorted by LBA.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.by_start.iter().map(|(first, last)| (*first, *last))
+    }
+
+    /// Return the lowest start, multiple of `align` LBAs, for `sectors` free LBAs.
+    pub(crate) fn find_first(&self, sectors: u64, align: u64) -> Option<u64> {
+        self.iter()
+            .find_map(|(first, last)| fit(first, last, sectors, align))
+    }
+
+    /// Return an aligned start for `sectors` free LBAs, picked according to `strategy`.
+    pub(crate) fn find(&self, sectors: u64, align: u64, strategy: Allocation) -> Option<u64> {
+        match strategy {
+            Allocation::FirstFit => self.find_first(sectors, align),
+            Allocation::BestFit => self
+                .iter()
+                .filter_map(|(first, last)| Some((last - first, fit(first, last, sectors, align)?)))
+                .min_by_key(|(len, _)| *len)
+                .map(|(_, start)| start),
+        }
+    }
+
+    /// Mark `first..=last` as used.
+    ///
+    /// The range must be entirely contained in a single free range.
+    pub(crate) fn allocate(&mut self, first: u64, last: u64) -> bool {
+        let (start, end) = match self.by_start.range(..=first).next_back() {
+            Some((start, end)) if *end >= last => (*start, *end),
+            _ => return false,
+        };
+        self.by_start.remove(&start);
+        if start < first {
+            self.insert(start, first - 1);
+        }
+        if last < end {
+            self.insert(last + 1, end);
+        }
+        true
+    }
+
+    fn insert(&mut self, first: u64, last: u64) {
+        self.by_start.insert(first, last);
+    }
+}
+
+/// Return the aligned start of `sectors` LBAs in `first..=last`, if they fit.
+fn fit(first: u64, last: u64, sectors: u64, align: u64) -> Option<u64> {
+    let start = first.checked_next_multiple_of(align.max(1))?;
+    let end = start.checked_add(sectors.checked_sub(1)?)?;
+    if end <= last {
+        Some(start)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::free_space::FreeSpace;
+    use crate::header::TableGeometry;
+    use crate::partition::{Allocation, Partition};
+
+    fn usable(first_usable: u64, last_usable: u64) -> TableGeometry {
+        TableGeometry {
+            first_usable,
+            last_usable,
+            ..TableGeometry::standard(128, 2048, crate::disk::LogicalBlockSize::Lb512).unwrap()
+        }
+    }
+
+    fn part(first_lba: u64, last_lba: u64) -> Partition {
+        let mut p = Partition::zero();
+        p.first_lba = first_lba;
+        p.last_lba = last_lba;
+        p
+    }
+
+    #[test]
+    fn test_free_space_new() {
+        let empty = FreeSpace::new(&usable(34, 100), &[]);
+        assert_eq!(empty.iter().collect::<Vec<_>>(), vec![(34, 100)]);
+
+        let pp = [part(50, 59), part(34, 39), part(90, 100)];
+        let free = FreeSpace::new(&usable(34, 100), &pp);
+        assert_eq!(free.iter().collect::<Vec<_>>(), vec![(40, 49), (60, 89)]);
+
+        let full = FreeSpace::new(&usable(34, 100), &[part(20, 200)]);
+        assert_eq!(full.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_free_space_find() {
+        let pp = [part(40, 49), part(55, 59)];
+        let free = FreeSpace::new(&usable(34, 100), &pp);
+        assert_eq!(free.find_first(5, 1), Some(34));
+        assert_eq!(free.find_first(6, 1), Some(34));
+        assert_eq!(free.find_first(7, 1), Some(60));
+        assert_eq!(free.find_first(42, 1), None);
+        assert_eq!(free.find_first(0, 1), None);
+
+        // Alignment skips the beginning of free ranges.
+        assert_eq!(free.find_first(4, 4), Some(36));
+        assert_eq!(free.find_first(6, 4), Some(60));
+        assert_eq!(free.find_first(8, 32), Some(64));
+        assert_eq!(free.find_first(8, 128), None);
+
+        // Best-fit prefers the smallest free range.
+        assert_eq!(free.find(5, 1, Allocation::BestFit), Some(50));
+        assert_eq!(free.find(6, 1, Allocation::BestFit), Some(34));
+        assert_eq!(free.find(7, 1, Allocation::BestFit), Some(60));
+        assert_eq!(free.find(5, 1, Allocation::FirstFit), Some(34));
+    }
+
+    #[test]
+    fn test_free_space_allocate() {
+        let mut free = FreeSpace::new(&usable(34, 100), &[]);
+        assert!(free.allocate(40, 49));
+        assert_eq!(free.iter().collect::<Vec<_>>(), vec![(34, 39), (50, 100)]);
+        assert!(!free.allocate(45, 55));
+        assert!(free.allocate(34, 39));
+        assert_eq!(free.iter().collect::<Vec<_>>(), vec![(50, 100)]);
+        assert_eq!(free, FreeSpace::new(&usable(34, 100), &[part(34, 49)]));
+    }
+}
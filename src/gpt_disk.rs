@@ -0,0 +1,3178 @@
+//! Configuration and handle of GPT disks, re-exported at the crate root.
+//!
+//! Everything here needs `std`, unlike the `raw` module.
+
+use log::*;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{Read, Seek, Write};
+use std::{fs, io, path, thread};
+
+#[cfg(feature = "async")]
+use crate::async_io;
+#[cfg(feature = "gzip")]
+use crate::backup;
+use crate::{
+    disk, free_space, header, mbr, observer, partition, quirks, table_backup, validate, Error,
+    Result,
+};
+
+/// Configuration options to open a GPT disk.
+#[derive(Debug, Eq, PartialEq)]
+pub struct GptConfig {
+    /// Logical block size.
+    pub(crate) lb_size: disk::LogicalBlockSize,
+    /// Whether to open a GPT partition table in writable mode.
+    writable: bool,
+    /// Whether to expect and parse an initialized disk image.
+    pub(crate) initialized: bool,
+    /// Whether to read primary and backup structures concurrently.
+    concurrent_reads: bool,
+    /// Whether to allow overwriting existing partition tables.
+    overwrite: bool,
+    /// Disk GUID to use for uninitialized disks, random if unset.
+    disk_guid: Option<uuid::Uuid>,
+    /// Whether to write even if headers were changed by someone else.
+    force_write: bool,
+    /// Whether to open the disk with `O_EXCL`.
+    exclusive: bool,
+    /// Whether to read back and compare all written structures.
+    verify_writes: bool,
+    /// Whether to sync written data to the device before returning.
+    sync_on_write: bool,
+    /// How to retry transient I/O errors.
+    retry_policy: disk::RetryPolicy,
+    /// Known deviations from the specification to tolerate on open.
+    quirks: quirks::Quirks,
+    /// How to validate tables before writing them.
+    write_check: validate::WriteCheck,
+    /// Intended disk size in bytes, overriding the backing file length.
+    disk_size: Option<u64>,
+    /// Alignment (in bytes) of new partitions, overriding the default.
+    alignment: Option<partition::Alignment>,
+    /// Whether to refuse disks without a valid protective MBR.
+    require_protective_mbr: bool,
+    /// How to pick free regions for new partitions.
+    allocation: partition::Allocation,
+    /// Whether to fall back to the backup table if the primary one is damaged.
+    readable_backup: bool,
+    /// Whether to detect the logical block size on open.
+    detect_block_size: bool,
+    /// Number and size (in bytes) of partition entries of new tables.
+    partition_entries: (u32, u32),
+    /// How to fill CHS addresses of written protective MBRs.
+    chs_mode: mbr::ChsMode,
+    /// Vendor-specific partition types to label partitions read from disk with.
+    custom_types: Vec<partition::PartitionType>,
+}
+
+impl GptConfig {
+    // TODO(lucab): complete support for skipping backup
+    // header, etc, then expose all config knobs here.
+
+    /// Create a new default configuration.
+    pub fn new() -> Self {
+        GptConfig::default()
+    }
+
+    /// Whether to open a GPT partition table in writable mode.
+    pub fn writable(mut self, writable: bool) -> Self {
+        self.writable = writable;
+        self
+    }
+
+    /// Whether to assume an initialized GPT disk and read its
+    /// partition table on open.
+    pub fn initialized(mut self, initialized: bool) -> Self {
+        self.initialized = initialized;
+        self
+    }
+
+    /// Whether to allow treating a disk with an existing partition
+    /// table as uninitialized.
+    ///
+    /// By default, opening a disk in writable mode with
+    /// `initialized(false)` fails if a valid GPT header or MBR
+    /// partition table is found on it, to avoid clobbering it.
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Whether to read the backup header concurrently with the
+    /// primary header and partition table.
+    ///
+    /// The backup header is read from a separate file handle on a
+    /// background thread, which can halve open latency on high-latency
+    /// (e.g. network-backed) devices.
+    pub fn concurrent_reads(mut self, concurrent: bool) -> Self {
+        self.concurrent_reads = concurrent;
+        self
+    }
+
+    /// Disk GUID to assign when opening an uninitialized disk.
+    ///
+    /// By default a random one is generated. This is ignored for
+    /// initialized disks, whose GUID is read from the primary header.
+    pub fn disk_guid(mut self, guid: uuid::Uuid) -> Self {
+        self.disk_guid = Some(guid);
+        self
+    }
+
+    /// Whether to write to disk even if its headers changed since open.
+    ///
+    /// By default, writes fail with an `ExternalModification` error if
+    /// the on-disk header checksums differ from the ones seen on open,
+    /// e.g. because another tool modified the disk in the meantime.
+    pub fn force_write(mut self, force: bool) -> Self {
+        self.force_write = force;
+        self
+    }
+
+    /// Whether to open the disk exclusively.
+    ///
+    /// On Linux, block devices opened with `O_EXCL` fail with a
+    /// `ResourceBusy` error if they are in use (e.g. mounted, or part of
+    /// a RAID or LVM setup), instead of being modified underneath their
+    /// current users. This has no effect on regular files.
+    pub fn exclusive(mut self, exclusive: bool) -> Self {
+        self.exclusive = exclusive;
+        self
+    }
+
+    /// Whether to read back every structure written to disk, and compare
+    /// it against the intended content.
+    ///
+    /// Mismatches, e.g. from devices silently dropping or mangling writes,
+    /// are reported as a `VerificationFailed` error. Data is synced to the
+    /// device before being read back.
+    pub fn verify_writes(mut self, verify: bool) -> Self {
+        self.verify_writes = verify;
+        self
+    }
+
+    /// Whether to sync written data to the device after every write operation.
+    ///
+    /// By default, writes are only flushed, and may sit in the operating
+    /// system caches for a while: a crash or power loss could then lose
+    /// them. With this, `GptDisk::sync()` is called once structures (or
+    /// partition contents) are written, before returning.
+    pub fn sync_on_write(mut self, sync: bool) -> Self {
+        self.sync_on_write = sync;
+        self
+    }
+
+    /// How to retry transient I/O errors and short transfers.
+    ///
+    /// Retries are counted in `GptDisk::io_stats()`.
+    pub fn retry_policy(mut self, policy: disk::RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Known deviations from the specification to tolerate on open.
+    ///
+    /// By default, none are tolerated. Quirks actually encountered are
+    /// logged and reported by `GptDisk::quirks()`.
+    pub fn quirks(mut self, quirks: quirks::Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// How to validate the partition table when writing it.
+    ///
+    /// By default, `GptDisk::write()` runs `validate::check_before_write()`
+    /// and fails with a `ValidationFailed` error on any error finding, so
+    /// that inconsistent tables (e.g. overlapping partitions) never reach
+    /// the disk. Warnings are only logged. The same mode applies to the
+    /// checks of `GptDisk::update_partitions()`.
+    pub fn write_check(mut self, check: validate::WriteCheck) -> Self {
+        self.write_check = check;
+        self
+    }
+
+    /// Intended size of the disk in bytes, instead of the backing file length.
+    ///
+    /// Headers, usable LBAs and the backup structures are laid out for
+    /// this size, e.g. for an under-sized sparse image which is grown
+    /// when deployed. Writing the backup structures at the end of the
+    /// disk extends such an image to `bytes`.
+    pub fn disk_size(mut self, bytes: u64) -> Self {
+        self.disk_size = Some(bytes);
+        self
+    }
+
+    /// Alignment of partitions allocated by `GptDisk::add_partition()` and
+    /// `layout::Layout::apply()`.
+    ///
+    /// By default, partitions are aligned to 1 MiB, or to a multiple of it
+    /// matching the preferred I/O size of the device (e.g. a RAID stripe
+    /// width). A plain number is an alignment in bytes, which must be a
+    /// multiple of the logical block size.
+    ///
+    /// ```rust,no_run
+    /// use gpt::partition::Alignment;
+    ///
+    /// // 64 KiB stripes on a 4K-native array.
+    /// let cfg = gpt::GptConfig::new()
+    ///     .logical_block_size(gpt::disk::LogicalBlockSize::Lb4096)
+    ///     .alignment(Alignment::Sectors(16));
+    /// ```
+    pub fn alignment(mut self, alignment: impl Into<partition::Alignment>) -> Self {
+        self.alignment = Some(alignment.into());
+        self
+    }
+
+    /// Number and size (in bytes) of partition entries, for new tables.
+    ///
+    /// By default, uninitialized disks get 128 entries of 128 bytes. More
+    /// entries (e.g. 1024 for large JBOD setups) take more LBAs, and move
+    /// the first usable LBA accordingly. Tables read from disk keep their
+    /// own geometry, see `GptDisk::set_partition_entries()` to change it.
+    pub fn partition_entries(mut self, num_parts: u32, part_size: u32) -> Self {
+        self.partition_entries = (num_parts, part_size);
+        self
+    }
+
+    /// How `GptDisk::add_partition()` picks among free regions.
+    pub fn allocation(mut self, strategy: partition::Allocation) -> Self {
+        self.allocation = strategy;
+        self
+    }
+
+    /// Whether to require a valid protective MBR in LBA0 on open.
+    ///
+    /// GPT disks are expected to carry a protective MBR (see
+    /// `mbr::ProtectiveMBR::validate()`), so that legacy tools do not
+    /// mistake them for unpartitioned. By default an invalid one is only
+    /// logged; with this set, opening an initialized disk fails instead.
+    pub fn require_protective_mbr(mut self, require: bool) -> Self {
+        self.require_protective_mbr = require;
+        self
+    }
+
+    /// Whether to open disks whose primary header or partition array is damaged.
+    ///
+    /// By default, opening such a disk fails. In recovery mode, the backup
+    /// header and partition array are used instead, and the disk is flagged
+    /// as `DiskState::Degraded` until `GptDisk::repair_primary()` rewrites
+    /// the damaged copy.
+    pub fn readable_backup(mut self, readable: bool) -> Self {
+        self.readable_backup = readable;
+        self
+    }
+
+    /// How to fill the CHS addresses of protective MBRs written to the disk.
+    ///
+    /// By default they are computed from the LBAs of the protective
+    /// record (see `mbr::lba_to_chs()`); some tools expect them zeroed.
+    pub fn chs_mode(mut self, mode: mbr::ChsMode) -> Self {
+        self.chs_mode = mode;
+        self
+    }
+
+    /// Label partitions of the given type read from the disk.
+    ///
+    /// This is meant for vendor-specific types missing from
+    /// `partition_types`; it only affects disks opened with this
+    /// configuration, and overrides a well-known label for the same GUID.
+    pub fn custom_type<T: Into<partition::PartitionType>>(mut self, part_type: T) -> Self {
+        let part_type = part_type.into();
+        self.custom_types.retain(|t| t.guid != part_type.guid);
+        self.custom_types.push(part_type);
+        self
+    }
+
+    /// Size of logical blocks (sectors) for this disk.
+    pub fn logical_block_size(mut self, lb_size: disk::LogicalBlockSize) -> Self {
+        self.lb_size = lb_size;
+        self
+    }
+
+    /// Whether to detect the size of logical blocks on open.
+    ///
+    /// The size reported by the kernel for block devices is used if
+    /// available (see `disk::device_block_size()`), otherwise LBA1 is
+    /// probed for a primary header (see `disk::detect_block_size()`). If
+    /// neither succeeds, e.g. on a blank image, `logical_block_size()`
+    /// is used as-is.
+    pub fn detect_block_size(mut self, detect: bool) -> Self {
+        self.detect_block_size = detect;
+        self
+    }
+
+    /// Open the GPT disk at the given path and inspect it according
+    /// to configuration options.
+    pub fn open(mut self, diskpath: &path::Path) -> Result<GptDisk> {
+        let mut file = self.open_file(diskpath)?;
+        self.detect_lb_size(&mut file, Some(diskpath));
+        let alignment = self.partition_alignment(disk::topology_alignment(diskpath))?;
+        self.open_device(file, Some(diskpath), alignment)
+    }
+
+    /// Inspect a GPT disk on an already opened device, according to
+    /// configuration options.
+    ///
+    /// This works with any `disk::DiskDevice`, e.g. an in-memory buffer.
+    /// The `exclusive()` and `concurrent_reads()` options only apply to
+    /// disks opened by path, and are ignored here.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// let image = std::fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+    /// let disk = gpt::GptConfig::new()
+    ///     .open_from_device(std::io::Cursor::new(image))
+    ///     .unwrap();
+    /// assert_eq!(disk.partitions()[&1].name, "primary");
+    /// ```
+    pub fn open_from_device<D: disk::DiskDevice>(mut self, mut device: D) -> Result<GptDisk<D>> {
+        self.detect_lb_size(&mut device, None);
+        let alignment = self.partition_alignment(None)?;
+        self.open_device(device, None, alignment)
+    }
+
+    /// Restore a partition table saved by `GptDisk::export_backup()` onto `target`, and open it.
+    ///
+    /// The protective MBR, both headers and the partition array are
+    /// written back as saved, like `sgdisk -l`. On a target larger than
+    /// the original disk, the backup header and array are moved to its
+    /// end. Partition contents are not touched. This requires `writable(true)`.
+    ///
+    /// The backup must have been saved from a disk with the configured
+    /// logical block size; otherwise this fails before writing anything.
+    pub fn restore_from_backup(self, backup: &path::Path, target: &path::Path) -> Result<GptDisk> {
+        if !self.writable {
+            return Err(Error::NotWritable);
+        }
+        let mut table = table_backup::TableBackup::load(&mut fs::File::open(backup)?)?;
+        let lb_size = self.lb_size;
+        let mut file = self.open_file(target)?;
+        let bak = header::find_backup_lba(&file, self.disk_size, lb_size)?;
+        // Backups do not record their block size: check that the saved
+        // geometry holds with the configured one before writing anything.
+        header::TableGeometry::from_headers(&table.primary, &table.backup)
+            .validate(table.backup.current_lba, lb_size)
+            .map_err(|e| {
+                Error::Invalid(format!(
+                    "backup does not fit {}-byte logical blocks: {}",
+                    lb_size.bytes(),
+                    e
+                ))
+            })?;
+        if bak < table.backup.current_lba {
+            return Err(Error::TooShort);
+        }
+        if bak > table.backup.current_lba {
+            debug!("moving restored backup table to LBA {}", bak);
+            table.relocate_backup(bak)?;
+        }
+
+        let (h1, h2) = (&table.primary, &table.backup);
+        let writes = [
+            (lb_size.lba_to_bytes(h2.part_start)?, table.array.clone()),
+            table_backup::header_sector(h2, lb_size)?,
+            (lb_size.lba_to_bytes(h1.part_start)?, table.array.clone()),
+            table_backup::header_sector(h1, lb_size)?,
+            (0, table.mbr.clone()),
+        ];
+        let mut stats = disk::IoStats::default();
+        for (offset, bytes) in &writes {
+            disk::write_all_at(&mut file, *offset, bytes, &self.retry_policy, &mut stats)?;
+        }
+        file.flush()?;
+        file.sync_all()?;
+        drop(file);
+        self.open(target)
+    }
+
+    /// Inspect a GPT disk on an asynchronous device, according to configuration options.
+    ///
+    /// The table structures are read without blocking into an in-memory
+    /// copy, which the returned `async_io::AsyncGptDisk` edits with the
+    /// usual `GptDisk` API until it is written back. As with
+    /// `open_from_device()`, `exclusive()` and `concurrent_reads()` are ignored.
+    #[cfg(feature = "async")]
+    pub async fn open_async<D>(self, mut device: D) -> Result<async_io::AsyncGptDisk<D>>
+    where
+        D: tokio::io::AsyncRead + tokio::io::AsyncWrite + tokio::io::AsyncSeek + Unpin,
+    {
+        let staged = async_io::load(&mut device).await?;
+        let table = self.open_from_device(staged)?;
+        Ok(async_io::AsyncGptDisk::new(device, table))
+    }
+
+    /// Replace the configured logical block size by the detected one, if enabled.
+    fn detect_lb_size<D: disk::DiskDevice>(
+        &mut self,
+        device: &mut D,
+        diskpath: Option<&path::Path>,
+    ) {
+        if !self.detect_block_size {
+            return;
+        }
+        let detected = diskpath
+            .and_then(disk::device_block_size)
+            .or_else(|| disk::detect_block_size(device));
+        if let Some(lb_size) = detected {
+            debug!("detected logical block size of {} bytes", lb_size.bytes());
+            self.lb_size = lb_size;
+        }
+    }
+
+    fn open_device<D: disk::DiskDevice>(
+        self,
+        mut file: D,
+        diskpath: Option<&path::Path>,
+        alignment: u64,
+    ) -> Result<GptDisk<D>> {
+        let policy = self.retry_policy;
+        let mut io_stats = disk::IoStats::default();
+        let name = diskpath.unwrap_or(path::Path::new("device")).display();
+        // Uninitialized disk, no headers/table to parse.
+        if !self.initialized {
+            if self.writable && !self.overwrite {
+                if let Some(label) = disk::probe_label(&mut file, self.lb_size) {
+                    return Err(Error::ExistingLabel(label));
+                }
+            }
+            let guid = self.disk_guid.unwrap_or_else(uuid::Uuid::new_v4);
+            let header_crcs = header::find_backup_lba(&file, self.disk_size, self.lb_size)
+                .and_then(|bak| {
+                    disk::with_retries(&policy, &mut io_stats, || {
+                        let c1 = header::read_raw_crc32(&mut file, 1, self.lb_size)?;
+                        let c2 = header::read_raw_crc32(&mut file, bak, self.lb_size)?;
+                        Ok((bak, c1, c2))
+                    })
+                })
+                .ok();
+            let entries = self.partition_entries;
+            let empty = GptDisk {
+                config: self,
+                file,
+                guid,
+                path: diskpath.map(path::Path::to_path_buf),
+                primary_header: None,
+                backup_header: None,
+                partitions: BTreeMap::new(),
+                checksums: ChecksumCache::default(),
+                free_space: None,
+                disk_backup_lba: Cell::new(None),
+                checkpoints: BTreeMap::new(),
+                alignment,
+                io_stats,
+                quirks: quirks::Quirks::empty(),
+                array_lbas: None,
+                entries,
+                reserved: vec![],
+                state: DiskState::Healthy,
+                observer: None,
+                resize_pmbr: false,
+                baseline: Baseline {
+                    guid,
+                    header_crcs,
+                    ..Baseline::default()
+                },
+            };
+            return Ok(empty);
+        }
+
+        // Proper GPT disk, fully inspect its layout.
+        let pmbr = header::find_backup_lba(&file, self.disk_size, self.lb_size).and_then(|bak| {
+            mbr::ProtectiveMBR::from_disk(&mut file, self.lb_size)?.validate(bak + 1)
+        });
+        match pmbr {
+            Err(e) if self.require_protective_mbr => return Err(e),
+            Err(e) => warn!("{}: {}", name, e),
+            Ok(()) => {}
+        }
+        let mut quirks = quirks::QuirkTracker::new(self.quirks);
+        let (primary, h2) = if let (true, Some(diskpath)) = (self.concurrent_reads, diskpath) {
+            let bakpath = diskpath.to_path_buf();
+            let lb_size = self.lb_size;
+            let disk_size = self.disk_size;
+            let allowed = self.quirks;
+            let backup = thread::spawn(move || {
+                let mut stats = disk::IoStats::default();
+                let mut quirks = quirks::QuirkTracker::new(allowed);
+                let mut bakfile = fs::File::open(bakpath)?;
+                let h2 = disk::with_retries(&policy, &mut stats, || {
+                    header::read_backup_header(&mut bakfile, disk_size, lb_size, &mut quirks)
+                });
+                Ok::<_, Error>((h2, stats, quirks))
+            });
+            let primary = self.read_primary(&mut file, &mut io_stats, &mut quirks);
+            let (h2, stats, bak_quirks) = backup
+                .join()
+                .map_err(|_| Error::Invalid("backup header reader panicked".into()))??;
+            io_stats.retries += stats.retries;
+            quirks.merge(&bak_quirks);
+            (primary, h2)
+        } else {
+            let primary = self.read_primary(&mut file, &mut io_stats, &mut quirks);
+            let h2 = disk::with_retries(&policy, &mut io_stats, || {
+                header::read_backup_header(&mut file, self.disk_size, self.lb_size, &mut quirks)
+            });
+            (primary, h2)
+        };
+        let (h1, h2, mut table, state) = match primary {
+            Ok((h1, table)) => {
+                let h2 = h2.or_else(|e| {
+                    header::read_recorded_backup_header(
+                        &mut file,
+                        &h1,
+                        self.lb_size,
+                        &mut quirks,
+                        e,
+                    )
+                })?;
+                (h1, h2, table, DiskState::Healthy)
+            }
+            Err(e) if self.readable_backup => {
+                warn!("{}: primary table unreadable ({}), using backup", name, e);
+                let h2 = h2?;
+                let table = disk::with_retries(&policy, &mut io_stats, || {
+                    partition::file_read_partitions(&mut file, &h2, self.lb_size, &mut quirks)
+                })?;
+                let part_start = header::read_damaged_primary_array(&mut file, &h2, self.lb_size);
+                (
+                    h2.primary_for_backup(part_start)?,
+                    h2,
+                    table,
+                    DiskState::Degraded,
+                )
+            }
+            Err(e) => return Err(e),
+        };
+        for (id, p) in table.iter_mut() {
+            p.number = *id;
+            if let Some(t) = self
+                .custom_types
+                .iter()
+                .find(|t| t.guid == p.part_type_guid.guid)
+            {
+                p.part_type_guid = t.clone();
+            }
+        }
+        for mismatch in header::compare_headers(&h1, &h2) {
+            warn!("{}: {}", name, mismatch);
+        }
+        for dup in partition::find_duplicates(&table) {
+            warn!("{}: {}", name, dup);
+        }
+        let geometry = header::TableGeometry::from_headers(&h1, &h2);
+        if let Err(e) = geometry.validate(h2.current_lba, self.lb_size) {
+            warn!("{}: {}", name, e);
+        }
+        let array_lbas = if geometry.has_standard_arrays(h2.current_lba, self.lb_size) {
+            None
+        } else {
+            debug!(
+                "{}: partition arrays at LBAs {} and {}",
+                name, h1.part_start, h2.part_start
+            );
+            Some((h1.part_start, h2.part_start))
+        };
+        // The damaged primary header is what later writes must find unchanged.
+        let primary_crc = match state {
+            DiskState::Healthy => h1.crc32,
+            DiskState::Degraded => disk::with_retries(&policy, &mut io_stats, || {
+                header::read_raw_crc32(&mut file, 1, self.lb_size)
+            })?,
+        };
+        let baseline = Baseline {
+            guid: h1.disk_guid,
+            primary_header: Some(h1.clone()),
+            partitions: table.clone(),
+            header_crcs: Some((h2.current_lba, primary_crc, h2.crc32)),
+        };
+        let entries = (h1.num_parts, h1.part_size);
+        let disk = GptDisk {
+            config: self,
+            file,
+            guid: h1.disk_guid,
+            path: diskpath.map(path::Path::to_path_buf),
+            primary_header: Some(h1),
+            backup_header: Some(h2),
+            partitions: table,
+            checksums: ChecksumCache::default(),
+            free_space: None,
+            baseline,
+            disk_backup_lba: Cell::new(None),
+            checkpoints: BTreeMap::new(),
+            alignment,
+            io_stats,
+            quirks: quirks.seen(),
+            array_lbas,
+            entries,
+            reserved: vec![],
+            state,
+            observer: None,
+            resize_pmbr: false,
+        };
+        Ok(disk)
+    }
+
+    /// Read the primary header and partition array.
+    fn read_primary<D: disk::DiskDevice>(
+        &self,
+        file: &mut D,
+        io_stats: &mut disk::IoStats,
+        quirks: &mut quirks::QuirkTracker,
+    ) -> Result<(header::Header, BTreeMap<u32, partition::Partition>)> {
+        let policy = &self.retry_policy;
+        let h1 = disk::with_retries(policy, io_stats, || {
+            header::read_primary_header(file, self.lb_size, quirks)
+        })?;
+        let table = disk::with_retries(policy, io_stats, || {
+            partition::file_read_partitions(file, &h1, self.lb_size, quirks)
+        })?;
+        Ok((h1, table))
+    }
+
+    /// Return the alignment (in LBAs) of new partitions.
+    ///
+    /// Unless configured, this is 1 MiB, rounded up to a multiple of the
+    /// `topology` alignment (in bytes) of the device, if any. For block
+    /// sizes which do not divide 1 MiB (e.g. 520 bytes), it is the number
+    /// of LBAs covering 1 MiB instead.
+    fn partition_alignment(&self, topology: Option<u64>) -> Result<u64> {
+        let lb_size = self.lb_size.bytes();
+        let alignment = match (self.alignment, topology) {
+            (Some(alignment), _) => alignment,
+            (None, Some(bytes)) if bytes > 0 && bytes % lb_size == 0 => {
+                debug!("topology alignment {} bytes", bytes);
+                partition::Alignment::Bytes(lcm(DEFAULT_ALIGNMENT, bytes))
+            }
+            (None, _) => partition::Alignment::Mebibyte,
+        };
+        alignment
+            .lbas(self.lb_size)
+            .ok_or_else(|| Error::Invalid("alignment not a multiple of logical block size".into()))
+    }
+
+    /// Open the disk file according to access options.
+    fn open_file(&self, diskpath: &path::Path) -> io::Result<fs::File> {
+        let mut opts = fs::OpenOptions::new();
+        opts.write(self.writable).read(true);
+        if self.exclusive {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                opts.custom_flags(libc::O_EXCL);
+            }
+            #[cfg(not(unix))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "exclusive open not supported on this platform",
+            ));
+        }
+        opts.open(diskpath).map_err(|e| {
+            #[cfg(unix)]
+            {
+                if e.raw_os_error() == Some(libc::EBUSY) {
+                    return io::Error::new(
+                        io::ErrorKind::ResourceBusy,
+                        format!("{}: device busy", diskpath.display()),
+                    );
+                }
+            }
+            e
+        })
+    }
+}
+
+impl Default for GptConfig {
+    fn default() -> Self {
+        Self {
+            lb_size: disk::DEFAULT_SECTOR_SIZE,
+            initialized: true,
+            writable: false,
+            concurrent_reads: false,
+            overwrite: false,
+            disk_guid: None,
+            force_write: false,
+            exclusive: false,
+            verify_writes: false,
+            sync_on_write: false,
+            retry_policy: disk::RetryPolicy::default(),
+            quirks: quirks::Quirks::empty(),
+            write_check: validate::WriteCheck::default(),
+            disk_size: None,
+            alignment: None,
+            require_protective_mbr: false,
+            readable_backup: false,
+            detect_block_size: false,
+            allocation: partition::Allocation::default(),
+            partition_entries: (header::MIN_NUM_PARTS, 128),
+            chs_mode: mbr::ChsMode::default(),
+            custom_types: vec![],
+        }
+    }
+}
+
+/// Size of the zeroed buffer used by `GptDisk::zero_partition()`.
+const ZERO_CHUNK: u64 = 1024 * 1024;
+
+/// Default alignment of new partitions, as used by most partitioning tools.
+const DEFAULT_ALIGNMENT: u64 = 1024 * 1024;
+
+/// Least common multiple of two non-zero integers.
+fn lcm(a: u64, b: u64) -> u64 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let r = x % y;
+        x = y;
+        y = r;
+    }
+    a / x * b
+}
+
+/// Return the write of header `h` at its LBA, as expected by `GptDisk::write_all_at()`.
+fn header_write(
+    h: &header::Header,
+    lb_size: disk::LogicalBlockSize,
+) -> Result<(observer::Region, u64, Vec<u8>)> {
+    let (offset, bytes) = h.exact_bytes(lb_size)?;
+    Ok((observer::Region::Header, offset, bytes))
+}
+
+/// Size of BIOS Boot Partitions, as recommended by GRUB.
+const BIOS_BOOT_SIZE: u64 = 1024 * 1024;
+
+/// Attributes of recovery partitions created by Windows setup: required, no drive letter.
+const WINDOWS_RECOVERY_FLAGS: u64 = 0x8000_0000_0000_0001;
+
+/// Free space expected by Apple tools after each Apple partition.
+const APPLE_GAP_SIZE: u64 = 128 * 1024 * 1024;
+
+/// Size of chunks used to stream images into partitions.
+const IMAGE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// CRC32 of the in-memory partition array, reused while unchanged.
+///
+/// Headers are small enough to be checksummed every time.
+#[derive(Debug, Default)]
+pub(crate) struct ChecksumCache {
+    /// Partition array geometry (entries, entry size) and its CRC32.
+    entries: Cell<Option<(u32, u32, u32)>>,
+}
+
+impl ChecksumCache {
+    /// Forget the partition array CRC32, after entries changed.
+    pub(crate) fn invalidate_entries(&self) {
+        self.entries.set(None);
+    }
+}
+
+/// On-disk state, as of open or last write, used to track changes.
+#[derive(Debug, Default)]
+struct Baseline {
+    guid: uuid::Uuid,
+    primary_header: Option<header::Header>,
+    partitions: BTreeMap<u32, partition::Partition>,
+    /// Backup header LBA, and raw CRC32 fields of primary and backup headers.
+    header_crcs: Option<(u64, u32, u32)>,
+}
+
+/// Error returned when writing to a disk whose headers changed since open.
+///
+/// This is returned as `Error::ExternalModification`.
+#[derive(Debug, Eq, PartialEq)]
+pub struct ExternalModification;
+
+impl std::fmt::Display for ExternalModification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "partition table changed underneath us")
+    }
+}
+
+impl std::error::Error for ExternalModification {}
+
+/// Error returned when data read back after a write differs from what was written.
+///
+/// This is returned as `Error::VerificationFailed`.
+#[derive(Debug, Eq, PartialEq)]
+pub struct VerificationFailed {
+    /// Byte offset of the structure which failed verification.
+    pub offset: u64,
+}
+
+impl std::fmt::Display for VerificationFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "read-back mismatch at byte offset {:#x}", self.offset)
+    }
+}
+
+impl std::error::Error for VerificationFailed {}
+
+/// Error returned when writing a table which failed validation.
+///
+/// This is returned as `Error::ValidationFailed`.
+#[derive(Debug, Eq, PartialEq)]
+pub struct ValidationFailed {
+    /// All findings, including warnings.
+    pub findings: Vec<validate::Finding>,
+}
+
+impl std::fmt::Display for ValidationFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let errors: Vec<&validate::Finding> = self
+            .findings
+            .iter()
+            .filter(|f| f.severity == validate::Severity::Error)
+            .collect();
+        write!(f, "partition table failed validation")?;
+        if let Some(first) = errors.first() {
+            write!(f, ": {}", first)?;
+        }
+        if errors.len() > 1 {
+            write!(f, " (and {} more errors)", errors.len() - 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationFailed {}
+
+/// Health of the on-disk partition table, as of open.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DiskState {
+    /// Primary table was read successfully.
+    #[default]
+    Healthy,
+    /// Primary table is damaged, and the backup one is in use.
+    Degraded,
+}
+
+/// Changes that `GptDisk::write()` would make, see `GptDisk::pending_changes()`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Changeset {
+    /// Groups of header fields about to change.
+    pub header_fields: Vec<header::FieldGroup>,
+    /// Partition entries about to change, by partition number.
+    pub partitions: Vec<partition::PartitionChange>,
+}
+
+impl Changeset {
+    /// Whether writing would leave the table on disk as it is.
+    pub fn is_empty(&self) -> bool {
+        self.header_fields.is_empty() && self.partitions.is_empty()
+    }
+}
+
+impl std::fmt::Display for Changeset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no changes");
+        }
+        for group in &self.header_fields {
+            writeln!(f, "rewrite header fields: {:?}", group)?;
+        }
+        for change in &self.partitions {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// A GPT disk, backed by a file unless opened with `GptConfig::open_from_device()`.
+#[derive(Debug)]
+pub struct GptDisk<D: disk::DiskDevice = fs::File> {
+    pub(crate) config: GptConfig,
+    pub(crate) file: D,
+    guid: uuid::Uuid,
+    /// Path the disk was opened from, if any.
+    path: Option<path::PathBuf>,
+    primary_header: Option<header::Header>,
+    backup_header: Option<header::Header>,
+    pub(crate) partitions: BTreeMap<u32, partition::Partition>,
+    pub(crate) checksums: ChecksumCache,
+    /// Free regions in the usable range, computed on first allocation, and
+    /// the geometry they were computed for.
+    pub(crate) free_space: Option<(header::TableGeometry, free_space::FreeSpace)>,
+    baseline: Baseline,
+    /// Backup header LBA derived from the disk size, queried once.
+    disk_backup_lba: Cell<Option<u64>>,
+    /// Alignment (in LBAs) of newly allocated partitions.
+    pub(crate) alignment: u64,
+    /// Named snapshots of the in-memory table, as disk GUID and entries.
+    checkpoints: BTreeMap<String, (uuid::Uuid, BTreeMap<u32, partition::Partition>)>,
+    /// Retry and short transfer counters, since open.
+    io_stats: disk::IoStats,
+    /// Quirks tolerated while opening the disk.
+    quirks: quirks::Quirks,
+    /// Non-standard starting LBAs of the primary and backup partition arrays.
+    array_lbas: Option<(u64, u64)>,
+    /// Minimum number and size (in bytes) of partition entries.
+    entries: (u32, u32),
+    /// Regions held back from partition allocation, sorted by LBA.
+    reserved: Vec<partition::ReservedRegion>,
+    /// Whether the disk was opened from its backup table.
+    state: DiskState,
+    /// Receiver of write notifications, see `set_observer()`.
+    observer: Option<Box<dyn observer::GptObserver>>,
+    /// Whether `write()` resizes the protective MBR, after `expand()` or `shrink_to()`.
+    resize_pmbr: bool,
+}
+
+impl<D: disk::DiskDevice> GptDisk<D> {
+    /// Retrieve primary header, if any.
+    ///
+    /// This is the header as read on open or as last written; see
+    /// `preview_headers()` for the one matching in-memory changes.
+    pub fn primary_header(&self) -> Option<&header::Header> {
+        self.primary_header.as_ref()
+    }
+
+    /// Retrieve backup header, if any.
+    ///
+    /// This is the header as read on open or as last written.
+    pub fn backup_header(&self) -> Option<&header::Header> {
+        self.backup_header.as_ref()
+    }
+
+    /// Retrieve primary header, if any, for low-level changes.
+    ///
+    /// Changes are only written by `write_headers()`, see also
+    /// `recompute_checksums()`; `write()` computes headers afresh from the
+    /// partition table instead.
+    pub fn primary_header_mut(&mut self) -> Option<&mut header::Header> {
+        self.primary_header.as_mut()
+    }
+
+    /// Retrieve backup header, if any, for low-level changes.
+    ///
+    /// Like `primary_header_mut()`, changes are only written by `write_headers()`.
+    pub fn backup_header_mut(&mut self) -> Option<&mut header::Header> {
+        self.backup_header.as_mut()
+    }
+
+    /// Retrieve partition entries, keyed by partition number.
+    ///
+    /// Partition numbers start from 1 and match the position of the
+    /// entry in the on-disk partition array.
+    pub fn partitions(&self) -> &BTreeMap<u32, partition::Partition> {
+        &self.partitions
+    }
+
+    /// Retrieve partition number `number`, if used.
+    pub fn partition(&self, number: u32) -> Option<&partition::Partition> {
+        self.partitions.get(&number)
+    }
+
+    /// Retrieve the partition with unique GUID `guid`, if any.
+    pub fn partition_by_guid(&self, guid: &uuid::Uuid) -> Option<&partition::Partition> {
+        self.partitions.values().find(|p| p.part_guid == *guid)
+    }
+
+    /// Retrieve the lowest-numbered partition named `name`, if any.
+    pub fn partition_by_name(&self, name: &str) -> Option<&partition::Partition> {
+        self.partitions.values().find(|p| p.name == name)
+    }
+
+    /// Iterate mutably over partition entries, by partition number.
+    ///
+    /// Unlike `with_partitions_mut()`, edits are not validated right away,
+    /// but only by `write()` according to `GptConfig::write_check()`.
+    /// Entries keep their partition number. No changes are recorded to disk
+    /// until `write()` is called.
+    pub fn partitions_mut(&mut self) -> impl Iterator<Item = &mut partition::Partition> {
+        self.checksums.invalidate_entries();
+        self.free_space = None;
+        self.partitions.values_mut()
+    }
+
+    /// Retrieve disk UUID.
+    pub fn guid(&self) -> &uuid::Uuid {
+        &self.guid
+    }
+
+    /// Retrieve the alignment (in LBAs) used for newly allocated partitions.
+    ///
+    /// For RAID and other stacked block devices this follows the
+    /// preferred I/O size reported by the kernel, e.g. the stripe width.
+    pub fn alignment(&self) -> u64 {
+        self.alignment
+    }
+
+    /// Look up the model, serial and transport of the underlying device.
+    ///
+    /// See `disk::device_identity()`.
+    #[cfg(feature = "device-info")]
+    pub fn device_identity(&self) -> Option<disk::DeviceIdentity> {
+        disk::device_identity(self.path.as_ref()?)
+    }
+
+    /// Retrieve the known quirks which were tolerated while opening the disk.
+    pub fn quirks(&self) -> quirks::Quirks {
+        self.quirks
+    }
+
+    /// Retrieve the health of the on-disk table, see `GptConfig::readable_backup()`.
+    pub fn state(&self) -> DiskState {
+        self.state
+    }
+
+    /// Retrieve statistics about retried and short I/O operations since open.
+    pub fn io_stats(&self) -> disk::IoStats {
+        self.io_stats
+    }
+
+    /// Notify `observer` of every write this disk object makes from now on.
+    ///
+    /// This covers the table structures written by `write_inplace()` and
+    /// similar, and partition contents written by `write_image_to_partition()`,
+    /// `zero_partition()` and `wipe_signatures()`, but not writes through
+    /// `open_partition()` or to other devices (e.g. `write_to()`). Any
+    /// previous observer is replaced.
+    pub fn set_observer(&mut self, observer: impl observer::GptObserver + 'static) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Stop notifying writes, returning the observer if any.
+    pub fn take_observer(&mut self) -> Option<Box<dyn observer::GptObserver>> {
+        self.observer.take()
+    }
+
+    /// Retrieve disk logical block size.
+    pub fn logical_block_size(&self) -> &disk::LogicalBlockSize {
+        &self.config.lb_size
+    }
+
+    /// Update disk UUID.
+    ///
+    /// If no UUID is specified, a new random one is generated.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn update_guid(&mut self, uuid: Option<uuid::Uuid>) -> Result<&Self> {
+        let guid = match uuid {
+            Some(u) => u,
+            None => {
+                let u = uuid::Uuid::new_v4();
+                debug!("Generated random uuid: {}", u);
+                u
+            }
+        };
+        self.guid = guid;
+        Ok(self)
+    }
+
+    /// Replace the disk GUID and all partition GUIDs with new random ones.
+    ///
+    /// This breaks GUID duplication between a disk and its clones (see
+    /// `find_cross_disk_duplicates()`), like `sgdisk -G`.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn randomize_all_guids(&mut self) -> Result<&Self> {
+        for p in self.partitions_mut() {
+            p.set_unique_guid(None);
+        }
+        self.update_guid(None)
+    }
+
+    /// Update current partition table, keyed by partition number.
+    ///
+    /// Entries are validated against the usable area of the disk, but
+    /// headers are only recomputed when previewed or written.
+    /// No changes are recorded to disk until `write()` is called.
+    ///
+    /// Unless `GptConfig::write_check()` is `Skip`, the whole table is then
+    /// checked with `validate::Profile::UefiSpec`: overlapping partitions,
+    /// nil or duplicate unique GUIDs and out-of-range partition numbers are
+    /// reported by a `ValidationFailed` error naming the conflicting
+    /// entries, or only logged with `WriteCheck::Warn`.
+    pub fn update_partitions(
+        &mut self,
+        mut pp: BTreeMap<u32, partition::Partition>,
+    ) -> Result<&Self> {
+        for (id, p) in pp.iter_mut() {
+            p.number = *id;
+        }
+        let geometry = self.geometry_for(&pp)?;
+        for (id, p) in &pp {
+            if *id == 0 {
+                return Err(Error::InvalidPartitionNumber(0));
+            }
+            if p.first_lba > p.last_lba {
+                return Err(Error::Invalid("partition ends before its start".into()));
+            }
+            if !geometry.is_usable(p.first_lba, p.last_lba) {
+                return Err(Error::OutsideUsableArea(*id));
+            }
+        }
+        self.check_partitions(&pp)?;
+        if pp != self.partitions {
+            self.checksums.invalidate_entries();
+            self.free_space = None;
+        }
+        self.partitions = pp;
+        self.config.initialized = true;
+        Ok(self)
+    }
+
+    /// Validate new partition entries, according to `GptConfig::write_check()`.
+    fn check_partitions(&self, pp: &BTreeMap<u32, partition::Partition>) -> Result<()> {
+        let mode = self.config.write_check;
+        if mode == validate::WriteCheck::Skip {
+            return Ok(());
+        }
+        let h = self.new_header(true, pp)?;
+        let findings = validate::Profile::UefiSpec.check(&h, pp, self.config.lb_size);
+        for finding in &findings {
+            warn!("partition table update: {}", finding);
+        }
+        let has_errors = findings
+            .iter()
+            .any(|f| f.severity == validate::Severity::Error);
+        if mode == validate::WriteCheck::Deny && has_errors {
+            return Err(Error::ValidationFailed(ValidationFailed { findings }));
+        }
+        Ok(())
+    }
+
+    /// Compute a new header for `pp`, keeping the partition arrays in place.
+    fn new_header(
+        &self,
+        primary: bool,
+        pp: &BTreeMap<u32, partition::Partition>,
+    ) -> Result<header::Header> {
+        let bak = self.disk_backup_lba()?;
+        let geometry = self.geometry_for(pp)?;
+        Ok(header::Header::from_geometry(
+            primary, &geometry, self.guid, bak,
+        ))
+    }
+
+    /// Compute the table geometry for `pp`, keeping the partition arrays in place.
+    fn geometry_for(
+        &self,
+        pp: &BTreeMap<u32, partition::Partition>,
+    ) -> Result<header::TableGeometry> {
+        let bak = self.disk_backup_lba()?;
+        let mut geometry = self.standard_geometry(pp, bak)?;
+        if let Some((primary, backup)) = self.array_lbas {
+            geometry.relocate_arrays(primary, backup, bak, self.config.lb_size)?;
+        }
+        Ok(geometry)
+    }
+
+    /// Compute the standard table geometry for `pp`, with the backup header at `bak`.
+    ///
+    /// The array grows beyond the configured number of entries to hold
+    /// the highest partition number of `pp`.
+    fn standard_geometry(
+        &self,
+        pp: &BTreeMap<u32, partition::Partition>,
+        bak: u64,
+    ) -> Result<header::TableGeometry> {
+        let (num_parts, part_size) = self.entries;
+        let num_parts = pp
+            .keys()
+            .next_back()
+            .map_or(num_parts, |id| num_parts.max(*id));
+        header::TableGeometry::with_entries(num_parts, part_size, bak, self.config.lb_size)
+    }
+
+    /// Retrieve the table geometry that would be written for the current partitions.
+    pub fn geometry(&self) -> Result<header::TableGeometry> {
+        self.geometry_for(&self.partitions)
+    }
+
+    /// Retrieve the starting LBAs of the primary and backup partition arrays.
+    ///
+    /// These are the standard locations, right after the primary header
+    /// and right before the backup one, unless the disk was opened with
+    /// arrays elsewhere or `set_partition_array_lbas()` was called.
+    pub fn partition_array_lbas(&self) -> Result<(u64, u64)> {
+        let geometry = self.geometry()?;
+        Ok((geometry.primary_array, geometry.backup_array))
+    }
+
+    /// Place the primary and backup partition arrays at the given LBAs.
+    ///
+    /// The usable area becomes the range between both arrays, and must
+    /// still hold all current partitions. `None` restores the standard
+    /// locations. No changes are recorded to disk until `write()` is called.
+    pub fn set_partition_array_lbas(&mut self, lbas: Option<(u64, u64)>) -> Result<()> {
+        let old = std::mem::replace(&mut self.array_lbas, lbas);
+        let res = self.geometry().and_then(|geometry| {
+            match self
+                .partitions
+                .values()
+                .find(|p| !geometry.is_usable(p.first_lba, p.last_lba))
+            {
+                Some(p) => Err(Error::Invalid(format!(
+                    "partition array overlaps partition \"{}\"",
+                    p.name
+                ))),
+                None => Ok(()),
+            }
+        });
+        if res.is_err() {
+            self.array_lbas = old;
+        }
+        self.free_space = None;
+        res
+    }
+
+    /// Change the number and size (in bytes) of partition entries.
+    ///
+    /// Entries must be at least 128 bytes, as a power-of-two multiple of
+    /// it, and the array must span at least 16 KiB as required by the
+    /// specification. The usable area is adjusted to the new array size,
+    /// and must still hold all current partitions, whose numbers must not
+    /// exceed `num_parts`. No changes are recorded to disk until `write()`
+    /// is called.
+    pub fn set_partition_entries(&mut self, num_parts: u32, part_size: u32) -> Result<()> {
+        if part_size < 128 || !part_size.is_power_of_two() {
+            return Err(Error::Invalid(format!(
+                "partition entry size {} is not 128 * 2^n",
+                part_size
+            )));
+        }
+        if u64::from(num_parts) * u64::from(part_size) < u64::from(header::MIN_NUM_PARTS) * 128 {
+            return Err(Error::Invalid("partition array below 16 KiB".into()));
+        }
+        if let Some(id) = self.partitions.keys().find(|id| **id > num_parts) {
+            return Err(Error::InvalidPartitionNumber(*id));
+        }
+        let old = std::mem::replace(&mut self.entries, (num_parts, part_size));
+        let res = self.geometry().and_then(|geometry| {
+            match self
+                .partitions
+                .iter()
+                .find(|(_, p)| !geometry.is_usable(p.first_lba, p.last_lba))
+            {
+                Some((id, _)) => Err(Error::Invalid(format!(
+                    "partition array overlaps partition {}",
+                    id
+                ))),
+                None => Ok(()),
+            }
+        });
+        if res.is_err() {
+            self.entries = old;
+        }
+        self.free_space = None;
+        res
+    }
+
+    /// Return the backup header LBA for the current disk size.
+    fn disk_backup_lba(&self) -> Result<u64> {
+        if let Some(lba) = self.disk_backup_lba.get() {
+            return Ok(lba);
+        }
+        let lba = header::find_backup_lba(&self.file, self.config.disk_size, self.config.lb_size)?;
+        self.disk_backup_lba.set(Some(lba));
+        Ok(lba)
+    }
+
+    /// Compute primary and backup headers matching the current in-memory state.
+    ///
+    /// These are the headers that would be written to disk, with
+    /// up-to-date checksums.
+    pub fn preview_headers(&self) -> Result<(header::Header, header::Header)> {
+        let mut h1 = self.new_header(true, &self.partitions)?;
+        let mut h2 = self.new_header(false, &self.partitions)?;
+        for h in &mut [&mut h1, &mut h2] {
+            h.crc32_parts = self.entries_checksum(h.num_parts, h.part_size)?;
+            h.crc32 = h.checksum()?;
+        }
+        Ok((h1, h2))
+    }
+
+    /// Return the CRC32 of the partition array, recomputing it only if needed.
+    fn entries_checksum(&self, num_parts: u32, part_size: u32) -> Result<u32> {
+        match self.checksums.entries.get() {
+            Some((n, size, crc)) if n == num_parts && size == part_size => Ok(crc),
+            _ => {
+                let bytes = partition::entries_as_bytes(&self.partitions, num_parts, part_size)?;
+                let crc = header::calculate_crc32(&bytes);
+                self.checksums
+                    .entries
+                    .set(Some((num_parts, part_size, crc)));
+                Ok(crc)
+            }
+        }
+    }
+
+    /// Return the partition numbers whose entries changed since open or last write.
+    ///
+    /// This includes added, modified and removed entries.
+    pub fn changed_partitions(&self) -> Vec<u32> {
+        let old = &self.baseline.partitions;
+        let mut ids: Vec<u32> = old
+            .keys()
+            .chain(self.partitions.keys())
+            .filter(|id| old.get(id) != self.partitions.get(id))
+            .cloned()
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// Return the groups of header fields which changed since open or last write.
+    pub fn changed_header_fields(&self) -> Result<Vec<header::FieldGroup>> {
+        let current = if self.config.initialized {
+            Some(self.preview_headers()?.0)
+        } else {
+            None
+        };
+        let mut changed = match (&self.baseline.primary_header, &current) {
+            (Some(old), Some(new)) => header::changed_fields(old, new),
+            (None, None) => vec![],
+            _ => vec![
+                header::FieldGroup::DiskGuid,
+                header::FieldGroup::Layout,
+                header::FieldGroup::PartitionArray,
+            ],
+        };
+        if self.guid != self.baseline.guid && !changed.contains(&header::FieldGroup::DiskGuid) {
+            changed.insert(0, header::FieldGroup::DiskGuid);
+        }
+        Ok(changed)
+    }
+
+    /// Describe the changes `write()` would make to the table on disk.
+    ///
+    /// This compares in-memory state against the table as read on open
+    /// or last written, without any I/O, e.g. to ask for confirmation
+    /// before writing.
+    pub fn pending_changes(&self) -> Result<Changeset> {
+        let old = &self.baseline.partitions;
+        let partitions = self
+            .changed_partitions()
+            .into_iter()
+            .filter_map(|id| match (old.get(&id), self.partitions.get(&id)) {
+                (None, Some(new)) => Some(partition::PartitionChange::Added(id, new.clone())),
+                (Some(old), None) => Some(partition::PartitionChange::Removed(id, old.clone())),
+                (Some(old), Some(new)) => Some(partition::PartitionChange::Modified {
+                    id,
+                    old: old.clone(),
+                    new: new.clone(),
+                }),
+                (None, None) => None,
+            })
+            .collect();
+        Ok(Changeset {
+            header_fields: self.changed_header_fields()?,
+            partitions,
+        })
+    }
+
+    /// Check the current partition table against the given validation profiles.
+    ///
+    /// Findings of all profiles are returned together, in profile order.
+    pub fn verify(&self, profiles: &[validate::Profile]) -> Result<Vec<validate::Finding>> {
+        let (h1, _) = self.preview_headers()?;
+        Ok(profiles
+            .iter()
+            .flat_map(|profile| profile.check(&h1, &self.partitions, self.config.lb_size))
+            .collect())
+    }
+
+    /// Check both on-disk headers and partition arrays for corruption.
+    ///
+    /// Signatures, header and partition array CRC32s, and header
+    /// locations are checked, and problems are returned together with
+    /// the LBA of the header they were found in. The partition array of
+    /// a header is only checked if the header itself is intact.
+    pub fn verify_integrity(&mut self) -> Result<Vec<(u64, Error)>> {
+        let mut errors = vec![];
+        let backup_lba = match self.verify_header_at(1, &mut errors)? {
+            Some(primary) => primary.backup_lba,
+            None => self.disk_backup_lba()?,
+        };
+        self.verify_header_at(backup_lba, &mut errors)?;
+        Ok(errors)
+    }
+
+    /// Check the header at `lba` and its partition array, appending problems to `errors`.
+    ///
+    /// The header is returned if it is intact.
+    fn verify_header_at(
+        &mut self,
+        lba: u64,
+        errors: &mut Vec<(u64, Error)>,
+    ) -> Result<Option<header::Header>> {
+        let lb_size = self.config.lb_size;
+        let offset = lba
+            .checked_mul(lb_size.into())
+            .ok_or(Error::Overflow("header offset"))?;
+        let (hdr, _) = header::file_read_header_unchecked(&mut self.file, offset)?;
+        let hdr_errors = hdr.check_integrity(lba);
+        if !hdr_errors.is_empty() {
+            errors.extend(hdr_errors.into_iter().map(|e| (lba, e)));
+            return Ok(None);
+        }
+        let found = header::partentry_checksum(&mut self.file, &hdr, lb_size)?;
+        if found != hdr.crc32_parts {
+            errors.push((
+                lba,
+                Error::PartitionArrayCrcMismatch {
+                    expected: hdr.crc32_parts,
+                    found,
+                },
+            ));
+        }
+        Ok(Some(hdr))
+    }
+
+    /// Validate the table about to be written, according to `GptConfig::write_check()`.
+    fn check_before_write(&self) -> Result<()> {
+        let mode = self.config.write_check;
+        if mode == validate::WriteCheck::Skip {
+            return Ok(());
+        }
+        let (h1, _) = self.preview_headers()?;
+        let findings = validate::check_before_write(
+            &h1,
+            &self.partitions,
+            self.config.lb_size,
+            self.alignment,
+        );
+        for finding in &findings {
+            warn!("pre-write validation: {}", finding);
+        }
+        let has_errors = findings
+            .iter()
+            .any(|f| f.severity == validate::Severity::Error);
+        if mode == validate::WriteCheck::Deny && has_errors {
+            return Err(Error::ValidationFailed(ValidationFailed { findings }));
+        }
+        Ok(())
+    }
+
+    /// Whether `other` has the same partition layout as this disk.
+    ///
+    /// Partition numbers, ranges, types, names and flags are compared,
+    /// while disk and partition GUIDs are ignored.
+    pub fn layout_eq<E: disk::DiskDevice>(&self, other: &GptDisk<E>) -> bool {
+        self.config.lb_size == other.config.lb_size
+            && self.partitions.len() == other.partitions.len()
+            && self
+                .partitions
+                .iter()
+                .zip(other.partitions.iter())
+                .all(|((id1, p1), (id2, p2))| id1 == id2 && p1.layout_eq(p2))
+    }
+
+    /// Find names and unique GUIDs shared by several partitions.
+    pub fn duplicates(&self) -> Vec<partition::Duplicate> {
+        partition::find_duplicates(&self.partitions)
+    }
+
+    /// Add a new partition, of at least `size` bytes, in a free region.
+    ///
+    /// The region is picked according to `GptConfig::allocation()`, by
+    /// default the first one large enough, and the partition starts on a
+    /// multiple of `alignment()`.
+    /// The new entry takes the lowest unused partition number and gets a
+    /// random unique GUID. The returned handle stays valid even if
+    /// entries are renumbered afterwards.
+    /// The type can be a `partition::PartitionType`, or a
+    /// `partition_types::Type`, including custom ones.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn add_partition<T: Into<partition::PartitionType>>(
+        &mut self,
+        name: &str,
+        size: u64,
+        part_type: T,
+        flags: u64,
+    ) -> Result<partition::PartitionHandle> {
+        let part_type = part_type.into();
+        let sectors = self.size_to_sectors(size)?;
+        let (align, strategy) = (self.alignment, self.config.allocation);
+        let first_lba = self
+            .free_space()?
+            .find(sectors, align, strategy)
+            .ok_or(Error::NoSpace)?;
+        self.place_partition(name, first_lba, sectors, part_type, flags)
+    }
+
+    /// Return the free ranges of the usable area, as `(first_lba, length)`.
+    ///
+    /// These are the LBAs covered neither by a partition nor by a
+    /// reserved region, sorted by LBA, i.e. where `add_partition()` can
+    /// place new partitions (before alignment).
+    pub fn find_free_sectors(&self) -> Result<Vec<(u64, u64)>> {
+        let geometry = self.allocation_geometry()?;
+        let computed;
+        let free = match &self.free_space {
+            Some((cached, free)) if *cached == geometry => free,
+            _ => {
+                computed = self.free_space_for(&geometry, &self.partitions);
+                &computed
+            }
+        };
+        Ok(free
+            .iter()
+            .map(|(first, last)| (first, last - first + 1))
+            .collect())
+    }
+
+    /// Convert a size in bytes to a non-zero number of LBAs, rounding up.
+    pub(crate) fn size_to_sectors(&self, size: u64) -> Result<u64> {
+        let lb_size = self.config.lb_size.bytes();
+        let sectors = size
+            .checked_add(lb_size - 1)
+            .ok_or(Error::Overflow("partition size"))?
+            / lb_size;
+        if sectors == 0 {
+            return Err(Error::Invalid("partition size must not be zero".into()));
+        }
+        Ok(sectors)
+    }
+
+    /// Retrieve free regions of the usable area, computing them if needed.
+    ///
+    /// Free regions are recomputed whenever the geometry changed, e.g.
+    /// after a write moved the backup header.
+    fn free_space(&mut self) -> Result<&mut free_space::FreeSpace> {
+        let geometry = self.allocation_geometry()?;
+        if self
+            .free_space
+            .as_ref()
+            .is_none_or(|(cached, _)| *cached != geometry)
+        {
+            let free = self.free_space_for(&geometry, &self.partitions);
+            self.free_space = Some((geometry, free));
+        }
+        let (_, free) = self
+            .free_space
+            .get_or_insert_with(|| (geometry, Default::default()));
+        Ok(free)
+    }
+
+    /// Return the geometry partitions are allocated in.
+    ///
+    /// This is the geometry `write()` lays the table out with, which may
+    /// differ from the one of the on-disk headers, e.g. after changing the
+    /// number of entries or for a disk opened with its backup misplaced.
+    pub(crate) fn allocation_geometry(&self) -> Result<header::TableGeometry> {
+        self.geometry()
+    }
+
+    /// Compute free regions for `pp` in `geometry`, leaving out reserved regions.
+    fn free_space_for(
+        &self,
+        geometry: &header::TableGeometry,
+        pp: &BTreeMap<u32, partition::Partition>,
+    ) -> free_space::FreeSpace {
+        let mut free = free_space::FreeSpace::new(geometry, pp.values());
+        for r in &self.reserved {
+            free.allocate(r.first_lba, r.last_lba);
+        }
+        free
+    }
+
+    /// Hold back `first_lba..=last_lba` from partition allocation, as `name`.
+    ///
+    /// The region must lie in the usable area, and must not overlap any
+    /// partition or other reserved region. Partitions added afterwards
+    /// (e.g. with `add_partition()`) are never placed over it. Reserved
+    /// regions only live in memory, and are not recorded on disk.
+    pub fn reserve_region(&mut self, name: &str, first_lba: u64, last_lba: u64) -> Result<()> {
+        if !self.allocation_geometry()?.is_usable(first_lba, last_lba) {
+            return Err(Error::Invalid(
+                "reserved region outside of usable LBAs".into(),
+            ));
+        }
+        let overlaps = |first: u64, last: u64| first <= last_lba && last >= first_lba;
+        if self
+            .partitions
+            .values()
+            .any(|p| overlaps(p.first_lba, p.last_lba))
+            || self
+                .reserved
+                .iter()
+                .any(|r| overlaps(r.first_lba, r.last_lba))
+        {
+            return Err(Error::Invalid(format!(
+                "reserved region \"{}\" overlaps existing allocations",
+                name
+            )));
+        }
+        debug!("reserving LBAs {} - {} as {}", first_lba, last_lba, name);
+        self.reserved.push(partition::ReservedRegion {
+            name: name.to_string(),
+            first_lba,
+            last_lba,
+        });
+        self.reserved.sort_by_key(|r| r.first_lba);
+        self.free_space = None;
+        Ok(())
+    }
+
+    /// Release all reserved regions named `name`, returning whether any existed.
+    pub fn release_region(&mut self, name: &str) -> bool {
+        let before = self.reserved.len();
+        self.reserved.retain(|r| r.name != name);
+        self.free_space = None;
+        self.reserved.len() != before
+    }
+
+    /// Retrieve reserved regions, sorted by LBA.
+    pub fn reserved_regions(&self) -> &[partition::ReservedRegion] {
+        &self.reserved
+    }
+
+    /// Return a map of the usable area, as partitions, reserved and free regions sorted by LBA.
+    pub fn layout_map(&self) -> Result<Vec<partition::MapEntry>> {
+        let geometry = self.allocation_geometry()?;
+        let mut map: Vec<partition::MapEntry> = self
+            .free_space_for(&geometry, &self.partitions)
+            .iter()
+            .map(|(first_lba, last_lba)| partition::MapEntry::Free {
+                first_lba,
+                last_lba,
+            })
+            .collect();
+        map.extend(
+            self.partitions
+                .iter()
+                .map(|(id, p)| partition::MapEntry::Partition {
+                    id: *id,
+                    first_lba: p.first_lba,
+                    last_lba: p.last_lba,
+                }),
+        );
+        map.extend(
+            self.reserved
+                .iter()
+                .cloned()
+                .map(partition::MapEntry::Reserved),
+        );
+        map.sort_by_key(|e| e.range());
+        Ok(map)
+    }
+
+    /// Insert a new partition at `first_lba`, in a free region, with the lowest unused number.
+    pub(crate) fn place_partition(
+        &mut self,
+        name: &str,
+        first_lba: u64,
+        sectors: u64,
+        part_type: partition::PartitionType,
+        flags: u64,
+    ) -> Result<partition::PartitionHandle> {
+        let id = (1..=self.entries.0)
+            .find(|id| !self.partitions.contains_key(id))
+            .ok_or(Error::TableFull)?;
+
+        let mut part = partition::Partition {
+            part_type_guid: part_type,
+            part_guid: uuid::Uuid::new_v4(),
+            first_lba,
+            last_lba: first_lba + sectors - 1,
+            flags,
+            name: String::new(),
+            raw_name: None,
+            number: id,
+        };
+        part.set_name(name)?;
+        if !self.free_space()?.allocate(part.first_lba, part.last_lba) {
+            return Err(Error::Overlap);
+        }
+        debug!("adding partition {}: {}", id, part);
+        let handle = partition::PartitionHandle {
+            id,
+            guid: part.part_guid,
+        };
+        self.partitions.insert(id, part);
+        self.checksums.invalidate_entries();
+        self.config.initialized = true;
+        Ok(handle)
+    }
+
+    /// Add a BIOS Boot Partition, for GRUB to boot from GPT disks on legacy BIOS.
+    ///
+    /// The partition is 1 MiB large, unformatted, and placed in the first
+    /// free region like any other new partition. This fails if the table
+    /// already has a BIOS Boot Partition.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn add_bios_boot_partition(&mut self) -> Result<partition::PartitionHandle> {
+        let guid = uuid::Uuid::parse_str(partition::BIOS_BOOT)
+            .map_err(|_| Error::Invalid("invalid BIOS boot partition type".into()))?;
+        let part_type = partition::PartitionType::from_guid(guid);
+        if self
+            .partitions
+            .values()
+            .any(|p| p.part_type_guid.guid == part_type.guid)
+        {
+            return Err(Error::Invalid("BIOS boot partition already exists".into()));
+        }
+        self.add_partition("BIOS boot partition", BIOS_BOOT_SIZE, part_type, 0)
+    }
+
+    /// Add a partition for use by macOS, keeping the gaps expected by Apple tools.
+    ///
+    /// Disk Utility expects 128 MiB of free space after each Apple
+    /// partition (e.g. HFS+ or APFS, see `PartitionType::is_apple()`), for
+    /// later resizing and for its own helper partitions. The new partition
+    /// is placed in the first free region leaving such a gap both after an
+    /// Apple partition preceding it and after itself, and is otherwise
+    /// handled like with `add_partition()`.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn add_apple_partition<T: Into<partition::PartitionType>>(
+        &mut self,
+        name: &str,
+        size: u64,
+        part_type: T,
+        flags: u64,
+    ) -> Result<partition::PartitionHandle> {
+        let part_type = part_type.into();
+        let sectors = self.size_to_sectors(size)?;
+        let gap = APPLE_GAP_SIZE / self.config.lb_size.bytes();
+        let align = self.alignment.max(1);
+        let apple_ends: Vec<u64> = self
+            .partitions
+            .values()
+            .filter(|p| p.part_type_guid.is_apple())
+            .map(|p| p.last_lba)
+            .collect();
+        let free: Vec<(u64, u64)> = self.free_space()?.iter().collect();
+        let first_lba = free
+            .into_iter()
+            .find_map(|(first, last)| {
+                // Skip the gap owed to an Apple partition right before this region.
+                let min = match apple_ends.iter().find(|end| **end + 1 == first) {
+                    Some(end) => end.checked_add(gap + 1)?,
+                    None => first,
+                };
+                let start = min.checked_next_multiple_of(align)?;
+                let end = start.checked_add(sectors - 1)?;
+                if end.checked_add(gap)? <= last {
+                    Some(start)
+                } else {
+                    None
+                }
+            })
+            .ok_or(Error::NoSpace)?;
+        self.place_partition(name, first_lba, sectors, part_type, flags)
+    }
+
+    /// Add the canonical Windows layout to an empty partition table.
+    ///
+    /// This creates, in order, an EFI System Partition of the minimum size
+    /// required by Windows (100 MiB, or 260 MiB on 4K-native disks), a
+    /// 16 MiB Microsoft Reserved Partition, and a Basic Data partition for
+    /// the Windows volume filling the rest of the disk. If `recovery_size`
+    /// is set, a Windows Recovery Environment partition of that size is
+    /// placed at the end of the disk, after the Windows volume, and rounded
+    /// up so that it starts on an alignment boundary. Handles are
+    /// returned in the same order. On error, the table is left empty.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn add_windows_layout(
+        &mut self,
+        recovery_size: Option<u64>,
+    ) -> Result<Vec<partition::PartitionHandle>> {
+        if !self.partitions.is_empty() {
+            return Err(Error::Invalid(
+                "Windows layout requires an empty partition table".into(),
+            ));
+        }
+        let res = self.add_windows_partitions(recovery_size);
+        if res.is_err() {
+            self.partitions.clear();
+            self.free_space = None;
+            self.checksums.invalidate_entries();
+        }
+        res
+    }
+
+    fn add_windows_partitions(
+        &mut self,
+        recovery_size: Option<u64>,
+    ) -> Result<Vec<partition::PartitionHandle>> {
+        let part_type = |guid: &str| {
+            uuid::Uuid::parse_str(guid)
+                .map(partition::PartitionType::from_guid)
+                .map_err(|_| Error::Invalid("invalid Windows partition type".into()))
+        };
+        let esp_size = match self.config.lb_size.bytes() {
+            4096 => validate::WINDOWS_ESP_MIN_4K,
+            _ => validate::WINDOWS_ESP_MIN,
+        };
+        let mut handles = vec![
+            self.add_partition(
+                "EFI system partition",
+                esp_size,
+                part_type(partition::EFI_SYSTEM)?,
+                0,
+            )?,
+            self.add_partition(
+                "Microsoft reserved partition",
+                validate::WINDOWS_MSR_SIZE,
+                part_type(partition::MICROSOFT_RESERVED)?,
+                0,
+            )?,
+        ];
+
+        // The Windows volume takes the largest remaining free region, short
+        // of the recovery partition, which starts aligned and runs up to
+        // the end of that region.
+        let lb_size = self.config.lb_size.bytes();
+        let align = self.alignment.max(1);
+        let (first, last) = self
+            .free_space()?
+            .iter()
+            .max_by_key(|(first, last)| last - first)
+            .ok_or(Error::NoSpace)?;
+        let start = first
+            .checked_next_multiple_of(align)
+            .ok_or(Error::Overflow("partition start"))?;
+        let end = match recovery_size {
+            Some(size) => (last + 1)
+                .checked_sub(size.div_ceil(lb_size))
+                .map(|lba| lba - lba % align),
+            None => (last + 1).checked_sub((last + 1) % align),
+        };
+        let end = end.filter(|end| *end > start).ok_or(Error::NoSpace)?;
+        handles.push(self.place_partition(
+            "Basic data partition",
+            start,
+            end - start,
+            part_type(partition::MICROSOFT_BASIC_DATA)?,
+            0,
+        )?);
+        if recovery_size.is_some() {
+            handles.push(self.place_partition(
+                "Recovery",
+                end,
+                last + 1 - end,
+                part_type(partition::WINDOWS_RECOVERY)?,
+                WINDOWS_RECOVERY_FLAGS,
+            )?);
+        }
+        Ok(handles)
+    }
+
+    /// Remove partition number `id` from the table, returning its entry.
+    ///
+    /// Partitions with the Required Partition attribute (bit 0) are
+    /// needed by the platform firmware, and are only removed if `force`
+    /// is set.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn remove_partition(&mut self, id: u32, force: bool) -> Result<partition::Partition> {
+        let p = self.partitions.get(&id).ok_or(Error::UnknownPartition)?;
+        let required = partition::PartitionAttributes::from_bits_retain(p.flags)
+            .contains(partition::PartitionAttributes::PLATFORM);
+        if required && !force {
+            return Err(Error::RequiredPartition(id));
+        }
+        debug!("removing partition {}", id);
+        let removed = self.partitions.remove(&id);
+        self.checksums.invalidate_entries();
+        self.free_space = None;
+        removed.ok_or(Error::UnknownPartition)
+    }
+
+    /// Remove the partition with unique GUID `guid`, returning its number and entry.
+    ///
+    /// See `remove_partition()`.
+    pub fn remove_partition_by_guid(
+        &mut self,
+        guid: &uuid::Uuid,
+        force: bool,
+    ) -> Result<(u32, partition::Partition)> {
+        let id = self
+            .partitions
+            .iter()
+            .find(|(_, p)| p.part_guid == *guid)
+            .map(|(id, _)| *id)
+            .ok_or(Error::UnknownPartition)?;
+        Ok((id, self.remove_partition(id, force)?))
+    }
+
+    /// Grow or shrink partition number `id` in place, to at least `size` bytes.
+    ///
+    /// The partition keeps its first LBA, and its new range must not
+    /// overlap other partitions or reserved regions, nor go beyond the
+    /// usable area. Checksums are recomputed on `write()`.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn resize_partition(&mut self, id: u32, size: u64) -> Result<&partition::Partition> {
+        let sectors = self.size_to_sectors(size)?;
+        let first_lba = self
+            .partitions
+            .get(&id)
+            .ok_or(Error::UnknownPartition)?
+            .first_lba;
+        let last_lba = first_lba
+            .checked_add(sectors - 1)
+            .ok_or(Error::Overflow("partition last LBA"))?;
+        let mut others = self.partitions.clone();
+        others.remove(&id);
+        let mut free = self.free_space_for(&self.allocation_geometry()?, &others);
+        if !free.allocate(first_lba, last_lba) {
+            return Err(Error::Invalid(
+                "resized partition overlaps a neighbor or the end of the usable area".into(),
+            ));
+        }
+        debug!(
+            "resizing partition {} to LBAs {}-{}",
+            id, first_lba, last_lba
+        );
+        self.checksums.invalidate_entries();
+        self.free_space = None;
+        let p = self
+            .partitions
+            .get_mut(&id)
+            .ok_or(Error::UnknownPartition)?;
+        p.last_lba = last_lba;
+        Ok(p)
+    }
+
+    /// Copy partition entries from another table into this one.
+    ///
+    /// Imported entries take the lowest unused partition numbers, in the
+    /// order of `other`, and keep their LBA ranges unless they conflict
+    /// with existing partitions, in which case `policy` decides.
+    /// On error, the current partition table is left untouched.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn import_partitions(
+        &mut self,
+        other: &BTreeMap<u32, partition::Partition>,
+        policy: partition::ConflictPolicy,
+    ) -> Result<Vec<partition::PartitionHandle>> {
+        let geometry = self.allocation_geometry()?;
+        let mut pp = self.partitions.clone();
+        let mut free = self.free_space_for(&geometry, &pp);
+        let mut handles = vec![];
+
+        for (src_id, src) in other {
+            let mut part = src.clone();
+            if !free.allocate(part.first_lba, part.last_lba) {
+                match policy.overlap {
+                    partition::OverlapPolicy::Abort => {
+                        return Err(Error::Invalid(format!(
+                            "imported partition {} overlaps existing partitions",
+                            src_id
+                        )));
+                    }
+                    partition::OverlapPolicy::Skip => {
+                        debug!("skipping overlapping imported partition {}", src_id);
+                        continue;
+                    }
+                    partition::OverlapPolicy::Relocate => {
+                        let sectors =
+                            part.last_lba.checked_sub(part.first_lba).ok_or_else(|| {
+                                Error::Invalid("partition ends before its start".into())
+                            })? + 1;
+                        part.first_lba = free
+                            .find_first(sectors, self.alignment)
+                            .ok_or(Error::NoSpace)?;
+                        part.last_lba = part.first_lba + sectors - 1;
+                        free.allocate(part.first_lba, part.last_lba);
+                    }
+                }
+            }
+            let collides = pp.values().any(|p| p.part_guid == part.part_guid);
+            if collides || policy.guids == partition::GuidPolicy::Regenerate {
+                part.part_guid = uuid::Uuid::new_v4();
+            }
+            let id = (1..=geometry.num_parts)
+                .find(|id| !pp.contains_key(id))
+                .ok_or(Error::TableFull)?;
+            debug!("importing partition {} as {}: {}", src_id, id, part);
+            handles.push(partition::PartitionHandle {
+                id,
+                guid: part.part_guid,
+            });
+            pp.insert(id, part);
+        }
+
+        self.update_partitions(pp)?;
+        Ok(handles)
+    }
+
+    /// Return the current partition number of the partition behind `handle`.
+    pub fn resolve(&self, handle: &partition::PartitionHandle) -> Option<u32> {
+        match self.partitions.get(&handle.id) {
+            Some(p) if p.part_guid == handle.guid => Some(handle.id),
+            _ => self
+                .partitions
+                .iter()
+                .find(|(_, p)| p.part_guid == handle.guid)
+                .map(|(id, _)| *id),
+        }
+    }
+
+    /// Retrieve the partition entry behind `handle`, if it still exists.
+    pub fn partition_by_handle(
+        &self,
+        handle: &partition::PartitionHandle,
+    ) -> Option<&partition::Partition> {
+        self.resolve(handle).and_then(|id| self.partitions.get(&id))
+    }
+
+    /// Edit current partition entries in place.
+    ///
+    /// The closure is given mutable access to a copy of the partition
+    /// entries. Once it returns, entries are validated as in
+    /// `update_partitions()`. If validation fails, the
+    /// current partition table is left untouched.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn with_partitions_mut<F>(&mut self, f: F) -> Result<&Self>
+    where
+        F: FnOnce(&mut BTreeMap<u32, partition::Partition>),
+    {
+        let mut pp = self.partitions.clone();
+        f(&mut pp);
+        self.update_partitions(pp)
+    }
+
+    /// Renumber partitions by on-disk order, like `sgdisk --sort`.
+    ///
+    /// Partitions are numbered from 1 by increasing first LBA, leaving no
+    /// gaps between partition numbers. Handles keep resolving to the
+    /// moved entries. No changes are recorded to disk until `write()` is
+    /// called.
+    pub fn sort_partitions(&mut self) -> Result<&Self> {
+        let mut sorted: Vec<partition::Partition> = self.partitions.values().cloned().collect();
+        sorted.sort_by_key(|p| p.first_lba);
+        let pp = (1..).zip(sorted).collect();
+        self.update_partitions(pp)
+    }
+
+    /// Swap the numbers of partitions `a` and `b`, like `sgdisk --transpose`.
+    ///
+    /// One of them may be unused, in which case the other partition is
+    /// simply renumbered. Both must fit in the partition array. No changes
+    /// are recorded to disk until `write()` is called.
+    pub fn swap_partition_numbers(&mut self, a: u32, b: u32) -> Result<&Self> {
+        let num_parts = self.geometry()?.num_parts;
+        if a == 0 || b == 0 || a > num_parts || b > num_parts {
+            let id = if a == 0 || a > num_parts { a } else { b };
+            return Err(Error::InvalidPartitionNumber(id));
+        }
+        if !self.partitions.contains_key(&a) && !self.partitions.contains_key(&b) {
+            return Err(Error::UnknownPartition);
+        }
+        let mut pp = self.partitions.clone();
+        let (pa, pb) = (pp.remove(&a), pp.remove(&b));
+        if let Some(p) = pa {
+            pp.insert(b, p);
+        }
+        if let Some(p) = pb {
+            pp.insert(a, p);
+        }
+        self.update_partitions(pp)
+    }
+
+    /// Save the current disk GUID and partition entries under `name`.
+    ///
+    /// An existing checkpoint with the same name is replaced. Checkpoints
+    /// only live in memory, and are never written to disk.
+    pub fn checkpoint(&mut self, name: &str) {
+        debug!("saving checkpoint '{}'", name);
+        self.checkpoints
+            .insert(name.to_string(), (self.guid, self.partitions.clone()));
+    }
+
+    /// Restore the disk GUID and partition entries saved under `name`.
+    ///
+    /// The checkpoint itself is kept, so it can be restored again.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn restore_checkpoint(&mut self, name: &str) -> Result<&Self> {
+        let (guid, pp) = self
+            .checkpoints
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::Invalid(format!("unknown checkpoint '{}'", name)))?;
+        debug!("restoring checkpoint '{}'", name);
+        self.guid = guid;
+        self.update_partitions(pp)
+    }
+
+    /// Return the names of the saved checkpoints, sorted.
+    pub fn checkpoints(&self) -> Vec<&str> {
+        self.checkpoints.keys().map(String::as_str).collect()
+    }
+
+    /// Forget the checkpoint saved under `name`, returning whether it existed.
+    pub fn drop_checkpoint(&mut self, name: &str) -> bool {
+        self.checkpoints.remove(name).is_some()
+    }
+
+    /// Stream an image (e.g. a filesystem) from `reader` into the sectors of a partition.
+    ///
+    /// Data is copied in chunks, and `progress` is called with the total
+    /// number of bytes written so far after each chunk. This fails as soon
+    /// as the image turns out to be larger than the partition, in which case
+    /// the partition is left partially written. On success, the size of the
+    /// image is returned. This directly writes to disk and is not affected
+    /// by `write()`.
+    pub fn write_image_to_partition<R, F>(
+        &mut self,
+        id: u32,
+        reader: &mut R,
+        mut progress: F,
+    ) -> Result<u64>
+    where
+        R: Read,
+        F: FnMut(u64),
+    {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        let p = self.partitions.get(&id).ok_or(Error::UnknownPartition)?;
+        let start = p.bytes_start(self.config.lb_size)?;
+        let len = p.bytes_len(self.config.lb_size)? + self.config.lb_size.bytes();
+        debug!("writing image to partition {} ({} bytes)", id, len);
+
+        let mut buf = vec![0u8; IMAGE_CHUNK_SIZE];
+        let mut written = 0u64;
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if written + n as u64 > len {
+                return Err(Error::Invalid(format!(
+                    "image larger than partition {} ({} bytes)",
+                    id, len
+                )));
+            }
+            disk::write_all_at(
+                &mut self.file,
+                start + written,
+                &buf[..n],
+                &self.config.retry_policy,
+                &mut self.io_stats,
+            )?;
+            self.notify(observer::Region::PartitionData(id), start + written, n);
+            written += n as u64;
+            progress(written);
+        }
+        self.flush()?;
+        Ok(written)
+    }
+
+    /// Save the contents of a partition as a gzip-compressed backup into `out`.
+    ///
+    /// The backup records the length and CRC32 of the data, which are
+    /// checked by `restore_partition()`. The size of the partition is returned.
+    #[cfg(feature = "gzip")]
+    pub fn backup_partition<W: Write>(&mut self, id: u32, out: W) -> Result<u64> {
+        let (start, len) = self.partition_extent(id)?;
+        debug!("backing up partition {} ({} bytes)", id, len);
+        Ok(backup::save(&mut self.file, start, len, out)?)
+    }
+
+    /// Restore the contents of a partition from a backup made by `backup_partition()`.
+    ///
+    /// The backup must fit in the partition. If it turns out to be corrupted,
+    /// the partition is left partially restored. This directly writes to
+    /// disk and is not affected by `write()`.
+    #[cfg(feature = "gzip")]
+    pub fn restore_partition<R: Read>(&mut self, id: u32, input: R) -> Result<u64> {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        let (start, len) = self.partition_extent(id)?;
+        debug!("restoring partition {} ({} bytes)", id, len);
+        Ok(backup::restore(&mut self.file, start, len, input)?)
+    }
+
+    /// Save the partition table structures to a file at `path`, like `sgdisk -b`.
+    ///
+    /// The protective MBR, both headers and both partition arrays are
+    /// saved as found on disk, ignoring in-memory changes. The backup can
+    /// be restored with `GptConfig::restore_from_backup()`.
+    pub fn export_backup(&mut self, path: &path::Path) -> Result<()> {
+        let (h1, h2) = match (&self.primary_header, &self.backup_header) {
+            (Some(h1), Some(h2)) => (h1.clone(), h2.clone()),
+            _ => return Err(Error::NotInitialized),
+        };
+        self.check_unmodified()?;
+        let len = usize::try_from(u64::from(h1.num_parts) * u64::from(h1.part_size))
+            .map_err(|_| Error::Invalid("partition array too large".to_string()))?;
+        let mut mbr = vec![0u8; 512];
+        let mut array = vec![0u8; len];
+        let mut backup_array = vec![0u8; len];
+        let reads = [
+            (0, &mut mbr),
+            (self.config.lb_size.lba_to_bytes(h1.part_start)?, &mut array),
+            (
+                self.config.lb_size.lba_to_bytes(h2.part_start)?,
+                &mut backup_array,
+            ),
+        ];
+        for (offset, buf) in reads {
+            disk::read_exact_at(
+                &mut self.file,
+                offset,
+                buf,
+                &self.config.retry_policy,
+                &mut self.io_stats,
+            )?;
+        }
+        debug!("exporting partition table backup to {}", path.display());
+        let table = table_backup::TableBackup {
+            mbr,
+            primary: h1,
+            backup: h2,
+            array,
+        };
+        let mut out = fs::File::create(path)?;
+        table.save(&mut out, &backup_array)?;
+        out.sync_all()?;
+        Ok(())
+    }
+
+    /// Return the byte offset and length of a partition.
+    #[cfg(feature = "gzip")]
+    fn partition_extent(&self, id: u32) -> Result<(u64, u64)> {
+        let p = self.partitions.get(&id).ok_or(Error::UnknownPartition)?;
+        let start = p.bytes_start(self.config.lb_size)?;
+        let len = p.bytes_len(self.config.lb_size)? + self.config.lb_size.bytes();
+        Ok((start, len))
+    }
+
+    /// Write raw chunks, as (region, byte offset, content), in order and flush them.
+    ///
+    /// With `verify_writes`, chunks are then synced and read back.
+    fn write_all_at(&mut self, writes: &[(observer::Region, u64, Vec<u8>)]) -> Result<()> {
+        for (region, offset, bytes) in writes {
+            trace!("writing {} bytes at {:#x}", bytes.len(), offset);
+            disk::write_all_at(
+                &mut self.file,
+                *offset,
+                bytes,
+                &self.config.retry_policy,
+                &mut self.io_stats,
+            )?;
+            self.notify(*region, *offset, bytes.len());
+        }
+        self.flush()?;
+        if !self.config.verify_writes {
+            return Ok(());
+        }
+
+        if !self.config.sync_on_write {
+            self.sync()?;
+        }
+        let mut buf = vec![];
+        for (_, offset, bytes) in writes {
+            buf.resize(bytes.len(), 0);
+            disk::read_exact_at(
+                &mut self.file,
+                *offset,
+                &mut buf,
+                &self.config.retry_policy,
+                &mut self.io_stats,
+            )?;
+            if &buf != bytes {
+                error!("verification failed for write at {:#x}", offset);
+                return Err(Error::VerificationFailed(VerificationFailed {
+                    offset: *offset,
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush written data, and sync it with `sync_on_write`.
+    fn flush(&mut self) -> Result<()> {
+        self.file.flush()?;
+        if self.config.sync_on_write {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Report a write of `len` bytes at `offset` to the observer, if any.
+    fn notify(&mut self, region: observer::Region, offset: u64, len: usize) {
+        if let Some(observer) = &mut self.observer {
+            observer.on_write(&observer::WriteEvent {
+                region,
+                offset,
+                len: len as u64,
+            });
+        }
+    }
+
+    /// Fail if on-disk headers changed since they were last read or written.
+    fn check_unmodified(&mut self) -> Result<()> {
+        let (bak, c1, c2) = match self.baseline.header_crcs {
+            Some(crcs) if !self.config.force_write => crcs,
+            _ => return Ok(()),
+        };
+        let lb_size = self.config.lb_size;
+        let file = &mut self.file;
+        let current = disk::with_retries(&self.config.retry_policy, &mut self.io_stats, || {
+            Ok::<_, Error>((
+                header::read_raw_crc32(file, 1, lb_size)?,
+                header::read_raw_crc32(file, bak, lb_size)?,
+            ))
+        })?;
+        if current != (c1, c2) {
+            let name = self.path.as_deref().unwrap_or(path::Path::new("device"));
+            warn!(
+                "{}: headers changed since open, refusing to write",
+                name.display()
+            );
+            return Err(Error::ExternalModification(ExternalModification));
+        }
+        Ok(())
+    }
+
+    /// Open the data region of partition number `id` for reading and writing.
+    ///
+    /// The returned view borrows the disk, and cannot reach outside of the
+    /// partition. Writes are only allowed if the disk was opened in
+    /// writable mode; they directly go to disk and are not affected by
+    /// `write()`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::io::{Read, Seek, SeekFrom, Write};
+    ///
+    /// let lb_size = gpt::disk::DEFAULT_SECTOR_SIZE;
+    /// let mut gdisk = gpt::GptDisk::new_in_memory(8 * 1024 * 1024, lb_size).unwrap();
+    /// let data = gpt::partition_types::Type::from_name("Linux Filesystem Data").unwrap();
+    /// let h = gdisk.add_partition("data", 1024 * 1024, data, 0).unwrap();
+    ///
+    /// let mut part = gdisk.open_partition(h.id()).unwrap();
+    /// part.write_all(b"hello").unwrap();
+    /// part.seek(SeekFrom::End(-1)).unwrap();
+    /// assert!(part.write_all(b"world").is_err());
+    /// ```
+    pub fn open_partition(&mut self, id: u32) -> Result<disk::PartitionIo<'_, D>> {
+        let (start, size) = self.partition_range(id)?;
+        let writable = self.config.writable;
+        Ok(disk::PartitionIo::new(
+            &mut self.file,
+            start,
+            size,
+            writable,
+        ))
+    }
+
+    /// Zero the first and last `len` bytes of partition number `id`.
+    ///
+    /// This erases old filesystem signatures and metadata (including
+    /// those kept at the end of a partition, e.g. by mdraid or ZFS) before
+    /// reusing a partition slot, e.g. with `len` of a few MiB. The whole
+    /// partition is zeroed if it is shorter than twice `len`. This
+    /// directly writes to disk and is not affected by `write()`.
+    pub fn zero_partition(&mut self, id: u32, len: u64) -> Result<()> {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        let (start, size) = self.partition_range(id)?;
+        let ranges = if len.saturating_mul(2) >= size {
+            vec![(start, size)]
+        } else {
+            vec![(start, len), (start + size - len, len)]
+        };
+
+        let zeros = vec![0u8; ZERO_CHUNK.min(len.min(size)) as usize];
+        for (offset, len) in ranges {
+            debug!(
+                "zeroing partition {}: {} bytes at offset {:#x}",
+                id, len, offset
+            );
+            let mut done = 0;
+            while done < len {
+                let n = (len - done).min(zeros.len() as u64);
+                disk::write_all_at(
+                    &mut self.file,
+                    offset + done,
+                    &zeros[..n as usize],
+                    &self.config.retry_policy,
+                    &mut self.io_stats,
+                )?;
+                self.notify(
+                    observer::Region::PartitionData(id),
+                    offset + done,
+                    n as usize,
+                );
+                done += n;
+            }
+        }
+        self.flush()
+    }
+
+    /// Discard the whole content of partition number `id`.
+    ///
+    /// This lets SSDs and thin-provisioned storage reclaim the space, see
+    /// `disk::DiskDevice::discard()`, and is supported by Linux block
+    /// devices with the `linux` feature. This directly acts on the disk and
+    /// is not affected by `write()`.
+    pub fn discard_partition(&mut self, id: u32) -> Result<()> {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        let (start, size) = self.partition_range(id)?;
+        debug!(
+            "discarding partition {}: {} bytes at offset {:#x}",
+            id, size, start
+        );
+        Ok(self.file.discard(start, size)?)
+    }
+
+    /// Return the byte offset and length of partition number `id`.
+    fn partition_range(&self, id: u32) -> Result<(u64, u64)> {
+        let p = self.partitions.get(&id).ok_or(Error::UnknownPartition)?;
+        let start = p.bytes_start(self.config.lb_size)?;
+        // `bytes_len()` excludes the last LBA.
+        let len = p.bytes_len(self.config.lb_size)? + self.config.lb_size.bytes();
+        Ok((start, len))
+    }
+
+    /// Zero well-known filesystem, RAID and LUKS signature areas of a partition.
+    ///
+    /// This is meant for freshly created partitions, to prevent stale
+    /// signatures lying around in their sectors from being detected
+    /// (e.g. by the kernel or udev) once the partition table is written.
+    /// This directly writes to disk and is not affected by `write()`.
+    pub fn wipe_signatures(&mut self, id: u32) -> Result<()> {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        let (start, len) = self.partition_range(id)?;
+        for (offset, size) in partition::signature_ranges(len) {
+            debug!(
+                "wiping partition {} signatures: {} bytes at offset {:#x}",
+                id, size, offset
+            );
+            disk::write_all_at(
+                &mut self.file,
+                start + offset,
+                &vec![0u8; size as usize],
+                &self.config.retry_policy,
+                &mut self.io_stats,
+            )?;
+            self.notify(
+                observer::Region::PartitionData(id),
+                start + offset,
+                size as usize,
+            );
+        }
+        self.flush()
+    }
+
+    /// Persist a single partition entry, and both headers, to disk.
+    ///
+    /// Only the entry for partition number `id` is written in the primary
+    /// and backup arrays (zeroed, if the partition was removed), followed by
+    /// the headers with updated checksums. This is much cheaper than a full
+    /// `write()` for small repeated tweaks, like flipping attribute bits,
+    /// but it assumes all other entries on disk match the in-memory table.
+    ///
+    /// If the partition arrays would move or change size compared to the
+    /// headers on disk, this falls back to a full `write_inplace()`.
+    pub fn write_partition_entry(&mut self, id: u32) -> Result<()> {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        if !self.config.initialized {
+            return Err(Error::NotInitialized);
+        }
+        let (h1, h2) = self.preview_headers()?;
+        let same_arrays = |old: Option<&header::Header>, new: &header::Header| {
+            old.is_some_and(|old| {
+                (old.part_start, old.num_parts, old.part_size, old.backup_lba)
+                    == (new.part_start, new.num_parts, new.part_size, new.backup_lba)
+            })
+        };
+        if !same_arrays(self.baseline.primary_header.as_ref(), &h1)
+            || !same_arrays(self.backup_header.as_ref(), &h2)
+        {
+            debug!("partition arrays changed, writing the whole table");
+            return self.write_inplace();
+        }
+        self.check_before_write()?;
+        self.check_unmodified()?;
+        let entry = self.partitions.get(&id).cloned();
+        debug!("writing partition entry {}", id);
+        let mut writes = vec![];
+        for h in &[&h2, &h1] {
+            let (offset, bytes) =
+                partition::entry_bytes(h, id, entry.as_ref(), self.config.lb_size)?;
+            writes.push((observer::Region::PartitionArray, offset, bytes));
+            writes.push(header_write(h, self.config.lb_size)?);
+        }
+        self.write_all_at(&writes)?;
+
+        match entry {
+            Some(p) => self.baseline.partitions.insert(id, p),
+            None => self.baseline.partitions.remove(&id),
+        };
+        self.baseline.guid = h1.disk_guid;
+        self.baseline.primary_header = Some(h1.clone());
+        self.baseline.header_crcs = Some((h2.current_lba, h1.crc32, h2.crc32));
+        self.primary_header = Some(h1);
+        self.backup_header = Some(h2);
+        Ok(())
+    }
+
+    /// Return the partition records and boot signature of a protective MBR
+    /// for a disk ending at `backup_lba`, if LBA0 holds a non-hybrid one.
+    fn resized_pmbr_records(&mut self, backup_lba: u64) -> Result<Option<Vec<u8>>> {
+        let current = match mbr::ProtectiveMBR::from_disk(&mut self.file, self.config.lb_size) {
+            Ok(pmbr) => pmbr,
+            Err(_) => return Ok(None),
+        };
+        let records = current.partitions();
+        let protective = records.iter().filter(|r| r.os_type == 0xEE).count();
+        if protective != 1 || records.iter().any(|r| !r.is_empty() && r.os_type != 0xEE) {
+            return Ok(None);
+        }
+        let mut pmbr = mbr::ProtectiveMBR::for_disk(backup_lba + 1);
+        pmbr.set_chs(self.config.chs_mode);
+        Ok(Some(pmbr.as_bytes()?[446..].to_vec()))
+    }
+
+    /// Write a protective MBR covering the whole disk to LBA0.
+    ///
+    /// This replaces the four partition records and the boot signature,
+    /// keeping the bootcode and disk signature of any existing MBR.
+    /// Together with `write()`, this turns a blank image into a fully
+    /// initialized GPT disk.
+    pub fn write_protective_mbr(&mut self) -> Result<()> {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        let mut pmbr = mbr::ProtectiveMBR::for_disk(self.disk_backup_lba()? + 1);
+        pmbr.set_chs(self.config.chs_mode);
+        let bytes = pmbr.as_bytes()?;
+        debug!("writing protective MBR");
+        self.write_all_at(&[(observer::Region::Mbr, 446, bytes[446..].to_vec())])
+    }
+
+    /// Zero the protective MBR, both headers and both partition arrays, like `sgdisk --zap-all`.
+    ///
+    /// Structures are wiped where the headers found on open place them,
+    /// and also at their standard locations for the size of the disk, so
+    /// that stale copies are erased even if a header is corrupted or
+    /// missing. Afterwards the disk is uninitialized and its in-memory
+    /// table empty; `write_initial_layout()` creates a fresh one.
+    pub fn zap(&mut self) -> Result<()> {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        let lb_size = self.config.lb_size;
+        let bak = self.disk_backup_lba()?;
+        let geometry =
+            header::TableGeometry::with_entries(self.entries.0, self.entries.1, bak, lb_size)?;
+        let array_lbas = geometry.array_lbas(lb_size)?;
+        let (header, array) = (observer::Region::Header, observer::Region::PartitionArray);
+        let mut regions = vec![
+            (0, 1, observer::Region::Mbr),
+            (1, 1, header),
+            (bak, 1, header),
+            (geometry.primary_array, array_lbas, array),
+            (geometry.backup_array, array_lbas, array),
+        ];
+        for h in self.primary_header.iter().chain(&self.backup_header) {
+            let array_bytes = u64::from(h.num_parts) * u64::from(h.part_size);
+            regions.push((h.current_lba, 1, header));
+            regions.push((h.backup_lba, 1, header));
+            regions.push((h.part_start, array_bytes.div_ceil(lb_size.bytes()), array));
+        }
+        regions.sort_unstable_by_key(|(lba, len, _)| (*lba, *len));
+        regions.dedup_by_key(|(lba, len, _)| (*lba, *len));
+        let mut writes = vec![];
+        for (lba, len, region) in regions.into_iter().filter(|(lba, _, _)| *lba <= bak) {
+            let len = len.min(bak + 1 - lba);
+            debug!("zapping {} LBAs at LBA {}", len, lba);
+            let bytes = usize::try_from(lb_size.lba_to_bytes(len)?)
+                .map_err(|_| Error::Invalid("zapped region too large".to_string()))?;
+            writes.push((region, lb_size.lba_to_bytes(lba)?, vec![0u8; bytes]));
+        }
+        self.write_all_at(&writes)?;
+        self.sync()?;
+
+        self.config.initialized = false;
+        self.primary_header = None;
+        self.backup_header = None;
+        self.partitions.clear();
+        self.checksums = ChecksumCache::default();
+        self.free_space = None;
+        self.state = DiskState::Healthy;
+        self.baseline = Baseline {
+            guid: self.guid,
+            header_crcs: Some((bak, 0, 0)),
+            ..Baseline::default()
+        };
+        Ok(())
+    }
+
+    /// Rewrite the primary header and partition array from the backup ones.
+    ///
+    /// This restores the table as found in the backup copy on open (or
+    /// last write), regardless of in-memory changes. The primary array
+    /// goes where the damaged primary header recorded it, if that header
+    /// was still readable on open, or else at its standard location. On
+    /// success, the disk is `Healthy` again.
+    pub fn repair_primary(&mut self) -> Result<()> {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        let h2 = self.backup_header.clone().ok_or(Error::NotInitialized)?;
+        self.check_unmodified()?;
+        let array =
+            partition::entries_as_bytes(&self.baseline.partitions, h2.num_parts, h2.part_size)?;
+        let part_start = self.primary_header.as_ref().map_or(2, |h| h.part_start);
+        let mut h1 = h2.primary_for_backup(part_start)?;
+        h1.crc32_parts = header::calculate_crc32(&array);
+        h1.crc32 = h1.checksum()?;
+        debug!(
+            "rewriting primary table from backup at LBA {}",
+            h2.current_lba
+        );
+        let array_offset = self.config.lb_size.lba_to_bytes(h1.part_start)?;
+        let writes = [
+            (observer::Region::PartitionArray, array_offset, array),
+            header_write(&h1, self.config.lb_size)?,
+        ];
+        self.write_all_at(&writes)?;
+
+        self.baseline.primary_header = Some(h1.clone());
+        self.baseline.header_crcs = Some((h2.current_lba, h1.crc32, h2.crc32));
+        self.primary_header = Some(h1);
+        self.state = DiskState::Healthy;
+        Ok(())
+    }
+
+    /// Recompute both CRC32 fields of the primary and backup headers.
+    ///
+    /// The partition array CRC32 of each header is computed from the array
+    /// it points to on disk, and the header CRC32 from its fields, which are
+    /// left untouched otherwise. In-memory partition changes are ignored.
+    /// This is meant for repairs after low-level edits, of the arrays on
+    /// disk or of the headers (see `primary_header_mut()`), followed by
+    /// `write_headers()`.
+    pub fn recompute_checksums(&mut self) -> Result<()> {
+        let lb_size = self.config.lb_size;
+        let headers = match (&mut self.primary_header, &mut self.backup_header) {
+            (Some(h1), Some(h2)) => [h1, h2],
+            _ => return Err(Error::NotInitialized),
+        };
+        for h in headers {
+            let len = usize::try_from(u64::from(h.num_parts) * u64::from(h.part_size))
+                .map_err(|_| Error::Invalid("partition array too large".to_string()))?;
+            let mut array = vec![0u8; len];
+            disk::read_exact_at(
+                &mut self.file,
+                lb_size.lba_to_bytes(h.part_start)?,
+                &mut array,
+                &self.config.retry_policy,
+                &mut self.io_stats,
+            )?;
+            h.crc32_parts = header::calculate_crc32(&array);
+            h.update_checksum()?;
+            debug!(
+                "recomputed checksums of header at LBA {}: {:#010x}, array {:#010x}",
+                h.current_lba, h.crc32, h.crc32_parts
+            );
+        }
+        Ok(())
+    }
+
+    /// Write the primary and backup headers as they are in memory, without the partition arrays.
+    ///
+    /// Headers are written at the LBA recorded in them, including their
+    /// CRC32 fields as-is: see `recompute_checksums()`. Unlike `write()`,
+    /// nothing is recomputed from the partition table.
+    pub fn write_headers(&mut self) -> Result<()> {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        let (h1, h2) = match (&self.primary_header, &self.backup_header) {
+            (Some(h1), Some(h2)) => (h1.clone(), h2.clone()),
+            _ => return Err(Error::NotInitialized),
+        };
+        self.check_unmodified()?;
+        debug!(
+            "writing headers at LBAs {} and {}",
+            h1.current_lba, h2.current_lba
+        );
+        let lb_size = self.config.lb_size;
+        self.write_all_at(&[header_write(&h2, lb_size)?, header_write(&h1, lb_size)?])?;
+
+        self.baseline.header_crcs = Some((h2.current_lba, h1.crc32, h2.crc32));
+        self.baseline.primary_header = Some(h1);
+        Ok(())
+    }
+
+    /// Move the backup header and partition array to the end of the disk.
+    ///
+    /// After a disk image was copied onto a larger device, its backup
+    /// structures are no longer at the last LBA. This writes them there,
+    /// and updates the backup location and usable area recorded in the
+    /// primary header, like `sgdisk -e`. Such disks can only be opened
+    /// with `Quirks::MISPLACED_BACKUP`. The table as found on open (or
+    /// last write) is relocated, regardless of in-memory changes.
+    pub fn rewrite_backup_at_end(&mut self) -> Result<()> {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        if !self.config.initialized {
+            return Err(Error::NotInitialized);
+        }
+        self.check_unmodified()?;
+        let bak = self.disk_backup_lba()?;
+        let pp = &self.baseline.partitions;
+        let geometry = self.geometry_for(pp)?;
+        if let Some(p) = pp
+            .values()
+            .find(|p| !geometry.is_usable(p.first_lba, p.last_lba))
+        {
+            return Err(Error::Invalid(format!(
+                "partition \"{}\" beyond the end of the disk",
+                p.name
+            )));
+        }
+        let array = partition::entries_as_bytes(pp, geometry.num_parts, geometry.part_size)?;
+        let guid = self.baseline.guid;
+        let mut h1 = header::Header::from_geometry(true, &geometry, guid, bak);
+        let mut h2 = header::Header::from_geometry(false, &geometry, guid, bak);
+        for h in &mut [&mut h1, &mut h2] {
+            h.crc32_parts = header::calculate_crc32(&array);
+            h.crc32 = h.checksum()?;
+        }
+        debug!("moving backup table to LBA {}", bak);
+        let array_offset = self.config.lb_size.lba_to_bytes(h2.part_start)?;
+        let writes = [
+            (observer::Region::PartitionArray, array_offset, array),
+            header_write(&h2, self.config.lb_size)?,
+            header_write(&h1, self.config.lb_size)?,
+        ];
+        self.write_all_at(&writes)?;
+
+        self.baseline.primary_header = Some(h1.clone());
+        self.baseline.header_crcs = Some((h2.current_lba, h1.crc32, h2.crc32));
+        self.primary_header = Some(h1);
+        self.backup_header = Some(h2);
+        self.free_space = None;
+        Ok(())
+    }
+
+    /// Adapt the table to a disk which was enlarged, e.g. a resized VM image.
+    ///
+    /// The size of the disk is looked up again (unless set with
+    /// `GptConfig::disk_size()`), so that `write()` moves the backup header
+    /// and partition array to its new end, and extends the usable area up
+    /// to them. With `grow_last`, the partition ending last also grows to
+    /// the end of the usable area, like `growpart`; its number is returned
+    /// if it grew.
+    /// Like `sgdisk -e`, `write()` also resizes the protective record of
+    /// the MBR to the new disk size, unless the MBR is a hybrid one.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn expand(&mut self, grow_last: bool) -> Result<Option<u32>> {
+        let old_bak = match &self.backup_header {
+            Some(h) if self.config.initialized => h.current_lba,
+            _ => return Err(Error::NotInitialized),
+        };
+        self.disk_backup_lba.set(None);
+        let bak = self.disk_backup_lba()?;
+        if bak < old_bak {
+            return Err(Error::Invalid(format!(
+                "disk shrank: backup header at LBA {}, last LBA is {}",
+                old_bak, bak
+            )));
+        }
+        if let Some((primary, backup)) = self.array_lbas {
+            self.array_lbas = Some((primary, backup + (bak - old_bak)));
+        }
+        self.free_space = None;
+        self.resize_pmbr = true;
+        debug!("expanding table from LBA {} to LBA {}", old_bak, bak);
+
+        let last = self.partitions.iter().max_by_key(|(_, p)| p.last_lba);
+        let geometry = self.allocation_geometry()?;
+        let (id, first_lba) = match last {
+            Some((id, p)) if grow_last && p.last_lba < geometry.last_usable => (*id, p.first_lba),
+            _ => return Ok(None),
+        };
+        let mut others = self.partitions.clone();
+        others.remove(&id);
+        let mut free = self.free_space_for(&geometry, &others);
+        if !free.allocate(first_lba, geometry.last_usable) {
+            return Err(Error::Invalid(format!(
+                "reserved region after partition {}, cannot grow it",
+                id
+            )));
+        }
+        self.checksums.invalidate_entries();
+        if let Some(p) = self.partitions.get_mut(&id) {
+            p.last_lba = geometry.last_usable;
+        }
+        Ok(Some(id))
+    }
+
+    /// Return the smallest disk size (in bytes) able to hold the current layout.
+    ///
+    /// This covers all partitions and reserved regions, followed by the
+    /// backup partition array and header at their standard location.
+    pub fn minimum_disk_size(&self) -> Result<u64> {
+        let lb_size = self.config.lb_size;
+        let geometry = self.geometry()?;
+        let last_used = self
+            .partitions
+            .values()
+            .map(|p| p.last_lba)
+            .chain(self.reserved.iter().map(|r| r.last_lba))
+            .fold(geometry.first_usable - 1, u64::max);
+        let lbas = last_used + geometry.array_lbas(lb_size)? + 2;
+        lb_size.lba_to_bytes(lbas)
+    }
+
+    /// Lay out the table for a disk shrunk to `size` bytes, e.g. before truncating an image.
+    ///
+    /// The size must be a multiple of the logical block size, and at
+    /// least `minimum_disk_size()`. The disk size is then fixed to `size`
+    /// as with `GptConfig::disk_size()`, so that `write()` moves the
+    /// backup header and partition array to the new end while the
+    /// underlying file is still larger. The protective record of the MBR
+    /// is resized along, as with `expand()`.
+    /// No changes are recorded to disk until `write()` is called.
+    pub fn shrink_to(&mut self, size: u64) -> Result<()> {
+        let lb_size = self.config.lb_size;
+        if size % lb_size.bytes() != 0 {
+            return Err(Error::Unaligned {
+                value: size,
+                alignment: lb_size.bytes(),
+            });
+        }
+        let minimum = self.minimum_disk_size()?;
+        if size < minimum {
+            return Err(Error::Invalid(format!(
+                "layout needs at least {} bytes, not {}",
+                minimum, size
+            )));
+        }
+        let old_bak = self.disk_backup_lba()?;
+        let bak = lb_size.bytes_to_lba(size)? - 1;
+        if bak > old_bak {
+            return Err(Error::Invalid(
+                "target size larger than the disk, see expand()".into(),
+            ));
+        }
+        let array_lbas = self.geometry()?.array_lbas(lb_size)?;
+        if let Some((primary, _)) = self.array_lbas {
+            self.array_lbas = Some((primary, bak - array_lbas));
+        }
+        debug!("shrinking table from LBA {} to LBA {}", old_bak, bak);
+        self.config.disk_size = Some(size);
+        self.disk_backup_lba.set(Some(bak));
+        self.free_space = None;
+        self.resize_pmbr = true;
+        Ok(())
+    }
+
+    /// Write a protective MBR and a complete GPT, e.g. onto a blank image.
+    ///
+    /// This is `write_protective_mbr()` followed by `write()`, on disks
+    /// opened with `initialized(false)` too: both headers and both
+    /// partition arrays are written, with the partitions added so far
+    /// (none, for a fresh table). The checks of `write()` run before the
+    /// MBR is written, so a rejected table leaves the disk untouched.
+    pub fn write_initial_layout(mut self) -> Result<D> {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        // Nothing may be written if `write()` is going to refuse the table.
+        self.check_before_write()?;
+        self.check_unmodified()?;
+        self.write_protective_mbr()?;
+        self.config.initialized = true;
+        self.write()
+    }
+
+    /// Write the current partition table to another target of `capacity` bytes.
+    ///
+    /// This writes a protective MBR, both headers and both partition arrays,
+    /// laid out for the given capacity, to `target` instead of the disk this
+    /// table was opened from, e.g. to stamp a prepared layout onto several
+    /// blank devices. The disk GUID and partition GUIDs are copied as-is.
+    /// Partitions must fit in the usable area of the target. Neither this
+    /// disk nor the rest of `target` is modified.
+    pub fn write_to<W: Write + Seek>(&self, target: &mut W, capacity: u64) -> Result<()> {
+        let lb_size = self.config.lb_size;
+        let bak = (capacity / lb_size.bytes())
+            .checked_sub(1)
+            .filter(|bak| *bak > 1)
+            .ok_or(Error::TooShort)?;
+        let geometry = self.standard_geometry(&self.partitions, bak)?;
+        let mut h1 = header::Header::from_geometry(true, &geometry, self.guid, bak);
+        let mut h2 = header::Header::from_geometry(false, &geometry, self.guid, bak);
+        if let Some((id, _)) = self
+            .partitions
+            .iter()
+            .find(|(_, p)| !geometry.is_usable(p.first_lba, p.last_lba))
+        {
+            return Err(Error::OutsideUsableArea(*id));
+        }
+
+        let array = partition::entries_as_bytes(&self.partitions, h1.num_parts, h1.part_size)?;
+        let mut pmbr = mbr::ProtectiveMBR::for_disk(bak + 1);
+        pmbr.set_chs(self.config.chs_mode);
+        let mut writes = vec![(0, pmbr.as_bytes()?)];
+        for h in &mut [&mut h1, &mut h2] {
+            h.crc32_parts = header::calculate_crc32(&array);
+            h.crc32 = h.checksum()?;
+            let array_offset = h
+                .part_start
+                .checked_mul(lb_size.bytes())
+                .ok_or(Error::Overflow("partition array offset"))?;
+            writes.push((array_offset, array.clone()));
+            writes.push(h.exact_bytes(lb_size)?);
+        }
+
+        let mut stats = disk::IoStats::default();
+        for (offset, bytes) in &writes {
+            trace!("writing {} bytes at {:#x} to target", bytes.len(), offset);
+            disk::write_all_at(
+                target,
+                *offset,
+                bytes,
+                &self.config.retry_policy,
+                &mut stats,
+            )?;
+        }
+        Ok(target.flush()?)
+    }
+
+    /// Persist state to disk, consuming this disk object.
+    ///
+    /// This is a destructive action, as it overwrite headers and
+    /// partitions entries on disk. All writes are flushed to disk
+    /// before returning the underlying device.
+    ///
+    /// The backup partition array and header are written first, and the
+    /// primary ones last, so that an interrupted write leaves at least one
+    /// consistent copy of the table behind. The device is then notified of
+    /// the change (see `disk::DiskDevice::partitions_changed()`); failing
+    /// to do so is only logged.
+    ///
+    /// See `write_inplace()` to keep using this disk object afterwards.
+    pub fn write(mut self) -> Result<D> {
+        self.write_inplace()?;
+        Ok(self.file)
+    }
+
+    /// Persist state to disk, keeping this disk object open.
+    ///
+    /// This writes the same structures as `write()`. The written table
+    /// becomes the new reference for later writes: further changes can be
+    /// made and written again without reopening the disk, and the check
+    /// against external modifications applies to this write onward.
+    pub fn write_inplace(&mut self) -> Result<()> {
+        if !self.config.writable {
+            return Err(Error::NotWritable);
+        }
+        if !self.config.initialized {
+            return Err(Error::NotInitialized);
+        }
+        self.check_before_write()?;
+        self.check_unmodified()?;
+        let (h1, h2) = self.preview_headers()?;
+        let array = partition::entries_as_bytes(&self.partitions, h1.num_parts, h1.part_size)?;
+        let mut writes = vec![];
+        for h in &[&h2, &h1] {
+            let array_offset = self.config.lb_size.lba_to_bytes(h.part_start)?;
+            writes.push((
+                observer::Region::PartitionArray,
+                array_offset,
+                array.clone(),
+            ));
+            writes.push(header_write(h, self.config.lb_size)?);
+        }
+        if self.resize_pmbr {
+            if let Some(records) = self.resized_pmbr_records(h2.current_lba)? {
+                debug!("resizing protective MBR to LBA {}", h2.current_lba);
+                writes.push((observer::Region::Mbr, 446, records));
+            }
+        }
+        self.write_all_at(&writes)?;
+        self.resize_pmbr = false;
+        let lb_size = self.config.lb_size;
+        if let Err(e) =
+            self.file
+                .partitions_changed(&self.baseline.partitions, &self.partitions, lb_size)
+        {
+            warn!("failed to notify partition table change: {}", e);
+        }
+
+        self.baseline = Baseline {
+            guid: h1.disk_guid,
+            primary_header: Some(h1.clone()),
+            partitions: self.partitions.clone(),
+            header_crcs: Some((h2.current_lba, h1.crc32, h2.crc32)),
+        };
+        self.primary_header = Some(h1);
+        self.backup_header = Some(h2);
+        self.state = DiskState::Healthy;
+        Ok(())
+    }
+
+    /// Persist all data written so far to the underlying storage.
+    ///
+    /// This is `disk::DiskDevice::sync_data()`, which for files and block
+    /// devices is `fdatasync()` on Unix and `FlushFileBuffers()` on Windows.
+    /// See also `GptConfig::sync_on_write()`.
+    pub fn sync(&mut self) -> Result<()> {
+        Ok(self.file.sync_data()?)
+    }
+}
+
+impl GptDisk<disk::MemoryDevice> {
+    /// Create a blank table for a disk of `disk_size` bytes, entirely in memory.
+    ///
+    /// Partitions can be added as usual, and the resulting table streamed
+    /// into an image or device later with `write_into()`. Only the chunks
+    /// written to are allocated, so this is cheap even for large disks.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// let lb_size = gpt::disk::DEFAULT_SECTOR_SIZE;
+    /// let mut gdisk = gpt::GptDisk::new_in_memory(64 * 1024 * 1024, lb_size).unwrap();
+    /// let esp = gpt::partition_types::Type::from_name("EFI System").unwrap();
+    /// gdisk.add_partition("esp", 1024 * 1024, esp, 0).unwrap();
+    ///
+    /// let mut image = std::io::Cursor::new(vec![0u8; 64 * 1024 * 1024]);
+    /// gdisk.write_into(&mut image).unwrap();
+    /// ```
+    pub fn new_in_memory(disk_size: u64, lb_size: disk::LogicalBlockSize) -> Result<Self> {
+        GptConfig::new()
+            .writable(true)
+            .initialized(false)
+            .logical_block_size(lb_size)
+            .open_from_device(disk::MemoryDevice::new(disk_size))
+    }
+
+    /// Write the protective MBR, headers and partition arrays to `target`.
+    ///
+    /// This is `write_to()`, for the size given to `new_in_memory()`.
+    pub fn write_into<W: Write + Seek>(&self, target: &mut W) -> Result<()> {
+        self.write_to(target, disk::DiskDevice::size(&self.file)?)
+    }
+}
+
+/// Create a blank GPT on the image or device at `path`, and open it.
+///
+/// A protective MBR, both headers and both (empty) partition arrays
+/// are written. This refuses to overwrite an existing GPT or MBR
+/// partition table, see `GptConfig::overwrite()`.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// let lb_size = gpt::disk::DEFAULT_SECTOR_SIZE;
+/// let gdisk = gpt::mkgpt(std::path::Path::new("disk.img"), lb_size).unwrap();
+/// assert!(gdisk.partitions().is_empty());
+/// ```
+pub fn mkgpt(path: &path::Path, lb_size: disk::LogicalBlockSize) -> Result<GptDisk> {
+    let cfg = || GptConfig::new().writable(true).logical_block_size(lb_size);
+    cfg()
+        .initialized(false)
+        .open(path)?
+        .write_initial_layout()?;
+    cfg().open(path)
+}
+
+/// A GUID shared across several disks, see `find_cross_disk_duplicates()`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CrossDiskDuplicate {
+    /// Several disks share the same disk GUID, as indices in the input slice.
+    DiskGuid(uuid::Uuid, Vec<usize>),
+    /// Several partitions share the same unique GUID, as (disk index, partition number).
+    PartitionGuid(uuid::Uuid, Vec<(usize, u32)>),
+}
+
+impl std::fmt::Display for CrossDiskDuplicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrossDiskDuplicate::DiskGuid(guid, disks) => {
+                write!(f, "duplicate disk GUID {} on disks {:?}", guid, disks)
+            }
+            CrossDiskDuplicate::PartitionGuid(guid, parts) => write!(
+                f,
+                "duplicate partition GUID {} in (disk, entry) {:?}",
+                guid, parts
+            ),
+        }
+    }
+}
+
+/// Find disk and partition GUIDs shared across several disks.
+///
+/// Cloned disks often end up with identical GUIDs, which breaks
+/// lookups by disk or partition UUID. Partitions sharing a GUID on
+/// the same disk are reported as well.
+pub fn find_cross_disk_duplicates<D: disk::DiskDevice>(
+    disks: &[&GptDisk<D>],
+) -> Vec<CrossDiskDuplicate> {
+    let mut disk_guids: BTreeMap<uuid::Uuid, Vec<usize>> = BTreeMap::new();
+    let mut part_guids: BTreeMap<uuid::Uuid, Vec<(usize, u32)>> = BTreeMap::new();
+    for (idx, disk) in disks.iter().enumerate() {
+        disk_guids.entry(disk.guid).or_default().push(idx);
+        for (id, p) in &disk.partitions {
+            part_guids.entry(p.part_guid).or_default().push((idx, *id));
+        }
+    }
+
+    let dup_disks = disk_guids
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .map(|(guid, ids)| CrossDiskDuplicate::DiskGuid(guid, ids));
+    let dup_parts = part_guids
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .map(|(guid, ids)| CrossDiskDuplicate::PartitionGuid(guid, ids));
+    dup_disks.chain(dup_parts).collect()
+}
+
+/// Replicate the partition table of `src` onto `dst`, like `sgdisk --replicate`.
+///
+/// All partitions of `dst` are replaced by those of `src`, with the same
+/// numbers, LBA ranges, types, names and flags, and the partition array
+/// takes the same number and size of entries. `dst` may be larger than
+/// `src`, but both must share the same logical block size, and all
+/// partitions must fit in the usable area of `dst`.
+///
+/// Unless `randomize_guids` is set, the disk GUID and partition GUIDs are
+/// copied as well; setting it gives `dst` fresh ones (like `sgdisk -G`),
+/// e.g. so that both disks can be attached at the same time. No changes
+/// are recorded to `dst` until `write()` is called.
+pub fn clone_layout<D: disk::DiskDevice, E: disk::DiskDevice>(
+    src: &GptDisk<D>,
+    dst: &mut GptDisk<E>,
+    randomize_guids: bool,
+) -> Result<()> {
+    if src.config.lb_size != dst.config.lb_size {
+        return Err(Error::Invalid(format!(
+            "logical block size mismatch: {} and {} bytes",
+            src.config.lb_size.bytes(),
+            dst.config.lb_size.bytes()
+        )));
+    }
+    let mut pp = src.partitions.clone();
+    if randomize_guids {
+        for p in pp.values_mut() {
+            p.part_guid = uuid::Uuid::new_v4();
+        }
+    }
+    let entries = std::mem::replace(&mut dst.entries, src.entries);
+    if let Err(e) = dst.update_partitions(pp) {
+        dst.entries = entries;
+        return Err(e);
+    }
+    dst.free_space = None;
+    dst.update_guid(if randomize_guids {
+        None
+    } else {
+        Some(src.guid)
+    })?;
+    Ok(())
+}
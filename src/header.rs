@@ -1,19 +1,29 @@
 //! GPT-header object and helper functions.
 
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use crc::{crc32, Hasher32};
+use byteorder::{LittleEndian, ReadBytesExt};
+use log::*;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs::{File, OpenOptions};
-use std::io::{Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::path::Path;
-use uuid;
-use log::*;
 
 use crate::disk;
 use crate::partition;
+use crate::quirks::{QuirkTracker, Quirks};
+use crate::raw::RawHeader;
+use crate::{Error, Result};
+
+/// Minimum number of entries in a partition array, as mandated by the spec.
+pub(crate) const MIN_NUM_PARTS: u32 = 128;
 
 /// Header describing a GPT disk.
+///
+/// Fields can be read and changed through typed accessors as well, e.g.
+/// `first_usable_lba()` and `set_usable_lbas()`. Setters mark the header
+/// CRC32 as stale, see `needs_checksum()` and `update_checksum()`.
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct Header {
     /// GPT header magic signature, hardcoded to "EFI PART".
     pub signature: String, // Offset  0. "EFI PART", 45h 46h 49h 20h 50h 41h 52h 54h
@@ -43,26 +53,232 @@ pub struct Header {
     pub part_size: u32, // Offset 84
     /// CRC32 of the partition table
     pub crc32_parts: u32, // Offset 88
+    /// Whether a setter changed a field since the CRC32 was last updated.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub crc32_stale: bool,
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Header {
+    /// Any field values, behind a valid signature.
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Header {
+            signature: "EFI PART".to_string(),
+            revision: u.arbitrary()?,
+            header_size_le: u.arbitrary()?,
+            crc32: u.arbitrary()?,
+            reserved: u.arbitrary()?,
+            current_lba: u.arbitrary()?,
+            backup_lba: u.arbitrary()?,
+            first_usable: u.arbitrary()?,
+            last_usable: u.arbitrary()?,
+            disk_guid: uuid::Uuid::from_bytes(u.arbitrary()?),
+            part_start: u.arbitrary()?,
+            num_parts: u.arbitrary()?,
+            part_size: u.arbitrary()?,
+            crc32_parts: u.arbitrary()?,
+            crc32_stale: false,
+        })
+    }
+}
+
+/// Number of partition array entries needed to hold `pp`.
+pub(crate) fn num_parts_for(pp: &BTreeMap<u32, partition::Partition>) -> u32 {
+    pp.keys()
+        .next_back()
+        .map_or(MIN_NUM_PARTS, |id| (*id).max(MIN_NUM_PARTS))
+}
+
+/// Geometry of a partition table: entry arrays and usable LBA range.
+///
+/// This is shared by the primary and backup headers of a disk, which
+/// only differ in the location of their own array copy.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TableGeometry {
+    /// Number of partition entries.
+    pub num_parts: u32,
+    /// Size of a partition entry, in bytes.
+    pub part_size: u32,
+    /// Starting LBA of the primary partition array.
+    pub primary_array: u64,
+    /// Starting LBA of the backup partition array.
+    pub backup_array: u64,
+    /// First usable LBA for partitions.
+    pub first_usable: u64,
+    /// Last usable LBA for partitions.
+    pub last_usable: u64,
+}
+
+impl TableGeometry {
+    /// Standard geometry for `num_parts` entries, with the backup header at `backup_lba`.
+    ///
+    /// Partition arrays are sized for `lb_size` (e.g. 32 LBAs of 512
+    /// bytes, or 4 LBAs of 4096 bytes, for 128 entries), right after the
+    /// primary header and right before the backup one.
+    pub fn standard(
+        num_parts: u32,
+        backup_lba: u64,
+        lb_size: disk::LogicalBlockSize,
+    ) -> Result<Self> {
+        Self::with_entries(num_parts, 128, backup_lba, lb_size)
+    }
+
+    /// Standard geometry for `num_parts` entries of `part_size` bytes each.
+    ///
+    /// As with `standard()`, arrays are placed right after the primary
+    /// header and right before the backup one, and larger arrays push
+    /// the first usable LBA further (e.g. to 66 for 256 entries of 128
+    /// bytes on 512 bytes sectors).
+    pub fn with_entries(
+        num_parts: u32,
+        part_size: u32,
+        backup_lba: u64,
+        lb_size: disk::LogicalBlockSize,
+    ) -> Result<Self> {
+        let mut geometry = Self {
+            num_parts,
+            part_size,
+            primary_array: 2,
+            backup_array: 0,
+            first_usable: 0,
+            last_usable: 0,
+        };
+        let array_lbas = geometry.array_lbas(lb_size)?;
+        geometry.backup_array = backup_lba
+            .checked_sub(array_lbas)
+            .ok_or(Error::Overflow("header backup array"))?;
+        geometry.first_usable = 2 + array_lbas;
+        geometry.last_usable = geometry
+            .backup_array
+            .checked_sub(1)
+            .ok_or(Error::Overflow("header last usable"))?;
+        Ok(geometry)
+    }
+
+    /// Geometry described by a pair of primary and backup headers.
+    pub fn from_headers(primary: &Header, backup: &Header) -> Self {
+        Self {
+            num_parts: primary.num_parts,
+            part_size: primary.part_size,
+            primary_array: primary.part_start,
+            backup_array: backup.part_start,
+            first_usable: primary.first_usable,
+            last_usable: primary.last_usable,
+        }
+    }
+
+    /// Size of a partition array, in bytes.
+    pub fn array_bytes(&self) -> Result<u64> {
+        u64::from(self.num_parts)
+            .checked_mul(self.part_size.into())
+            .ok_or(Error::Overflow("partition array size"))
+    }
+
+    /// Size of a partition array, in LBAs.
+    pub fn array_lbas(&self, lb_size: disk::LogicalBlockSize) -> Result<u64> {
+        Ok(self.array_bytes()?.div_ceil(lb_size.bytes()))
+    }
+
+    /// Number of usable LBAs.
+    pub fn usable_lbas(&self) -> u64 {
+        (self.last_usable + 1).saturating_sub(self.first_usable)
+    }
+
+    /// Whether `first_lba..=last_lba` lies in the usable range.
+    pub fn is_usable(&self, first_lba: u64, last_lba: u64) -> bool {
+        first_lba <= last_lba && first_lba >= self.first_usable && last_lba <= self.last_usable
+    }
+
+    /// Whether partition arrays sit at their standard locations, for a backup header at `backup_lba`.
+    pub fn has_standard_arrays(&self, backup_lba: u64, lb_size: disk::LogicalBlockSize) -> bool {
+        let array_lbas = match self.array_lbas(lb_size) {
+            Ok(lbas) => lbas,
+            Err(_) => return false,
+        };
+        self.primary_array == 2 && self.backup_array.checked_add(array_lbas) == Some(backup_lba)
+    }
+
+    /// Move the partition arrays to non-standard locations.
+    ///
+    /// The usable range is shrunk to the LBAs left between both arrays,
+    /// which must not overlap the headers nor each other.
+    pub fn relocate_arrays(
+        &mut self,
+        primary_array: u64,
+        backup_array: u64,
+        backup_lba: u64,
+        lb_size: disk::LogicalBlockSize,
+    ) -> Result<()> {
+        let array_lbas = self.array_lbas(lb_size)?;
+        let first_usable = primary_array
+            .checked_add(array_lbas)
+            .ok_or(Error::Overflow("partition array primary end"))?;
+        let backup_end = backup_array
+            .checked_add(array_lbas)
+            .ok_or(Error::Overflow("partition array backup end"))?;
+        if primary_array < 2 || backup_end > backup_lba || first_usable >= backup_array {
+            return Err(Error::Invalid("invalid partition array location".into()));
+        }
+        self.primary_array = primary_array;
+        self.backup_array = backup_array;
+        self.first_usable = first_usable;
+        self.last_usable = backup_array - 1;
+        Ok(())
+    }
+
+    /// Check this geometry for a disk with the backup header at `backup_lba`.
+    ///
+    /// Entries must be at least 128 bytes, as a power-of-two multiple of
+    /// it, and neither array may overlap the headers or the usable range.
+    pub fn validate(&self, backup_lba: u64, lb_size: disk::LogicalBlockSize) -> Result<()> {
+        if self.part_size < 128 || !self.part_size.is_power_of_two() {
+            return Err(Error::Invalid("invalid partition entry size".into()));
+        }
+        if self.first_usable > self.last_usable || self.last_usable >= backup_lba {
+            return Err(Error::Invalid("invalid usable LBA range".into()));
+        }
+        let array_lbas = self.array_lbas(lb_size)?;
+        for start in &[self.primary_array, self.backup_array] {
+            let end = start
+                .checked_add(array_lbas)
+                .ok_or(Error::Overflow("partition array end"))?;
+            let overlaps_usable = *start <= self.last_usable && end > self.first_usable;
+            if *start < 2 || end > backup_lba || overlaps_usable {
+                return Err(Error::Invalid("invalid partition array location".into()));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Header {
     pub(crate) fn compute_new(
         primary: bool,
-        pp: &[partition::Partition],
+        pp: &BTreeMap<u32, partition::Partition>,
         guid: uuid::Uuid,
         backup_offset: u64,
+        lb_size: disk::LogicalBlockSize,
     ) -> Result<Self> {
-        let (cur, bak) = if primary {
-            (1, backup_offset)
+        let geometry = TableGeometry::standard(num_parts_for(pp), backup_offset, lb_size)?;
+        Ok(Self::from_geometry(primary, &geometry, guid, backup_offset))
+    }
+
+    /// Build a new header for `geometry`, with the backup header at `backup_lba`.
+    ///
+    /// Checksums are left zeroed.
+    pub(crate) fn from_geometry(
+        primary: bool,
+        geometry: &TableGeometry,
+        guid: uuid::Uuid,
+        backup_lba: u64,
+    ) -> Self {
+        let (cur, bak, part_start) = if primary {
+            (1, backup_lba, geometry.primary_array)
         } else {
-            (backup_offset, 1)
+            (backup_lba, 1, geometry.backup_array)
         };
-        let first = 34u64;
-        let last = backup_offset
-            .checked_sub(first)
-            .ok_or_else(|| Error::new(ErrorKind::Other, "header underflow - last usable"))?;
-
-        let hdr = Header {
+        Header {
             signature: "EFI PART".to_string(),
             revision: 65536,
             header_size_le: 92,
@@ -70,28 +286,46 @@ impl Header {
             reserved: 0,
             current_lba: cur,
             backup_lba: bak,
-            first_usable: first,
-            last_usable: last,
+            first_usable: geometry.first_usable,
+            last_usable: geometry.last_usable,
             disk_guid: guid,
-            part_start: 2,
-            num_parts: pp.len() as u32,
-            part_size: 128,
+            part_start,
+            num_parts: geometry.num_parts,
+            part_size: geometry.part_size,
             crc32_parts: 0,
-        };
+            crc32_stale: false,
+        }
+    }
 
-        Ok(hdr)
+    /// Build the primary header matching this backup header.
+    ///
+    /// The primary partition array is placed at `part_start`, e.g. LBA 2
+    /// for its standard location right after the primary header. The
+    /// header CRC32 is recomputed, while the partition array one is kept.
+    pub(crate) fn primary_for_backup(&self, part_start: u64) -> Result<Header> {
+        let mut h = Header {
+            current_lba: 1,
+            backup_lba: self.current_lba,
+            part_start,
+            ..self.clone()
+        };
+        h.crc32 = h.checksum()?;
+        Ok(h)
     }
 
     /// Write the primary header.
     ///
     /// With a CRC32 set to zero this will set the crc32 after
     /// writing the header out.
-    pub fn write_primary(&self, file: &mut File, lb_size: disk::LogicalBlockSize) -> Result<usize> {
+    pub fn write_primary<D: Read + Write + Seek>(
+        &self,
+        file: &mut D,
+        lb_size: disk::LogicalBlockSize,
+    ) -> Result<usize> {
         // This is the primary header. It must start before the backup one.
         if self.current_lba >= self.backup_lba {
-            return Err(Error::new(
-                ErrorKind::Other,
-                "primary header does not start before backup one",
+            return Err(Error::Invalid(
+                "primary header does not start before backup one".into(),
             ));
         }
         self.file_write_header(file, self.current_lba, lb_size)
@@ -101,12 +335,15 @@ impl Header {
     ///
     /// With a CRC32 set to zero this will set the crc32 after
     /// writing the header out.
-    pub fn write_backup(&self, file: &mut File, lb_size: disk::LogicalBlockSize) -> Result<usize> {
+    pub fn write_backup<D: Read + Write + Seek>(
+        &self,
+        file: &mut D,
+        lb_size: disk::LogicalBlockSize,
+    ) -> Result<usize> {
         // This is the backup header. It must start after the primary one.
         if self.current_lba <= self.backup_lba {
-            return Err(Error::new(
-                ErrorKind::Other,
-                "backup header does not start after primary one",
+            return Err(Error::Invalid(
+                "backup header does not start after primary one".into(),
             ));
         }
         self.file_write_header(file, self.current_lba, lb_size)
@@ -116,9 +353,9 @@ impl Header {
     ///
     /// With a CRC32 set to zero this will set the crc32 after
     /// writing the header out.
-    fn file_write_header(
+    fn file_write_header<D: Read + Write + Seek>(
         &self,
-        file: &mut File,
+        file: &mut D,
         lba: u64,
         lb_size: disk::LogicalBlockSize,
     ) -> Result<usize> {
@@ -127,41 +364,216 @@ impl Header {
         let bytes = self.as_bytes(None, Some(parts_checksum))?;
 
         // Calculate the CRC32 from the byte array
-        let checksum = calculate_crc32(&bytes)?;
+        let checksum = calculate_crc32(&bytes);
 
         // Write it to disk in 1 shot
-        let start = lba.checked_mul(lb_size.into())
-            .ok_or_else(|| Error::new(ErrorKind::Other, "header overflow - offset"))?;
+        let start = lba
+            .checked_mul(lb_size.into())
+            .ok_or(Error::Overflow("header offset"))?;
         let _ = file.seek(SeekFrom::Start(start))?;
         let len = file.write(&self.as_bytes(Some(checksum), Some(parts_checksum))?)?;
 
         Ok(len)
     }
 
+    /// Return the byte offset of this header, and its on-disk representation as-is.
+    ///
+    /// Unlike `write_primary()` and `write_backup()`, both checksums are
+    /// taken from the header fields instead of being recomputed from disk.
+    pub(crate) fn exact_bytes(&self, lb_size: disk::LogicalBlockSize) -> Result<(u64, Vec<u8>)> {
+        let start = self
+            .current_lba
+            .checked_mul(lb_size.into())
+            .ok_or(Error::Overflow("header offset"))?;
+        Ok((
+            start,
+            self.as_bytes(Some(self.crc32), Some(self.crc32_parts))?,
+        ))
+    }
+
+    /// Compute the CRC32 of this header, as it would be written out.
+    ///
+    /// The `crc32` field itself is ignored, while the current value of
+    /// `crc32_parts` is included in the checksum.
+    pub(crate) fn checksum(&self) -> Result<u32> {
+        let bytes = self.as_bytes(None, Some(self.crc32_parts))?;
+        Ok(calculate_crc32(&bytes))
+    }
+
     fn as_bytes(&self, checksum: Option<u32>, parts_checksum: Option<u32>) -> Result<Vec<u8>> {
-        let mut buff: Vec<u8> = Vec::new();
-
-        buff.write_all(self.signature.as_bytes())?;
-        buff.write_u32::<LittleEndian>(self.revision)?;
-        buff.write_u32::<LittleEndian>(self.header_size_le)?;
-        match checksum {
-            Some(c) => buff.write_u32::<LittleEndian>(c)?,
-            None => buff.write_u32::<LittleEndian>(0)?,
-        };
-        buff.write_u32::<LittleEndian>(0)?;
-        buff.write_u64::<LittleEndian>(self.current_lba)?;
-        buff.write_u64::<LittleEndian>(self.backup_lba)?;
-        buff.write_u64::<LittleEndian>(self.first_usable)?;
-        buff.write_u64::<LittleEndian>(self.last_usable)?;
-        buff.write_all(self.disk_guid.as_bytes())?;
-        buff.write_u64::<LittleEndian>(self.part_start)?;
-        buff.write_u32::<LittleEndian>(self.num_parts)?;
-        buff.write_u32::<LittleEndian>(self.part_size)?;
-        match parts_checksum {
-            Some(c) => buff.write_u32::<LittleEndian>(c)?,
-            None => buff.write_u32::<LittleEndian>(0)?,
-        };
-        Ok(buff)
+        let mut raw = self.to_raw();
+        raw.crc32 = checksum.unwrap_or(0);
+        raw.reserved = 0;
+        raw.crc32_parts = parts_checksum.unwrap_or(0);
+        Ok(raw.to_bytes().to_vec())
+    }
+
+    /// Return the on-disk fields of this header, see `raw::RawHeader`.
+    pub fn to_raw(&self) -> RawHeader {
+        let mut signature = [0; 8];
+        let bytes = self.signature.as_bytes();
+        let n = bytes.len().min(signature.len());
+        signature[..n].copy_from_slice(&bytes[..n]);
+        RawHeader {
+            signature,
+            revision: self.revision,
+            header_size: self.header_size_le,
+            crc32: self.crc32,
+            reserved: self.reserved,
+            current_lba: self.current_lba,
+            backup_lba: self.backup_lba,
+            first_usable: self.first_usable,
+            last_usable: self.last_usable,
+            disk_guid: self.disk_guid,
+            part_start: self.part_start,
+            num_parts: self.num_parts,
+            part_size: self.part_size,
+            crc32_parts: self.crc32_parts,
+        }
+    }
+
+    /// Check the signature, CRC32 and location of this header, read at `lba`.
+    ///
+    /// The partition array checksum is not checked, as this needs the
+    /// on-disk array; see `GptDisk::verify_integrity()`.
+    pub fn check_integrity(&self, lba: u64) -> Vec<Error> {
+        let mut errors = vec![];
+        if self.signature != "EFI PART" {
+            errors.push(Error::InvalidSignature);
+        }
+        if let Ok(c) = self.checksum() {
+            if c != self.crc32 {
+                errors.push(Error::HeaderCrcMismatch {
+                    expected: self.crc32,
+                    found: c,
+                });
+            }
+        }
+        if self.current_lba != lba {
+            errors.push(Error::CurrentLbaMismatch {
+                expected: lba,
+                found: self.current_lba,
+            });
+        }
+        errors
+    }
+
+    /// Whether this header, read at `lba`, passes `check_integrity()`.
+    pub fn is_valid(&self, lba: u64) -> bool {
+        self.check_integrity(lba).is_empty()
+    }
+
+    /// LBA of this header.
+    pub fn current_lba(&self) -> u64 {
+        self.current_lba
+    }
+
+    /// LBA of the other header (the backup one, for the primary header).
+    pub fn alternate_lba(&self) -> u64 {
+        self.backup_lba
+    }
+
+    /// First LBA usable by partitions.
+    pub fn first_usable_lba(&self) -> u64 {
+        self.first_usable
+    }
+
+    /// Last LBA usable by partitions.
+    pub fn last_usable_lba(&self) -> u64 {
+        self.last_usable
+    }
+
+    /// Starting LBA of the partition array.
+    pub fn partition_entry_lba(&self) -> u64 {
+        self.part_start
+    }
+
+    /// Number of entries in the partition array.
+    pub fn num_partition_entries(&self) -> u32 {
+        self.num_parts
+    }
+
+    /// Size (in bytes) of each partition entry.
+    pub fn partition_entry_size(&self) -> u32 {
+        self.part_size
+    }
+
+    /// GUID of the disk.
+    pub fn disk_guid(&self) -> uuid::Uuid {
+        self.disk_guid
+    }
+
+    /// CRC32 of the partition array, as recorded in this header.
+    pub fn partition_array_crc32(&self) -> u32 {
+        self.crc32_parts
+    }
+
+    /// Set the LBA of this header.
+    ///
+    /// Like all setters, this marks the header CRC32 as stale until
+    /// `update_checksum()` is called, see `needs_checksum()`.
+    pub fn set_current_lba(&mut self, lba: u64) -> &mut Self {
+        self.current_lba = lba;
+        self.crc32_stale = true;
+        self
+    }
+
+    /// Set the LBA of the other header.
+    pub fn set_alternate_lba(&mut self, lba: u64) -> &mut Self {
+        self.backup_lba = lba;
+        self.crc32_stale = true;
+        self
+    }
+
+    /// Set the range of LBAs usable by partitions, failing if it is reversed.
+    pub fn set_usable_lbas(&mut self, first_lba: u64, last_lba: u64) -> Result<&mut Self> {
+        if first_lba > last_lba {
+            return Err(Error::Invalid(format!(
+                "usable range {} - {} is reversed",
+                first_lba, last_lba
+            )));
+        }
+        self.first_usable = first_lba;
+        self.last_usable = last_lba;
+        self.crc32_stale = true;
+        Ok(self)
+    }
+
+    /// Set the starting LBA of the partition array.
+    pub fn set_partition_entry_lba(&mut self, lba: u64) -> &mut Self {
+        self.part_start = lba;
+        self.crc32_stale = true;
+        self
+    }
+
+    /// Set the GUID of the disk.
+    pub fn set_disk_guid(&mut self, guid: uuid::Uuid) -> &mut Self {
+        self.disk_guid = guid;
+        self.crc32_stale = true;
+        self
+    }
+
+    /// Set the CRC32 of the partition array, e.g. from `calculate_crc32()`.
+    pub fn set_partition_array_crc32(&mut self, crc32: u32) -> &mut Self {
+        self.crc32_parts = crc32;
+        self.crc32_stale = true;
+        self
+    }
+
+    /// Whether the header CRC32 needs to be updated.
+    ///
+    /// This is the case once a setter was called since the last
+    /// `update_checksum()`. Fields changed directly are caught by
+    /// recomputing the checksum, which is only done if no setter was used.
+    pub fn needs_checksum(&self) -> Result<bool> {
+        Ok(self.crc32_stale || self.checksum()? != self.crc32)
+    }
+
+    /// Recompute the header CRC32 from the current fields.
+    pub fn update_checksum(&mut self) -> Result<&mut Self> {
+        self.crc32 = self.checksum()?;
+        self.crc32_stale = false;
+        Ok(self)
     }
 }
 
@@ -180,20 +592,114 @@ pub fn parse_uuid(rdr: &mut Cursor<&[u8]>) -> Result<uuid::Uuid> {
 
     match uuid {
         Ok(uuid) => Ok(uuid),
-        Err(_) => Err(Error::new(ErrorKind::Other, "Invalid Disk UUID?")),
+        Err(_) => Err(Error::Invalid("Invalid Disk UUID?".into())),
     }
 }
 
 impl fmt::Display for Header {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let crc_status = match self.checksum() {
+            Ok(c) if c == self.crc32 => "valid",
+            _ => "invalid",
+        };
         write!(
             f,
-            "Disk:\t\t{}\nCRC32:\t\t{}\nTable CRC:\t{}",
-            self.disk_guid, self.crc32, self.crc32_parts
+            "Disk GUID:\t\t{}\nRevision:\t\t{}.{}\nHeader LBA:\t\t{} (other copy at {})\n\
+             Usable LBAs:\t\t{} - {}\nPartition entries:\t{} x {} bytes, from LBA {}\n\
+             Header CRC32:\t\t{:#010x} ({})\nEntries CRC32:\t\t{:#010x}",
+            self.disk_guid,
+            self.revision >> 16,
+            self.revision & 0xFFFF,
+            self.current_lba,
+            self.backup_lba,
+            self.first_usable,
+            self.last_usable,
+            self.num_parts,
+            self.part_size,
+            self.part_start,
+            self.crc32,
+            crc_status,
+            self.crc32_parts
         )
     }
 }
 
+/// Groups of related header fields, used to report changes.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum FieldGroup {
+    /// Disk GUID.
+    DiskGuid,
+    /// Header and usable area locations (current, backup, first/last usable LBAs).
+    Layout,
+    /// Partition array geometry (starting LBA, number and size of entries).
+    PartitionArray,
+}
+
+/// Compare two versions of the same header.
+///
+/// Return the groups of fields which differ, ignoring checksums and
+/// fields which cannot be tweaked (signature, revision, size).
+pub(crate) fn changed_fields(old: &Header, new: &Header) -> Vec<FieldGroup> {
+    let mut changed = vec![];
+    if old.disk_guid != new.disk_guid {
+        changed.push(FieldGroup::DiskGuid);
+    }
+    if (
+        old.current_lba,
+        old.backup_lba,
+        old.first_usable,
+        old.last_usable,
+    ) != (
+        new.current_lba,
+        new.backup_lba,
+        new.first_usable,
+        new.last_usable,
+    ) {
+        changed.push(FieldGroup::Layout);
+    }
+    if (old.part_start, old.num_parts, old.part_size)
+        != (new.part_start, new.num_parts, new.part_size)
+    {
+        changed.push(FieldGroup::PartitionArray);
+    }
+    changed
+}
+
+/// Compare a primary header with its backup copy.
+///
+/// Return a description of each field which is not consistent
+/// between the two copies.
+pub(crate) fn compare_headers(primary: &Header, backup: &Header) -> Vec<String> {
+    let mut mismatches = vec![];
+    if primary.backup_lba != backup.current_lba {
+        mismatches.push(format!(
+            "backup header found at LBA {}, expected at LBA {}",
+            backup.current_lba, primary.backup_lba
+        ));
+    }
+    if backup.backup_lba != primary.current_lba {
+        mismatches.push(format!(
+            "backup header points to primary at LBA {}, found at LBA {}",
+            backup.backup_lba, primary.current_lba
+        ));
+    }
+    if primary.disk_guid != backup.disk_guid {
+        mismatches.push(format!(
+            "disk GUID mismatch: {} (primary) vs {} (backup)",
+            primary.disk_guid, backup.disk_guid
+        ));
+    }
+    if (primary.first_usable, primary.last_usable) != (backup.first_usable, backup.last_usable) {
+        mismatches.push("usable LBA range mismatch between headers".to_string());
+    }
+    if (primary.num_parts, primary.part_size, primary.crc32_parts)
+        != (backup.num_parts, backup.part_size, backup.crc32_parts)
+    {
+        mismatches.push("partition array mismatch between headers".to_string());
+    }
+    mismatches
+}
+
 /// Read a GPT header from a given path.
 ///
 /// ## Example
@@ -208,91 +714,228 @@ impl fmt::Display for Header {
 /// ```
 pub fn read_header(path: &Path, sector_size: disk::LogicalBlockSize) -> Result<Header> {
     let mut file = File::open(path)?;
-    read_primary_header(&mut file, sector_size)
+    read_primary_header(&mut file, sector_size, &mut QuirkTracker::default())
 }
 
-pub(crate) fn read_primary_header(
-    file: &mut File,
+/// Read and validate a GPT header at an arbitrary LBA of a device.
+///
+/// The signature and header CRC32 are checked like for headers read on
+/// open, but `current_lba` is not required to match `lba`, so that
+/// relocated copies can be found by scanning. The partition array is not
+/// read.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use gpt::header::read_header_at;
+///
+/// let lb_size = gpt::disk::DEFAULT_SECTOR_SIZE;
+/// let mut device = std::fs::File::open("/dev/sdz").unwrap();
+///
+/// let found: Vec<u64> = (0..2048)
+///     .filter(|lba| read_header_at(&mut device, *lba, lb_size).is_ok())
+///     .collect();
+/// ```
+pub fn read_header_at<D: Read + Seek>(
+    device: &mut D,
+    lba: u64,
     sector_size: disk::LogicalBlockSize,
 ) -> Result<Header> {
-    let cur = file.seek(SeekFrom::Current(0)).unwrap_or(0);
+    let offset = lba
+        .checked_mul(sector_size.into())
+        .ok_or(Error::Overflow("header offset"))?;
+    file_read_header(device, offset, &mut QuirkTracker::default())
+}
+
+pub(crate) fn read_primary_header<D: Read + Seek>(
+    file: &mut D,
+    sector_size: disk::LogicalBlockSize,
+    quirks: &mut QuirkTracker,
+) -> Result<Header> {
+    let cur = file.stream_position().unwrap_or(0);
     let offset: u64 = sector_size.into();
-    let res = file_read_header(file, offset);
+    let res = file_read_header(file, offset, quirks);
     let _ = file.seek(SeekFrom::Start(cur));
     res
 }
 
-pub(crate) fn read_backup_header(
-    file: &mut File,
+pub(crate) fn read_backup_header<D: disk::DiskDevice>(
+    file: &mut D,
+    disk_size: Option<u64>,
     sector_size: disk::LogicalBlockSize,
+    quirks: &mut QuirkTracker,
 ) -> Result<Header> {
-    let cur = file.seek(SeekFrom::Current(0)).unwrap_or(0);
-    let h2sect = find_backup_lba(file, sector_size)?;
+    let cur = file.stream_position().unwrap_or(0);
+    let h2sect = find_backup_lba(file, disk_size, sector_size)?;
     let offset = h2sect
         .checked_mul(sector_size.into())
-        .ok_or_else(|| Error::new(ErrorKind::Other, "backup header overflow - offset"))?;
-    let res = file_read_header(file, offset);
+        .ok_or(Error::Overflow("backup header offset"))?;
+    let res = file_read_header(file, offset, quirks);
     let _ = file.seek(SeekFrom::Start(cur));
     res
 }
 
-pub(crate) fn file_read_header(file: &mut File, offset: u64) -> Result<Header> {
-    let _ = file.seek(SeekFrom::Start(offset));
-    let mut hdr: [u8; 92] = [0; 92];
-
-    let _ = file.read_exact(&mut hdr);
-    let mut reader = Cursor::new(&hdr[..]);
+/// Fall back to the backup header location recorded in `primary`.
+///
+/// This is only attempted if `Quirks::MISPLACED_BACKUP` is tolerated,
+/// otherwise `err` (from reading the backup header at the end of the
+/// disk) is returned as-is.
+pub(crate) fn read_recorded_backup_header<D: Read + Seek>(
+    file: &mut D,
+    primary: &Header,
+    sector_size: disk::LogicalBlockSize,
+    quirks: &mut QuirkTracker,
+    err: Error,
+) -> Result<Header> {
+    if !quirks.allows(Quirks::MISPLACED_BACKUP) {
+        return Err(err);
+    }
+    let offset = match primary.backup_lba.checked_mul(sector_size.into()) {
+        Some(offset) => offset,
+        None => return Err(err),
+    };
+    let cur = file.stream_position().unwrap_or(0);
+    let res = file_read_header(file, offset, quirks);
+    let _ = file.seek(SeekFrom::Start(cur));
+    match res {
+        Ok(h) if h.current_lba == primary.backup_lba => {
+            let what = format!("backup header at LBA {}", h.current_lba);
+            quirks.tolerate(Quirks::MISPLACED_BACKUP, &what);
+            Ok(h)
+        }
+        _ => Err(err),
+    }
+}
 
-    let sigstr = String::from_utf8_lossy(
-        &reader.get_ref()[reader.position() as usize..reader.position() as usize + 8],
-    );
-    reader.seek(SeekFrom::Current(8))?;
+/// Return the primary partition array LBA recorded in a damaged primary header.
+///
+/// The header in LBA1 is parsed without checking its checksum, and its
+/// array location is only trusted if it has a GPT signature and fits the
+/// geometry of `backup`. Otherwise the standard location, LBA 2, is returned.
+pub(crate) fn read_damaged_primary_array<D: Read + Seek>(
+    file: &mut D,
+    backup: &Header,
+    sector_size: disk::LogicalBlockSize,
+) -> u64 {
+    let cur = file.stream_position().unwrap_or(0);
+    let res = file_read_header_unchecked(file, sector_size.into());
+    let _ = file.seek(SeekFrom::Start(cur));
+    match res {
+        Ok((h, _)) if h.signature == "EFI PART" && h.part_start != 2 => {
+            let mut geometry = TableGeometry::from_headers(backup, backup);
+            geometry.primary_array = h.part_start;
+            if geometry.validate(backup.current_lba, sector_size).is_ok() {
+                debug!(
+                    "damaged primary header records array at LBA {}",
+                    h.part_start
+                );
+                return h.part_start;
+            }
+            2
+        }
+        _ => 2,
+    }
+}
 
-    if sigstr != "EFI PART" {
-        return Err(Error::new(ErrorKind::Other, "invalid GPT signature"));
-    };
+/// Read the raw header CRC32 field at `lba`, without parsing or validating the header.
+pub(crate) fn read_raw_crc32<D: Read + Seek>(
+    file: &mut D,
+    lba: u64,
+    sector_size: disk::LogicalBlockSize,
+) -> Result<u32> {
+    let offset = lba
+        .checked_mul(sector_size.into())
+        .and_then(|o| o.checked_add(16))
+        .ok_or(Error::Overflow("header checksum offset"))?;
+    file.seek(SeekFrom::Start(offset))?;
+    Ok(file.read_u32::<LittleEndian>()?)
+}
 
-    let h = Header {
-        signature: sigstr.to_string(),
-        revision: reader.read_u32::<LittleEndian>()?,
-        header_size_le: reader.read_u32::<LittleEndian>()?,
-        crc32: reader.read_u32::<LittleEndian>()?,
-        reserved: reader.read_u32::<LittleEndian>()?,
-        current_lba: reader.read_u64::<LittleEndian>()?,
-        backup_lba: reader.read_u64::<LittleEndian>()?,
-        first_usable: reader.read_u64::<LittleEndian>()?,
-        last_usable: reader.read_u64::<LittleEndian>()?,
-        disk_guid: parse_uuid(&mut reader)?,
-        part_start: reader.read_u64::<LittleEndian>()?,
-        num_parts: reader.read_u32::<LittleEndian>()?,
-        part_size: reader.read_u32::<LittleEndian>()?,
-        crc32_parts: reader.read_u32::<LittleEndian>()?,
+pub(crate) fn file_read_header<D: Read + Seek>(
+    file: &mut D,
+    offset: u64,
+    quirks: &mut QuirkTracker,
+) -> Result<Header> {
+    let (h, hdr) = file_read_header_unchecked(file, offset)?;
+    if h.signature != "EFI PART" {
+        return Err(Error::InvalidSignature);
     };
 
     let mut hdr_crc = hdr;
     for crc_byte in hdr_crc.iter_mut().skip(16).take(4) {
         *crc_byte = 0;
     }
-    let c = crc32::checksum_ieee(&hdr_crc);
+    let c = calculate_crc32(&hdr_crc);
     trace!("header CRC32: {:#x} - computed CRC32: {:#x}", h.crc32, c);
-    if crc32::checksum_ieee(&hdr_crc) == h.crc32 {
+    // Some tools never fill in the header checksum.
+    let zero_crc = h.crc32 == 0;
+    if c == h.crc32 || (zero_crc && quirks.tolerate(Quirks::ZERO_HEADER_CRC, "zero header CRC32")) {
         Ok(h)
     } else {
-        Err(Error::new(ErrorKind::Other, "invalid CRC32 checksum"))
+        Err(Error::HeaderCrcMismatch {
+            expected: h.crc32,
+            found: c,
+        })
     }
 }
 
-pub(crate) fn find_backup_lba(f: &mut File, sector_size: disk::LogicalBlockSize) -> Result<u64> {
-    trace!("querying file size to find backup header location");
+/// Parse the header at `offset`, without checking its signature or checksum.
+///
+/// The raw header bytes are returned too.
+pub(crate) fn file_read_header_unchecked<D: Read + Seek>(
+    file: &mut D,
+    offset: u64,
+) -> Result<(Header, [u8; 92])> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut hdr: [u8; 92] = [0; 92];
+
+    file.read_exact(&mut hdr)?;
+    let raw = RawHeader::parse(&hdr)?;
+    Ok((Header::from(raw), hdr))
+}
+
+impl From<RawHeader> for Header {
+    fn from(raw: RawHeader) -> Self {
+        Header {
+            signature: String::from_utf8_lossy(&raw.signature).to_string(),
+            revision: raw.revision,
+            header_size_le: raw.header_size,
+            crc32: raw.crc32,
+            reserved: raw.reserved,
+            current_lba: raw.current_lba,
+            backup_lba: raw.backup_lba,
+            first_usable: raw.first_usable,
+            last_usable: raw.last_usable,
+            disk_guid: raw.disk_guid,
+            part_start: raw.part_start,
+            num_parts: raw.num_parts,
+            part_size: raw.part_size,
+            crc32_parts: raw.crc32_parts,
+            crc32_stale: false,
+        }
+    }
+}
+
+/// Find the backup header LBA of a disk, of `disk_size` bytes if set.
+///
+/// Otherwise, the current size of the file is used.
+pub(crate) fn find_backup_lba<D: disk::DiskDevice + ?Sized>(
+    f: &D,
+    disk_size: Option<u64>,
+    sector_size: disk::LogicalBlockSize,
+) -> Result<u64> {
     let lb_size: u64 = sector_size.into();
-    let m = f.metadata()?;
-    if m.len() <= lb_size {
-        return Err(Error::new(
-            ErrorKind::Other,
-            "disk image too small for backup header",
-        ));
+    let len = match disk_size {
+        Some(len) => len,
+        None => {
+            trace!("querying file size to find backup header location");
+            f.size()?
+        }
+    };
+    if len <= lb_size {
+        return Err(Error::TooShort);
     }
-    let bak_offset = m.len().saturating_sub(lb_size);
+    let bak_offset = len.saturating_sub(lb_size);
     let bak_lba = bak_offset / lb_size;
     trace!(
         "backup header: LBA={}, bytes offset={}",
@@ -303,36 +946,42 @@ pub(crate) fn find_backup_lba(f: &mut File, sector_size: disk::LogicalBlockSize)
     Ok(bak_lba)
 }
 
-fn calculate_crc32(b: &[u8]) -> Result<u32> {
-    let mut digest = crc32::Digest::new(crc32::IEEE);
-    trace!("Writing buffer to digest calculator");
-    digest.write(b);
+/// Largest partition array read from a device, to survive corrupted headers.
+pub(crate) const MAX_ARRAY_LEN: u64 = 4 * 1024 * 1024;
 
-    Ok(digest.sum32())
+/// Compute the CRC32 (IEEE) of a buffer.
+///
+/// This uses a SIMD/hardware-accelerated implementation when the CPU
+/// supports it, falling back to a table-driven one otherwise.
+pub(crate) fn calculate_crc32(b: &[u8]) -> u32 {
+    trace!("computing CRC32 over {} bytes", b.len());
+    crc32fast::hash(b)
 }
 
-pub(crate) fn partentry_checksum(
-    file: &mut File,
+pub(crate) fn partentry_checksum<D: Read + Seek>(
+    file: &mut D,
     hdr: &Header,
     lb_size: disk::LogicalBlockSize,
 ) -> Result<u32> {
     // Seek to start of partition table.
-    let start = hdr.part_start
+    let start = hdr
+        .part_start
         .checked_mul(lb_size.into())
-        .ok_or_else(|| Error::new(ErrorKind::Other, "header overflow - partition table start"))?;
+        .ok_or(Error::Overflow("header partition table start"))?;
     let _ = file.seek(SeekFrom::Start(start))?;
 
     // Read partition table.
     let pt_len = u64::from(hdr.num_parts)
         .checked_mul(hdr.part_size.into())
-        .ok_or_else(|| Error::new(ErrorKind::Other, "partition table - size"))?;
+        .ok_or(Error::Overflow("partition array size"))?;
+    if pt_len > MAX_ARRAY_LEN {
+        return Err(Error::Invalid("partition array too large".into()));
+    }
     let mut buf = vec![0; pt_len as usize];
     file.read_exact(&mut buf)?;
 
     // Compute CRC32 over all table bits.
-    let mut digest = crc32::Digest::new(crc32::IEEE);
-    digest.write(&buf);
-    Ok(digest.sum32())
+    Ok(calculate_crc32(&buf))
 }
 
 /// A helper function to create a new header and write it to disk.
@@ -347,7 +996,7 @@ pub fn write_header(
 ) -> Result<uuid::Uuid> {
     debug!("opening {} for writing", p.display());
     let mut file = OpenOptions::new().write(true).read(true).open(p)?;
-    let bak = find_backup_lba(&mut file, sector_size)?;
+    let bak = find_backup_lba(&file, None, sector_size)?;
     let guid = match uuid {
         Some(u) => u,
         None => {
@@ -357,9 +1006,177 @@ pub fn write_header(
         }
     };
 
-    let hdr = Header::compute_new(true, &[], guid, bak)?;
+    let hdr = Header::compute_new(true, &BTreeMap::new(), guid, bak, sector_size)?;
     debug!("new header: {:#?}", hdr);
     hdr.write_primary(&mut file, sector_size)?;
 
     Ok(guid)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::disk::LogicalBlockSize;
+    use crate::header::{
+        calculate_crc32, changed_fields, compare_headers, FieldGroup, Header, TableGeometry,
+    };
+    use std::collections::BTreeMap;
+    use std::convert::TryFrom;
+
+    const LB: LogicalBlockSize = LogicalBlockSize::Lb512;
+
+    #[test]
+    fn test_calculate_crc32() {
+        assert_eq!(calculate_crc32(b""), 0);
+        assert_eq!(calculate_crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(calculate_crc32(&[0u8; 16384]), 0xAB54_D286);
+    }
+
+    #[test]
+    fn test_header_bytes_roundtrip() {
+        let lb_size = crate::disk::LogicalBlockSize::Lb512;
+        let diskpath = "tests/fixtures/gpt-linux-disk-01.img";
+        let mut file = std::fs::File::open(diskpath).unwrap();
+        let h = crate::header::read_header_at(&mut file, 1, lb_size).unwrap();
+        let (offset, bytes) = h.exact_bytes(lb_size).unwrap();
+        let raw = std::fs::read(diskpath).unwrap();
+        // The disk GUID is stored mixed-endian, like all GUIDs on disk.
+        assert_eq!(bytes[56..72], raw[512 + 56..512 + 72]);
+        assert_eq!(
+            bytes[..],
+            raw[offset as usize..offset as usize + bytes.len()]
+        );
+    }
+
+    #[test]
+    fn test_header_display() {
+        let guid = uuid::Uuid::nil();
+        let mut h = Header::compute_new(true, &BTreeMap::new(), guid, 95, LB).unwrap();
+        let out = h.to_string();
+        assert!(out.contains("Revision:\t\t1.0\n"));
+        assert!(out.contains("Usable LBAs:\t\t34 - 62\n"));
+        assert!(out.contains("128 x 128 bytes, from LBA 2"));
+        assert!(out.contains("(invalid)"));
+
+        h.crc32 = h.checksum().unwrap();
+        assert!(h.to_string().contains("(valid)"));
+    }
+
+    #[test]
+    fn test_header_setters() {
+        let guid = uuid::Uuid::new_v4();
+        let mut h = Header::compute_new(true, &BTreeMap::new(), guid, 95, LB).unwrap();
+        h.update_checksum().unwrap();
+        assert!(!h.needs_checksum().unwrap());
+        assert_eq!((h.first_usable_lba(), h.last_usable_lba()), (34, 62));
+        assert_eq!((h.current_lba(), h.alternate_lba()), (1, 95));
+
+        h.set_usable_lbas(40, 60).unwrap().set_alternate_lba(96);
+        h.set_usable_lbas(61, 60).unwrap_err();
+        assert_eq!((h.first_usable_lba(), h.last_usable_lba()), (40, 60));
+        assert!(h.crc32_stale);
+        assert!(h.needs_checksum().unwrap());
+        assert!(!h.is_valid(1));
+        h.update_checksum().unwrap();
+        assert!(!h.crc32_stale);
+        assert!(h.is_valid(1));
+
+        // Setting a field back to its value still marks the CRC32 as stale.
+        let guid = h.disk_guid();
+        h.set_disk_guid(guid);
+        assert!(h.needs_checksum().unwrap());
+        assert!(h.is_valid(1));
+    }
+
+    #[test]
+    fn test_partentry_checksum_too_large() {
+        let guid = uuid::Uuid::new_v4();
+        let mut h = Header::compute_new(true, &BTreeMap::new(), guid, 95, LB).unwrap();
+        let mut device = std::io::Cursor::new(vec![0u8; 95 * 512]);
+        assert_eq!(
+            super::partentry_checksum(&mut device, &h, LB).unwrap(),
+            calculate_crc32(&[0u8; 128 * 128])
+        );
+
+        // Bogus headers are refused before allocating the array.
+        h.num_parts = u32::MAX;
+        assert!(matches!(
+            super::partentry_checksum(&mut device, &h, LB),
+            Err(crate::Error::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_changed_fields() {
+        let pp = BTreeMap::new();
+        let guid = uuid::Uuid::new_v4();
+        let h1 = Header::compute_new(true, &pp, guid, 95, LB).unwrap();
+        assert!(changed_fields(&h1, &h1).is_empty());
+
+        let mut h2 = h1.clone();
+        h2.crc32 = 42;
+        h2.num_parts = 256;
+        h2.disk_guid = uuid::Uuid::new_v4();
+        assert_eq!(
+            changed_fields(&h1, &h2),
+            vec![FieldGroup::DiskGuid, FieldGroup::PartitionArray]
+        );
+    }
+
+    #[test]
+    fn test_table_geometry() {
+        let lb = LB;
+        let mut geo = TableGeometry::standard(128, 4095, lb).unwrap();
+        assert!(geo.has_standard_arrays(4095, lb));
+        assert_eq!((geo.first_usable, geo.last_usable), (34, 4062));
+        assert_eq!(geo.backup_array, 4063);
+        assert_eq!(geo.array_lbas(lb).unwrap(), 32);
+        assert_eq!(geo.usable_lbas(), 4029);
+        geo.validate(4095, lb).unwrap();
+        geo.validate(4000, lb).unwrap_err();
+
+        geo.relocate_arrays(1024, 3000, 4095, lb).unwrap();
+        assert_eq!((geo.first_usable, geo.last_usable), (1056, 2999));
+        assert!(geo.is_usable(1056, 2999) && !geo.is_usable(1000, 1100));
+        assert!(!geo.has_standard_arrays(4095, lb));
+        geo.validate(4095, lb).unwrap();
+        let h1 = Header::from_geometry(true, &geo, uuid::Uuid::nil(), 4095);
+        let h2 = Header::from_geometry(false, &geo, uuid::Uuid::nil(), 4095);
+        assert_eq!((h1.part_start, h2.part_start), (1024, 3000));
+        assert_eq!(TableGeometry::from_headers(&h1, &h2), geo);
+
+        geo.relocate_arrays(1, 3000, 4095, lb).unwrap_err();
+        geo.relocate_arrays(1024, 4080, 4095, lb).unwrap_err();
+        geo.relocate_arrays(1024, 1040, 4095, lb).unwrap_err();
+        geo.part_size = 192;
+        geo.validate(4095, lb).unwrap_err();
+
+        // Arrays shrink to 4 LBAs on 4Kn disks, and span partial LBAs otherwise.
+        let geo = TableGeometry::standard(128, 4095, LogicalBlockSize::Lb4096).unwrap();
+        assert_eq!((geo.first_usable, geo.last_usable), (6, 4090));
+        assert!(geo.has_standard_arrays(4095, LogicalBlockSize::Lb4096));
+        assert!(!geo.has_standard_arrays(4095, lb));
+        geo.validate(4095, LogicalBlockSize::Lb4096).unwrap();
+        let geo =
+            TableGeometry::standard(128, 4095, LogicalBlockSize::try_from(520).unwrap()).unwrap();
+        assert_eq!((geo.first_usable, geo.backup_array), (34, 4063));
+        TableGeometry::standard(128, 4, LogicalBlockSize::Lb4096).unwrap_err();
+
+        let geo = TableGeometry::with_entries(256, 128, 4095, lb).unwrap();
+        assert_eq!((geo.first_usable, geo.backup_array), (66, 4031));
+        let geo = TableGeometry::with_entries(1024, 256, 65535, lb).unwrap();
+        assert_eq!((geo.first_usable, geo.backup_array), (514, 65023));
+        geo.validate(65535, lb).unwrap();
+    }
+
+    #[test]
+    fn test_compare_headers() {
+        let guid = uuid::Uuid::new_v4();
+        let h1 = Header::compute_new(true, &BTreeMap::new(), guid, 95, LB).unwrap();
+        let mut h2 = Header::compute_new(false, &BTreeMap::new(), guid, 95, LB).unwrap();
+        assert!(compare_headers(&h1, &h2).is_empty());
+
+        h2.current_lba = 94;
+        h2.disk_guid = uuid::Uuid::new_v4();
+        assert_eq!(compare_headers(&h1, &h2).len(), 2);
+    }
+}
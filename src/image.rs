@@ -0,0 +1,40 @@
+//! Disk image files.
+//!
+//! ```rust,no_run
+//! let lb_size = gpt::disk::DEFAULT_SECTOR_SIZE;
+//! let path = std::path::Path::new("disk.img");
+//! let mut gdisk = gpt::image::create_sparse(path, 64 * 1024 * 1024, lb_size).unwrap();
+//! let esp = gpt::partition_types::Type::from_name("EFI System").unwrap();
+//! gdisk.add_partition("esp", 1024 * 1024, esp, 0).unwrap();
+//! gdisk.write().unwrap();
+//! ```
+
+use log::*;
+use std::fs;
+use std::path::Path;
+
+use crate::disk::LogicalBlockSize;
+use crate::{Error, GptDisk, Result};
+
+/// Create a sparse image file of `size` bytes at `path`, with a blank GPT, and open it.
+///
+/// The file must not exist yet. It is extended with `set_len()`, so that
+/// only the blocks holding the protective MBR, headers and partition
+/// arrays are allocated on filesystems supporting sparse files. The
+/// returned disk is writable, see `mkgpt()`.
+pub fn create_sparse(path: &Path, size: u64, lb_size: LogicalBlockSize) -> Result<GptDisk> {
+    if size % lb_size.bytes() != 0 {
+        return Err(Error::Unaligned {
+            value: size,
+            alignment: lb_size.bytes(),
+        });
+    }
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    debug!("creating {} bytes sparse image {}", size, path.display());
+    file.set_len(size)?;
+    drop(file);
+    crate::mkgpt(path, lb_size)
+}
@@ -0,0 +1,420 @@
+//! Declarative partition layouts.
+//!
+//! A `Layout` lists partitions in disk order, with sizes either in bytes
+//! (e.g. "512MiB") or as a share of the space left by fixed-size ones
+//! (e.g. "100%"), in the spirit of `sfdisk` scripts and `systemd-repart`
+//! definitions. With the `serde` feature, layouts can be loaded from
+//! JSON, TOML, etc.
+//!
+//! ```rust
+//! use gpt::layout::{Layout, PartitionSpec};
+//!
+//! let layout = Layout {
+//!     disk_guid: None,
+//!     partitions: vec![
+//!         PartitionSpec::new("esp", "512MiB".parse().unwrap(), "EFI System"),
+//!         PartitionSpec::new("root", "100%".parse().unwrap(), "Linux Filesystem Data"),
+//!     ],
+//! };
+//! let lb_size = gpt::disk::DEFAULT_SECTOR_SIZE;
+//! let mut gdisk = gpt::GptDisk::new_in_memory(4 * 1024 * 1024 * 1024, lb_size).unwrap();
+//! let handles = layout.apply(&mut gdisk).unwrap();
+//! assert_eq!(gdisk.partitions()[&handles[1].id()].name, "root");
+//! ```
+//!
+//! `Layout::stage()` lays out the partitions in memory only, for further
+//! changes before writing the table.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::partition::{PartitionHandle, PartitionType};
+use crate::partition_types::Type;
+use crate::{disk, Error, GptDisk, Result};
+
+/// Binary unit suffixes accepted in sizes, with their multiplier.
+const UNITS: &[(&str, u64)] = &[
+    ("TiB", 1 << 40),
+    ("GiB", 1 << 30),
+    ("MiB", 1 << 20),
+    ("KiB", 1 << 10),
+    ("T", 1 << 40),
+    ("G", 1 << 30),
+    ("M", 1 << 20),
+    ("K", 1 << 10),
+    ("B", 1),
+];
+
+/// Size of a partition in a layout.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(::serde::Serialize, ::serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub enum Size {
+    /// Fixed size in bytes, rounded up to whole logical blocks.
+    Bytes(u64),
+    /// Percentage (1 to 100) of the space left by fixed-size partitions.
+    Percent(u8),
+}
+
+impl FromStr for Size {
+    type Err = Error;
+
+    /// Parse a size such as "4096", "512MiB", "2G" or "100%".
+    ///
+    /// Units are binary, i.e. "1M" and "1MiB" are both 1048576 bytes.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let invalid = || Error::Invalid(format!("invalid size: {:?}", s));
+        if let Some(pct) = s.strip_suffix('%') {
+            let pct = pct.trim().parse::<u8>().map_err(|_| invalid())?;
+            if pct == 0 || pct > 100 {
+                return Err(invalid());
+            }
+            return Ok(Size::Percent(pct));
+        }
+        let (digits, mult) = UNITS
+            .iter()
+            .find_map(|(unit, mult)| s.strip_suffix(unit).map(|digits| (digits, *mult)))
+            .unwrap_or((s, 1));
+        digits
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(mult))
+            .filter(|bytes| *bytes > 0)
+            .map(Size::Bytes)
+            .ok_or_else(invalid)
+    }
+}
+
+impl TryFrom<String> for Size {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Size::Percent(pct) => write!(f, "{}%", pct),
+            Size::Bytes(bytes) => {
+                let unit = UNITS
+                    .iter()
+                    .take(4)
+                    .find(|(_, mult)| bytes % mult == 0)
+                    .map(|(unit, mult)| (*unit, bytes / mult));
+                match unit {
+                    Some((unit, n)) => write!(f, "{}{}", n, unit),
+                    None => write!(f, "{}", bytes),
+                }
+            }
+        }
+    }
+}
+
+impl From<Size> for String {
+    fn from(size: Size) -> String {
+        size.to_string()
+    }
+}
+
+/// Specification of a single partition in a layout.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PartitionSpec {
+    /// Partition name.
+    pub name: String,
+    /// Partition size.
+    pub size: Size,
+    /// Partition type, as a well-known name (see `Type::from_name()`) or a GUID.
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub part_type: String,
+    /// Unique GUID of the partition, random if unset.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub guid: Option<uuid::Uuid>,
+    /// Partition attribute flags.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub flags: u64,
+}
+
+impl PartitionSpec {
+    /// Create a specification with a random GUID and no flags.
+    pub fn new(name: &str, size: Size, part_type: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            size,
+            part_type: part_type.to_string(),
+            guid: None,
+            flags: 0,
+        }
+    }
+
+    /// Resolve the partition type, by name or GUID.
+    fn partition_type(&self) -> Result<PartitionType> {
+        if let Some(t) = Type::from_name(&self.part_type) {
+            return Ok(t.into());
+        }
+        uuid::Uuid::parse_str(&self.part_type)
+            .map(PartitionType::from_guid)
+            .map_err(|_| Error::Invalid(format!("unknown partition type {:?}", self.part_type)))
+    }
+}
+
+/// Partition layout of a whole disk.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Layout {
+    /// Disk GUID, kept as-is if unset.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub disk_guid: Option<uuid::Uuid>,
+    /// Partitions, in disk order.
+    pub partitions: Vec<PartitionSpec>,
+}
+
+impl Layout {
+    /// Lay out the partitions of this layout on `disk`, and write the table.
+    ///
+    /// This is `stage()` followed by `GptDisk::write_inplace()`. If the
+    /// write fails, the partitions are left staged on `disk`.
+    pub fn apply<D: disk::DiskDevice>(
+        &self,
+        disk: &mut GptDisk<D>,
+    ) -> Result<Vec<PartitionHandle>> {
+        let handles = self.stage(disk)?;
+        disk.write_inplace()?;
+        Ok(handles)
+    }
+
+    /// Stage the partitions of this layout on `disk`, which must have none.
+    ///
+    /// Partitions are placed back to back in order, each one starting at
+    /// the disk alignment (see `GptDisk::alignment()`). Percentages are
+    /// shares of what fixed-size partitions leave of the usable area,
+    /// and must add up to at most 100; a last partition of a layout whose
+    /// percentages add up to exactly 100 extends to the end of the usable
+    /// area. Handles of the new partitions are returned in order.
+    ///
+    /// All ranges are computed before `disk` is changed, and on error the
+    /// disk is left without partitions and with its GUID. This only
+    /// stages the table: no changes are recorded to disk until
+    /// `GptDisk::write()` is called, unlike with `apply()`.
+    pub fn stage<D: disk::DiskDevice>(
+        &self,
+        disk: &mut GptDisk<D>,
+    ) -> Result<Vec<PartitionHandle>> {
+        if !disk.partitions.is_empty() {
+            return Err(Error::Invalid(
+                "layouts can only be applied to disks without partitions".into(),
+            ));
+        }
+        let types = self
+            .partitions
+            .iter()
+            .map(PartitionSpec::partition_type)
+            .collect::<Result<Vec<_>>>()?;
+        let percent: u32 = self
+            .partitions
+            .iter()
+            .filter_map(|p| match p.size {
+                Size::Percent(pct) => Some(u32::from(pct)),
+                Size::Bytes(_) => None,
+            })
+            .sum();
+        if percent > 100 {
+            return Err(Error::Invalid("layout percentages exceed 100%".into()));
+        }
+
+        let align = disk.alignment.max(1);
+        let align_up = |lba: u64| lba.div_ceil(align) * align;
+        let geometry = disk.allocation_geometry()?;
+        let end = geometry.last_usable + 1;
+        let mut fixed = 0u64;
+        for p in &self.partitions {
+            if let Size::Bytes(bytes) = p.size {
+                fixed += align_up(disk.size_to_sectors(bytes)?);
+            }
+        }
+        let remaining = end
+            .checked_sub(align_up(geometry.first_usable))
+            .and_then(|usable| usable.checked_sub(fixed))
+            .ok_or(Error::NoSpace)?;
+
+        // Compute all ranges before touching the disk.
+        let mut cursor = geometry.first_usable;
+        let mut ranges = Vec::with_capacity(self.partitions.len());
+        for (idx, spec) in self.partitions.iter().enumerate() {
+            let first_lba = align_up(cursor);
+            let is_last = idx + 1 == self.partitions.len();
+            let sectors = match spec.size {
+                Size::Bytes(bytes) => disk.size_to_sectors(bytes)?,
+                Size::Percent(_) if is_last && percent == 100 => end.saturating_sub(first_lba),
+                Size::Percent(pct) => remaining * u64::from(pct) / 100 / align * align,
+            };
+            if sectors == 0 || first_lba + sectors > end {
+                return Err(Error::NoSpace);
+            }
+            ranges.push((first_lba, sectors));
+            cursor = first_lba + sectors;
+        }
+
+        let initialized = disk.config.initialized;
+        let res = self.place_all(disk, ranges, types);
+        match res {
+            Ok(_) => {
+                if let Some(guid) = self.disk_guid {
+                    disk.update_guid(Some(guid))?;
+                }
+            }
+            Err(_) => {
+                disk.partitions.clear();
+                disk.free_space = None;
+                disk.checksums.invalidate_entries();
+                disk.config.initialized = initialized;
+            }
+        }
+        res
+    }
+
+    /// Insert the partitions of this layout at the given `(first_lba, sectors)` ranges.
+    fn place_all<D: disk::DiskDevice>(
+        &self,
+        disk: &mut GptDisk<D>,
+        ranges: Vec<(u64, u64)>,
+        types: Vec<PartitionType>,
+    ) -> Result<Vec<PartitionHandle>> {
+        let mut handles = Vec::with_capacity(self.partitions.len());
+        for ((spec, part_type), (first_lba, sectors)) in
+            self.partitions.iter().zip(types).zip(ranges)
+        {
+            let mut handle =
+                disk.place_partition(&spec.name, first_lba, sectors, part_type, spec.flags)?;
+            if let Some(guid) = spec.guid {
+                if let Some(p) = disk.partitions.get_mut(&handle.id) {
+                    p.part_guid = guid;
+                }
+                handle.guid = guid;
+            }
+            handles.push(handle);
+        }
+        Ok(handles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layout::{Layout, PartitionSpec, Size};
+
+    #[test]
+    fn test_size_parse() {
+        assert_eq!("4096".parse::<Size>().unwrap(), Size::Bytes(4096));
+        assert_eq!("512MiB".parse::<Size>().unwrap(), Size::Bytes(512 << 20));
+        assert_eq!(" 2 G ".parse::<Size>().unwrap(), Size::Bytes(2 << 30));
+        assert_eq!("100%".parse::<Size>().unwrap(), Size::Percent(100));
+        for bad in &["", "0", "1.5G", "12QiB", "0%", "101%", "99999999999T"] {
+            bad.parse::<Size>().unwrap_err();
+        }
+        assert_eq!(Size::Bytes(512 << 20).to_string(), "512MiB");
+        assert_eq!(Size::Bytes(1000).to_string(), "1000");
+        assert_eq!(Size::Percent(50).to_string(), "50%");
+    }
+
+    #[test]
+    fn test_layout_apply() {
+        let lb_size = crate::disk::DEFAULT_SECTOR_SIZE;
+        let mut gdisk = crate::GptDisk::new_in_memory(64 << 20, lb_size).unwrap();
+        let guid = uuid::Uuid::new_v4();
+        let mut home = PartitionSpec::new("home", Size::Percent(100), "Linux Filesystem Data");
+        home.guid = Some(guid);
+        let layout = Layout {
+            disk_guid: Some(uuid::Uuid::nil()),
+            partitions: vec![
+                PartitionSpec::new("esp", Size::Bytes(8 << 20), "EFI System Partition"),
+                PartitionSpec::new(
+                    "swap",
+                    Size::Percent(50),
+                    "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F",
+                ),
+                home,
+            ],
+        };
+        layout.apply(&mut gdisk).unwrap_err();
+
+        let layout = Layout {
+            partitions: vec![
+                layout.partitions[0].clone(),
+                layout.partitions[1].clone(),
+                PartitionSpec {
+                    size: Size::Percent(50),
+                    ..layout.partitions[2].clone()
+                },
+            ],
+            ..layout
+        };
+        let handles = layout.stage(&mut gdisk).unwrap();
+        assert_eq!(gdisk.guid(), &uuid::Uuid::nil());
+        assert_eq!(gdisk.changed_partitions().len(), 3);
+        let pp: Vec<_> = handles
+            .iter()
+            .map(|h| &gdisk.partitions()[&h.id()])
+            .collect();
+        assert_eq!((pp[0].first_lba, pp[0].last_lba), (2048, 18431));
+        assert_eq!(pp[1].first_lba, 18432);
+        assert_eq!(pp[1].part_type_guid.description, "Swap Partition");
+        assert_eq!(pp[2].first_lba, pp[1].last_lba + 1);
+        assert_eq!(pp[2].last_lba, gdisk.geometry().unwrap().last_usable);
+        assert_eq!(pp[2].part_guid, guid);
+
+        // Only blank disks are laid out.
+        layout.apply(&mut gdisk).unwrap_err();
+
+        // Applying writes the table right away.
+        let mut gdisk = crate::GptDisk::new_in_memory(64 << 20, lb_size).unwrap();
+        assert!(gdisk.primary_header().is_none());
+        layout.apply(&mut gdisk).unwrap();
+        assert!(gdisk.changed_partitions().is_empty());
+        assert_eq!(gdisk.primary_header().unwrap().disk_guid, uuid::Uuid::nil());
+    }
+
+    #[test]
+    fn test_layout_apply_error() {
+        let lb_size = crate::disk::DEFAULT_SECTOR_SIZE;
+        let mut gdisk = crate::GptDisk::new_in_memory(64 << 20, lb_size).unwrap();
+        let guid = *gdisk.guid();
+        // The second name is too long, after the first partition fits.
+        let layout = Layout {
+            disk_guid: Some(uuid::Uuid::nil()),
+            partitions: vec![
+                PartitionSpec::new("esp", Size::Bytes(8 << 20), "EFI System Partition"),
+                PartitionSpec::new(&"x".repeat(37), Size::Percent(100), "Linux Filesystem Data"),
+            ],
+        };
+        layout.apply(&mut gdisk).unwrap_err();
+        assert!(gdisk.partitions().is_empty());
+        assert_eq!(gdisk.guid(), &guid);
+        assert_eq!(gdisk.find_free_sectors().unwrap().len(), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_layout_serde() {
+        let json = r#"{
+            "partitions": [
+                {"name": "esp", "size": "512MiB", "type": "EFI System"},
+                {"name": "root", "size": "100%", "type": "Linux Filesystem Data", "flags": 4}
+            ]
+        }"#;
+        let layout: Layout = serde_json::from_str(json).unwrap();
+        assert_eq!(layout.disk_guid, None);
+        assert_eq!(layout.partitions[0].size, Size::Bytes(512 << 20));
+        assert_eq!(layout.partitions[1].flags, 4);
+        let back: Layout = serde_json::from_str(&serde_json::to_string(&layout).unwrap()).unwrap();
+        assert_eq!(back, layout);
+        serde_json::from_str::<Size>(r#""12%%""#).unwrap_err();
+    }
+}
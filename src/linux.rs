@@ -0,0 +1,180 @@
+//! Linux block device integration, through ioctls.
+//!
+//! These helpers query block devices for their real size and logical
+//! block size, and make the kernel pick up a rewritten partition table
+//! without having to run `partprobe`. They fail with `ENOTTY` when
+//! used on regular files.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::AsRawFd;
+
+use crate::disk;
+use crate::partition::Partition;
+
+const BLKRRPART: libc::Ioctl = libc::_IO(0x12, 95);
+const BLKPG: libc::Ioctl = libc::_IO(0x12, 105);
+const BLKGETSIZE64: libc::Ioctl = libc::_IOR::<libc::size_t>(0x12, 114);
+const BLKDISCARD: libc::Ioctl = libc::_IO(0x12, 119);
+
+const BLKPG_ADD_PARTITION: libc::c_int = 1;
+const BLKPG_DEL_PARTITION: libc::c_int = 2;
+
+/// `struct blkpg_partition` from `linux/blkpg.h`.
+#[repr(C)]
+struct BlkpgPartition {
+    start: i64,
+    length: i64,
+    pno: libc::c_int,
+    devname: [libc::c_char; 64],
+    volname: [libc::c_char; 64],
+}
+
+/// `struct blkpg_ioctl_arg` from `linux/blkpg.h`.
+#[repr(C)]
+struct BlkpgIoctlArg {
+    op: libc::c_int,
+    flags: libc::c_int,
+    datalen: libc::c_int,
+    data: *mut libc::c_void,
+}
+
+/// Whether `file` is a block device.
+pub fn is_block_device(file: &fs::File) -> bool {
+    file.metadata()
+        .map(|meta| meta.file_type().is_block_device())
+        .unwrap_or(false)
+}
+
+/// Return the size (in bytes) of a block device (`BLKGETSIZE64`).
+pub fn device_size(file: &fs::File) -> io::Result<u64> {
+    let mut size: u64 = 0;
+    // SAFETY: BLKGETSIZE64 stores a u64 at the given address.
+    unsafe { ioctl(file, BLKGETSIZE64, &mut size) }?;
+    Ok(size)
+}
+
+/// Return the logical block size of a block device (`BLKSSZGET`).
+pub fn logical_block_size(file: &fs::File) -> io::Result<disk::LogicalBlockSize> {
+    let mut size: libc::c_int = 0;
+    // SAFETY: BLKSSZGET stores an int at the given address.
+    unsafe { ioctl(file, libc::BLKSSZGET, &mut size) }?;
+    let bytes = u64::try_from(size).map_err(|_| io::Error::other("invalid block size"))?;
+    Ok(disk::LogicalBlockSize::try_from(bytes)?)
+}
+
+/// Discard `len` bytes of a block device from `offset` (`BLKDISCARD`).
+///
+/// Both must be multiples of the logical block size of the device.
+pub fn discard(file: &fs::File, offset: u64, len: u64) -> io::Result<()> {
+    let mut range: [u64; 2] = [offset, len];
+    // SAFETY: BLKDISCARD reads a start and length pair of u64 at the given address.
+    unsafe { ioctl(file, BLKDISCARD, range.as_mut_ptr()) }?;
+    Ok(())
+}
+
+/// Ask the kernel to re-read the partition table of a block device (`BLKRRPART`).
+///
+/// This fails with `EBUSY` if any partition of the device is in use
+/// (e.g. mounted), see `update_partitions()` for that case.
+pub fn reread_partitions(file: &fs::File) -> io::Result<()> {
+    // SAFETY: BLKRRPART takes no argument.
+    unsafe { ioctl(file, BLKRRPART, std::ptr::null_mut::<libc::c_void>()) }?;
+    Ok(())
+}
+
+/// Update the partitions known to the kernel one by one (`BLKPG`).
+///
+/// Partitions of `old` which are missing or moved in `new` are removed,
+/// and the new or moved ones of `new` are added. Unchanged partitions are
+/// left alone, so that this works while some of them are in use.
+pub fn update_partitions(
+    file: &fs::File,
+    old: &BTreeMap<u32, Partition>,
+    new: &BTreeMap<u32, Partition>,
+    lb_size: disk::LogicalBlockSize,
+) -> io::Result<()> {
+    let unchanged = |id: &u32| match (old.get(id), new.get(id)) {
+        (Some(a), Some(b)) => (a.first_lba, a.last_lba) == (b.first_lba, b.last_lba),
+        _ => false,
+    };
+    for id in old.keys().filter(|id| !unchanged(id)) {
+        match blkpg(file, BLKPG_DEL_PARTITION, *id, 0, 0) {
+            // Not known to the kernel in the first place.
+            Err(e) if e.raw_os_error() == Some(libc::ENXIO) => {}
+            res => res?,
+        }
+    }
+    for (id, p) in new.iter().filter(|(id, _)| !unchanged(id)) {
+        let start = p.bytes_start(lb_size)?;
+        let length = p.bytes_len(lb_size)?;
+        blkpg(file, BLKPG_ADD_PARTITION, *id, start, length)?;
+    }
+    Ok(())
+}
+
+/// Add or delete partition `pno`, spanning `length` bytes from `start`.
+fn blkpg(file: &fs::File, op: libc::c_int, pno: u32, start: u64, length: u64) -> io::Result<()> {
+    let invalid = |_| io::Error::other("partition out of BLKPG range");
+    let mut part = BlkpgPartition {
+        start: i64::try_from(start).map_err(invalid)?,
+        length: i64::try_from(length).map_err(invalid)?,
+        pno: libc::c_int::try_from(pno).map_err(invalid)?,
+        devname: [0; 64],
+        volname: [0; 64],
+    };
+    let mut arg = BlkpgIoctlArg {
+        op,
+        flags: 0,
+        datalen: std::mem::size_of::<BlkpgPartition>() as libc::c_int,
+        data: &mut part as *mut BlkpgPartition as *mut libc::c_void,
+    };
+    // SAFETY: `arg` points to a valid `blkpg_partition`, which outlives the call.
+    unsafe { ioctl(file, BLKPG, &mut arg) }?;
+    Ok(())
+}
+
+/// Issue an ioctl on `file`.
+///
+/// # Safety
+///
+/// `arg` must be valid for the argument type of `request`.
+unsafe fn ioctl<T>(file: &fs::File, request: libc::Ioctl, arg: *mut T) -> io::Result<libc::c_int> {
+    let ret = libc::ioctl(file.as_raw_fd(), request, arg);
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_ioctls_on_file() {
+        let file = std::fs::File::open("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+        assert!(!super::is_block_device(&file));
+        let enotty = Some(libc::ENOTTY);
+        assert_eq!(
+            super::device_size(&file).unwrap_err().raw_os_error(),
+            enotty
+        );
+        super::logical_block_size(&file).unwrap_err();
+        assert_eq!(
+            super::discard(&file, 0, 512).unwrap_err().raw_os_error(),
+            enotty
+        );
+        assert_eq!(
+            super::reread_partitions(&file).unwrap_err().raw_os_error(),
+            enotty
+        );
+
+        let lb_size = crate::disk::DEFAULT_SECTOR_SIZE;
+        let none = BTreeMap::new();
+        super::update_partitions(&file, &none, &none, lb_size).unwrap();
+    }
+}
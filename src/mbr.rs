@@ -3,10 +3,15 @@
 //! This module provides access to low-level primitives
 //! to work with Master Boot Record (MBR), also known as LBA0.
 
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use crate::disk;
+use crate::partition::Partition;
+use crate::raw::RawRecord;
+use crate::{Error, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
 use std::io::{Read, Seek, Write};
-use std::{fmt, fs, io};
+use std::{fmt, io};
 
 /// Protective MBR, as defined by GPT.
 pub struct ProtectiveMBR {
@@ -23,6 +28,65 @@ impl fmt::Debug for ProtectiveMBR {
     }
 }
 
+impl fmt::Display for ProtectiveMBR {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Protective MBR")?;
+        fmt_table(f, &self.disk_signature, &self.signature, &self.partitions)
+    }
+}
+
+/// Format the fields shared by protective and legacy MBRs.
+fn fmt_table(
+    f: &mut fmt::Formatter<'_>,
+    disk_signature: &[u8; 4],
+    signature: &[u8; 2],
+    partitions: &[PartRecord; 4],
+) -> fmt::Result {
+    let boot_status = if signature == &[0x55, 0xAA] {
+        "valid"
+    } else {
+        "invalid"
+    };
+    write!(
+        f,
+        "Disk signature:\t\t{:#010x}\nBoot signature:\t\t{:02X}{:02X} ({})",
+        u32::from_le_bytes(*disk_signature),
+        signature[0],
+        signature[1],
+        boot_status
+    )?;
+    for (i, p) in partitions.iter().enumerate() {
+        write!(f, "\nPartition {}:\t\t{}", i + 1, p)?;
+    }
+    Ok(())
+}
+
+/// Return a short description of well-known MBR partition types.
+fn os_type_name(os_type: u8) -> &'static str {
+    match os_type {
+        0x00 => "Empty",
+        0x01 => "FAT12",
+        0x04 | 0x06 | 0x0E => "FAT16",
+        0x05 | 0x0F => "Extended",
+        0x07 => "NTFS/exFAT",
+        0x0B | 0x0C => "FAT32",
+        0x27 => "Windows recovery",
+        0x82 => "Linux swap",
+        0x83 => "Linux",
+        0x85 => "Linux extended",
+        0x8E => "Linux LVM",
+        0xA5 => "FreeBSD",
+        0xA6 => "OpenBSD",
+        0xA8 => "Apple UFS",
+        0xA9 => "NetBSD",
+        0xAF => "Apple HFS+",
+        0xEE => "GPT protective",
+        0xEF => "EFI System",
+        0xFD => "Linux RAID",
+        _ => "Unknown",
+    }
+}
+
 impl Default for ProtectiveMBR {
     fn default() -> Self {
         Self {
@@ -62,66 +126,103 @@ impl ProtectiveMBR {
         }
     }
 
+    /// Create a protective-MBR object for a disk of `disk_size_lba` logical blocks.
+    ///
+    /// The protective partition covers the whole disk after LBA0, and is
+    /// capped to `0xFFFFFFFF` LBs on larger disks.
+    pub fn for_disk(disk_size_lba: u64) -> Self {
+        let size = u32::try_from(disk_size_lba.saturating_sub(1)).unwrap_or(u32::MAX);
+        Self::with_lb_size(size)
+    }
+
+    /// Fill the CHS addresses of the protective partition record.
+    ///
+    /// Records created by `with_lb_size()` carry the fixed CHS values
+    /// suggested by the UEFI specification; this recomputes or zeroes them,
+    /// see `ChsMode`.
+    pub fn set_chs(&mut self, mode: ChsMode) -> &Self {
+        for record in self.partitions.iter_mut().filter(|p| !p.is_empty()) {
+            record.set_chs(mode);
+        }
+        self
+    }
+
+    /// Check that this is a protective MBR for a disk of `disk_size_lba` logical blocks.
+    ///
+    /// This requires the boot signature, and exactly one partition record
+    /// of type 0xEE, starting at LBA 1 and covering the rest of the disk
+    /// (or `0xFFFFFFFF` LBs). Hybrid MBRs are accepted too: when other
+    /// records are used, the 0xEE one may be of any size, like the ones
+    /// written by `HybridMBR`, and the other records are not checked.
+    pub fn validate(&self, disk_size_lba: u64) -> Result<()> {
+        if self.signature != [0x55, 0xAA] {
+            return Err(Error::InvalidSignature);
+        }
+        let mut protective = self.partitions.iter().filter(|p| p.os_type == 0xEE);
+        let record = match (protective.next(), protective.next()) {
+            (Some(record), None) => record,
+            (None, _) => return Err(Error::Invalid("no GPT protective partition in MBR".into())),
+            (Some(_), Some(_)) => {
+                return Err(Error::Invalid(
+                    "multiple GPT protective partitions in MBR".into(),
+                ))
+            }
+        };
+        if record.lb_start != 1 {
+            return Err(Error::Invalid(
+                "GPT protective partition does not start at LBA 1".into(),
+            ));
+        }
+        let hybrid = self
+            .partitions
+            .iter()
+            .any(|p| p.os_type != 0xEE && !p.is_empty());
+        let expected = u32::try_from(disk_size_lba.saturating_sub(1)).unwrap_or(u32::MAX);
+        if !hybrid && record.lb_size != expected && record.lb_size != u32::MAX {
+            return Err(Error::Invalid(format!(
+                "GPT protective partition covers {} LBs, expected {}",
+                record.lb_size, expected
+            )));
+        }
+        Ok(())
+    }
+
     /// Parse input bytes into a protective-MBR object.
-    pub fn from_bytes(buf: &[u8], sector_size: disk::LogicalBlockSize) -> io::Result<Self> {
+    pub fn from_bytes(buf: &[u8], sector_size: disk::LogicalBlockSize) -> Result<Self> {
         let mut pmbr = Self::new();
         let totlen: u64 = sector_size.into();
 
         if buf.len() != (totlen as usize) {
-            return Err(io::Error::new(io::ErrorKind::Other, "invalid MBR length"));
+            return Err(Error::Invalid("invalid MBR length".into()));
         }
 
         pmbr.bootcode.copy_from_slice(&buf[0..440]);
         pmbr.disk_signature.copy_from_slice(&buf[440..444]);
         pmbr.unknown = (&buf[444..446]).read_u16::<LittleEndian>()?;
 
-        for (i, p) in pmbr.partitions.iter_mut().enumerate() {
-            let start = i
-                .checked_mul(16)
-                .ok_or_else(|| {
-                    io::Error::new(
-                        io::ErrorKind::Other,
-                        "partition record overflow - entry start",
-                    )
-                })?
-                .checked_add(446)
-                .ok_or_else(|| {
-                    io::Error::new(io::ErrorKind::Other, "partition overflow - start offset")
-                })?;
-            let end = start.checked_add(16).ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::Other,
-                    "partition record overflow - end offset",
-                )
-            })?;
-            *p = PartRecord::from_bytes(&buf[start..end])?;
-        }
+        pmbr.partitions = parse_records(buf)?;
 
         pmbr.signature.copy_from_slice(&buf[510..512]);
         if pmbr.signature != [0x55, 0xAA] {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "invalid MBR signature",
-            ));
+            return Err(Error::InvalidSignature);
         };
         Ok(pmbr)
     }
 
     /// Read the LBA0 of a disk and parse it into a protective-MBR object.
-    pub fn from_disk(file: &mut fs::File, sector_size: disk::LogicalBlockSize) -> io::Result<Self> {
-        let totlen: u64 = sector_size.into();
-        let mut buf = vec![0u8; totlen as usize];
-        let cur = file.seek(io::SeekFrom::Current(0))?;
-
-        file.seek(io::SeekFrom::Start(0))?;
-        file.read_exact(&mut buf)?;
+    pub fn from_disk<D: Read + Seek>(
+        file: &mut D,
+        sector_size: disk::LogicalBlockSize,
+    ) -> Result<Self> {
+        let cur = file.stream_position()?;
+        let buf = read_lba0(file, sector_size)?;
         let pmbr = Self::from_bytes(&buf, sector_size);
         file.seek(io::SeekFrom::Start(cur))?;
         pmbr
     }
 
     /// Return the memory representation of this MBR as a byte vector.
-    pub fn as_bytes(&self) -> io::Result<Vec<u8>> {
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
         let mut buf: Vec<u8> = Vec::with_capacity(512);
 
         buf.write_all(&self.bootcode)?;
@@ -163,9 +264,14 @@ impl ProtectiveMBR {
         self
     }
 
+    /// Return the four partition records.
+    pub fn partitions(&self) -> &[PartRecord; 4] {
+        &self.partitions
+    }
+
     /// Write a protective MBR to LBA0, overwriting any existing data.
-    pub fn overwrite_lba0(&self, file: &mut fs::File) -> io::Result<usize> {
-        let cur = file.seek(io::SeekFrom::Current(0))?;
+    pub fn overwrite_lba0<D: Write + Seek>(&self, file: &mut D) -> Result<usize> {
+        let cur = file.stream_position()?;
         let _ = file.seek(io::SeekFrom::Start(0))?;
         let data = self.as_bytes()?;
         file.write_all(&data)?;
@@ -179,8 +285,8 @@ impl ProtectiveMBR {
     ///
     /// This overwrites the four MBR partition records and the
     /// well-known signature, leaving all other MBR bits as-is.
-    pub fn update_conservative(&self, file: &mut fs::File) -> io::Result<usize> {
-        let cur = file.seek(io::SeekFrom::Current(0))?;
+    pub fn update_conservative<D: Write + Seek>(&self, file: &mut D) -> Result<usize> {
+        let cur = file.stream_position()?;
         // Seek to first partition record.
         // (GPT spec 2.7 - sec. 5.2.3 - table 15)
         let _ = file.seek(io::SeekFrom::Start(446))?;
@@ -197,22 +303,443 @@ impl ProtectiveMBR {
     }
 }
 
+/// Parse the four partition records of an MBR-style boot sector.
+fn parse_records(buf: &[u8]) -> Result<[PartRecord; 4]> {
+    let mut records = [
+        PartRecord::zero(),
+        PartRecord::zero(),
+        PartRecord::zero(),
+        PartRecord::zero(),
+    ];
+    for (i, p) in records.iter_mut().enumerate() {
+        let start = i
+            .checked_mul(16)
+            .ok_or(Error::Overflow("partition record entry start"))?
+            .checked_add(446)
+            .ok_or(Error::Overflow("partition start offset"))?;
+        let end = start
+            .checked_add(16)
+            .ok_or(Error::Overflow("partition record end offset"))?;
+        *p = PartRecord::from_bytes(&buf[start..end])?;
+    }
+    Ok(records)
+}
+
+/// Legacy (BIOS-era) MBR, with four arbitrary partition records.
+///
+/// Unlike `ProtectiveMBR`, this can describe any DOS-style partition
+/// table, e.g. for BIOS-only images.
+#[derive(Clone)]
+pub struct MbrTable {
+    bootcode: [u8; 440],
+    disk_signature: [u8; 4],
+    unknown: u16,
+    partitions: [PartRecord; 4],
+    signature: [u8; 2],
+}
+
+impl fmt::Debug for MbrTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MBR, partitions: {:#?}", self.partitions)
+    }
+}
+
+impl fmt::Display for MbrTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "MBR partition table")?;
+        fmt_table(f, &self.disk_signature, &self.signature, &self.partitions)
+    }
+}
+
+impl Default for MbrTable {
+    fn default() -> Self {
+        Self {
+            bootcode: [0x00; 440],
+            disk_signature: [0x00; 4],
+            unknown: 0,
+            partitions: [
+                PartRecord::zero(),
+                PartRecord::zero(),
+                PartRecord::zero(),
+                PartRecord::zero(),
+            ],
+            signature: [0x55, 0xAA],
+        }
+    }
+}
+
+impl MbrTable {
+    /// Create an empty MBR table, with no partitions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse input bytes into an MBR table.
+    pub fn from_bytes(buf: &[u8], sector_size: disk::LogicalBlockSize) -> Result<Self> {
+        let totlen: u64 = sector_size.into();
+        if buf.len() != (totlen as usize) {
+            return Err(Error::Invalid("invalid MBR length".into()));
+        }
+
+        let mut mbr = Self::new();
+        mbr.bootcode.copy_from_slice(&buf[0..440]);
+        mbr.disk_signature.copy_from_slice(&buf[440..444]);
+        mbr.unknown = (&buf[444..446]).read_u16::<LittleEndian>()?;
+        mbr.partitions = parse_records(buf)?;
+        mbr.signature.copy_from_slice(&buf[510..512]);
+        if mbr.signature != [0x55, 0xAA] {
+            return Err(Error::InvalidSignature);
+        };
+        Ok(mbr)
+    }
+
+    /// Read the LBA0 of a disk and parse it into an MBR table.
+    pub fn from_disk<D: Read + Seek>(
+        file: &mut D,
+        sector_size: disk::LogicalBlockSize,
+    ) -> Result<Self> {
+        let cur = file.stream_position()?;
+        let buf = read_lba0(file, sector_size)?;
+        let mbr = Self::from_bytes(&buf, sector_size);
+        file.seek(io::SeekFrom::Start(cur))?;
+        mbr
+    }
+
+    /// Return the memory representation of this MBR as a byte vector.
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        let mut buf: Vec<u8> = Vec::with_capacity(512);
+
+        buf.write_all(&self.bootcode)?;
+        buf.write_all(&self.disk_signature)?;
+        buf.write_u16::<LittleEndian>(self.unknown)?;
+        for p in &self.partitions {
+            let pdata = p.as_bytes()?;
+            buf.write_all(&pdata)?;
+        }
+        buf.write_all(&self.signature)?;
+        Ok(buf)
+    }
+
+    /// Return the four partition records.
+    pub fn partitions(&self) -> &[PartRecord; 4] {
+        &self.partitions
+    }
+
+    /// Set the partition record in slot `idx` (0 to 3).
+    ///
+    /// The record must not overlap any other non-empty record.
+    /// This only changes the in-memory state, without overwriting
+    /// any on-disk data.
+    pub fn set_partition(&mut self, idx: usize, record: PartRecord) -> Result<&Self> {
+        if idx >= self.partitions.len() {
+            return Err(Error::Invalid("invalid MBR partition slot".into()));
+        }
+        if !record.is_empty() {
+            let overlapping = self
+                .partitions
+                .iter()
+                .enumerate()
+                .filter(|(i, p)| *i != idx && !p.is_empty())
+                .any(|(_, p)| p.overlaps(&record));
+            if overlapping {
+                return Err(Error::Invalid("overlapping MBR partition records".into()));
+            }
+        }
+        self.partitions[idx] = record;
+        Ok(self)
+    }
+
+    /// Set or clear the bootable flag of the partition in slot `idx` (0 to 3).
+    ///
+    /// This only changes the in-memory state, without overwriting
+    /// any on-disk data.
+    pub fn set_bootable(&mut self, idx: usize, bootable: bool) -> Result<&Self> {
+        let p = self
+            .partitions
+            .get_mut(idx)
+            .ok_or_else(|| Error::Invalid("invalid MBR partition slot".into()))?;
+        p.boot_indicator = if bootable { 0x80 } else { 0x00 };
+        Ok(self)
+    }
+
+    /// Return the 440 bytes of BIOS bootcode.
+    pub fn bootcode(&self) -> &[u8; 440] {
+        &self.bootcode
+    }
+
+    /// Set the 440 bytes of BIOS bootcode.
+    ///
+    /// This only changes the in-memory state, without overwriting
+    /// any on-disk data.
+    pub fn set_bootcode(&mut self, bootcode: [u8; 440]) -> &Self {
+        self.bootcode = bootcode;
+        self
+    }
+
+    /// Return the 4 bytes of MBR disk signature.
+    pub fn disk_signature(&self) -> &[u8; 4] {
+        &self.disk_signature
+    }
+
+    /// Set the 4 bytes of MBR disk signature.
+    ///
+    /// This only changes the in-memory state, without overwriting
+    /// any on-disk data.
+    pub fn set_disk_signature(&mut self, sig: [u8; 4]) -> &Self {
+        self.disk_signature = sig;
+        self
+    }
+
+    /// Write this MBR to LBA0, overwriting any existing data.
+    pub fn overwrite_lba0<D: Write + Seek>(&self, file: &mut D) -> Result<usize> {
+        let cur = file.stream_position()?;
+        let _ = file.seek(io::SeekFrom::Start(0))?;
+        let data = self.as_bytes()?;
+        file.write_all(&data)?;
+        file.flush()?;
+
+        file.seek(io::SeekFrom::Start(cur))?;
+        Ok(data.len())
+    }
+}
+
+/// Hybrid MBR, mirroring up to three GPT partitions for legacy bootloaders.
+///
+/// Slot 0 holds the 0xEE protective record, covering the GPT structures
+/// up to the first mirrored partition, and slots 1 to 3 mirror GPT
+/// partitions with a DOS partition type, so that BIOS-only bootloaders
+/// can find them. Hybrid MBRs are not part of the UEFI specification,
+/// and both tables must be kept in sync when partitions change.
+#[derive(Clone, Debug)]
+pub struct HybridMBR {
+    table: MbrTable,
+    /// Size of the disk (in LB), covered by the protective record when nothing is mirrored.
+    disk_size_lba: u64,
+    /// GPT partition numbers mirrored in slots 1 to 3.
+    mirrored: Vec<u32>,
+    /// How CHS addresses of the records are filled.
+    chs: ChsMode,
+}
+
+impl HybridMBR {
+    /// Create a hybrid MBR for a disk of `disk_size_lba` logical blocks, mirroring nothing yet.
+    pub fn new(disk_size_lba: u64) -> Self {
+        let mut table = MbrTable::new();
+        let size = u32::try_from(disk_size_lba.saturating_sub(1)).unwrap_or(u32::MAX);
+        table.partitions[0] = PartRecord::new_protective(Some(size));
+        table.partitions[0].set_chs(ChsMode::default());
+        Self {
+            table,
+            disk_size_lba,
+            mirrored: vec![],
+            chs: ChsMode::default(),
+        }
+    }
+
+    /// How to fill the CHS addresses of the records, synthesized by default.
+    ///
+    /// This applies to the protective and mirrored records written by
+    /// later calls to `sync()`.
+    pub fn chs_mode(mut self, mode: ChsMode) -> Self {
+        self.chs = mode;
+        self.table.partitions[0].set_chs(mode);
+        self
+    }
+
+    /// Mirror the GPT partitions `mirror`, as (partition number, MBR type), from `pp`.
+    ///
+    /// Previously mirrored partitions are replaced, keeping the bootable
+    /// flag of those mirrored again. Partitions must end below the 2 TiB
+    /// limit of MBR records (on 512 bytes sectors).
+    /// This only changes the in-memory state, without overwriting
+    /// any on-disk data.
+    pub fn sync(&mut self, pp: &BTreeMap<u32, Partition>, mirror: &[(u32, u8)]) -> Result<&Self> {
+        if mirror.len() > 3 {
+            return Err(Error::Invalid(
+                "at most 3 partitions can be mirrored in a hybrid MBR".into(),
+            ));
+        }
+        let mut records = vec![];
+        for (id, os_type) in mirror {
+            let p = pp.get(id).ok_or(Error::UnknownPartition)?;
+            let start = u32::try_from(p.first_lba)
+                .map_err(|_| Error::Invalid("partition beyond MBR addressing limit".into()))?;
+            let size = p
+                .last_lba
+                .checked_sub(p.first_lba)
+                .and_then(|len| u32::try_from(len + 1).ok())
+                .filter(|size| start.checked_add(*size).is_some())
+                .ok_or_else(|| Error::Invalid("partition beyond MBR addressing limit".into()))?;
+            let mut record = PartRecord::new(*os_type, start, size);
+            record.set_chs(self.chs);
+            if let Some(slot) = self.mirrored.iter().position(|m| m == id) {
+                record.boot_indicator = self.table.partitions[slot + 1].boot_indicator;
+            }
+            records.push(record);
+        }
+
+        // The protective record covers everything before the first mirrored partition.
+        let protective_size = match records.iter().map(|r| r.lb_start).min() {
+            Some(first) if first > 1 => first - 1,
+            Some(_) => return Err(Error::Invalid("mirrored partition overlaps LBA 1".into())),
+            None => u32::try_from(self.disk_size_lba.saturating_sub(1)).unwrap_or(u32::MAX),
+        };
+        let mut table = self.table.clone();
+        table.partitions = [
+            PartRecord::new_protective(Some(protective_size)),
+            PartRecord::zero(),
+            PartRecord::zero(),
+            PartRecord::zero(),
+        ];
+        table.partitions[0].set_chs(self.chs);
+        for (slot, record) in records.into_iter().enumerate() {
+            table.set_partition(slot + 1, record)?;
+        }
+        self.table = table;
+        self.mirrored = mirror.iter().map(|(id, _)| *id).collect();
+        Ok(self)
+    }
+
+    /// Return the GPT partition numbers mirrored in slots 1 to 3.
+    pub fn mirrored(&self) -> &[u32] {
+        &self.mirrored
+    }
+
+    /// Set or clear the bootable flag of the record mirroring GPT partition `id`.
+    ///
+    /// This only changes the in-memory state, without overwriting
+    /// any on-disk data.
+    pub fn set_bootable(&mut self, id: u32, bootable: bool) -> Result<&Self> {
+        let slot = self
+            .mirrored
+            .iter()
+            .position(|m| *m == id)
+            .ok_or_else(|| Error::Invalid("partition not mirrored in hybrid MBR".into()))?;
+        self.table.set_bootable(slot + 1, bootable)?;
+        Ok(self)
+    }
+
+    /// Return the underlying MBR table.
+    pub fn table(&self) -> &MbrTable {
+        &self.table
+    }
+
+    /// Return the memory representation of this MBR as a byte vector.
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        self.table.as_bytes()
+    }
+
+    /// Update LBA0 with the hybrid partition records.
+    ///
+    /// Like `ProtectiveMBR::update_conservative()`, this only overwrites
+    /// the four partition records and the signature, keeping any
+    /// bootcode already installed.
+    pub fn update_conservative<D: Write + Seek>(&self, file: &mut D) -> Result<usize> {
+        let cur = file.stream_position()?;
+        let data = self.as_bytes()?;
+        file.seek(io::SeekFrom::Start(446))?;
+        file.write_all(&data[446..])?;
+        file.flush()?;
+
+        file.seek(io::SeekFrom::Start(cur))?;
+        Ok(data.len() - 446)
+    }
+}
+
+/// Read LBA0 of `file`, failing with `TooShort` on disks smaller than a block.
+fn read_lba0<D: Read + Seek>(file: &mut D, sector_size: disk::LogicalBlockSize) -> Result<Vec<u8>> {
+    let totlen: u64 = sector_size.into();
+    let mut buf = vec![0u8; totlen as usize];
+    file.seek(io::SeekFrom::Start(0))?;
+    file.read_exact(&mut buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => Error::TooShort,
+        _ => e.into(),
+    })?;
+    Ok(buf)
+}
+
+/// How to fill the CHS (cylinder/head/sector) addresses of partition records.
+///
+/// Modern systems only use LBA addressing, but some BIOSes and validation
+/// tools reject records whose CHS addresses are all zero.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ChsMode {
+    /// Leave CHS addresses zeroed.
+    Zeroed,
+    /// Compute CHS addresses from the LBAs, with the usual geometry of
+    /// 255 heads and 63 sectors per track, see `lba_to_chs()`.
+    #[default]
+    Synthesized,
+}
+
+/// Largest CHS address, as (cylinder, head, sector).
+const CHS_MAX: (u16, u8, u8) = (1023, 254, 63);
+/// Heads per cylinder of the synthesized geometry.
+const CHS_HEADS: u64 = 255;
+/// Sectors per track of the synthesized geometry.
+const CHS_SECTORS: u64 = 63;
+
+/// Convert `lba` into a CHS address, encoded as in partition records.
+///
+/// The geometry is 255 heads and 63 sectors per track; LBAs beyond
+/// its reach are capped at cylinder 1023, head 254 and sector 63. The
+/// returned bytes are the head, the sector (with bits 8 and 9 of the
+/// cylinder in its top bits) and the low bits of the cylinder.
+pub fn lba_to_chs(lba: u64) -> [u8; 3] {
+    let track = lba / CHS_SECTORS;
+    let (cylinder, head, sector) = match track / CHS_HEADS {
+        c if c <= u64::from(CHS_MAX.0) => (
+            c as u16,
+            (track % CHS_HEADS) as u8,
+            (lba % CHS_SECTORS) as u8 + 1,
+        ),
+        _ => CHS_MAX,
+    };
+    [
+        head,
+        sector | ((cylinder >> 2) & 0xC0) as u8,
+        (cylinder & 0xFF) as u8,
+    ]
+}
+
 /// A partition record, MBR-style.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct PartRecord {
-    boot_indicator: u8,
-    start_head: u8,
-    start_sector: u8,
-    start_track: u8,
-    os_type: u8,
-    end_head: u8,
-    end_sector: u8,
-    end_track: u8,
-    lb_start: u32,
-    lb_size: u32,
+    /// Boot indicator, 0x80 for active (bootable) partitions.
+    pub boot_indicator: u8,
+    /// Head of the CHS start address.
+    pub start_head: u8,
+    /// Sector of the CHS start address.
+    pub start_sector: u8,
+    /// Track of the CHS start address.
+    pub start_track: u8,
+    /// Partition type (OS indicator).
+    pub os_type: u8,
+    /// Head of the CHS end address.
+    pub end_head: u8,
+    /// Sector of the CHS end address.
+    pub end_sector: u8,
+    /// Track of the CHS end address.
+    pub end_track: u8,
+    /// Starting LBA of the partition.
+    pub lb_start: u32,
+    /// Size of the partition (in LB).
+    pub lb_size: u32,
 }
 
 impl PartRecord {
+    /// Create a Partition Record of the given type, spanning `lb_size` LBs from `lb_start`.
+    ///
+    /// CHS addresses are left zeroed, as LBA addressing is used.
+    pub fn new(os_type: u8, lb_start: u32, lb_size: u32) -> Self {
+        Self {
+            os_type,
+            lb_start,
+            lb_size,
+            ..Self::zero()
+        }
+    }
+
     /// Create a protective Partition Record object with a specific disk size (in LB).
     pub fn new_protective(lb_size: Option<u32>) -> Self {
         let size = lb_size.unwrap_or(0xFF_FF_FF_FF);
@@ -247,55 +774,227 @@ impl PartRecord {
     }
 
     /// Parse input bytes into a Partition Record.
-    pub fn from_bytes(buf: &[u8]) -> io::Result<Self> {
+    pub fn from_bytes(buf: &[u8]) -> Result<Self> {
         if buf.len() != 16 {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "invalid length for a partition record",
+            return Err(Error::Invalid(
+                "invalid length for a partition record".into(),
             ));
         };
-        let pr = Self {
-            boot_indicator: buf[0],
-            start_head: buf[1],
-            start_sector: buf[2],
-            start_track: buf[3],
-            os_type: buf[4],
-            end_head: buf[5],
-            end_sector: buf[6],
-            end_track: buf[7],
-            lb_start: (&buf[8..12]).read_u32::<LittleEndian>()?,
-            lb_size: (&buf[12..16]).read_u32::<LittleEndian>()?,
+        let raw = RawRecord::parse(buf)?;
+        Ok(Self::from(raw))
+    }
+
+    /// Fill the CHS start and end addresses from the LBAs of this record, or zero them.
+    pub fn set_chs(&mut self, mode: ChsMode) -> &Self {
+        let (start, end) = match mode {
+            ChsMode::Zeroed => ([0; 3], [0; 3]),
+            ChsMode::Synthesized => {
+                let start = u64::from(self.lb_start);
+                let end = (start + u64::from(self.lb_size)).saturating_sub(1);
+                (lba_to_chs(start), lba_to_chs(end))
+            }
         };
-        Ok(pr)
+        [self.start_head, self.start_sector, self.start_track] = start;
+        [self.end_head, self.end_sector, self.end_track] = end;
+        self
+    }
+
+    /// Whether this record is unused.
+    pub fn is_empty(&self) -> bool {
+        self.os_type == 0x00 || self.lb_size == 0
+    }
+
+    /// Whether this record is marked as active (bootable).
+    pub fn is_bootable(&self) -> bool {
+        self.boot_indicator == 0x80
+    }
+
+    /// Whether this record and `other` share any LBA.
+    fn overlaps(&self, other: &PartRecord) -> bool {
+        let (s1, s2) = (u64::from(self.lb_start), u64::from(other.lb_start));
+        let (e1, e2) = (s1 + u64::from(self.lb_size), s2 + u64::from(other.lb_size));
+        s1 < e2 && s2 < e1
+    }
+
+    /// Whether this record describes an extended partition (an EBR container).
+    pub fn is_extended(&self) -> bool {
+        matches!(self.os_type, 0x05 | 0x0F | 0x85)
     }
 
     /// Return the memory representation of this Partition Record as a byte vector.
-    pub fn as_bytes(&self) -> io::Result<Vec<u8>> {
-        let mut buf: Vec<u8> = Vec::with_capacity(16);
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        Ok(RawRecord::from(self.clone()).to_bytes().to_vec())
+    }
+}
+
+impl From<RawRecord> for PartRecord {
+    fn from(raw: RawRecord) -> Self {
+        let [start_head, start_sector, start_track] = raw.start_chs;
+        let [end_head, end_sector, end_track] = raw.end_chs;
+        Self {
+            boot_indicator: raw.boot_indicator,
+            start_head,
+            start_sector,
+            start_track,
+            os_type: raw.os_type,
+            end_head,
+            end_sector,
+            end_track,
+            lb_start: raw.lb_start,
+            lb_size: raw.lb_size,
+        }
+    }
+}
 
-        buf.write_u8(self.boot_indicator)?;
+impl From<PartRecord> for RawRecord {
+    fn from(pr: PartRecord) -> Self {
+        Self {
+            boot_indicator: pr.boot_indicator,
+            start_chs: [pr.start_head, pr.start_sector, pr.start_track],
+            os_type: pr.os_type,
+            end_chs: [pr.end_head, pr.end_sector, pr.end_track],
+            lb_start: pr.lb_start,
+            lb_size: pr.lb_size,
+        }
+    }
+}
 
-        buf.write_u8(self.start_head)?;
-        buf.write_u8(self.start_sector)?;
-        buf.write_u8(self.start_track)?;
+impl fmt::Display for PartRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "empty");
+        }
+        let last = u64::from(self.lb_start) + u64::from(self.lb_size) - 1;
+        write!(
+            f,
+            "{:#04x} ({}), LBA {} - {}",
+            self.os_type,
+            os_type_name(self.os_type),
+            self.lb_start,
+            last
+        )?;
+        if self.is_bootable() {
+            write!(f, ", bootable")?;
+        }
+        Ok(())
+    }
+}
 
-        buf.write_u8(self.os_type)?;
+/// A logical partition, found by walking the EBR chain of an extended partition.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LogicalPartition {
+    /// LBA of the Extended Boot Record describing this partition.
+    pub ebr_lba: u64,
+    /// Partition record, as stored in the EBR (relative to `ebr_lba`).
+    pub record: PartRecord,
+}
 
-        buf.write_u8(self.end_head)?;
-        buf.write_u8(self.end_sector)?;
-        buf.write_u8(self.end_track)?;
+impl LogicalPartition {
+    /// Return the absolute first LBA of this logical partition.
+    pub fn first_lba(&self) -> u64 {
+        self.ebr_lba + u64::from(self.record.lb_start)
+    }
 
-        buf.write_u32::<LittleEndian>(self.lb_start)?;
-        buf.write_u32::<LittleEndian>(self.lb_size)?;
+    /// Return the absolute last LBA of this logical partition.
+    pub fn last_lba(&self) -> u64 {
+        (self.first_lba() + u64::from(self.record.lb_size)).saturating_sub(1)
+    }
+}
 
-        Ok(buf)
+impl fmt::Display for LogicalPartition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#04x} ({}), LBA {} - {} (EBR at LBA {})",
+            self.record.os_type,
+            os_type_name(self.record.os_type),
+            self.first_lba(),
+            self.last_lba(),
+            self.ebr_lba
+        )
+    }
+}
+
+/// Return the logical partitions contained in the extended partition, if any.
+///
+/// This reads the MBR in LBA0 and follows the chain of Extended Boot
+/// Records (EBR) of the first extended partition found there. An empty
+/// vector is returned if the MBR has no extended partition.
+pub fn read_logical_partitions<D: Read + Seek>(
+    diskf: &mut D,
+    sector_size: disk::LogicalBlockSize,
+) -> Result<Vec<LogicalPartition>> {
+    let mbr = ProtectiveMBR::from_disk(diskf, sector_size)?;
+    let ext = match mbr.partitions.iter().find(|p| p.is_extended()) {
+        Some(p) => p,
+        None => return Ok(vec![]),
+    };
+    let ext_start = u64::from(ext.lb_start);
+    let ext_end = ext_start + u64::from(ext.lb_size);
+
+    let cur = diskf.stream_position()?;
+    let res = read_ebr_chain(diskf, ext_start, ext_end, sector_size);
+    let _ = diskf.seek(io::SeekFrom::Start(cur));
+    res
+}
+
+/// Follow the EBR chain of the extended partition spanning `ext_start..ext_end`.
+fn read_ebr_chain<D: Read + Seek>(
+    diskf: &mut D,
+    ext_start: u64,
+    ext_end: u64,
+    sector_size: disk::LogicalBlockSize,
+) -> Result<Vec<LogicalPartition>> {
+    let mut logical = vec![];
+    let mut visited = HashSet::new();
+    let mut ebr_lba = ext_start;
+    loop {
+        if ebr_lba < ext_start || ebr_lba >= ext_end {
+            return Err(Error::Invalid("EBR outside of extended partition".into()));
+        }
+        if !visited.insert(ebr_lba) {
+            return Err(Error::Invalid("loop in EBR chain".into()));
+        }
+
+        let (part, next) = read_ebr(diskf, ebr_lba, sector_size)?;
+        if !part.is_empty() {
+            logical.push(LogicalPartition {
+                ebr_lba,
+                record: part,
+            });
+        }
+        // Links to the next EBR are relative to the extended partition start.
+        if !next.is_extended() || next.lb_start == 0 {
+            break;
+        }
+        ebr_lba = ext_start + u64::from(next.lb_start);
+    }
+    Ok(logical)
+}
+
+/// Read an EBR, returning its partition record and link to the next EBR.
+fn read_ebr<D: Read + Seek>(
+    diskf: &mut D,
+    lba: u64,
+    sector_size: disk::LogicalBlockSize,
+) -> Result<(PartRecord, PartRecord)> {
+    let offset = sector_size.lba_to_bytes(lba)?;
+    let mut buf = vec![0u8; sector_size.bytes() as usize];
+    diskf.seek(io::SeekFrom::Start(offset))?;
+    diskf.read_exact(&mut buf)?;
+
+    if buf[510..512] != [0x55, 0xAA] {
+        return Err(Error::Invalid("invalid EBR signature".into()));
     }
+    let part = PartRecord::from_bytes(&buf[446..462])?;
+    let next = PartRecord::from_bytes(&buf[462..478])?;
+    Ok((part, next))
 }
 
 /// Return the 440 bytes of BIOS bootcode.
-pub fn read_bootcode(diskf: &mut fs::File) -> io::Result<[u8; 440]> {
+pub fn read_bootcode<D: Read + Seek>(diskf: &mut D) -> Result<[u8; 440]> {
     let bootcode_offset = 0;
-    let cur = diskf.seek(io::SeekFrom::Current(0))?;
+    let cur = diskf.stream_position()?;
     let _ = diskf.seek(io::SeekFrom::Start(bootcode_offset))?;
     let mut bootcode = [0x00; 440];
     diskf.read_exact(&mut bootcode)?;
@@ -305,9 +1004,9 @@ pub fn read_bootcode(diskf: &mut fs::File) -> io::Result<[u8; 440]> {
 }
 
 /// Write the 440 bytes of BIOS bootcode.
-pub fn write_bootcode(diskf: &mut fs::File, bootcode: &[u8; 440]) -> io::Result<()> {
+pub fn write_bootcode<D: Write + Seek>(diskf: &mut D, bootcode: &[u8; 440]) -> Result<()> {
     let bootcode_offset = 0;
-    let cur = diskf.seek(io::SeekFrom::Current(0))?;
+    let cur = diskf.stream_position()?;
     let _ = diskf.seek(io::SeekFrom::Start(bootcode_offset))?;
     diskf.write_all(bootcode)?;
     diskf.flush()?;
@@ -317,9 +1016,9 @@ pub fn write_bootcode(diskf: &mut fs::File, bootcode: &[u8; 440]) -> io::Result<
 }
 
 /// Read the 4 bytes of MBR disk signature.
-pub fn read_disk_signature(diskf: &mut fs::File) -> io::Result<[u8; 4]> {
+pub fn read_disk_signature<D: Read + Seek>(diskf: &mut D) -> Result<[u8; 4]> {
     let dsig_offset = 440;
-    let cur = diskf.seek(io::SeekFrom::Current(0))?;
+    let cur = diskf.stream_position()?;
     let _ = diskf.seek(io::SeekFrom::Start(dsig_offset))?;
     let mut dsig = [0x00; 4];
     diskf.read_exact(&mut dsig)?;
@@ -329,10 +1028,10 @@ pub fn read_disk_signature(diskf: &mut fs::File) -> io::Result<[u8; 4]> {
 }
 
 /// Write the 4 bytes of MBR disk signature.
-#[cfg_attr(feature = "cargo-clippy", allow(clippy::trivially_copy_pass_by_ref))]
-pub fn write_disk_signature(diskf: &mut fs::File, sig: &[u8; 4]) -> io::Result<()> {
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub fn write_disk_signature<D: Write + Seek>(diskf: &mut D, sig: &[u8; 4]) -> Result<()> {
     let dsig_offset = 440;
-    let cur = diskf.seek(io::SeekFrom::Current(0))?;
+    let cur = diskf.stream_position()?;
     let _ = diskf.seek(io::SeekFrom::Start(dsig_offset))?;
     diskf.write_all(sig)?;
     diskf.flush()?;
@@ -0,0 +1,231 @@
+//! Metadata-only export of partition tables, e.g. for bug reports.
+//!
+//! An export captures the MBR, both GPT headers and both partition
+//! arrays of a disk as raw sectors, without any partition content.
+//! It can optionally be scrubbed of partition names and GUIDs, and
+//! loaded back into a (sparse) disk image for reproduction.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use log::*;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::disk;
+use crate::header::calculate_crc32;
+use crate::{Error, Result};
+
+/// Magic signature at the start of a metadata export.
+const MAGIC: &[u8; 8] = b"GPTMETA1";
+/// Upper bound for a single exported region, to survive corrupted headers.
+const MAX_REGION_LEN: u64 = 4 * 1024 * 1024;
+
+/// A raw byte range of the disk, and its content.
+struct Region {
+    offset: u64,
+    data: Vec<u8>,
+}
+
+/// Export GPT/MBR metadata of `file` to `out`.
+///
+/// If `scrub` is set, partition names, the MBR disk signature, and all
+/// disk and partition GUIDs are replaced. Checksums which were valid
+/// are recomputed, so that scrubbed exports still open cleanly, while
+/// invalid ones are kept as-is.
+pub fn export<W: Write>(
+    file: &mut File,
+    lb_size: disk::LogicalBlockSize,
+    out: &mut W,
+    scrub: bool,
+) -> Result<()> {
+    let lb = lb_size.bytes();
+    let disk_len = file.seek(SeekFrom::End(0))?;
+    if disk_len < 2 * lb {
+        return Err(Error::TooShort);
+    }
+    let last_lba = disk_len / lb - 1;
+
+    let mut regions = vec![read_region(file, 0, lb)?];
+    let mut guids = BTreeMap::new();
+    let primary = read_region(file, lb, lb)?;
+    let mut header_lbas = vec![1, last_lba];
+    if let Some(geo) = Geometry::parse(&primary.data) {
+        let in_disk = geo
+            .backup_lba
+            .checked_mul(lb)
+            .is_some_and(|off| off < disk_len);
+        if geo.backup_lba != last_lba && in_disk {
+            header_lbas.push(geo.backup_lba);
+        }
+    }
+    for lba in header_lbas {
+        let mut hdr = read_region(file, lba * lb, lb)?;
+        let geo = match Geometry::parse(&hdr.data) {
+            Some(geo) => geo,
+            None => {
+                regions.push(hdr);
+                continue;
+            }
+        };
+        let array_len = u64::from(geo.num_parts) * u64::from(geo.part_size);
+        let array_start = geo.part_start.checked_mul(lb);
+        let array_end = array_start.and_then(|start| start.checked_add(array_len));
+        let mut array = match (array_start, array_end) {
+            (Some(start), Some(end)) if array_len <= MAX_REGION_LEN && end <= disk_len => {
+                Some(read_region(file, start, array_len)?)
+            }
+            _ => {
+                warn!("skipping partition array of header at LBA {}", lba);
+                None
+            }
+        };
+        if scrub {
+            scrub_header(&mut hdr.data, array.as_mut(), &geo, &mut guids);
+        }
+        regions.push(hdr);
+        regions.extend(array);
+    }
+    if scrub {
+        // MBR disk signature.
+        regions[0].data[440..444].copy_from_slice(&[0; 4]);
+    }
+
+    regions.sort_by_key(|r| r.offset);
+    regions.dedup_by_key(|r| r.offset);
+    out.write_all(MAGIC)?;
+    out.write_u32::<LittleEndian>(lb as u32)?;
+    out.write_u64::<LittleEndian>(disk_len)?;
+    out.write_u32::<LittleEndian>(regions.len() as u32)?;
+    for r in &regions {
+        trace!("exporting {} bytes at {:#x}", r.data.len(), r.offset);
+        out.write_u64::<LittleEndian>(r.offset)?;
+        out.write_u32::<LittleEndian>(r.data.len() as u32)?;
+        out.write_all(&r.data)?;
+    }
+    Ok(out.flush()?)
+}
+
+/// Load a metadata export from `input` into the disk image `image`.
+///
+/// The image is resized to the size of the original disk, leaving
+/// everything but the exported metadata sparse. The logical block
+/// size of the original disk is returned.
+pub fn load<R: Read>(input: &mut R, image: &mut File) -> Result<disk::LogicalBlockSize> {
+    let mut magic = [0u8; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(Error::Invalid("invalid metadata export signature".into()));
+    }
+    let lb_size = disk::LogicalBlockSize::try_from(u64::from(input.read_u32::<LittleEndian>()?))?;
+    let disk_len = input.read_u64::<LittleEndian>()?;
+    let count = input.read_u32::<LittleEndian>()?;
+
+    image.set_len(disk_len)?;
+    for _ in 0..count {
+        let offset = input.read_u64::<LittleEndian>()?;
+        let len = u64::from(input.read_u32::<LittleEndian>()?);
+        if len > MAX_REGION_LEN || offset.checked_add(len).is_none_or(|end| end > disk_len) {
+            return Err(Error::Invalid("metadata region outside of disk".into()));
+        }
+        let mut data = vec![0u8; len as usize];
+        input.read_exact(&mut data)?;
+        image.seek(SeekFrom::Start(offset))?;
+        image.write_all(&data)?;
+    }
+    image.flush()?;
+    Ok(lb_size)
+}
+
+fn read_region(file: &mut File, offset: u64, len: u64) -> Result<Region> {
+    let mut data = vec![0u8; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(Region { offset, data })
+}
+
+/// Raw header fields needed to locate and scrub partition arrays.
+struct Geometry {
+    header_size: usize,
+    backup_lba: u64,
+    part_start: u64,
+    num_parts: u32,
+    part_size: u32,
+}
+
+impl Geometry {
+    /// Parse an header sector, without validating its checksums.
+    fn parse(sector: &[u8]) -> Option<Self> {
+        if &sector[..8] != b"EFI PART" {
+            return None;
+        }
+        let field = |at: usize, len: usize| &sector[at..at + len];
+        let header_size = field(12, 4).read_u32::<LittleEndian>().ok()? as usize;
+        let geo = Geometry {
+            header_size,
+            backup_lba: field(32, 8).read_u64::<LittleEndian>().ok()?,
+            part_start: field(72, 8).read_u64::<LittleEndian>().ok()?,
+            num_parts: field(80, 4).read_u32::<LittleEndian>().ok()?,
+            part_size: field(84, 4).read_u32::<LittleEndian>().ok()?,
+        };
+        if header_size < 92 || header_size > sector.len() || geo.part_size < 128 {
+            return None;
+        }
+        Some(geo)
+    }
+}
+
+/// Compute the CRC32 of an header, with its own checksum field zeroed.
+fn header_crc(hdr: &[u8]) -> u32 {
+    let mut buf = hdr.to_vec();
+    buf[16..20].copy_from_slice(&[0; 4]);
+    calculate_crc32(&buf)
+}
+
+/// Replace GUIDs and names in a header and its partition array.
+///
+/// `guids` maps original GUIDs to their replacements, so that both
+/// copies of the table are scrubbed consistently.
+fn scrub_header(
+    sector: &mut [u8],
+    array: Option<&mut Region>,
+    geo: &Geometry,
+    guids: &mut BTreeMap<[u8; 16], [u8; 16]>,
+) {
+    let mut replace = |raw: &mut [u8]| {
+        let mut orig = [0u8; 16];
+        orig.copy_from_slice(raw);
+        let new = guids
+            .entry(orig)
+            .or_insert_with(|| *uuid::Uuid::new_v4().as_bytes());
+        raw.copy_from_slice(new);
+    };
+    let hdr_len = geo.header_size;
+    let hdr_valid = header_crc(&sector[..hdr_len]) == u32::from_le_bytes(field4(sector, 16));
+    replace(&mut sector[56..72]);
+
+    if let Some(array) = array {
+        let parts_valid = calculate_crc32(&array.data) == u32::from_le_bytes(field4(sector, 88));
+        for entry in array.data.chunks_exact_mut(geo.part_size as usize) {
+            if entry[..16].iter().all(|b| *b == 0) {
+                continue;
+            }
+            replace(&mut entry[16..32]);
+            for b in &mut entry[56..128] {
+                *b = 0;
+            }
+        }
+        if parts_valid {
+            let crc = calculate_crc32(&array.data);
+            sector[88..92].copy_from_slice(&crc.to_le_bytes());
+        }
+    }
+    if hdr_valid {
+        let crc = header_crc(&sector[..hdr_len]);
+        sector[16..20].copy_from_slice(&crc.to_le_bytes());
+    }
+}
+
+fn field4(buf: &[u8], at: usize) -> [u8; 4] {
+    [buf[at], buf[at + 1], buf[at + 2], buf[at + 3]]
+}
@@ -0,0 +1,82 @@
+//! Notification of the writes made to a disk.
+//!
+//! Auditing and provisioning tools often need to know exactly which bytes
+//! of a disk were touched. A `GptObserver` registered with
+//! `GptDisk::set_observer()` gets a `WriteEvent` for every write to the
+//! disk, once it succeeded.
+//!
+//! ```rust,no_run
+//! let mut disk = gpt::GptConfig::new()
+//!     .writable(true)
+//!     .open(std::path::Path::new("/dev/sdz"))
+//!     .unwrap();
+//! disk.set_observer(|event: &gpt::observer::WriteEvent| println!("{}", event));
+//! disk.write_inplace().unwrap();
+//! ```
+
+use std::fmt;
+
+/// Kind of structure written to a disk.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Region {
+    /// The MBR in LBA0, e.g. a protective one.
+    Mbr,
+    /// A primary or backup GPT header.
+    Header,
+    /// A partition entry array, whole or a single entry of it.
+    PartitionArray,
+    /// Contents of the partition with the given number.
+    PartitionData(u32),
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::Mbr => write!(f, "MBR"),
+            Region::Header => write!(f, "header"),
+            Region::PartitionArray => write!(f, "partition array"),
+            Region::PartitionData(id) => write!(f, "partition {} data", id),
+        }
+    }
+}
+
+/// A write made to a disk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WriteEvent {
+    /// What was written.
+    pub region: Region,
+    /// Byte offset of the write on the disk.
+    pub offset: u64,
+    /// Number of bytes written.
+    pub len: u64,
+}
+
+impl fmt::Display for WriteEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} write of {} bytes at {:#x}",
+            self.region, self.len, self.offset
+        )
+    }
+}
+
+/// Receiver of the writes made to a disk, see `GptDisk::set_observer()`.
+///
+/// This is implemented by closures taking a `&WriteEvent`.
+pub trait GptObserver: Send {
+    /// Called after each successful write.
+    fn on_write(&mut self, event: &WriteEvent);
+}
+
+impl<F: FnMut(&WriteEvent) + Send> GptObserver for F {
+    fn on_write(&mut self, event: &WriteEvent) {
+        self(event)
+    }
+}
+
+impl fmt::Debug for dyn GptObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("GptObserver")
+    }
+}
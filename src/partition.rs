@@ -3,23 +3,29 @@
 //! This module provides access to low-level primitives
 //! to work with GPT partitions.
 
-
+use bitflags::*;
+use byteorder::{LittleEndian, WriteBytesExt};
+use log::*;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::fs::{File, OpenOptions};
-use std::io::{Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use crc::crc32;
-use uuid;
-use log::*;
-use bitflags::*;
 
 use crate::disk;
-use crate::header::{parse_uuid, partentry_checksum, Header};
-use crate::partition_types::PART_HASHMAP;
+use crate::header::{calculate_crc32, partentry_checksum, Header, MAX_ARRAY_LEN};
+use crate::partition_types::Type;
+use crate::quirks::{QuirkTracker, Quirks};
+use crate::raw::{self, RawEntry};
+use crate::{Error, Result};
 
 bitflags! {
     /// Partition entry attributes, defined for UEFI.
+    ///
+    /// Bits without a name (e.g. type-specific ones) are preserved as-is.
+    #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
     pub struct PartitionAttributes: u64 {
         /// Required platform partition.
         const PLATFORM   = 1;
@@ -30,8 +36,206 @@ bitflags! {
     }
 }
 
+/// Textual names of attribute bits, as used by `Display` and `FromStr`.
+const ATTRIBUTE_NAMES: &[(PartitionAttributes, &str)] = &[
+    (PartitionAttributes::PLATFORM, "required"),
+    (PartitionAttributes::EFI, "no-block-io"),
+    (PartitionAttributes::BOOTABLE, "legacy-boot"),
+];
+
+impl fmt::Display for PartitionAttributes {
+    /// List set flags by name, e.g. "required, legacy-boot, bit56".
+    ///
+    /// Bits without a name are shown by position, and no flags as "none".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "none");
+        }
+        let mut sep = "";
+        for bit in 0..64 {
+            let flag = PartitionAttributes::from_bits_retain(1 << bit);
+            if !self.contains(flag) {
+                continue;
+            }
+            match ATTRIBUTE_NAMES.iter().find(|(attr, _)| *attr == flag) {
+                Some((_, name)) => write!(f, "{}{}", sep, name)?,
+                None => write!(f, "{}bit{}", sep, bit)?,
+            }
+            sep = ", ";
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for PartitionAttributes {
+    type Err = Error;
+
+    /// Parse a comma-separated list of flags, in the `Display` format.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut attrs = PartitionAttributes::empty();
+        for token in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let token = token.to_ascii_lowercase();
+            if token == "none" {
+                continue;
+            }
+            if let Some((attr, _)) = ATTRIBUTE_NAMES.iter().find(|(_, name)| *name == token) {
+                attrs |= *attr;
+                continue;
+            }
+            let bit = token
+                .strip_prefix("bit")
+                .and_then(|n| n.parse::<u32>().ok())
+                .filter(|n| *n < 64)
+                .ok_or_else(|| {
+                    Error::Invalid(format!("unknown partition attribute '{}'", token))
+                })?;
+            attrs |= PartitionAttributes::from_bits_retain(1 << bit);
+        }
+        Ok(attrs)
+    }
+}
+
+impl PartitionAttributes {
+    /// Return the type-specific attribute bits 48 to 63.
+    pub fn type_specific(&self) -> u16 {
+        (self.bits() >> 48) as u16
+    }
+
+    /// Replace the type-specific attribute bits 48 to 63.
+    pub fn with_type_specific(self, bits: u16) -> Self {
+        let common = self.bits() & ((1 << 48) - 1);
+        Self::from_bits_retain(common | (u64::from(bits) << 48))
+    }
+}
+
+/// Maximum length of a partition name, in UTF-16 code units.
+pub const MAX_NAME_UNITS: usize = 36;
+
+// Type-GUIDs used by this crate, also labelled in `partition_types`.
+/// Type-GUID of EFI System Partitions.
+pub const EFI_SYSTEM: &str = "C12A7328-F81F-11D2-BA4B-00A0C93EC93B";
+/// Type-GUID of BIOS Boot Partitions.
+pub const BIOS_BOOT: &str = "21686148-6449-6E6F-744E-656564454649";
+/// Type-GUID of Microsoft Reserved Partitions.
+pub const MICROSOFT_RESERVED: &str = "E3C9E316-0B5C-4DB8-817D-F92DF00215AE";
+/// Type-GUID of Microsoft basic data partitions.
+pub const MICROSOFT_BASIC_DATA: &str = "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7";
+/// Type-GUID of Windows Recovery Environment partitions.
+pub const WINDOWS_RECOVERY: &str = "DE94BBA4-06D1-4D40-A16A-BFD50179D6AC";
+/// Type-GUID of Linux filesystem data partitions.
+pub const LINUX_FILESYSTEM: &str = "0FC63DAF-8483-4772-8E79-3D69D8477DE4";
+/// Type-GUID of Linux swap partitions.
+pub const LINUX_SWAP: &str = "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F";
+/// Type-GUID of Linux LVM partitions.
+pub const LINUX_LVM: &str = "E6D6D379-F507-44C2-A23C-238F2A3DF928";
+/// Type-GUID of Linux RAID partitions.
+pub const LINUX_RAID: &str = "A19D880F-05FC-4D3B-A006-743F0F84911E";
+/// Type-GUID of extended boot loader (XBOOTLDR) partitions.
+pub const XBOOTLDR: &str = "BC13C2FF-59E6-4262-A352-B275FD6F7172";
+/// Type-GUID of FreeBSD data partitions.
+pub const FREEBSD_DATA: &str = "516E7CB4-6ECF-11D6-8FF8-00022D09712B";
+/// Type-GUID of ChromeOS kernel partitions.
+pub const CHROMEOS_KERNEL: &str = "FE3A2A5D-4F32-41A7-B725-ACCC3285A309";
+/// Type-GUID of Apple APFS containers.
+pub const APPLE_APFS: &str = "7C3457EF-0000-11AA-AA11-00306543ECAC";
+/// Type-GUID of Apple HFS+ partitions.
+pub const APPLE_HFS_PLUS: &str = "48465300-0000-11AA-AA11-00306543ECAC";
+/// Type-GUID of Apple Boot (Recovery HD) partitions.
+pub const APPLE_BOOT: &str = "426F6F74-0000-11AA-AA11-00306543ECAC";
+
+bitflags! {
+    /// Type-specific attributes of Microsoft basic data partitions.
+    #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+    pub struct MicrosoftAttributes: u64 {
+        /// Read-only volume.
+        const READ_ONLY       = (1 << 60);
+        /// Shadow copy of another partition.
+        const SHADOW_COPY     = (1 << 61);
+        /// Hidden volume.
+        const HIDDEN          = (1 << 62);
+        /// No drive letter assigned by default.
+        const NO_DRIVE_LETTER = (1 << 63);
+    }
+}
+
+/// Boot attributes of ChromeOS kernel partitions, in bits 48 to 56.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ChromeOsAttributes {
+    /// Boot priority, from 0 (not bootable) to 15 (highest).
+    pub priority: u8,
+    /// Remaining boot attempts, from 0 to 15.
+    pub tries: u8,
+    /// Whether the kernel booted successfully.
+    pub successful: bool,
+}
+
+impl ChromeOsAttributes {
+    /// Decode ChromeOS boot attributes from partition entry flags.
+    pub fn decode(flags: u64) -> Self {
+        Self {
+            priority: ((flags >> 48) & 0xF) as u8,
+            tries: ((flags >> 52) & 0xF) as u8,
+            successful: flags & (1 << 56) != 0,
+        }
+    }
+
+    /// Encode these attributes into `flags`, keeping all other bits.
+    ///
+    /// Priority and tries are truncated to 4 bits.
+    pub fn encode(&self, flags: u64) -> u64 {
+        let cleared = flags & !(0x1FF << 48);
+        cleared
+            | (u64::from(self.priority & 0xF) << 48)
+            | (u64::from(self.tries & 0xF) << 52)
+            | (u64::from(self.successful) << 56)
+    }
+}
+
+/// Type-specific attributes of a partition, decoded according to its type.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum TypeAttributes {
+    /// Microsoft basic data partition.
+    Microsoft(MicrosoftAttributes),
+    /// ChromeOS kernel partition.
+    ChromeOs(ChromeOsAttributes),
+    /// Any other type, as raw bits 48 to 63.
+    Other(u16),
+}
+
+/// Handle to a partition, stable across renumbering.
+///
+/// A handle records both the partition number and the unique GUID of
+/// a partition, so that it can still be resolved after entries have been
+/// moved to different slots.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct PartitionHandle {
+    pub(crate) id: u32,
+    pub(crate) guid: uuid::Uuid,
+}
+
+impl PartitionHandle {
+    /// Return the partition number this handle was created for.
+    ///
+    /// This may be stale if entries were renumbered afterwards, see
+    /// `GptDisk::resolve()` for the current value.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Return the unique GUID of the partition.
+    pub fn guid(&self) -> &uuid::Uuid {
+        &self.guid
+    }
+}
+
 /// A partition entry in a GPT partition table.
-#[derive(Clone, Debug, Eq, PartialEq)]
+///
+/// Entries compare equal if they hold the same data, whatever their number.
+#[derive(Clone, Debug, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct Partition {
     /// GUID of the partition type.
     pub part_type_guid: PartitionType,
@@ -45,9 +249,36 @@ pub struct Partition {
     pub flags: u64,
     /// Partition name.
     pub name: String,
+    /// Raw UTF-16 code units of the name as stored on disk, when they do not
+    /// round-trip through `name` (invalid UTF-16, or data after a NUL).
+    pub(crate) raw_name: Option<Vec<u16>>,
+    /// Partition number, see `number()`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) number: u32,
+}
+
+impl PartialEq for Partition {
+    fn eq(&self, other: &Self) -> bool {
+        self.part_type_guid == other.part_type_guid
+            && self.part_guid == other.part_guid
+            && self.first_lba == other.first_lba
+            && self.last_lba == other.last_lba
+            && self.flags == other.flags
+            && self.name == other.name
+            && self.raw_name == other.raw_name
+    }
 }
 
 impl Partition {
+    /// Partition number, i.e. the 1-based position of this entry in the
+    /// partition array, or 0 for entries not read from a table.
+    ///
+    /// This mirrors the key of the entry in `GptDisk::partitions()`, and is
+    /// kept in line by `GptDisk` whenever entries are read or updated.
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
     /// Create a partition entry of type "unused", whose bytes are all 0s.
     pub fn zero() -> Self {
         Self {
@@ -61,49 +292,120 @@ impl Partition {
             last_lba: 0,
             flags: 0,
             name: "".to_string(),
+            raw_name: None,
+            number: 0,
         }
     }
 
-    /// Serialize this partition entry to its bytes representation.
-    fn as_bytes(&self, entry_size: u16) -> Result<Vec<u8>> {
-        let mut buf: Vec<u8> = Vec::with_capacity(entry_size as usize);
+    /// Set the partition name, failing if it does not fit in a partition entry.
+    ///
+    /// Names are stored as up to 36 UTF-16 code units (characters outside
+    /// the Basic Multilingual Plane take two), padded with NULs, and may
+    /// not contain NUL characters themselves.
+    pub fn set_name(&mut self, name: &str) -> Result<()> {
+        if name.contains('\0') {
+            return Err(Error::Invalid(
+                "partition name contains a NUL character".into(),
+            ));
+        }
+        let units = name.encode_utf16().count();
+        if units > MAX_NAME_UNITS {
+            return Err(Error::Invalid(format!(
+                "partition name too long: {} UTF-16 code units, at most {}",
+                units, MAX_NAME_UNITS
+            )));
+        }
+        self.name = name.to_string();
+        self.raw_name = None;
+        Ok(())
+    }
+
+    /// Set the unique GUID of this partition.
+    ///
+    /// If no GUID is specified, a new random one is generated.
+    pub fn set_unique_guid(&mut self, guid: Option<uuid::Uuid>) {
+        self.part_guid = guid.unwrap_or_else(uuid::Uuid::new_v4);
+    }
 
-        // Type GUID.
-        let tyguid = self.part_type_guid.guid.as_fields();
-        buf.write_u32::<LittleEndian>(tyguid.0)?;
-        buf.write_u16::<LittleEndian>(tyguid.1)?;
-        buf.write_u16::<LittleEndian>(tyguid.2)?;
-        buf.write_all(tyguid.3)?;
+    /// Partition name, with invalid UTF-16 replaced by U+FFFD.
+    ///
+    /// Use `name_strict()` to detect invalid names instead.
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
 
-        // Partition GUID.
-        let pguid = self.part_guid.as_fields();
-        buf.write_u32::<LittleEndian>(pguid.0)?;
-        buf.write_u16::<LittleEndian>(pguid.1)?;
-        buf.write_u16::<LittleEndian>(pguid.2)?;
-        buf.write_all(pguid.3)?;
+    /// Partition name, with invalid UTF-16 replaced by U+FFFD.
+    #[deprecated(note = "use `name()`, which is lossy too")]
+    pub fn name_lossy(&self) -> String {
+        self.name()
+    }
 
-        // LBAs and flags.
-        buf.write_u64::<LittleEndian>(self.first_lba)?;
-        buf.write_u64::<LittleEndian>(self.last_lba)?;
-        buf.write_u64::<LittleEndian>(self.flags)?;
+    /// Partition name, failing if the stored name is not valid UTF-16.
+    pub fn name_strict(&self) -> std::result::Result<String, NameError> {
+        match self.stored_name_units() {
+            Some(units) => decode_name_strict(units),
+            None => Ok(self.name.clone()),
+        }
+    }
 
-        // Partition name as UTF16-LE.
-        for utf16_char in self.name.encode_utf16().take(36) {
-            buf.write_u16::<LittleEndian>(utf16_char)?;
+    /// UTF-16 code units of the name, as stored on disk (up to 36 units).
+    ///
+    /// Trailing NUL padding is only included for names read from disk
+    /// which do not round-trip through `name`.
+    pub fn name_units(&self) -> Vec<u16> {
+        match self.stored_name_units() {
+            Some(units) => units.to_vec(),
+            None => {
+                // Never split a surrogate pair when truncating names set directly.
+                let mut units = vec![];
+                for c in self.name.chars() {
+                    if units.len() + c.len_utf16() > MAX_NAME_UNITS {
+                        break;
+                    }
+                    units.extend(c.encode_utf16(&mut [0; 2]).iter());
+                }
+                units
+            }
         }
+    }
 
-        // Resize buffer to exact entry size.
-        buf.resize(entry_size as usize, 0x00);
+    /// Raw UTF-16 code units of the name as read from disk, when they do not
+    /// round-trip through `name` (invalid UTF-16, or data after a NUL).
+    pub fn raw_name(&self) -> Option<&[u16]> {
+        self.raw_name.as_deref()
+    }
 
-        Ok(buf)
+    /// Raw name units, unless `name` has been changed since they were read.
+    fn stored_name_units(&self) -> Option<&[u16]> {
+        self.raw_name
+            .as_deref()
+            .filter(|units| decode_name_lossy(units) == self.name)
+    }
+
+    /// Serialize this partition entry to its bytes representation.
+    fn as_bytes(&self, entry_size: u16) -> Result<Vec<u8>> {
+        let mut name = [0; raw::NAME_UNITS];
+        for (unit, c) in name.iter_mut().zip(self.name_units()) {
+            *unit = c;
+        }
+        let entry = RawEntry {
+            type_guid: self.part_type_guid.guid,
+            part_guid: self.part_guid,
+            first_lba: self.first_lba,
+            last_lba: self.last_lba,
+            flags: self.flags,
+            name,
+        };
+        Ok(entry.to_bytes(usize::from(entry_size)))
     }
 
     /// Write the partition entry to the partitions area and update crc32 for the Header.
     pub fn write(&self, p: &Path, h: &Header, lb_size: disk::LogicalBlockSize) -> Result<()> {
         debug!("writing partition to file: {}", p.display());
-        let pstart = h.part_start
+        let pstart = h
+            .part_start
             .checked_mul(lb_size.into())
-            .ok_or_else(|| Error::new(ErrorKind::Other, "partition overflow - start offset"))?;
+            .ok_or(Error::Overflow("partition start offset"))?;
         let mut file = OpenOptions::new().write(true).read(true).open(p)?;
         trace!("seeking to partition start: {:#x}", pstart);
         file.seek(SeekFrom::Start(pstart))?;
@@ -111,38 +413,76 @@ impl Partition {
 
         let parts_checksum = partentry_checksum(&mut file, h, lb_size)?;
         // Seek to header partition checksum location and update it.
-        let hdr_csum = h.current_lba
+        let hdr_csum = h
+            .current_lba
             .checked_mul(lb_size.into())
-            .ok_or_else(|| Error::new(ErrorKind::Other, "partition overflow - header start"))?
+            .ok_or(Error::Overflow("partition header start"))?
             .checked_add(88)
-            .ok_or_else(|| Error::new(ErrorKind::Other, "partition overflow - checksum offset"))?;
+            .ok_or(Error::Overflow("partition checksum offset"))?;
         let _ = file.seek(SeekFrom::Start(hdr_csum))?;
         file.write_u32::<LittleEndian>(parts_checksum)?;
 
         Ok(())
     }
 
+    /// Whether this entry has the same range, type, name and flags as `other`.
+    ///
+    /// Unique partition GUIDs are ignored.
+    pub fn layout_eq(&self, other: &Partition) -> bool {
+        self.part_type_guid.guid == other.part_type_guid.guid
+            && self.first_lba == other.first_lba
+            && self.last_lba == other.last_lba
+            && self.flags == other.flags
+            && self.name == other.name
+    }
+
+    /// Decode the type-specific attribute bits of this partition, according to its type.
+    pub fn type_attributes(&self) -> TypeAttributes {
+        let guid = self.part_type_guid.guid.to_hyphenated_ref().to_string();
+        if guid.eq_ignore_ascii_case(MICROSOFT_BASIC_DATA) {
+            TypeAttributes::Microsoft(MicrosoftAttributes::from_bits_truncate(self.flags))
+        } else if guid.eq_ignore_ascii_case(CHROMEOS_KERNEL) {
+            TypeAttributes::ChromeOs(ChromeOsAttributes::decode(self.flags))
+        } else {
+            TypeAttributes::Other(PartitionAttributes::from_bits_retain(self.flags).type_specific())
+        }
+    }
+
+    /// Whether this partition starts on an `alignment` (in LBAs) boundary.
+    ///
+    /// Use `Alignment::lbas()` to convert an alignment policy, or compare
+    /// against `GptDisk::alignment()`.
+    pub fn is_aligned(&self, alignment: u64) -> bool {
+        alignment == 0 || self.first_lba % alignment == 0
+    }
+
     /// Return the length (in bytes) of this partition.
     pub fn bytes_len(&self, lb_size: disk::LogicalBlockSize) -> Result<u64> {
-        let len = self.last_lba
+        let len = self
+            .last_lba
             .checked_sub(self.first_lba)
-            .ok_or_else(|| Error::new(ErrorKind::Other, "partition length underflow - sectors"))?
+            .ok_or(Error::Overflow("partition length in sectors"))?
             .checked_mul(lb_size.into())
-            .ok_or_else(|| Error::new(ErrorKind::Other, "partition length overflow - bytes"))?;
+            .ok_or(Error::Overflow("partition length in bytes"))?;
         Ok(len)
     }
 
     /// Return the starting offset (in bytes) of this partition.
     pub fn bytes_start(&self, lb_size: disk::LogicalBlockSize) -> Result<u64> {
-        let len = self.first_lba
+        let len = self
+            .first_lba
             .checked_mul(lb_size.into())
-            .ok_or_else(|| Error::new(ErrorKind::Other, "partition start overflow - bytes"))?;
+            .ok_or(Error::Overflow("partition start in bytes"))?;
         Ok(len)
     }
 }
 
+/// Trailing bytes shared by all Apple type-GUIDs.
+const APPLE_GUID_SUFFIX: [u8; 10] = [0x11, 0xAA, 0xAA, 0x11, 0x00, 0x30, 0x65, 0x43, 0xEC, 0xAC];
+
 /// Partition type, with optional description.
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct PartitionType {
     /// Type-GUID for a GPT partition.
     pub guid: uuid::Uuid,
@@ -152,6 +492,67 @@ pub struct PartitionType {
     pub description: String,
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for PartitionAttributes {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self::from_bits_retain(u.arbitrary()?))
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for PartitionType {
+    /// Either a well-known type, with its labels, or an unknown type-GUID.
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        if u.arbitrary()? {
+            Ok(u.arbitrary::<Type>()?.into())
+        } else {
+            Ok(PartitionType {
+                guid: uuid::Uuid::from_bytes(u.arbitrary()?),
+                os: String::new(),
+                description: String::new(),
+            })
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Partition {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Partition {
+            part_type_guid: u.arbitrary()?,
+            part_guid: uuid::Uuid::from_bytes(u.arbitrary()?),
+            first_lba: u.arbitrary()?,
+            last_lba: u.arbitrary()?,
+            flags: u.arbitrary()?,
+            name: u.arbitrary()?,
+            raw_name: None,
+            number: u.arbitrary()?,
+        })
+    }
+}
+
+impl PartitionType {
+    /// Build a partition type from its type-GUID, with well-known labels if any.
+    pub fn from_guid(guid: uuid::Uuid) -> Self {
+        parse_parttype_guid(guid)
+    }
+
+    /// Whether this is one of Apple's partition types (HFS+, APFS, etc.).
+    pub fn is_apple(&self) -> bool {
+        self.guid.as_bytes()[6..] == APPLE_GUID_SUFFIX
+    }
+}
+
+impl From<Type> for PartitionType {
+    fn from(t: Type) -> Self {
+        PartitionType {
+            guid: t.guid,
+            os: t.os.to_string(),
+            description: t.description.to_string(),
+        }
+    }
+}
+
 impl fmt::Display for Partition {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -169,30 +570,62 @@ impl fmt::Display for Partition {
     }
 }
 
-fn read_part_name(rdr: &mut Cursor<&[u8]>) -> Result<String> {
-    trace!("Reading partition name");
-    let mut namebytes: Vec<u16> = Vec::new();
-    for _ in 0..36 {
-        let b = rdr.read_u16::<LittleEndian>()?;
-        if b != 0 {
-            namebytes.push(b);
-        }
+/// Error returned by `Partition::name_strict()` for names which are not valid UTF-16.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NameError {
+    /// Index of the offending code unit in the name.
+    pub index: usize,
+    /// The unpaired surrogate code unit.
+    pub unit: u16,
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid UTF-16 in partition name: unpaired surrogate {:#06x} at unit {}",
+            self.unit, self.index
+        )
+    }
+}
+
+impl std::error::Error for NameError {}
+
+/// Decode a partition name, returning it and its raw units if they don't round-trip.
+fn read_part_name(raw: &[u8]) -> (String, Option<Vec<u16>>) {
+    let units: Vec<u16> = raw
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    let name = decode_name_lossy(&units);
+    let mut encoded: Vec<u16> = name.encode_utf16().collect();
+    encoded.resize(units.len(), 0);
+    if encoded == units {
+        (name, None)
+    } else {
+        (name, Some(units))
     }
+}
+
+/// Decode a UTF-16LE partition name, replacing invalid UTF-16 with U+FFFD.
+fn decode_name_lossy(units: &[u16]) -> String {
+    raw::decode_name(units)
+        .map(|(_, c)| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
+        .collect()
+}
 
-    Ok(String::from_utf16_lossy(&namebytes))
+fn decode_name_strict(units: &[u16]) -> std::result::Result<String, NameError> {
+    raw::decode_name(units)
+        .map(|(index, c)| c.map_err(|unit| NameError { index, unit }))
+        .collect()
 }
 
 fn parse_parttype_guid(u: uuid::Uuid) -> PartitionType {
-    let s = u.to_hyphenated().to_string().to_uppercase();
-    debug!("looking up partition type, GUID {}", s);
-    match PART_HASHMAP.get(&s) {
-        Some(part_id) => PartitionType {
-            guid: u,
-            os: part_id.0.into(),
-            description: part_id.1.into(),
-        },
+    debug!("looking up partition type, GUID {}", u);
+    match Type::from_guid(u) {
+        Some(t) => t.into(),
         None => {
-            error!("Unknown partition type: {}", s);
+            debug!("unknown partition type: {}", u);
             PartitionType {
                 guid: u,
                 os: "".to_string(),
@@ -202,7 +635,365 @@ fn parse_parttype_guid(u: uuid::Uuid) -> PartitionType {
     }
 }
 
-/// Read a GPT partition table.
+/// Well-known signature areas, as (offset from partition start, length) in bytes.
+///
+/// These cover boot sectors (FAT, NTFS, exFAT), XFS, LUKS, LVM2, bcache,
+/// ext2/3/4 and swap (4 KiB pages) in the first 8 KiB, then the LUKS2
+/// secondary header, ISO9660 volume descriptors, swap with 64 KiB pages,
+/// and the btrfs superblock.
+const HEAD_SIGNATURES: &[(u64, u64)] = &[
+    (0, 8 * 1024),
+    (16 * 1024, 4 * 1024),
+    (32 * 1024, 4 * 1024),
+    (60 * 1024, 8 * 1024),
+];
+
+/// Well-known signature areas at the end of a partition, as length in bytes.
+///
+/// This covers md RAID 0.90 and 1.0 superblocks.
+const TAIL_SIGNATURES: u64 = 128 * 1024;
+
+/// Return the byte ranges, relative to a partition of `len` bytes, that
+/// hold well-known filesystem/RAID/LUKS signatures.
+///
+/// Ranges are returned as (offset, length), clamped to the partition size.
+pub(crate) fn signature_ranges(len: u64) -> Vec<(u64, u64)> {
+    let tail = (len.saturating_sub(TAIL_SIGNATURES), TAIL_SIGNATURES);
+    HEAD_SIGNATURES
+        .iter()
+        .chain(std::iter::once(&tail))
+        .filter(|(offset, _)| *offset < len)
+        .map(|(offset, size)| (*offset, (*size).min(len - offset)))
+        .collect()
+}
+
+/// Serialize partition entries into a full partition array.
+///
+/// The array holds `num_parts` entries of `part_size` bytes, with unused
+/// entries zeroed.
+pub(crate) fn entries_as_bytes(
+    pp: &BTreeMap<u32, Partition>,
+    num_parts: u32,
+    part_size: u32,
+) -> Result<Vec<u8>> {
+    let entry_size =
+        u16::try_from(part_size).map_err(|_| Error::Overflow("partition entry size"))?;
+    let len = u64::from(num_parts)
+        .checked_mul(part_size.into())
+        .ok_or(Error::Overflow("partition array size"))?;
+    let mut buf = vec![0u8; len as usize];
+    for (id, p) in pp {
+        if *id == 0 || *id > num_parts {
+            return Err(Error::InvalidPartitionNumber(*id));
+        }
+        let start = (*id as usize - 1) * part_size as usize;
+        buf[start..start + part_size as usize].copy_from_slice(&p.as_bytes(entry_size)?);
+    }
+    Ok(buf)
+}
+
+/// Return the byte offset and content of a single entry of the partition array described by `h`.
+///
+/// The entry for partition number `id` is serialized from `entry`, or
+/// zeroed if `None`.
+pub(crate) fn entry_bytes(
+    h: &Header,
+    id: u32,
+    entry: Option<&Partition>,
+    lb_size: disk::LogicalBlockSize,
+) -> Result<(u64, Vec<u8>)> {
+    if id == 0 || id > h.num_parts {
+        return Err(Error::InvalidPartitionNumber(id));
+    }
+    let entry_size =
+        u16::try_from(h.part_size).map_err(|_| Error::Overflow("partition entry size"))?;
+    let offset = h
+        .part_start
+        .checked_mul(lb_size.into())
+        .and_then(|start| start.checked_add(u64::from(id - 1) * u64::from(h.part_size)))
+        .ok_or(Error::Overflow("partition entry offset"))?;
+    let bytes = match entry {
+        Some(p) => p.as_bytes(entry_size)?,
+        None => vec![0u8; entry_size.into()],
+    };
+    Ok((offset, bytes))
+}
+
+/// How to handle imported entries overlapping existing partitions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OverlapPolicy {
+    /// Fail the whole import.
+    Abort,
+    /// Leave the conflicting entry out.
+    Skip,
+    /// Move the conflicting entry to the first free region large enough.
+    Relocate,
+}
+
+/// How to assign unique GUIDs to imported entries.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GuidPolicy {
+    /// Keep original GUIDs, generating new ones only on collisions.
+    Preserve,
+    /// Always generate new GUIDs.
+    Regenerate,
+}
+
+/// Policy for importing partitions from another table.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ConflictPolicy {
+    /// Handling of overlapping entries.
+    pub overlap: OverlapPolicy,
+    /// Handling of unique GUIDs.
+    pub guids: GuidPolicy,
+}
+
+impl Default for ConflictPolicy {
+    fn default() -> Self {
+        Self {
+            overlap: OverlapPolicy::Abort,
+            guids: GuidPolicy::Preserve,
+        }
+    }
+}
+
+/// How to pick the free region for a new partition.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Allocation {
+    /// The first (lowest) free region large enough.
+    #[default]
+    FirstFit,
+    /// The smallest free region large enough, keeping large ones intact.
+    BestFit,
+}
+
+/// Alignment of new partitions, see `GptConfig::alignment()`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Alignment {
+    /// 1 MiB, as used by most partitioning tools. For block sizes which
+    /// do not divide 1 MiB (e.g. 520 bytes), the number of LBAs covering it.
+    Mebibyte,
+    /// A number of bytes, e.g. a RAID stripe width. It must be a multiple
+    /// of the logical block size.
+    Bytes(u64),
+    /// A number of logical blocks.
+    Sectors(u64),
+}
+
+impl Alignment {
+    /// Return this alignment in LBAs, or `None` if it is not a non-zero
+    /// whole number of logical blocks.
+    pub fn lbas(self, lb_size: disk::LogicalBlockSize) -> Option<u64> {
+        let lb = lb_size.bytes();
+        match self {
+            Alignment::Mebibyte => Some(MEBIBYTE.div_ceil(lb)),
+            Alignment::Bytes(bytes) if bytes > 0 && bytes % lb == 0 => Some(bytes / lb),
+            Alignment::Sectors(lbas) if lbas > 0 => Some(lbas),
+            _ => None,
+        }
+    }
+}
+
+impl From<u64> for Alignment {
+    /// Alignment of a number of bytes.
+    fn from(bytes: u64) -> Self {
+        Alignment::Bytes(bytes)
+    }
+}
+
+const MEBIBYTE: u64 = 1024 * 1024;
+
+/// A named range of LBAs held back from partition allocation.
+///
+/// Reserved regions are not partitions and are never written to disk,
+/// e.g. for firmware scratch areas or room for future growth.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReservedRegion {
+    /// Name of the region.
+    pub name: String,
+    /// First LBA of the region.
+    pub first_lba: u64,
+    /// Last LBA of the region (inclusive).
+    pub last_lba: u64,
+}
+
+/// An entry of the LBA map of a disk, see `GptDisk::layout_map()`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MapEntry {
+    /// A partition, by number.
+    Partition {
+        /// Partition number.
+        id: u32,
+        /// First LBA of the partition.
+        first_lba: u64,
+        /// Last LBA of the partition (inclusive).
+        last_lba: u64,
+    },
+    /// A reserved region.
+    Reserved(ReservedRegion),
+    /// Unallocated LBAs.
+    Free {
+        /// First free LBA.
+        first_lba: u64,
+        /// Last free LBA (inclusive).
+        last_lba: u64,
+    },
+}
+
+impl MapEntry {
+    /// Return the first and last LBAs of this entry.
+    pub fn range(&self) -> (u64, u64) {
+        match self {
+            MapEntry::Partition {
+                first_lba,
+                last_lba,
+                ..
+            }
+            | MapEntry::Free {
+                first_lba,
+                last_lba,
+            } => (*first_lba, *last_lba),
+            MapEntry::Reserved(r) => (r.first_lba, r.last_lba),
+        }
+    }
+}
+
+impl fmt::Display for MapEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (first, last) = self.range();
+        write!(f, "{} - {}\t", first, last)?;
+        match self {
+            MapEntry::Partition { id, .. } => write!(f, "partition {}", id),
+            MapEntry::Reserved(r) => write!(f, "reserved ({})", r.name),
+            MapEntry::Free { .. } => write!(f, "free"),
+        }
+    }
+}
+
+/// An identifier shared by several entries of a partition table.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Duplicate {
+    /// Several partitions share the same name (label).
+    Name(String, Vec<u32>),
+    /// Several partitions share the same unique GUID.
+    Guid(uuid::Uuid, Vec<u32>),
+}
+
+impl fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Duplicate::Name(name, ids) => {
+                write!(
+                    f,
+                    "duplicate partition name {:?} in entries {:?}",
+                    name, ids
+                )
+            }
+            Duplicate::Guid(guid, ids) => {
+                write!(f, "duplicate partition GUID {} in entries {:?}", guid, ids)
+            }
+        }
+    }
+}
+
+/// A pending change to a partition entry, see `GptDisk::pending_changes()`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PartitionChange {
+    /// A new entry, by partition number.
+    Added(u32, Partition),
+    /// An entry about to be cleared, by partition number.
+    Removed(u32, Partition),
+    /// An entry about to be rewritten with different content.
+    Modified {
+        /// Partition number.
+        id: u32,
+        /// Entry currently on disk.
+        old: Partition,
+        /// Entry about to be written.
+        new: Partition,
+    },
+}
+
+impl PartitionChange {
+    /// Return the number of the changed partition.
+    pub fn id(&self) -> u32 {
+        match self {
+            PartitionChange::Added(id, _)
+            | PartitionChange::Removed(id, _)
+            | PartitionChange::Modified { id, .. } => *id,
+        }
+    }
+}
+
+impl fmt::Display for PartitionChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PartitionChange::Added(id, p) => write!(
+                f,
+                "add partition {} {:?} ({} - {})",
+                id, p.name, p.first_lba, p.last_lba
+            ),
+            PartitionChange::Removed(id, p) => write!(
+                f,
+                "remove partition {} {:?} ({} - {})",
+                id, p.name, p.first_lba, p.last_lba
+            ),
+            PartitionChange::Modified { id, old, new } => {
+                write!(f, "modify partition {}:", id)?;
+                if (old.first_lba, old.last_lba) != (new.first_lba, new.last_lba) {
+                    write!(
+                        f,
+                        " range {} - {} -> {} - {}",
+                        old.first_lba, old.last_lba, new.first_lba, new.last_lba
+                    )?;
+                }
+                if old.name != new.name || old.raw_name != new.raw_name {
+                    write!(f, " name {:?} -> {:?}", old.name, new.name)?;
+                }
+                if old.part_type_guid.guid != new.part_type_guid.guid {
+                    write!(
+                        f,
+                        " type {} -> {}",
+                        old.part_type_guid.guid, new.part_type_guid.guid
+                    )?;
+                }
+                if old.part_guid != new.part_guid {
+                    write!(f, " GUID {} -> {}", old.part_guid, new.part_guid)?;
+                }
+                if old.flags != new.flags {
+                    write!(f, " flags {:#x} -> {:#x}", old.flags, new.flags)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Find names and unique GUIDs shared by several partitions.
+///
+/// Both break by-label and by-partuuid lookups. Empty names are ignored.
+pub fn find_duplicates(pp: &BTreeMap<u32, Partition>) -> Vec<Duplicate> {
+    let mut names: BTreeMap<&str, Vec<u32>> = BTreeMap::new();
+    let mut guids: BTreeMap<uuid::Uuid, Vec<u32>> = BTreeMap::new();
+    for (id, p) in pp {
+        if !p.name.is_empty() {
+            names.entry(&p.name).or_default().push(*id);
+        }
+        guids.entry(p.part_guid).or_default().push(*id);
+    }
+
+    let dup_guids = guids
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .map(|(guid, ids)| Duplicate::Guid(guid, ids));
+    let dup_names = names
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .map(|(name, ids)| Duplicate::Name(name.to_string(), ids));
+    dup_guids.chain(dup_names).collect()
+}
+
+/// Read a GPT partition table, keyed by partition number.
 ///
 /// ## Example
 ///
@@ -220,66 +1011,100 @@ pub fn read_partitions(
     path: &Path,
     header: &Header,
     lb_size: disk::LogicalBlockSize,
-) -> Result<Vec<Partition>> {
+) -> Result<BTreeMap<u32, Partition>> {
     debug!("reading partitions from file: {}", path.display());
     let mut file = File::open(path)?;
-    file_read_partitions(&mut file, header, lb_size)
+    read_partitions_from(&mut file, header, lb_size)
+}
+
+/// Read a GPT partition table from an open device, keyed by partition number.
+///
+/// Like `read_partitions()`, the partition array CRC32 is checked
+/// against `header`.
+pub fn read_partitions_from<D: Read + Seek>(
+    device: &mut D,
+    header: &Header,
+    lb_size: disk::LogicalBlockSize,
+) -> Result<BTreeMap<u32, Partition>> {
+    file_read_partitions(device, header, lb_size, &mut QuirkTracker::default())
 }
 
-/// Read a GPT partition table from an open `File` object.
-pub(crate) fn file_read_partitions(
-    file: &mut File,
+/// Check the partition array CRC32 over used entries only, if tolerated.
+fn used_entries_crc_matches(table: &[u8], header: &Header, quirks: &mut QuirkTracker) -> bool {
+    if !quirks.allows(Quirks::USED_ENTRIES_CRC) {
+        return false;
+    }
+    let entry_size = header.part_size as usize;
+    let used = table
+        .chunks_exact(entry_size)
+        .rposition(|entry| entry[16..32].iter().any(|b| *b != 0))
+        .map_or(0, |last| last + 1);
+    calculate_crc32(&table[..used * entry_size]) == header.crc32_parts
+        && quirks.tolerate(
+            Quirks::USED_ENTRIES_CRC,
+            &format!("partition array CRC32 over {} used entries", used),
+        )
+}
+
+/// Read a GPT partition table from an open device.
+pub(crate) fn file_read_partitions<D: Read + Seek>(
+    file: &mut D,
     header: &Header,
     lb_size: disk::LogicalBlockSize,
-) -> Result<Vec<Partition>> {
+    quirks: &mut QuirkTracker,
+) -> Result<BTreeMap<u32, Partition>> {
     let pstart = header
         .part_start
         .checked_mul(lb_size.into())
-        .ok_or_else(|| Error::new(ErrorKind::Other, "partition overflow - start offset"))?;
+        .ok_or(Error::Overflow("partition start offset"))?;
+    let pt_len = u64::from(header.num_parts)
+        .checked_mul(header.part_size.into())
+        .ok_or(Error::Overflow("partition array size"))?;
+    let entry_size = header.part_size as usize;
+    if entry_size < 128 {
+        return Err(Error::Invalid("partition entry size too small".into()));
+    }
+    if pt_len > MAX_ARRAY_LEN {
+        return Err(Error::Invalid("partition array too large".into()));
+    }
+
+    // Read the whole array at once, as a single buffer is needed for its CRC anyway.
     trace!("seeking to partitions start: {:#x}", pstart);
     let _ = file.seek(SeekFrom::Start(pstart))?;
-    let mut parts: Vec<Partition> = Vec::new();
-
-    trace!("scanning {} partitions", header.num_parts);
-    for _ in 0..header.num_parts {
-        let mut bytes: [u8; 56] = [0; 56];
-        let mut nameraw: [u8; 72] = [0; 72];
-
-        file.read_exact(&mut bytes)?;
-        file.read_exact(&mut nameraw)?;
+    let mut table = vec![0; pt_len as usize];
+    file.read_exact(&mut table)?;
 
-        let mut reader = Cursor::new(&bytes[..]);
-        let type_guid = parse_uuid(&mut reader)?;
-        let part_guid = parse_uuid(&mut reader)?;
+    debug!("checking partition table CRC");
+    let comp_crc = calculate_crc32(&table);
+    if comp_crc != header.crc32_parts && !used_entries_crc_matches(&table, header, quirks) {
+        return Err(Error::PartitionArrayCrcMismatch {
+            expected: header.crc32_parts,
+            found: comp_crc,
+        });
+    }
 
-        if part_guid.to_simple().to_string() == "00000000000000000000000000000000" {
+    trace!("scanning {} partitions", header.num_parts);
+    let mut parts: BTreeMap<u32, Partition> = BTreeMap::new();
+    for (i, entry) in (1..).zip(table.chunks_exact(entry_size)) {
+        // Unused entries have a nil unique GUID, skip them before any parsing.
+        if entry[16..32].iter().all(|b| *b == 0) {
             continue;
         }
 
-        let partname = read_part_name(&mut Cursor::new(&nameraw[..]))?;
+        let raw = RawEntry::parse(entry)?;
+        let (name, raw_name) = read_part_name(&entry[56..128]);
         let p: Partition = Partition {
-            part_type_guid: parse_parttype_guid(type_guid),
-            part_guid,
-            first_lba: reader.read_u64::<LittleEndian>()?,
-            last_lba: reader.read_u64::<LittleEndian>()?,
-            flags: reader.read_u64::<LittleEndian>()?,
-            name: partname.to_string(),
+            part_type_guid: parse_parttype_guid(raw.type_guid),
+            part_guid: raw.part_guid,
+            first_lba: raw.first_lba,
+            last_lba: raw.last_lba,
+            flags: raw.flags,
+            name,
+            raw_name,
+            number: i,
         };
 
-        parts.push(p);
-    }
-
-    debug!("checking partition table CRC");
-    let _ = file.seek(SeekFrom::Start(pstart))?;
-    let pt_len = u64::from(header.num_parts)
-        .checked_mul(header.part_size.into())
-        .ok_or_else(|| Error::new(ErrorKind::Other, "partitions - size"))?;
-    let mut table = vec![0; pt_len as usize];
-    file.read_exact(&mut table)?;
-
-    let comp_crc = crc32::checksum_ieee(&table);
-    if comp_crc != header.crc32_parts {
-        return Err(Error::new(ErrorKind::Other, "partition table CRC mismatch"));
+        parts.insert(i, p);
     }
 
     Ok(parts)
@@ -288,7 +1113,59 @@ pub(crate) fn file_read_partitions(
 #[cfg(test)]
 mod tests {
     use crate::disk;
+    use crate::header::{calculate_crc32, Header};
     use crate::partition;
+    use std::collections::BTreeMap;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_part_name_decoding() {
+        let mut raw = [0u8; 72];
+        for (i, u) in [0x61u16, 0xd800, 0x62].iter().enumerate() {
+            raw[2 * i..2 * i + 2].copy_from_slice(&u.to_le_bytes());
+        }
+        let (name, raw_name) = partition::read_part_name(&raw);
+        let mut p = partition::Partition::zero();
+        p.name = name;
+        p.raw_name = raw_name;
+        assert_eq!(p.name(), "a\u{fffd}b");
+        let err = p.name_strict().unwrap_err();
+        assert_eq!((err.index, err.unit), (1, 0xd800));
+        assert_eq!(&p.name_units()[..3], &[0x61, 0xd800, 0x62]);
+        assert_eq!(p.raw_name(), Some(&p.name_units()[..]));
+        // Invalid units are written back unchanged.
+        assert_eq!(&p.as_bytes(128).unwrap()[56..128], &raw[..]);
+
+        // Renaming drops the stored units.
+        p.name = "ok".to_string();
+        assert_eq!(p.name_strict().unwrap(), "ok");
+        assert_eq!(p.name_units(), vec![0x6f, 0x6b]);
+
+        let valid = partition::read_part_name(&p.as_bytes(128).unwrap()[56..128]);
+        assert_eq!(valid, ("ok".to_string(), None));
+    }
+
+    #[test]
+    fn test_part_name_decoders_agree() {
+        // A surrogate pair split by a NUL, then an unpaired surrogate.
+        let mut raw = [0u8; 72];
+        for (i, u) in [0xd83du16, 0, 0xde00, 0, 0xdc00].iter().enumerate() {
+            raw[2 * i..2 * i + 2].copy_from_slice(&u.to_le_bytes());
+        }
+        let (name, raw_name) = partition::read_part_name(&raw);
+        let mut p = partition::Partition::zero();
+        p.name = name;
+        p.raw_name = raw_name;
+        assert_eq!(p.name(), "\u{1f600}\u{fffd}");
+        let err = p.name_strict().unwrap_err();
+        assert_eq!((err.index, err.unit), (4, 0xdc00));
+
+        let mut entry = [0u8; 128];
+        entry[16] = 1;
+        entry[56..].copy_from_slice(&raw);
+        let entry = crate::raw::RawEntry::parse(&entry).unwrap();
+        assert_eq!(entry.name(), p.name());
+    }
 
     #[test]
     fn test_zero_part() {
@@ -303,6 +1180,35 @@ mod tests {
         assert_eq!(b256, vec![0u8; 256]);
     }
 
+    #[test]
+    fn test_alignment() {
+        use crate::disk::LogicalBlockSize;
+        use crate::partition::Alignment;
+
+        let lb512 = LogicalBlockSize::Lb512;
+        let lb4k = LogicalBlockSize::Lb4096;
+        assert_eq!(Alignment::Mebibyte.lbas(lb512), Some(2048));
+        assert_eq!(Alignment::Mebibyte.lbas(lb4k), Some(256));
+        assert_eq!(
+            Alignment::Mebibyte.lbas(LogicalBlockSize::try_from(520).unwrap()),
+            Some(2017)
+        );
+        assert_eq!(Alignment::from(65536).lbas(lb4k), Some(16));
+        assert_eq!(Alignment::Bytes(1000).lbas(lb512), None);
+        assert_eq!(Alignment::Bytes(0).lbas(lb512), None);
+        assert_eq!(Alignment::Sectors(7).lbas(lb4k), Some(7));
+        assert_eq!(Alignment::Sectors(0).lbas(lb4k), None);
+
+        let p = partition::Partition {
+            first_lba: 4096,
+            last_lba: 5000,
+            ..partition::Partition::zero()
+        };
+        assert!(p.is_aligned(2048));
+        assert!(p.is_aligned(1));
+        assert!(!p.is_aligned(3));
+    }
+
     #[test]
     fn test_part_bytes_len() {
         {
@@ -326,7 +1232,7 @@ mod tests {
         {
             // Overflowing u64 length.
             let mut p2 = partition::Partition::zero();
-            p2.last_lba = <u64>::max_value();
+            p2.last_lba = u64::MAX;
             p2.bytes_len(disk::LogicalBlockSize::Lb512).unwrap_err();
             p2.bytes_len(disk::LogicalBlockSize::Lb4096).unwrap_err();
         }
@@ -359,7 +1265,7 @@ mod tests {
         {
             // Overflowing u64 start.
             let mut p1 = partition::Partition::zero();
-            p1.first_lba = <u64>::max_value();
+            p1.first_lba = u64::MAX;
             p1.bytes_len(disk::LogicalBlockSize::Lb512).unwrap_err();
             p1.bytes_len(disk::LogicalBlockSize::Lb4096).unwrap_err();
         }
@@ -375,4 +1281,177 @@ mod tests {
             assert_eq!(b4096start, 2 * 4096);
         }
     }
+
+    #[test]
+    fn test_attributes_display_parse() {
+        let attrs = partition::PartitionAttributes::PLATFORM
+            | partition::PartitionAttributes::BOOTABLE
+            | partition::PartitionAttributes::from_bits_retain(1 << 56);
+        assert_eq!(attrs.to_string(), "required, legacy-boot, bit56");
+        assert_eq!(
+            attrs
+                .to_string()
+                .parse::<partition::PartitionAttributes>()
+                .unwrap(),
+            attrs
+        );
+        assert_eq!(partition::PartitionAttributes::empty().to_string(), "none");
+        assert!("none"
+            .parse::<partition::PartitionAttributes>()
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            " No-Block-IO ,bit63"
+                .parse::<partition::PartitionAttributes>()
+                .unwrap()
+                .bits(),
+            (1 << 63) | 2
+        );
+        assert!("bit64".parse::<partition::PartitionAttributes>().is_err());
+        assert!("hidden".parse::<partition::PartitionAttributes>().is_err());
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary_partition() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let raw: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+        let mut u = Unstructured::new(&raw);
+        for _ in 0..8 {
+            let p = partition::Partition::arbitrary(&mut u).unwrap();
+            assert_eq!(p.as_bytes(128).unwrap().len(), 128);
+            let known =
+                crate::partition_types::Type::all().any(|t| t.guid == p.part_type_guid.guid);
+            assert_eq!(known, !p.part_type_guid.description.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_part_number() {
+        let mut p = partition::Partition::zero();
+        let zero = p.clone();
+        p.number = 3;
+        assert_eq!(p.number(), 3);
+        assert_eq!(p, zero);
+    }
+
+    #[test]
+    fn test_part_set_name() {
+        let mut p = partition::Partition::zero();
+        // 18 characters outside the BMP take all 36 code units.
+        let emoji = "\u{1F600}".repeat(18);
+        p.set_name(&emoji).unwrap();
+        assert_eq!(p.name_units().len(), 36);
+        let bytes = p.as_bytes(128).unwrap();
+        assert_eq!(partition::read_part_name(&bytes[56..]).0, emoji);
+
+        p.set_name(&"x".repeat(37)).unwrap_err();
+        p.set_name("a\0b").unwrap_err();
+        assert_eq!(p.name, emoji);
+        p.set_name("données").unwrap();
+        assert_eq!(p.name(), "données");
+
+        // Names set directly are truncated on whole characters.
+        p.name = format!("a{}", emoji);
+        assert_eq!(p.name_units().len(), 35);
+    }
+
+    #[test]
+    fn test_type_attributes() {
+        use partition::{
+            ChromeOsAttributes, MicrosoftAttributes, PartitionAttributes, TypeAttributes,
+        };
+
+        let attrs = PartitionAttributes::PLATFORM.with_type_specific(0x8001);
+        assert_eq!(attrs.bits(), 1 | (1 << 48) | (1 << 63));
+        assert_eq!(attrs.type_specific(), 0x8001);
+        assert_eq!(attrs.with_type_specific(0), PartitionAttributes::PLATFORM);
+
+        let mut p = partition::Partition::zero();
+        p.flags = attrs.bits();
+        assert_eq!(p.type_attributes(), TypeAttributes::Other(0x8001));
+        p.part_type_guid = partition::PartitionType::from_guid(
+            uuid::Uuid::parse_str(partition::MICROSOFT_BASIC_DATA).unwrap(),
+        );
+        assert_eq!(
+            p.type_attributes(),
+            TypeAttributes::Microsoft(MicrosoftAttributes::NO_DRIVE_LETTER)
+        );
+
+        let cros = ChromeOsAttributes {
+            priority: 2,
+            tries: 15,
+            successful: true,
+        };
+        p.flags = cros.encode(p.flags);
+        assert_eq!(p.flags & 1, 1);
+        p.part_type_guid = partition::PartitionType::from_guid(
+            uuid::Uuid::parse_str(partition::CHROMEOS_KERNEL).unwrap(),
+        );
+        assert_eq!(p.type_attributes(), TypeAttributes::ChromeOs(cros));
+        assert_eq!(ChromeOsAttributes::decode(cros.encode(u64::MAX)), cros);
+    }
+
+    #[test]
+    fn test_signature_ranges() {
+        assert!(partition::signature_ranges(0).is_empty());
+        assert_eq!(
+            partition::signature_ranges(4096),
+            vec![(0, 4096), (0, 4096)]
+        );
+
+        let mib = 1024 * 1024;
+        let ranges = partition::signature_ranges(mib);
+        assert_eq!(ranges.len(), 5);
+        assert_eq!(ranges[0], (0, 8192));
+        assert_eq!(ranges[3], (60 * 1024, 8192));
+        assert_eq!(ranges[4], (mib - 128 * 1024, 128 * 1024));
+    }
+
+    #[test]
+    fn test_read_partitions_too_large() {
+        let lb = disk::LogicalBlockSize::Lb512;
+        let guid = uuid::Uuid::new_v4();
+        let mut h = Header::compute_new(true, &BTreeMap::new(), guid, 95, lb).unwrap();
+        h.crc32_parts = calculate_crc32(&[0u8; 128 * 128]);
+        let mut device = std::io::Cursor::new(vec![0u8; 95 * 512]);
+        assert!(partition::read_partitions_from(&mut device, &h, lb)
+            .unwrap()
+            .is_empty());
+
+        // Bogus headers are refused before allocating the array.
+        h.num_parts = u32::MAX;
+        h.part_size = 1024;
+        assert!(matches!(
+            partition::read_partitions_from(&mut device, &h, lb),
+            Err(crate::Error::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let mut pp = BTreeMap::new();
+        for (id, name) in [(1, "boot"), (2, "root"), (3, "root"), (4, ""), (5, "")].iter() {
+            let mut p = partition::Partition::zero();
+            p.part_guid = uuid::Uuid::new_v4();
+            p.name = name.to_string();
+            pp.insert(*id, p);
+        }
+        assert!(partition::find_duplicates(&BTreeMap::new()).is_empty());
+        assert_eq!(
+            partition::find_duplicates(&pp),
+            vec![partition::Duplicate::Name("root".to_string(), vec![2, 3])]
+        );
+
+        let guid = pp[&1].part_guid;
+        pp.get_mut(&5).unwrap().part_guid = guid;
+        assert_eq!(
+            partition::find_duplicates(&pp),
+            vec![
+                partition::Duplicate::Guid(guid, vec![1, 5]),
+                partition::Duplicate::Name("root".to_string(), vec![2, 3]),
+            ]
+        );
+    }
 }
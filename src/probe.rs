@@ -0,0 +1,294 @@
+//! Detection of stale or foreign partitioning metadata on a disk.
+//!
+//! Besides the partition table in use, disks often carry leftovers of
+//! previous partitionings: an MBR table under a freshly created GPT, or
+//! the backup header of an older GPT laid out for a smaller disk (e.g.
+//! an image written to a larger device). `scan()` reports all of them,
+//! so that provisioning tools can warn before overwriting anything.
+//!
+//! ```rust
+//! let mut image = std::fs::File::open("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+//! let labels = gpt::probe::scan(&mut image, false).unwrap();
+//! assert!(labels.iter().all(|l| !l.stale));
+//! ```
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::disk::{self, LogicalBlockSize};
+use crate::header::{self, calculate_crc32};
+use crate::mbr::MbrTable;
+use crate::{Error, Result};
+
+/// Logical block sizes tried when looking for GPT headers.
+const LB_SIZES: [LogicalBlockSize; 2] = [LogicalBlockSize::Lb512, LogicalBlockSize::Lb4096];
+/// Size of chunks read by a full scan.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Kind of partitioning structure found on a disk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Label {
+    /// MBR partition table, with its number of non-empty partition records.
+    Mbr {
+        /// Number of non-empty partition records.
+        partitions: usize,
+        /// Whether one of the records is a protective (0xEE) one.
+        protective: bool,
+    },
+    /// GPT header, as found on disk.
+    Gpt {
+        /// LBA of the header itself, as recorded in it.
+        current_lba: u64,
+        /// LBA of the other header, as recorded in it.
+        backup_lba: u64,
+        /// Disk GUID recorded in the header.
+        disk_guid: uuid::Uuid,
+        /// Whether the header CRC32 matches its content.
+        valid_crc: bool,
+    },
+}
+
+/// A partitioning structure found by `scan()`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Detection {
+    /// Byte offset of the structure on the disk.
+    pub offset: u64,
+    /// What was found.
+    pub label: Label,
+    /// Whether this is a leftover, not part of the partitioning in use.
+    pub stale: bool,
+}
+
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.stale { "stale" } else { "current" };
+        match &self.label {
+            Label::Mbr {
+                partitions,
+                protective,
+            } => {
+                let kind = if *protective { "protective MBR" } else { "MBR" };
+                write!(f, "{} {} with {} partition(s)", state, kind, partitions)
+            }
+            Label::Gpt {
+                current_lba,
+                valid_crc,
+                ..
+            } => {
+                let crc = if *valid_crc { "" } else { ", bad CRC32" };
+                write!(
+                    f,
+                    "{} GPT header for LBA {} at byte {:#x}{}",
+                    state, current_lba, self.offset, crc
+                )
+            }
+        }
+    }
+}
+
+/// Look for partition tables on a disk, current or stale.
+///
+/// LBA0 is checked for an MBR, and GPT headers are looked up at their
+/// standard locations for 512 and 4096 bytes sectors, then at the
+/// locations recorded in any header found and so on. With `full`, every
+/// 512 bytes sector of the disk is checked for a GPT signature too, which
+/// means reading the whole disk.
+///
+/// GPT headers are current if valid, and at LBA1 or at the last LBA of
+/// the disk. An MBR is stale if it is protective with no current GPT,
+/// or if it is a plain MBR next to a current GPT.
+pub fn scan<D: disk::DiskDevice>(device: &mut D, full: bool) -> Result<Vec<Detection>> {
+    let size = device.size()?;
+    let mut offsets = BTreeSet::new();
+    for lb_size in &LB_SIZES {
+        let lb = lb_size.bytes();
+        offsets.insert(lb);
+        offsets.insert(size.saturating_sub(lb) / lb * lb);
+    }
+    if full {
+        offsets.extend(scan_signatures(device, size)?);
+    }
+
+    let mut found = vec![];
+    let mut pending: Vec<u64> = offsets.iter().copied().collect();
+    while let Some(offset) = pending.pop() {
+        let label = match read_gpt(device, offset, size)? {
+            Some(label) => label,
+            None => continue,
+        };
+        if let Label::Gpt {
+            current_lba,
+            backup_lba,
+            ..
+        } = label
+        {
+            for lb in implied_lb_sizes(offset, current_lba) {
+                let other = backup_lba.checked_mul(lb).filter(|o| *o + 92 <= size);
+                if let Some(other) = other.filter(|o| offsets.insert(*o)) {
+                    pending.push(other);
+                }
+            }
+        }
+        let stale = !is_current(&label, offset, size);
+        found.push(Detection {
+            offset,
+            label,
+            stale,
+        });
+    }
+    found.sort_by_key(|d| d.offset);
+
+    let gpt_in_use = found.iter().any(|d| !d.stale);
+    if let Some(label) = read_mbr(device)? {
+        let protective = matches!(
+            label,
+            Label::Mbr {
+                protective: true,
+                ..
+            }
+        );
+        let stale = protective != gpt_in_use;
+        found.insert(
+            0,
+            Detection {
+                offset: 0,
+                label,
+                stale,
+            },
+        );
+    }
+    Ok(found)
+}
+
+/// Parse the MBR in LBA0, if any partition record is set.
+fn read_mbr<D: Read + Seek>(device: &mut D) -> Result<Option<Label>> {
+    let mbr = match MbrTable::from_disk(device, LogicalBlockSize::Lb512) {
+        Ok(mbr) => mbr,
+        // No MBR signature, or a disk smaller than a sector.
+        Err(Error::InvalidSignature | Error::TooShort) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let records: Vec<_> = mbr.partitions().iter().filter(|p| !p.is_empty()).collect();
+    if records.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Label::Mbr {
+        partitions: records.len(),
+        protective: records.iter().any(|p| p.os_type == 0xEE),
+    }))
+}
+
+/// Parse the GPT header at `offset`, if it has a valid signature.
+fn read_gpt<D: Read + Seek>(device: &mut D, offset: u64, size: u64) -> Result<Option<Label>> {
+    if offset + 92 > size {
+        return Ok(None);
+    }
+    let (h, mut raw) = header::file_read_header_unchecked(device, offset)?;
+    if h.signature != "EFI PART" {
+        return Ok(None);
+    }
+    raw[16..20].fill(0);
+    Ok(Some(Label::Gpt {
+        current_lba: h.current_lba,
+        backup_lba: h.backup_lba,
+        disk_guid: h.disk_guid,
+        valid_crc: calculate_crc32(&raw) == h.crc32,
+    }))
+}
+
+/// Return the sector sizes for which a header at `offset` is at `lba`.
+fn implied_lb_sizes(offset: u64, lba: u64) -> impl Iterator<Item = u64> {
+    LB_SIZES
+        .iter()
+        .map(|lb| lb.bytes())
+        .filter(move |lb| lba.checked_mul(*lb) == Some(offset))
+}
+
+/// Whether a GPT header is part of the table in use on a disk of `size` bytes.
+fn is_current(label: &Label, offset: u64, size: u64) -> bool {
+    match label {
+        Label::Gpt {
+            current_lba,
+            valid_crc: true,
+            ..
+        } => implied_lb_sizes(offset, *current_lba)
+            .any(|lb| *current_lba == 1 || *current_lba + 1 == size / lb),
+        _ => false,
+    }
+}
+
+/// Return the offsets of all 512 bytes sectors starting with a GPT signature.
+fn scan_signatures<D: Read + Seek>(device: &mut D, size: u64) -> Result<Vec<u64>> {
+    let sector = LogicalBlockSize::Lb512.bytes() as usize;
+    let mut offsets = vec![];
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut pos = 0;
+    device.seek(SeekFrom::Start(0))?;
+    while pos < size {
+        let n = (size - pos).min(CHUNK_SIZE as u64) as usize;
+        device.read_exact(&mut buf[..n])?;
+        for (i, chunk) in buf[..n].chunks(sector).enumerate() {
+            if chunk.starts_with(b"EFI PART") {
+                offsets.push(pos + (i * sector) as u64);
+            }
+        }
+        pos += n as u64;
+    }
+    Ok(offsets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{scan, Label};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_scan_stale_backup() {
+        let mut image = std::fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+        let found = scan(&mut Cursor::new(image.clone()), true).unwrap();
+        assert_eq!(found.len(), 3);
+        assert!(found.iter().all(|d| !d.stale), "{:?}", found);
+
+        // A plain MBR next to a GPT is a leftover.
+        let mut mbr = image.clone();
+        mbr[446 + 4] = 0x83;
+        let found = scan(&mut Cursor::new(mbr), false).unwrap();
+        assert!(found[0].stale);
+        assert_eq!(found[0].to_string(), "stale MBR with 1 partition(s)");
+
+        // Image copied to a larger disk: the backup header is left behind.
+        image.resize(200 * 512, 0);
+        let found = scan(&mut Cursor::new(image.clone()), false).unwrap();
+        let stale: Vec<_> = found.iter().filter(|d| d.stale).collect();
+        assert_eq!(stale.len(), 1, "{:?}", found);
+        assert_eq!(stale[0].offset, 95 * 512);
+
+        // Once a table is written for the whole disk, only a full scan finds it.
+        let image = crate::GptConfig::new()
+            .writable(true)
+            .quirks(crate::quirks::Quirks::MISPLACED_BACKUP)
+            .open_from_device(Cursor::new(image))
+            .unwrap()
+            .write()
+            .unwrap();
+        let found = scan(&mut image.clone(), false).unwrap();
+        assert!(found.iter().all(|d| !d.stale), "{:?}", found);
+        let found = scan(&mut image.clone(), true).unwrap();
+        let stale: Vec<_> = found.iter().filter(|d| d.stale).collect();
+        assert_eq!(stale.len(), 1, "{:?}", found);
+        assert!(matches!(
+            stale[0].label,
+            Label::Gpt {
+                current_lba: 95,
+                backup_lba: 1,
+                valid_crc: true,
+                ..
+            }
+        ));
+        assert_eq!(
+            stale[0].to_string(),
+            "stale GPT header for LBA 95 at byte 0xbe00"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//! Compatibility quirks of known-buggy firmware and tools.
+//!
+//! Some implementations emit GPT structures which deviate from the
+//! UEFI specification in small, well-understood ways. Quirks enabled in
+//! `GptConfig::quirks()` are tolerated on open instead of failing, and
+//! the ones actually encountered are reported by `GptDisk::quirks()`.
+//! Tables are always written back in the canonical format.
+
+use bitflags::*;
+use log::*;
+
+bitflags! {
+    /// Known deviations from the UEFI specification.
+    #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+    pub struct Quirks: u32 {
+        /// Header CRC32 field left as zero instead of being computed.
+        const ZERO_HEADER_CRC   = 1;
+        /// Backup header not at the last LBA of the disk, but where the
+        /// primary header says it is (e.g. after a disk image was grown).
+        const MISPLACED_BACKUP  = (1 << 1);
+        /// Partition array CRC32 computed only over the entries up to
+        /// the last used one, instead of the whole array.
+        const USED_ENTRIES_CRC  = (1 << 2);
+    }
+}
+
+/// Track tolerated quirks while parsing a disk.
+#[derive(Debug, Default)]
+pub(crate) struct QuirkTracker {
+    allowed: Quirks,
+    seen: Quirks,
+}
+
+impl QuirkTracker {
+    pub(crate) fn new(allowed: Quirks) -> Self {
+        Self {
+            allowed,
+            seen: Quirks::empty(),
+        }
+    }
+
+    /// Whether `quirk` is to be tolerated.
+    pub(crate) fn allows(&self, quirk: Quirks) -> bool {
+        self.allowed.contains(quirk)
+    }
+
+    /// Record `quirk` as encountered, if allowed, describing it as `what`.
+    ///
+    /// Return whether the deviation can be tolerated.
+    pub(crate) fn tolerate(&mut self, quirk: Quirks, what: &str) -> bool {
+        if !self.allows(quirk) {
+            return false;
+        }
+        warn!("tolerating known quirk: {}", what);
+        self.seen |= quirk;
+        true
+    }
+
+    /// Quirks which have been encountered so far.
+    pub(crate) fn seen(&self) -> Quirks {
+        self.seen
+    }
+
+    /// Merge quirks encountered by another tracker, e.g. in another thread.
+    pub(crate) fn merge(&mut self, other: &QuirkTracker) {
+        self.seen |= other.seen;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::quirks::{QuirkTracker, Quirks};
+
+    #[test]
+    fn test_quirk_tracker() {
+        let mut tracker = QuirkTracker::new(Quirks::ZERO_HEADER_CRC);
+        assert!(!tracker.tolerate(Quirks::USED_ENTRIES_CRC, "entries"));
+        assert!(tracker.seen().is_empty());
+        assert!(tracker.tolerate(Quirks::ZERO_HEADER_CRC, "zero crc"));
+
+        let mut other = QuirkTracker::new(Quirks::all());
+        assert!(other.tolerate(Quirks::MISPLACED_BACKUP, "backup"));
+        tracker.merge(&other);
+        assert_eq!(
+            tracker.seen(),
+            Quirks::ZERO_HEADER_CRC | Quirks::MISPLACED_BACKUP
+        );
+    }
+}
@@ -0,0 +1,394 @@
+//! Byte-level parsing and serialization of GPT structures, without `std`.
+//!
+//! This module only depends on `core` and `alloc`, and is all there is
+//! to the crate with the `std` feature disabled, e.g. for bootloaders and
+//! UEFI applications. It works on byte slices, reading them from a disk is
+//! up to the caller; the `header`, `partition` and `mbr` modules build on
+//! it for the rest of the crate.
+//!
+//! ```rust
+//! let image = std::fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+//! let h = gpt::raw::RawHeader::parse_checked(&image[512..1024]).unwrap();
+//! let start = h.part_start as usize * 512;
+//! let array = &image[start..start + (h.num_parts * h.part_size) as usize];
+//! assert_eq!(gpt::raw::crc32(array), h.crc32_parts);
+//! let (number, entry) = gpt::raw::entries(array, h.part_size as usize).next().unwrap();
+//! assert_eq!((number, entry.name().as_str()), (1, "primary"));
+//! ```
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{Error, Result};
+
+/// Signature at the start of GPT headers.
+pub const SIGNATURE: &[u8; 8] = b"EFI PART";
+/// Length of the fields of a GPT header, as covered by its CRC32.
+pub const HEADER_LEN: usize = 92;
+/// Length of the fields of a partition entry.
+pub const ENTRY_LEN: usize = 128;
+/// Length of an MBR, in LBA0.
+pub const MBR_LEN: usize = 512;
+/// Maximum length of a partition name, in UTF-16 code units.
+pub const NAME_UNITS: usize = 36;
+
+/// Compute the CRC32 (IEEE) of a buffer, as used by GPT headers and arrays.
+pub fn crc32(buf: &[u8]) -> u32 {
+    crc32fast::hash(buf)
+}
+
+/// Fields of a GPT header, as stored on disk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RawHeader {
+    /// Signature, "EFI PART" for valid headers.
+    pub signature: [u8; 8],
+    /// Revision, 0x00010000 for UEFI 2.x.
+    pub revision: u32,
+    /// Size of the header, in bytes.
+    pub header_size: u32,
+    /// CRC32 of the header, with this field zeroed.
+    pub crc32: u32,
+    /// Reserved, must be zero.
+    pub reserved: u32,
+    /// LBA of this header.
+    pub current_lba: u64,
+    /// LBA of the other header.
+    pub backup_lba: u64,
+    /// First usable LBA for partitions.
+    pub first_usable: u64,
+    /// Last usable LBA for partitions.
+    pub last_usable: u64,
+    /// Disk GUID.
+    pub disk_guid: uuid::Uuid,
+    /// Starting LBA of the partition array.
+    pub part_start: u64,
+    /// Number of partition entries.
+    pub num_parts: u32,
+    /// Size of a partition entry, in bytes.
+    pub part_size: u32,
+    /// CRC32 of the partition array.
+    pub crc32_parts: u32,
+}
+
+impl RawHeader {
+    /// Parse the first `HEADER_LEN` bytes of `buf`, without checking them.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        let buf = buf.get(..HEADER_LEN).ok_or(Error::TooShort)?;
+        let mut signature = [0; 8];
+        signature.copy_from_slice(&buf[..8]);
+        Ok(Self {
+            signature,
+            revision: u32_at(buf, 8),
+            header_size: u32_at(buf, 12),
+            crc32: u32_at(buf, 16),
+            reserved: u32_at(buf, 20),
+            current_lba: u64_at(buf, 24),
+            backup_lba: u64_at(buf, 32),
+            first_usable: u64_at(buf, 40),
+            last_usable: u64_at(buf, 48),
+            disk_guid: guid_at(buf, 56),
+            part_start: u64_at(buf, 72),
+            num_parts: u32_at(buf, 80),
+            part_size: u32_at(buf, 84),
+            crc32_parts: u32_at(buf, 88),
+        })
+    }
+
+    /// Parse a header, checking its signature and CRC32.
+    pub fn parse_checked(buf: &[u8]) -> Result<Self> {
+        let h = Self::parse(buf)?;
+        if &h.signature != SIGNATURE {
+            return Err(Error::InvalidSignature);
+        }
+        let found = h.checksum();
+        if found != h.crc32 {
+            return Err(Error::HeaderCrcMismatch {
+                expected: h.crc32,
+                found,
+            });
+        }
+        Ok(h)
+    }
+
+    /// Serialize this header, as is.
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut buf = [0; HEADER_LEN];
+        buf[..8].copy_from_slice(&self.signature);
+        buf[8..12].copy_from_slice(&self.revision.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.header_size.to_le_bytes());
+        buf[16..20].copy_from_slice(&self.crc32.to_le_bytes());
+        buf[20..24].copy_from_slice(&self.reserved.to_le_bytes());
+        buf[24..32].copy_from_slice(&self.current_lba.to_le_bytes());
+        buf[32..40].copy_from_slice(&self.backup_lba.to_le_bytes());
+        buf[40..48].copy_from_slice(&self.first_usable.to_le_bytes());
+        buf[48..56].copy_from_slice(&self.last_usable.to_le_bytes());
+        buf[56..72].copy_from_slice(&guid_to_bytes(&self.disk_guid));
+        buf[72..80].copy_from_slice(&self.part_start.to_le_bytes());
+        buf[80..84].copy_from_slice(&self.num_parts.to_le_bytes());
+        buf[84..88].copy_from_slice(&self.part_size.to_le_bytes());
+        buf[88..92].copy_from_slice(&self.crc32_parts.to_le_bytes());
+        buf
+    }
+
+    /// Compute the CRC32 of this header, with the `crc32` field zeroed.
+    pub fn checksum(&self) -> u32 {
+        let mut buf = self.to_bytes();
+        buf[16..20].fill(0);
+        crc32(&buf)
+    }
+}
+
+/// Fields of a partition entry, as stored on disk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RawEntry {
+    /// Partition type GUID.
+    pub type_guid: uuid::Uuid,
+    /// Unique partition GUID, nil for unused entries.
+    pub part_guid: uuid::Uuid,
+    /// First LBA of the partition.
+    pub first_lba: u64,
+    /// Last LBA of the partition, inclusive.
+    pub last_lba: u64,
+    /// Attribute bits.
+    pub flags: u64,
+    /// Partition name, as UTF-16 code units padded with NULs.
+    pub name: [u16; NAME_UNITS],
+}
+
+impl RawEntry {
+    /// Parse the first `ENTRY_LEN` bytes of `buf`.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        let buf = buf.get(..ENTRY_LEN).ok_or(Error::TooShort)?;
+        let mut name = [0; NAME_UNITS];
+        for (unit, c) in name.iter_mut().zip(buf[56..].chunks_exact(2)) {
+            *unit = u16::from_le_bytes([c[0], c[1]]);
+        }
+        Ok(Self {
+            type_guid: guid_at(buf, 0),
+            part_guid: guid_at(buf, 16),
+            first_lba: u64_at(buf, 32),
+            last_lba: u64_at(buf, 40),
+            flags: u64_at(buf, 48),
+            name,
+        })
+    }
+
+    /// Serialize this entry, zero-padded to `entry_size` bytes (at least `ENTRY_LEN`).
+    pub fn to_bytes(&self, entry_size: usize) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(entry_size.max(ENTRY_LEN));
+        buf.extend_from_slice(&guid_to_bytes(&self.type_guid));
+        buf.extend_from_slice(&guid_to_bytes(&self.part_guid));
+        buf.extend_from_slice(&self.first_lba.to_le_bytes());
+        buf.extend_from_slice(&self.last_lba.to_le_bytes());
+        buf.extend_from_slice(&self.flags.to_le_bytes());
+        for unit in &self.name {
+            buf.extend_from_slice(&unit.to_le_bytes());
+        }
+        buf.resize(entry_size.max(ENTRY_LEN), 0);
+        buf
+    }
+
+    /// Whether this entry is unused, i.e. has a nil partition GUID.
+    pub fn is_unused(&self) -> bool {
+        self.part_guid.is_nil()
+    }
+
+    /// Decode the partition name, replacing invalid UTF-16 with U+FFFD.
+    pub fn name(&self) -> String {
+        decode_name(&self.name)
+            .map(|(_, c)| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+}
+
+/// Decode UTF-16 name units, skipping NUL code units.
+///
+/// Yields each character with the index of its first unit in `units`, or
+/// the unpaired surrogate found there. NULs are skipped before decoding,
+/// so that lossy and strict decoding always agree on the valid characters.
+pub(crate) fn decode_name(
+    units: &[u16],
+) -> impl Iterator<Item = (usize, core::result::Result<char, u16>)> + '_ {
+    let mut indexes = (0..units.len()).filter(move |i| units[*i] != 0);
+    core::char::decode_utf16(units.iter().cloned().filter(|u| *u != 0)).map(move |c| {
+        let index = indexes.next().unwrap_or_default();
+        match c {
+            Ok(c) => {
+                if c.len_utf16() == 2 {
+                    indexes.next();
+                }
+                (index, Ok(c))
+            }
+            Err(e) => (index, Err(e.unpaired_surrogate())),
+        }
+    })
+}
+
+/// Iterate over the used entries of a partition array, with 1-based partition numbers.
+///
+/// The array checksum is not checked, see `crc32()`.
+pub fn entries(array: &[u8], part_size: usize) -> impl Iterator<Item = (u32, RawEntry)> + '_ {
+    (1..)
+        .zip(array.chunks_exact(part_size.max(ENTRY_LEN)))
+        .filter(|(_, raw)| raw[16..32].iter().any(|b| *b != 0))
+        .filter_map(|(i, raw)| RawEntry::parse(raw).ok().map(|e| (i, e)))
+}
+
+/// An MBR partition record, as stored on disk.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RawRecord {
+    /// Boot indicator, 0x80 for active (bootable) partitions.
+    pub boot_indicator: u8,
+    /// CHS start address.
+    pub start_chs: [u8; 3],
+    /// Partition type (OS indicator).
+    pub os_type: u8,
+    /// CHS end address.
+    pub end_chs: [u8; 3],
+    /// Starting LBA of the partition.
+    pub lb_start: u32,
+    /// Size of the partition (in LB).
+    pub lb_size: u32,
+}
+
+impl RawRecord {
+    /// Parse a 16 bytes partition record.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        let buf = buf.get(..16).ok_or(Error::TooShort)?;
+        Ok(Self {
+            boot_indicator: buf[0],
+            start_chs: [buf[1], buf[2], buf[3]],
+            os_type: buf[4],
+            end_chs: [buf[5], buf[6], buf[7]],
+            lb_start: u32_at(buf, 8),
+            lb_size: u32_at(buf, 12),
+        })
+    }
+
+    /// Serialize this partition record.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut buf = [0; 16];
+        buf[0] = self.boot_indicator;
+        buf[1..4].copy_from_slice(&self.start_chs);
+        buf[4] = self.os_type;
+        buf[5..8].copy_from_slice(&self.end_chs);
+        buf[8..12].copy_from_slice(&self.lb_start.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.lb_size.to_le_bytes());
+        buf
+    }
+}
+
+/// Parse the four partition records of an MBR, checking its boot signature.
+pub fn mbr_records(lba0: &[u8]) -> Result<[RawRecord; 4]> {
+    let lba0 = lba0.get(..MBR_LEN).ok_or(Error::TooShort)?;
+    if lba0[510..] != [0x55, 0xAA] {
+        return Err(Error::InvalidSignature);
+    }
+    let mut records = [RawRecord::default(); 4];
+    for (record, raw) in records.iter_mut().zip(lba0[446..510].chunks_exact(16)) {
+        *record = RawRecord::parse(raw)?;
+    }
+    Ok(records)
+}
+
+/// Build a protective MBR for a disk of `disk_size_lba` logical blocks.
+///
+/// The protective record covers the whole disk after LBA0, capped to
+/// `0xFFFFFFFF` LBs; bootcode and disk signature are zeroed.
+pub fn protective_mbr(disk_size_lba: u64) -> [u8; MBR_LEN] {
+    let lb_size = disk_size_lba.saturating_sub(1).min(u64::from(u32::MAX)) as u32;
+    let record = RawRecord {
+        start_chs: [0x00, 0x02, 0x00],
+        os_type: 0xEE,
+        end_chs: [0xFF, 0xFF, 0xFF],
+        lb_start: 1,
+        lb_size,
+        ..RawRecord::default()
+    };
+    let mut buf = [0; MBR_LEN];
+    buf[446..462].copy_from_slice(&record.to_bytes());
+    buf[510] = 0x55;
+    buf[511] = 0xAA;
+    buf
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(&buf[at..at + 4]);
+    u32::from_le_bytes(b)
+}
+
+fn u64_at(buf: &[u8], at: usize) -> u64 {
+    let mut b = [0; 8];
+    b.copy_from_slice(&buf[at..at + 8]);
+    u64::from_le_bytes(b)
+}
+
+/// Parse a GUID at `at`, with its first three fields in little endian.
+fn guid_at(buf: &[u8], at: usize) -> uuid::Uuid {
+    let b = &buf[at..at + 16];
+    let mut bytes = [0; 16];
+    bytes[..4].copy_from_slice(&[b[3], b[2], b[1], b[0]]);
+    bytes[4..6].copy_from_slice(&[b[5], b[4]]);
+    bytes[6..8].copy_from_slice(&[b[7], b[6]]);
+    bytes[8..].copy_from_slice(&b[8..]);
+    uuid::Uuid::from_bytes(bytes)
+}
+
+/// Serialize a GUID, with its first three fields in little endian.
+fn guid_to_bytes(guid: &uuid::Uuid) -> [u8; 16] {
+    let (d1, d2, d3, d4) = guid.as_fields();
+    let mut bytes = [0; 16];
+    bytes[..4].copy_from_slice(&d1.to_le_bytes());
+    bytes[4..6].copy_from_slice(&d2.to_le_bytes());
+    bytes[6..8].copy_from_slice(&d3.to_le_bytes());
+    bytes[8..].copy_from_slice(d4);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mbr_records, protective_mbr, RawEntry, RawHeader};
+    use crate::Error;
+
+    #[test]
+    fn test_raw_roundtrip() {
+        let image = std::fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+        let h = RawHeader::parse_checked(&image[512..]).unwrap();
+        assert_eq!(h.to_bytes()[..], image[512..512 + 92]);
+        assert_eq!(
+            h.disk_guid.to_string().to_uppercase(),
+            crate::GptConfig::new()
+                .open(std::path::Path::new("tests/fixtures/gpt-linux-disk-01.img"))
+                .unwrap()
+                .guid()
+                .to_string()
+                .to_uppercase()
+        );
+        let mut corrupted = image[512..1024].to_vec();
+        corrupted[24] ^= 1;
+        assert!(matches!(
+            RawHeader::parse_checked(&corrupted),
+            Err(Error::HeaderCrcMismatch { expected, .. }) if expected == h.crc32
+        ));
+        assert!(matches!(
+            RawHeader::parse(&image[..10]),
+            Err(Error::TooShort)
+        ));
+
+        let entry = &image[1024..1024 + 128];
+        let e = RawEntry::parse(entry).unwrap();
+        assert_eq!((e.first_lba, e.last_lba), (34, 62));
+        assert_eq!(e.to_bytes(128), entry);
+
+        let records = mbr_records(&image).unwrap();
+        assert_eq!(records[0].os_type, 0xEE);
+        assert_eq!(records[0].lb_size, 95);
+        let pmbr = mbr_records(&protective_mbr(96)).unwrap();
+        assert_eq!(
+            (pmbr[0].os_type, pmbr[0].lb_start, pmbr[0].lb_size),
+            (0xEE, 1, 95)
+        );
+        assert_eq!(pmbr[1..], records[1..]);
+    }
+}
@@ -0,0 +1,118 @@
+//! Raw backups of the partition table, in the layout used by `sgdisk -b`.
+//!
+//! A backup is made of 512-byte blocks: the protective MBR, the primary
+//! header and the backup header, each in its own block, followed by the
+//! primary partition array. The backup partition array is appended after
+//! it; `sgdisk` ignores it, and it is optional on restore.
+
+use std::convert::TryFrom;
+use std::io::{Cursor, Error, Read, Result, Write};
+
+use crate::disk;
+use crate::header::{self, Header};
+use crate::quirks::QuirkTracker;
+
+/// Size of the MBR and header blocks of a backup.
+const BLOCK_SIZE: usize = 512;
+
+/// Partition table structures, as saved in a backup.
+pub(crate) struct TableBackup {
+    /// Raw LBA0, i.e. the protective MBR.
+    pub(crate) mbr: Vec<u8>,
+    pub(crate) primary: Header,
+    pub(crate) backup: Header,
+    /// Raw partition array, matching the primary header checksum.
+    pub(crate) array: Vec<u8>,
+}
+
+impl TableBackup {
+    /// Write this backup to `out`, with `backup_array` appended.
+    pub(crate) fn save<W: Write>(&self, out: &mut W, backup_array: &[u8]) -> Result<()> {
+        out.write_all(&block(&self.mbr))?;
+        for h in &[&self.primary, &self.backup] {
+            let (_, bytes) = h.exact_bytes(disk::DEFAULT_SECTOR_SIZE)?;
+            out.write_all(&block(&bytes))?;
+        }
+        out.write_all(&self.array)?;
+        out.write_all(backup_array)?;
+        out.flush()
+    }
+
+    /// Read a backup from `input`, checking both headers and the partition array.
+    ///
+    /// The primary array is used if its checksum matches, otherwise
+    /// the appended backup array is tried.
+    pub(crate) fn load<R: Read>(input: &mut R) -> Result<Self> {
+        let mut data = vec![];
+        input.read_to_end(&mut data)?;
+        if data.len() < 3 * BLOCK_SIZE {
+            return Err(Error::other("partition table backup too short"));
+        }
+        let mut quirks = QuirkTracker::default();
+        let mut cur = Cursor::new(&data[..]);
+        let primary = header::file_read_header(&mut cur, BLOCK_SIZE as u64, &mut quirks)?;
+        let backup = header::file_read_header(&mut cur, 2 * BLOCK_SIZE as u64, &mut quirks)?;
+        if (primary.num_parts, primary.part_size) != (backup.num_parts, backup.part_size) {
+            return Err(Error::other("headers disagree on partition array size"));
+        }
+        let len = u64::from(primary.num_parts) * u64::from(primary.part_size);
+        let len = usize::try_from(len).map_err(|_| Error::other("partition array too large"))?;
+
+        let arrays = &data[3 * BLOCK_SIZE..];
+        let array = arrays
+            .chunks(len.max(1))
+            .take(2)
+            .find(|a| a.len() == len && header::calculate_crc32(a) == primary.crc32_parts)
+            .ok_or_else(|| {
+                let found = header::calculate_crc32(&arrays[..len.min(arrays.len())]);
+                Error::other(crate::Error::PartitionArrayCrcMismatch {
+                    expected: primary.crc32_parts,
+                    found,
+                })
+            })?
+            .to_vec();
+        Ok(Self {
+            mbr: data[..BLOCK_SIZE].to_vec(),
+            primary,
+            backup,
+            array,
+        })
+    }
+
+    /// Move the backup header and partition array to `backup_lba`, e.g. on a larger disk.
+    ///
+    /// Both header checksums are updated, and the protective partition
+    /// record of the MBR (if any) is extended to the new disk size.
+    pub(crate) fn relocate_backup(&mut self, backup_lba: u64) -> Result<()> {
+        let shift = backup_lba - self.backup.current_lba;
+        self.backup.part_start += shift;
+        self.backup.current_lba = backup_lba;
+        self.primary.backup_lba = backup_lba;
+        self.primary.crc32 = self.primary.checksum()?;
+        self.backup.crc32 = self.backup.checksum()?;
+
+        // First partition record: type at offset 4, start LBA at 8 and size at 12.
+        let record = &mut self.mbr[446..462];
+        if record[4] == 0xEE && record[8..12] == 1u32.to_le_bytes() {
+            let size = u32::try_from(backup_lba).unwrap_or(u32::MAX);
+            record[12..16].copy_from_slice(&size.to_le_bytes());
+        }
+        Ok(())
+    }
+}
+
+/// Return the byte offset of header `h`, and its content padded to a whole logical block.
+pub(crate) fn header_sector(h: &Header, lb_size: disk::LogicalBlockSize) -> Result<(u64, Vec<u8>)> {
+    let (offset, bytes) = h.exact_bytes(lb_size)?;
+    let mut sector = vec![0u8; lb_size.bytes() as usize];
+    sector[..bytes.len()].copy_from_slice(&bytes);
+    Ok((offset, sector))
+}
+
+/// Pad (or truncate) `bytes` to a single block.
+fn block(bytes: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0u8; BLOCK_SIZE];
+    let n = bytes.len().min(BLOCK_SIZE);
+    buf[..n].copy_from_slice(&bytes[..n]);
+    buf
+}
@@ -0,0 +1,213 @@
+//! UEFI integration, through the `EFI_BLOCK_IO_PROTOCOL`.
+//!
+//! Pre-boot tools get at disks through the Block I/O protocol, which only
+//! transfers whole blocks from buffers aligned as the media requires.
+//! `BlockIoDevice` implements block-sized transfers on top of it, and
+//! `open_block_io()` wraps it in a `disk::AlignedDevice` for use with
+//! `GptConfig::open_from_device()`.
+//!
+//! Like the rest of the `GptDisk` API, this module needs `std`: the
+//! application must be built for a UEFI target with the standard library
+//! (e.g. `x86_64-unknown-uefi` with `-Zbuild-std`, see the rustc platform
+//! support notes). `no_std` uefi-rs applications can still parse tables
+//! read through Block I/O with the `raw` module.
+//!
+//! ```rust,no_run
+//! # fn inspect(handle: ::uefi::Handle) {
+//! let block_io =
+//!     ::uefi::boot::open_protocol_exclusive::<::uefi::proto::media::block::BlockIO>(handle)
+//!         .unwrap();
+//! let (device, lb_size) = gpt::uefi::open_block_io(block_io).unwrap();
+//! let disk = gpt::GptConfig::new()
+//!     .logical_block_size(lb_size)
+//!     .open_from_device(device)
+//!     .unwrap();
+//! println!("{:#?}", disk.partitions());
+//! # }
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::DerefMut;
+
+use ::uefi::proto::media::block::BlockIO;
+
+use crate::disk;
+
+/// Block device over the Block I/O protocol, transferring whole blocks only.
+///
+/// `D` is any handle to the protocol, e.g. a `uefi::boot::ScopedProtocol`
+/// or a plain `&mut BlockIO`.
+pub struct BlockIoDevice<D> {
+    block_io: D,
+    media_id: u32,
+    block_size: u64,
+    io_align: usize,
+    size: u64,
+    pos: u64,
+}
+
+impl<D: DerefMut<Target = BlockIO>> BlockIoDevice<D> {
+    /// Wrap the Block I/O protocol of the current media.
+    ///
+    /// This fails if no media is present.
+    pub fn new(block_io: D) -> io::Result<Self> {
+        let media = block_io.media();
+        if !media.is_media_present() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no media present"));
+        }
+        let block_size = u64::from(media.block_size());
+        let size = media
+            .last_block()
+            .checked_add(1)
+            .and_then(|blocks| blocks.checked_mul(block_size))
+            .ok_or_else(|| io::Error::other("media size overflow"))?;
+        Ok(Self {
+            media_id: media.media_id(),
+            block_size,
+            io_align: usize::try_from(media.io_align()).unwrap_or(1).max(1),
+            size,
+            pos: 0,
+            block_io,
+        })
+    }
+
+    /// Return the logical block size of the media.
+    pub fn logical_block_size(&self) -> io::Result<disk::LogicalBlockSize> {
+        Ok(disk::LogicalBlockSize::try_from(self.block_size)?)
+    }
+
+    /// Whether the media is write-protected.
+    pub fn is_read_only(&self) -> bool {
+        self.block_io.media().is_read_only()
+    }
+
+    /// Return the wrapped protocol handle.
+    pub fn into_inner(self) -> D {
+        self.block_io
+    }
+
+    /// Return the LBA at the current position, which must be block-aligned.
+    fn current_lba(&self, len: usize) -> io::Result<u64> {
+        if self.pos % self.block_size != 0 || (len as u64) % self.block_size != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "unaligned block I/O transfer",
+            ));
+        }
+        Ok(self.pos / self.block_size)
+    }
+}
+
+/// Allocate a zeroed buffer of `len` bytes, returning it with the offset
+/// of its `align`-aligned part.
+fn aligned_buffer(len: usize, align: usize) -> (Vec<u8>, usize) {
+    let buf = vec![0u8; len + align - 1];
+    let offset = buf.as_ptr().align_offset(align);
+    (buf, offset)
+}
+
+/// Convert a Block I/O error into an `io::Error`.
+fn block_io_error(e: ::uefi::Error) -> io::Error {
+    io::Error::other(format!("block I/O: {}", e.status()))
+}
+
+impl<D: DerefMut<Target = BlockIO>> Read for BlockIoDevice<D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.size.saturating_sub(self.pos) as usize);
+        if len == 0 {
+            return Ok(0);
+        }
+        let lba = self.current_lba(len)?;
+        let (mut aligned, offset) = aligned_buffer(len, self.io_align);
+        let aligned = &mut aligned[offset..offset + len];
+        self.block_io
+            .read_blocks(self.media_id, lba, aligned)
+            .map_err(block_io_error)?;
+        buf[..len].copy_from_slice(aligned);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<D: DerefMut<Target = BlockIO>> Write for BlockIoDevice<D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pos + buf.len() as u64 > self.size {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "write past the end of device",
+            ));
+        }
+        let lba = self.current_lba(buf.len())?;
+        let (mut aligned, offset) = aligned_buffer(buf.len(), self.io_align);
+        let aligned = &mut aligned[offset..offset + buf.len()];
+        aligned.copy_from_slice(buf);
+        self.block_io
+            .write_blocks(self.media_id, lba, aligned)
+            .map_err(block_io_error)?;
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.block_io.flush_blocks().map_err(block_io_error)
+    }
+}
+
+impl<D: DerefMut<Target = BlockIO>> Seek for BlockIoDevice<D> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = disk::seek_position(self.pos, self.size, pos)?;
+        Ok(self.pos)
+    }
+}
+
+impl<D> fmt::Debug for BlockIoDevice<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockIoDevice")
+            .field("media_id", &self.media_id)
+            .field("block_size", &self.block_size)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl<D: DerefMut<Target = BlockIO>> disk::DiskDevice for BlockIoDevice<D> {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.size)
+    }
+}
+
+/// Open a disk through its Block I/O protocol, for use with `GptConfig::open_from_device()`.
+///
+/// The logical block size of the media is returned along with the
+/// device, to be passed to `GptConfig::logical_block_size()`.
+pub fn open_block_io<D: DerefMut<Target = BlockIO>>(
+    block_io: D,
+) -> io::Result<(
+    disk::AlignedDevice<BlockIoDevice<D>>,
+    disk::LogicalBlockSize,
+)> {
+    let device = BlockIoDevice::new(block_io)?;
+    let lb_size = device.logical_block_size()?;
+    let size = device.size;
+    Ok((disk::AlignedDevice::new(device, lb_size, size), lb_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::aligned_buffer;
+
+    #[test]
+    fn test_aligned_buffer() {
+        for align in &[1, 2, 512, 4096] {
+            let (buf, offset) = aligned_buffer(1024, *align);
+            assert!(offset < *align);
+            assert!(buf[offset..].len() >= 1024);
+            assert_eq!(buf[offset..].as_ptr() as usize % align, 0);
+        }
+    }
+}
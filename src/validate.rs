@@ -0,0 +1,490 @@
+//! Validation of partition tables against platform requirements.
+//!
+//! Each `Profile` encodes the rules of a specific platform or
+//! specification, and reports violations as a list of `Finding`s.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::disk;
+use crate::header::{Header, MIN_NUM_PARTS};
+use crate::partition::{
+    find_duplicates, Duplicate, Partition, EFI_SYSTEM, MICROSOFT_BASIC_DATA, MICROSOFT_RESERVED,
+    XBOOTLDR,
+};
+
+/// Type-GUIDs from the Discoverable Partitions Specification, where
+/// only the first partition of each type is automatically used.
+const DPS_SINGLETONS: &[(&str, &str)] = &[
+    ("44479540-F297-41B2-9AF7-D131D5F0458A", "root (x86)"),
+    ("4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709", "root (x86-64)"),
+    ("69DAD710-2CE4-4E3C-B16C-21A1D49ABED3", "root (ARM)"),
+    ("B921B045-1DF0-41C3-AF44-4C6F280D3FAE", "root (AArch64)"),
+    ("72EC70A6-CF74-40E6-BD49-4BDA08E8F224", "root (RISC-V 64)"),
+    ("933AC7E1-2EB4-4F13-B844-0E14E2AEF915", "/home"),
+    ("3B8F8425-20E0-4F3B-907F-1A25A76F98E8", "/srv"),
+    ("4D21B016-B534-45C2-A9FB-5C16E091FD2D", "/var"),
+    ("7EC6F557-3BC5-4ACA-B293-16EF5DF639D1", "/var/tmp"),
+    (XBOOTLDR, "extended boot loader"),
+];
+
+/// Minimum ESP size recommended for Windows, on 512 bytes sectors.
+pub(crate) const WINDOWS_ESP_MIN: u64 = 100 * 1024 * 1024;
+/// Minimum ESP size required for Windows, on 4K-native disks.
+pub(crate) const WINDOWS_ESP_MIN_4K: u64 = 260 * 1024 * 1024;
+/// MSR size used by Windows setup.
+pub(crate) const WINDOWS_MSR_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Set of rules to check a partition table against.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Profile {
+    /// Requirements from the UEFI specification.
+    UefiSpec,
+    /// Layout expected by Windows setup for a boot disk (EFI_SYSTEM, MICROSOFT_RESERVED, data).
+    WindowsBoot,
+    /// Conventions from the systemd Discoverable Partitions Specification.
+    SystemdDps,
+}
+
+/// How serious a finding is.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// Unusual, but accepted by the target platform.
+    Warning,
+    /// Violates a requirement of the target platform.
+    Error,
+}
+
+/// What `GptDisk::write()` does with findings of its automatic validation.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum WriteCheck {
+    /// Don't validate tables before writing them.
+    Skip,
+    /// Log all findings, and write anyway.
+    Warn,
+    /// Refuse to write a table with any error finding, only logging warnings.
+    #[default]
+    Deny,
+}
+
+/// A single problem found while validating a partition table.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Finding {
+    /// Profile which reported this finding.
+    pub profile: Profile,
+    /// Severity of this finding.
+    pub severity: Severity,
+    /// Numbers of the partitions involved, if any.
+    pub partitions: Vec<u32>,
+    /// Human-readable description.
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{:?} {}: {}", self.profile, severity, self.message)?;
+        if !self.partitions.is_empty() {
+            write!(f, " (partitions {:?})", self.partitions)?;
+        }
+        Ok(())
+    }
+}
+
+impl Profile {
+    /// Check a partition table, described by its primary header, against this profile.
+    pub fn check(
+        self,
+        h: &Header,
+        pp: &BTreeMap<u32, Partition>,
+        lb_size: disk::LogicalBlockSize,
+    ) -> Vec<Finding> {
+        let mut findings = Findings {
+            profile: self,
+            list: vec![],
+        };
+        match self {
+            Profile::UefiSpec => check_uefi(&mut findings, h, pp, lb_size),
+            Profile::WindowsBoot => check_windows(&mut findings, pp, lb_size),
+            Profile::SystemdDps => check_dps(&mut findings, pp),
+        }
+        findings.list
+    }
+}
+
+/// Check a partition table before it is written to disk.
+///
+/// This runs the `Profile::UefiSpec` rules, and additionally warns about
+/// partitions not starting on an `alignment` (in LBAs) boundary and about
+/// disks with several EFI System Partitions.
+pub fn check_before_write(
+    h: &Header,
+    pp: &BTreeMap<u32, Partition>,
+    lb_size: disk::LogicalBlockSize,
+    alignment: u64,
+) -> Vec<Finding> {
+    let mut findings = Findings {
+        profile: Profile::UefiSpec,
+        list: vec![],
+    };
+    check_uefi(&mut findings, h, pp, lb_size);
+    if alignment > 1 {
+        for (id, p) in pp {
+            if !p.is_aligned(alignment) {
+                findings.push(
+                    Severity::Warning,
+                    vec![*id],
+                    format!("partition not aligned to {} LBAs", alignment),
+                );
+            }
+        }
+    }
+    let esps: Vec<u32> = pp
+        .iter()
+        .filter(|(_, p)| has_type(p, EFI_SYSTEM))
+        .map(|(id, _)| *id)
+        .collect();
+    if esps.len() > 1 {
+        findings.push(
+            Severity::Warning,
+            esps,
+            "multiple EFI System Partitions".to_string(),
+        );
+    }
+    findings.list
+}
+
+/// Accumulator for findings of a single profile.
+struct Findings {
+    profile: Profile,
+    list: Vec<Finding>,
+}
+
+impl Findings {
+    fn push(&mut self, severity: Severity, partitions: Vec<u32>, message: String) {
+        self.list.push(Finding {
+            profile: self.profile,
+            severity,
+            partitions,
+            message,
+        });
+    }
+}
+
+fn has_type(p: &Partition, guid: &str) -> bool {
+    uuid::Uuid::parse_str(guid).is_ok_and(|g| p.part_type_guid.guid == g)
+}
+
+fn size_bytes(p: &Partition, lb_size: disk::LogicalBlockSize) -> u64 {
+    (p.last_lba.saturating_sub(p.first_lba) + 1).saturating_mul(lb_size.into())
+}
+
+fn check_uefi(
+    findings: &mut Findings,
+    h: &Header,
+    pp: &BTreeMap<u32, Partition>,
+    lb_size: disk::LogicalBlockSize,
+) {
+    if h.part_size < 128 || !h.part_size.is_power_of_two() {
+        findings.push(
+            Severity::Error,
+            vec![],
+            format!("partition entry size {} is not 128 * 2^n", h.part_size),
+        );
+    }
+    let array_bytes = u64::from(h.num_parts) * u64::from(h.part_size);
+    if array_bytes < u64::from(MIN_NUM_PARTS) * 128 {
+        findings.push(
+            Severity::Error,
+            vec![],
+            format!("partition array of {} bytes is below 16 KiB", array_bytes),
+        );
+    }
+    let lb: u64 = lb_size.into();
+    let array_end = h.part_start + array_bytes.div_ceil(lb);
+    if h.first_usable < array_end {
+        findings.push(
+            Severity::Error,
+            vec![],
+            "first usable LBA overlaps the partition array".to_string(),
+        );
+    }
+
+    for id in pp.keys().filter(|id| **id == 0 || **id > h.num_parts) {
+        findings.push(
+            Severity::Error,
+            vec![*id],
+            format!("partition number outside of 1..={}", h.num_parts),
+        );
+    }
+
+    let mut sorted: Vec<(&u32, &Partition)> = pp.iter().collect();
+    sorted.sort_by_key(|(_, p)| p.first_lba);
+    for (id, p) in &sorted {
+        if p.first_lba > p.last_lba {
+            findings.push(
+                Severity::Error,
+                vec![**id],
+                "partition ends before its start".to_string(),
+            );
+        } else if p.first_lba < h.first_usable || p.last_lba > h.last_usable {
+            findings.push(
+                Severity::Error,
+                vec![**id],
+                "partition outside of usable LBAs".to_string(),
+            );
+        }
+        if p.part_guid.is_nil() {
+            findings.push(
+                Severity::Error,
+                vec![**id],
+                "partition has a nil unique GUID".to_string(),
+            );
+        }
+    }
+    // Sorted by start, so each partition can only overlap the following
+    // ones which start before its end; this catches nested partitions too.
+    for (i, (id1, p1)) in sorted.iter().enumerate() {
+        for (id2, _) in sorted[i + 1..]
+            .iter()
+            .take_while(|(_, p2)| p2.first_lba <= p1.last_lba)
+        {
+            let mut ids = vec![**id1, **id2];
+            ids.sort_unstable();
+            findings.push(Severity::Error, ids, "partitions overlap".to_string());
+        }
+    }
+    for dup in find_duplicates(pp) {
+        if let Duplicate::Guid(guid, ids) = dup {
+            findings.push(
+                Severity::Error,
+                ids,
+                format!("duplicate unique GUID {}", guid),
+            );
+        }
+    }
+}
+
+fn check_windows(
+    findings: &mut Findings,
+    pp: &BTreeMap<u32, Partition>,
+    lb_size: disk::LogicalBlockSize,
+) {
+    let by_type = |guid: &str| -> Vec<(u32, &Partition)> {
+        pp.iter()
+            .filter(|(_, p)| has_type(p, guid))
+            .map(|(id, p)| (*id, p))
+            .collect()
+    };
+    let esps = by_type(EFI_SYSTEM);
+    let msrs = by_type(MICROSOFT_RESERVED);
+    let data = by_type(MICROSOFT_BASIC_DATA);
+
+    match esps.as_slice() {
+        [] => findings.push(
+            Severity::Error,
+            vec![],
+            "no EFI System Partition".to_string(),
+        ),
+        [(id, esp)] => {
+            let min = match lb_size.bytes() {
+                4096 => WINDOWS_ESP_MIN_4K,
+                _ => WINDOWS_ESP_MIN,
+            };
+            if size_bytes(esp, lb_size) < min {
+                findings.push(
+                    Severity::Error,
+                    vec![*id],
+                    format!("EFI System Partition smaller than {} MiB", min >> 20),
+                );
+            }
+        }
+        _ => findings.push(
+            Severity::Warning,
+            esps.iter().map(|(id, _)| *id).collect(),
+            "multiple EFI System Partitions".to_string(),
+        ),
+    }
+
+    match msrs.as_slice() {
+        [] => findings.push(
+            Severity::Warning,
+            vec![],
+            "no Microsoft Reserved Partition".to_string(),
+        ),
+        [(id, msr)] => {
+            if size_bytes(msr, lb_size) != WINDOWS_MSR_SIZE {
+                findings.push(
+                    Severity::Warning,
+                    vec![*id],
+                    "Microsoft Reserved Partition is not 16 MiB".to_string(),
+                );
+            }
+            if let Some((esp_id, esp)) = esps.first() {
+                if msr.first_lba < esp.first_lba {
+                    findings.push(
+                        Severity::Warning,
+                        vec![*esp_id, *id],
+                        "Microsoft Reserved Partition placed before the ESP".to_string(),
+                    );
+                }
+            }
+            for (data_id, part) in &data {
+                if part.first_lba < msr.first_lba {
+                    findings.push(
+                        Severity::Error,
+                        vec![*id, *data_id],
+                        "basic data partition placed before the MSR".to_string(),
+                    );
+                }
+            }
+        }
+        _ => findings.push(
+            Severity::Error,
+            msrs.iter().map(|(id, _)| *id).collect(),
+            "multiple Microsoft Reserved Partitions".to_string(),
+        ),
+    }
+
+    if data.is_empty() {
+        findings.push(
+            Severity::Error,
+            vec![],
+            "no basic data partition for Windows".to_string(),
+        );
+    }
+}
+
+fn check_dps(findings: &mut Findings, pp: &BTreeMap<u32, Partition>) {
+    for (guid, label) in DPS_SINGLETONS {
+        let ids: Vec<u32> = pp
+            .iter()
+            .filter(|(_, p)| has_type(p, guid))
+            .map(|(id, _)| *id)
+            .collect();
+        if ids.len() > 1 {
+            findings.push(
+                Severity::Warning,
+                ids,
+                format!("multiple {} partitions, only the first one is used", label),
+            );
+        }
+    }
+    let esps: Vec<u32> = pp
+        .iter()
+        .filter(|(_, p)| has_type(p, EFI_SYSTEM))
+        .map(|(id, _)| *id)
+        .collect();
+    if esps.len() > 1 {
+        findings.push(
+            Severity::Warning,
+            esps.clone(),
+            "multiple EFI System Partitions, only the first one is used".to_string(),
+        );
+    }
+    let xbootldr: Vec<u32> = pp
+        .iter()
+        .filter(|(_, p)| has_type(p, XBOOTLDR))
+        .map(|(id, _)| *id)
+        .collect();
+    if !xbootldr.is_empty() && esps.is_empty() {
+        findings.push(
+            Severity::Error,
+            xbootldr,
+            "extended boot loader partition without an EFI System Partition".to_string(),
+        );
+    }
+    if !DPS_SINGLETONS[..5]
+        .iter()
+        .any(|(guid, _)| pp.values().any(|p| has_type(p, guid)))
+    {
+        findings.push(Severity::Warning, vec![], "no root partition".to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disk;
+    use crate::header::Header;
+    use crate::partition::{Partition, PartitionType};
+    use crate::partition::{EFI_SYSTEM, MICROSOFT_BASIC_DATA, MICROSOFT_RESERVED};
+    use crate::validate::{check_before_write, Profile, Severity};
+    use std::collections::BTreeMap;
+
+    const LB: disk::LogicalBlockSize = disk::LogicalBlockSize::Lb512;
+
+    fn part(ty: &str, first_lba: u64, mib: u64) -> Partition {
+        let mut p = Partition::zero();
+        p.part_type_guid = PartitionType {
+            guid: uuid::Uuid::parse_str(ty).unwrap(),
+            os: String::new(),
+            description: String::new(),
+        };
+        p.part_guid = uuid::Uuid::new_v4();
+        p.first_lba = first_lba;
+        p.last_lba = first_lba + mib * 2048 - 1;
+        p
+    }
+
+    #[test]
+    fn test_uefi_overlaps() {
+        let mut pp = BTreeMap::new();
+        pp.insert(1, part(EFI_SYSTEM, 2048, 1));
+        pp.insert(2, part(EFI_SYSTEM, 3000, 1));
+        let h = Header::compute_new(true, &pp, uuid::Uuid::new_v4(), 1 << 20, LB).unwrap();
+        let findings = Profile::UefiSpec.check(&h, &pp, LB);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].partitions, vec![1, 2]);
+
+        pp.get_mut(&2).unwrap().first_lba = 40;
+        pp.get_mut(&2).unwrap().last_lba = 2047;
+        assert!(Profile::UefiSpec.check(&h, &pp, LB).is_empty());
+
+        // Partitions 2 and 3 are both nested in partition 1.
+        pp.insert(1, part(EFI_SYSTEM, 2048, 4));
+        pp.insert(2, part(EFI_SYSTEM, 3000, 1));
+        pp.insert(3, part(EFI_SYSTEM, 6000, 1));
+        let findings = Profile::UefiSpec.check(&h, &pp, LB);
+        let overlaps: Vec<_> = findings.iter().map(|f| f.partitions.clone()).collect();
+        assert_eq!(overlaps, [[1, 2], [1, 3]]);
+    }
+
+    #[test]
+    fn test_windows_boot() {
+        let h =
+            Header::compute_new(true, &BTreeMap::new(), uuid::Uuid::nil(), 1 << 20, LB).unwrap();
+        let mut pp = BTreeMap::new();
+        pp.insert(1, part(EFI_SYSTEM, 2048, 100));
+        pp.insert(2, part(MICROSOFT_RESERVED, 206_848, 16));
+        pp.insert(3, part(MICROSOFT_BASIC_DATA, 239_616, 100));
+        assert!(Profile::WindowsBoot.check(&h, &pp, LB).is_empty());
+
+        // Data before MICROSOFT_RESERVED, and a too small ESP.
+        pp.insert(1, part(EFI_SYSTEM, 2048, 50));
+        pp.get_mut(&3).unwrap().first_lba = 150_000;
+        let findings = Profile::WindowsBoot.check(&h, &pp, LB);
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(|f| f.severity == Severity::Error));
+    }
+
+    #[test]
+    fn test_check_before_write() {
+        let mut pp = BTreeMap::new();
+        pp.insert(1, part(EFI_SYSTEM, 2048, 1));
+        pp.insert(2, part(EFI_SYSTEM, 4097, 1));
+        let h = Header::compute_new(true, &pp, uuid::Uuid::new_v4(), 1 << 20, LB).unwrap();
+        let findings = check_before_write(&h, &pp, LB, 2048);
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(|f| f.severity == Severity::Warning));
+        assert_eq!(findings[0].partitions, vec![2]);
+        assert_eq!(findings[1].partitions, vec![1, 2]);
+
+        pp.get_mut(&2).unwrap().first_lba = 3000;
+        let findings = check_before_write(&h, &pp, LB, 1);
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].severity, Severity::Error);
+    }
+}
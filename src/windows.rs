@@ -0,0 +1,117 @@
+//! Windows raw disk integration, through `DeviceIoControl`.
+//!
+//! Physical drives (e.g. `\\.\PhysicalDrive0`) only accept sector-aligned
+//! transfers, and do not report their length through seeking or file
+//! metadata. `open_physical_drive()` queries the drive geometry, and wraps
+//! it in a `disk::AlignedDevice`.
+//!
+//! ```rust,no_run
+//! let path = std::path::Path::new(r"\\.\PhysicalDrive1");
+//! let (drive, lb_size) = gpt::windows::open_physical_drive(path, false).unwrap();
+//! let disk = gpt::GptConfig::new()
+//!     .logical_block_size(lb_size)
+//!     .open_from_device(drive)
+//!     .unwrap();
+//! println!("{:#?}", disk.partitions());
+//! ```
+
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::os::windows::io::AsRawHandle;
+use std::{fs, io, mem, path, ptr};
+
+use crate::disk;
+
+const IOCTL_DISK_GET_DRIVE_GEOMETRY_EX: u32 = 0x0007_00A0;
+const IOCTL_DISK_GET_LENGTH_INFO: u32 = 0x0007_405C;
+
+/// `DISK_GEOMETRY` from `winioctl.h`.
+#[repr(C)]
+#[derive(Default)]
+struct DiskGeometry {
+    cylinders: i64,
+    media_type: u32,
+    tracks_per_cylinder: u32,
+    sectors_per_track: u32,
+    bytes_per_sector: u32,
+}
+
+/// `DISK_GEOMETRY_EX` from `winioctl.h`, without its variable-length tail.
+#[repr(C)]
+#[derive(Default)]
+struct DiskGeometryEx {
+    geometry: DiskGeometry,
+    disk_size: i64,
+    data: [u8; 8],
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn DeviceIoControl(
+        device: *mut c_void,
+        code: u32,
+        in_buf: *mut c_void,
+        in_size: u32,
+        out_buf: *mut c_void,
+        out_size: u32,
+        returned: *mut u32,
+        overlapped: *mut c_void,
+    ) -> i32;
+}
+
+/// Return the size (in bytes) of a drive (`IOCTL_DISK_GET_LENGTH_INFO`).
+pub fn disk_length(file: &fs::File) -> io::Result<u64> {
+    let mut length: i64 = 0;
+    device_io_control(file, IOCTL_DISK_GET_LENGTH_INFO, &mut length)?;
+    u64::try_from(length).map_err(|_| io::Error::other("invalid disk length"))
+}
+
+/// Return the logical block size of a drive (`IOCTL_DISK_GET_DRIVE_GEOMETRY_EX`).
+pub fn logical_block_size(file: &fs::File) -> io::Result<disk::LogicalBlockSize> {
+    let mut geometry = DiskGeometryEx::default();
+    device_io_control(file, IOCTL_DISK_GET_DRIVE_GEOMETRY_EX, &mut geometry)?;
+    Ok(disk::LogicalBlockSize::try_from(u64::from(
+        geometry.geometry.bytes_per_sector,
+    ))?)
+}
+
+/// Open a physical drive, e.g. `\\.\PhysicalDrive0`, for sector-aligned I/O.
+///
+/// The drive is opened for reading, and for writing too if `writable` is
+/// set. Its logical block size is returned along with the device, to be
+/// passed to `GptConfig::logical_block_size()`.
+pub fn open_physical_drive(
+    path: &path::Path,
+    writable: bool,
+) -> io::Result<(disk::AlignedDevice<fs::File>, disk::LogicalBlockSize)> {
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(writable)
+        .open(path)?;
+    let lb_size = logical_block_size(&file)?;
+    let size = disk_length(&file)?;
+    Ok((disk::AlignedDevice::new(file, lb_size, size), lb_size))
+}
+
+/// Issue a `DeviceIoControl` without input, filling `out`.
+fn device_io_control<T>(file: &fs::File, code: u32, out: &mut T) -> io::Result<()> {
+    let mut returned = 0u32;
+    // SAFETY: `out` is valid for writes of its whole size, and the call is
+    // synchronous as the handle is not opened for overlapped I/O.
+    let ok = unsafe {
+        DeviceIoControl(
+            file.as_raw_handle() as *mut c_void,
+            code,
+            ptr::null_mut(),
+            0,
+            out as *mut T as *mut c_void,
+            mem::size_of::<T>() as u32,
+            &mut returned,
+            ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -1,10 +1,29 @@
-use gpt;
-
-
 use gpt::disk;
-use std::path;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::{fs, path};
 use tempfile::NamedTempFile;
 
+/// Create a zeroed temporary disk image of `len` bytes.
+fn temp_disk(len: u64) -> NamedTempFile {
+    let tempdisk = NamedTempFile::new().expect("failed to create tempfile disk");
+    tempdisk.as_file().set_len(len).unwrap();
+    tempdisk
+}
+
+/// Create a temporary copy of the `gpt-linux-disk-01.img` fixture.
+fn fixture_disk() -> NamedTempFile {
+    let tempdisk = NamedTempFile::new().expect("failed to create tempfile disk");
+    fs::copy("tests/fixtures/gpt-linux-disk-01.img", tempdisk.path()).unwrap();
+    tempdisk
+}
+
+/// Linux filesystem data partition type.
+fn linux_fs() -> gpt::partition::PartitionType {
+    gpt::partition_types::Type::from_name("Linux Filesystem Data")
+        .unwrap()
+        .into()
+}
+
 #[test]
 fn test_gptconfig_empty() {
     let tempdisk = NamedTempFile::new().expect("failed to create tempfile disk");
@@ -16,7 +35,8 @@ fn test_gptconfig_empty() {
     };
 
     let lb_size = disk::LogicalBlockSize::Lb4096;
-    let disk = cfg.initialized(false)
+    let disk = cfg
+        .initialized(false)
         .logical_block_size(lb_size)
         .open(tempdisk.path())
         .unwrap();
@@ -45,7 +65,9 @@ fn test_gptdisk_linux_01() {
     assert_eq!(h2.current_lba, 95);
     assert_eq!(h2.backup_lba, 1);
 
-    let p1 = &gdisk.partitions()[0];
+    assert!(gdisk.partitions().get(&2).is_none());
+
+    let p1 = &gdisk.partitions()[&1];
     assert_eq!(p1.name, "primary");
     assert_eq!(p1.part_type_guid.description, "Linux Filesystem Data");
     let p1_start = p1.bytes_start(*gdisk.logical_block_size()).unwrap();
@@ -53,3 +75,2226 @@ fn test_gptdisk_linux_01() {
     let p1_len = p1.bytes_len(*gdisk.logical_block_size()).unwrap();
     assert_eq!(p1_len, (0x3E - 0x22) * 512);
 }
+
+#[test]
+fn test_gptdisk_backup_array_lba() {
+    let mut gdisk = gpt::GptConfig::new()
+        .open(path::Path::new("tests/fixtures/gpt-linux-disk-01.img"))
+        .unwrap();
+    let pp = gdisk.partitions().clone();
+    gdisk.update_partitions(pp).unwrap();
+    // The backup array sits right before the backup header.
+    let h2 = gdisk.backup_header().unwrap();
+    assert_eq!(h2.part_start, 63);
+    assert_eq!(h2.part_start, h2.last_usable + 1);
+}
+
+#[test]
+fn test_gptdisk_write_backup_header() {
+    let tempdisk = fixture_disk();
+    let gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk.write().unwrap();
+
+    // The backup header is written as such, at the last LBA.
+    let gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    let h2 = gdisk.backup_header().unwrap();
+    assert_eq!((h2.current_lba, h2.backup_lba), (95, 1));
+    assert_eq!(gdisk.primary_header().unwrap().backup_lba, 95);
+}
+
+#[test]
+fn test_gptdisk_partitions_mut() {
+    let tempdisk = fixture_disk();
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk
+        .with_partitions_mut(|pp| {
+            let p1 = pp.get_mut(&1).unwrap();
+            p1.name = "renamed".to_string();
+            p1.last_lba = 60;
+        })
+        .unwrap();
+    assert_eq!(gdisk.partitions()[&1].name, "renamed");
+    assert_eq!(gdisk.partitions()[&1].last_lba, 60);
+
+    // Invalid edits are rejected, leaving the table as-is.
+    gdisk
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().last_lba = 1000)
+        .unwrap_err();
+    gdisk
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().first_lba = 61)
+        .unwrap_err();
+    assert_eq!(gdisk.partitions()[&1].first_lba, 34);
+    assert_eq!(gdisk.partitions()[&1].last_lba, 60);
+
+    // Partition numbers start from 1.
+    gdisk
+        .with_partitions_mut(|pp| {
+            let p1 = pp.remove(&1).unwrap();
+            pp.insert(0, p1);
+        })
+        .unwrap_err();
+}
+
+#[test]
+fn test_gptdisk_add_partition_handle() {
+    let tempdisk = temp_disk(1024 * 1024);
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .alignment(512)
+        .open(tempdisk.path())
+        .unwrap();
+    let h1 = gdisk.add_partition("first", 4096, linux_fs(), 0).unwrap();
+    let h2 = gdisk.add_partition("second", 1000, linux_fs(), 0).unwrap();
+    assert_eq!(h1.id(), 1);
+    assert_eq!(h2.id(), 2);
+    let p1 = gdisk.partition_by_handle(&h1).unwrap();
+    assert_eq!(p1.name, "first");
+    assert_eq!((p1.first_lba, p1.last_lba), (34, 41));
+    let p2 = gdisk.partition_by_handle(&h2).unwrap();
+    assert_eq!((p2.first_lba, p2.last_lba), (42, 43));
+    gdisk
+        .add_partition("too big", 1024 * 1024, linux_fs(), 0)
+        .unwrap_err();
+
+    // Handles survive renumbering.
+    gdisk
+        .with_partitions_mut(|pp| {
+            let p1 = pp.remove(&1).unwrap();
+            pp.insert(5, p1);
+        })
+        .unwrap();
+    assert_eq!(gdisk.resolve(&h1), Some(5));
+    assert_eq!(gdisk.resolve(&h2), Some(2));
+    assert_eq!(gdisk.partition_by_handle(&h1).unwrap().name, "first");
+    assert_eq!(gdisk.partition(5).unwrap().number(), 5);
+    assert_eq!(gdisk.partition_by_guid(h2.guid()).unwrap().number(), 2);
+    assert_eq!(gdisk.partition_by_name("first").unwrap().number(), 5);
+    assert!(gdisk.partition(1).is_none());
+    assert!(gdisk.partition_by_name("third").is_none());
+
+    for p in gdisk.partitions_mut() {
+        p.name.push_str("-renamed");
+    }
+    assert_eq!(gdisk.partition(2).unwrap().name, "second-renamed");
+    assert!(gdisk.partition_by_name("first").is_none());
+
+    gdisk.with_partitions_mut(|pp| pp.clear()).unwrap();
+    assert_eq!(gdisk.resolve(&h1), None);
+}
+
+#[test]
+fn test_gptdisk_checksums() {
+    let tempdisk = fixture_disk();
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    let orig_parts = gdisk.partitions().clone();
+
+    // CRC of an array of 128 empty entries.
+    gdisk.with_partitions_mut(|pp| pp.clear()).unwrap();
+    let (h1, _) = gdisk.preview_headers().unwrap();
+    assert_eq!(h1.crc32_parts, 0xAB54_D286);
+    assert_ne!(h1.crc32, 0);
+    // On-disk headers are untouched until written.
+    assert_eq!(gdisk.primary_header().unwrap().crc32_parts, 151_952_294);
+
+    // Restoring the original entries gives back the on-disk checksums.
+    gdisk.update_partitions(orig_parts).unwrap();
+    let (h2, _) = gdisk.preview_headers().unwrap();
+    assert_eq!(h2.crc32_parts, 151_952_294);
+    gdisk.update_partitions(gdisk.partitions().clone()).unwrap();
+    assert_eq!(gdisk.preview_headers().unwrap().0.crc32_parts, 151_952_294);
+}
+
+#[test]
+fn test_gptdisk_concurrent_reads() {
+    let diskpath = path::Path::new("tests/fixtures/gpt-linux-disk-01.img");
+    let sequential = gpt::GptConfig::new().open(diskpath).unwrap();
+    let concurrent = gpt::GptConfig::new()
+        .concurrent_reads(true)
+        .open(diskpath)
+        .unwrap();
+    assert_eq!(sequential.primary_header(), concurrent.primary_header());
+    assert_eq!(sequential.backup_header(), concurrent.backup_header());
+    assert_eq!(sequential.partitions(), concurrent.partitions());
+}
+
+#[test]
+fn test_gptconfig_uninitialized_existing() {
+    let tempdisk = fixture_disk();
+
+    // Existing tables are not clobbered unless explicitly requested.
+    let cfg = gpt::GptConfig::new().writable(true).initialized(false);
+    let err = cfg.open(tempdisk.path()).unwrap_err();
+    assert!(matches!(
+        err,
+        gpt::Error::ExistingLabel("a GPT partition table")
+    ));
+    assert_eq!(
+        std::io::Error::from(err).kind(),
+        std::io::ErrorKind::AlreadyExists
+    );
+    gpt::GptConfig::new()
+        .writable(false)
+        .initialized(false)
+        .open(tempdisk.path())
+        .unwrap();
+    let disk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .overwrite(true)
+        .open(tempdisk.path())
+        .unwrap();
+    assert!(disk.partitions().is_empty());
+}
+
+#[test]
+fn test_gptdisk_wipe_signatures() {
+    let mut tempdisk = NamedTempFile::new().expect("failed to create tempfile disk");
+    tempdisk.as_file().set_len(4 * 1024 * 1024).unwrap();
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .open(tempdisk.path())
+        .unwrap();
+    let h1 = gdisk
+        .add_partition("stale", 1024 * 1024, linux_fs(), 0)
+        .unwrap();
+    let p1 = gdisk.partition_by_handle(&h1).unwrap();
+    let start = p1.bytes_start(disk::LogicalBlockSize::Lb512).unwrap();
+
+    // Fake ext4 and btrfs magics, plus some unrelated data.
+    let stale: &[(u64, &[u8])] = &[
+        (1024 + 56, &[0x53, 0xEF]),
+        (64 * 1024 + 64, b"_BHRfS_M"),
+        (200 * 1024, b"payload"),
+    ];
+    for (offset, data) in stale {
+        tempdisk.seek(SeekFrom::Start(start + offset)).unwrap();
+        tempdisk.write_all(data).unwrap();
+    }
+
+    gdisk.wipe_signatures(h1.id()).unwrap();
+    gdisk.wipe_signatures(42).unwrap_err();
+    for (offset, data) in stale {
+        let mut buf = vec![0u8; data.len()];
+        tempdisk.seek(SeekFrom::Start(start + offset)).unwrap();
+        tempdisk.read_exact(&mut buf).unwrap();
+        if *offset == 200 * 1024 {
+            assert_eq!(&buf, data);
+        } else {
+            assert!(buf.iter().all(|b| *b == 0));
+        }
+    }
+}
+
+#[test]
+fn test_gptdisk_write_partition_entry() {
+    let tempdisk = fixture_disk();
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().flags |= 1 << 48)
+        .unwrap();
+    gdisk.write_partition_entry(1).unwrap();
+    gdisk.write_partition_entry(0).unwrap_err();
+    let expected = gdisk.partitions().clone();
+    drop(gdisk);
+
+    // Both arrays and headers must be consistent after reopening.
+    let gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert_eq!(gdisk.partitions(), &expected);
+    let h1 = gdisk.primary_header().unwrap();
+    let h2 = gdisk.backup_header().unwrap();
+    assert_eq!(h1.crc32_parts, h2.crc32_parts);
+    let lb_size = disk::LogicalBlockSize::Lb512;
+    let backup = gpt::partition::read_partitions(tempdisk.path(), h2, lb_size).unwrap();
+    assert_eq!(backup, expected);
+}
+
+#[test]
+fn test_gptdisk_write_partition_entry_checks() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("disk.img");
+    let lb_size = disk::DEFAULT_SECTOR_SIZE;
+    let mut gdisk = gpt::image::create_sparse(&path, 8 * 1024 * 1024, lb_size).unwrap();
+    gdisk
+        .add_partition("data", 1024 * 1024, linux_fs(), 0)
+        .unwrap();
+    gdisk.write().unwrap();
+
+    // Resized arrays are written in full.
+    let mut gdisk = gpt::GptConfig::new().writable(true).open(&path).unwrap();
+    gdisk.set_partition_entries(256, 128).unwrap();
+    gdisk
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().flags |= 1 << 48)
+        .unwrap();
+    gdisk.write_partition_entry(1).unwrap();
+    let expected = gdisk.partitions().clone();
+    let mut gdisk = gpt::GptConfig::new().open(&path).unwrap();
+    let h2 = gdisk.backup_header().unwrap();
+    assert_eq!((h2.num_parts, h2.part_start), (256, 16319));
+    let backup = gpt::partition::read_partitions(&path, h2, lb_size).unwrap();
+    assert_eq!(backup, expected);
+    assert!(gdisk.verify_integrity().unwrap().is_empty());
+
+    // The table is validated like for write().
+    let mut gdisk = gpt::GptConfig::new().writable(true).open(&path).unwrap();
+    gdisk.partitions_mut().next().unwrap().last_lba = 20000;
+    match gdisk.write_partition_entry(1) {
+        Err(gpt::Error::ValidationFailed(_)) => {}
+        r => panic!("unexpected result {:?}", r),
+    }
+    let gdisk = gpt::GptConfig::new().open(&path).unwrap();
+    assert_eq!(gdisk.partitions(), &expected);
+}
+
+#[test]
+fn test_gptdisk_changes() {
+    let tempdisk = fixture_disk();
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    assert!(gdisk.changed_partitions().is_empty());
+    assert!(gdisk.changed_header_fields().unwrap().is_empty());
+
+    gdisk
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().flags = 1 << 60)
+        .unwrap();
+    assert_eq!(gdisk.changed_partitions(), vec![1]);
+    assert!(gdisk.changed_header_fields().unwrap().is_empty());
+
+    gdisk.update_guid(None).unwrap();
+    assert_eq!(
+        gdisk.changed_header_fields().unwrap(),
+        vec![gpt::header::FieldGroup::DiskGuid]
+    );
+
+    gdisk.write_partition_entry(1).unwrap();
+    assert!(gdisk.changed_partitions().is_empty());
+}
+
+#[test]
+fn test_gptdisk_randomize_all_guids() {
+    let tempdisk = fixture_disk();
+    let open = || {
+        gpt::GptConfig::new()
+            .writable(true)
+            .open(tempdisk.path())
+            .unwrap()
+    };
+    let original = open();
+    let mut gdisk = open();
+    gdisk.randomize_all_guids().unwrap();
+    assert_ne!(gdisk.guid(), original.guid());
+    assert_ne!(
+        gdisk.partitions()[&1].part_guid,
+        original.partitions()[&1].part_guid
+    );
+    assert_eq!(
+        gpt::find_cross_disk_duplicates(&[&original, &gdisk]),
+        vec![]
+    );
+    gdisk.write().unwrap();
+
+    let mut gdisk = open();
+    let guid = uuid::Uuid::parse_str("11111111-2222-3333-4444-555555555555").unwrap();
+    gdisk
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().set_unique_guid(Some(guid)))
+        .unwrap();
+    gdisk.write().unwrap();
+    assert_eq!(open().partitions()[&1].part_guid, guid);
+}
+
+#[test]
+fn test_gptconfig_disk_guid() {
+    let tempdisk = temp_disk(1024 * 1024);
+    let guid = uuid::Uuid::parse_str("11111111-2222-3333-4444-555555555555").unwrap();
+
+    let gdisk = gpt::GptConfig::new()
+        .initialized(false)
+        .disk_guid(guid)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.guid(), &guid);
+
+    // Initialized disks keep their own GUID.
+    let gdisk = gpt::GptConfig::new()
+        .disk_guid(guid)
+        .open(path::Path::new("tests/fixtures/gpt-linux-disk-01.img"))
+        .unwrap();
+    assert_ne!(gdisk.guid(), &guid);
+}
+
+#[test]
+fn test_gptdisk_import_partitions() {
+    use gpt::partition::{ConflictPolicy, GuidPolicy, OverlapPolicy};
+
+    let source = gpt::GptConfig::new()
+        .open(path::Path::new("tests/fixtures/gpt-linux-disk-01.img"))
+        .unwrap();
+    let tempdisk = fixture_disk();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .alignment(512)
+        .open(tempdisk.path())
+        .unwrap();
+
+    // Importing a table into itself always overlaps.
+    gdisk
+        .import_partitions(source.partitions(), ConflictPolicy::default())
+        .unwrap_err();
+    let skip = ConflictPolicy {
+        overlap: OverlapPolicy::Skip,
+        guids: GuidPolicy::Preserve,
+    };
+    assert!(gdisk
+        .import_partitions(source.partitions(), skip)
+        .unwrap()
+        .is_empty());
+    assert_eq!(gdisk.partitions().len(), 1);
+
+    // Shrink the existing partition to make room for a relocated copy.
+    gdisk
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().last_lba = 40)
+        .unwrap();
+    let mut small = source.partitions().clone();
+    small.get_mut(&1).unwrap().last_lba = 40;
+    let relocate = ConflictPolicy {
+        overlap: OverlapPolicy::Relocate,
+        guids: GuidPolicy::Preserve,
+    };
+    let handles = gdisk.import_partitions(&small, relocate).unwrap();
+    assert_eq!(handles.len(), 1);
+    let imported = gdisk.partition_by_handle(&handles[0]).unwrap();
+    assert_eq!(handles[0].id(), 2);
+    assert_eq!((imported.first_lba, imported.last_lba), (41, 47));
+    assert_eq!(imported.name, "primary");
+    // The GUID collided with the original entry.
+    assert_ne!(imported.part_guid, source.partitions()[&1].part_guid);
+}
+
+#[test]
+fn test_gptdisk_verify_profiles() {
+    use gpt::validate::{Profile, Severity};
+
+    let gdisk = gpt::GptConfig::new()
+        .open(path::Path::new("tests/fixtures/gpt-linux-disk-01.img"))
+        .unwrap();
+    assert!(gdisk.verify(&[Profile::UefiSpec]).unwrap().is_empty());
+
+    let findings = gdisk
+        .verify(&[Profile::WindowsBoot, Profile::SystemdDps])
+        .unwrap();
+    assert!(findings
+        .iter()
+        .any(|f| f.profile == Profile::WindowsBoot && f.severity == Severity::Error));
+    assert!(findings
+        .iter()
+        .any(|f| f.profile == Profile::SystemdDps && f.message == "no root partition"));
+}
+
+#[test]
+fn test_gptdisk_layout_eq() {
+    let diskpath = path::Path::new("tests/fixtures/gpt-linux-disk-01.img");
+    let gdisk = gpt::GptConfig::new().open(diskpath).unwrap();
+    let tempdisk = fixture_disk();
+    let mut other = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    assert!(gdisk.layout_eq(&other));
+
+    other.update_guid(None).unwrap();
+    other
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().part_guid = uuid::Uuid::new_v4())
+        .unwrap();
+    assert!(gdisk.layout_eq(&other));
+
+    other
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().name = "renamed".to_string())
+        .unwrap();
+    assert!(!gdisk.layout_eq(&other));
+}
+
+#[test]
+fn test_metadata_export_load() {
+    let diskpath = path::Path::new("tests/fixtures/gpt-linux-disk-01.img");
+    let orig = gpt::GptConfig::new().open(diskpath).unwrap();
+    let lb_size = disk::LogicalBlockSize::Lb512;
+
+    for scrub in &[false, true] {
+        let mut export = vec![];
+        let mut diskf = fs::File::open(diskpath).unwrap();
+        gpt::metadata::export(&mut diskf, lb_size, &mut export, *scrub).unwrap();
+        assert!(export.len() < 96 * 512);
+
+        let mut image = NamedTempFile::new().expect("failed to create tempfile disk");
+        let loaded_lb = gpt::metadata::load(&mut export.as_slice(), image.as_file_mut()).unwrap();
+        assert_eq!(loaded_lb, lb_size);
+        assert_eq!(image.as_file().metadata().unwrap().len(), 96 * 512);
+
+        let loaded = gpt::GptConfig::new().open(image.path()).unwrap();
+        let (p1, p2) = (&orig.partitions()[&1], &loaded.partitions()[&1]);
+        assert_eq!((p1.first_lba, p1.last_lba), (p2.first_lba, p2.last_lba));
+        assert_eq!(p1.part_type_guid, p2.part_type_guid);
+        if *scrub {
+            assert_ne!(orig.guid(), loaded.guid());
+            assert_eq!(loaded.guid(), &loaded.backup_header().unwrap().disk_guid);
+            assert_ne!(p1.part_guid, p2.part_guid);
+            assert!(p2.name.is_empty());
+        } else {
+            assert_eq!(orig.primary_header(), loaded.primary_header());
+            assert_eq!(orig.backup_header(), loaded.backup_header());
+            assert_eq!(orig.partitions(), loaded.partitions());
+        }
+    }
+
+    let mut image = NamedTempFile::new().unwrap();
+    gpt::metadata::load(&mut &b"garbage!"[..], image.as_file_mut()).unwrap_err();
+}
+
+#[test]
+fn test_gptdisk_checkpoints() {
+    let tempdisk = fixture_disk();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    let orig = gdisk.partitions().clone();
+    let orig_guid = *gdisk.guid();
+
+    gdisk.checkpoint("start");
+    gdisk.update_guid(None).unwrap();
+    gdisk.with_partitions_mut(|pp| pp.clear()).unwrap();
+    gdisk.checkpoint("empty");
+    assert_eq!(gdisk.checkpoints(), vec!["empty", "start"]);
+
+    gdisk.restore_checkpoint("start").unwrap();
+    assert_eq!(gdisk.partitions(), &orig);
+    assert_eq!(gdisk.guid(), &orig_guid);
+    assert!(gdisk.changed_partitions().is_empty());
+
+    gdisk.restore_checkpoint("empty").unwrap();
+    assert!(gdisk.partitions().is_empty());
+    assert!(gdisk.drop_checkpoint("empty"));
+    gdisk.restore_checkpoint("empty").unwrap_err();
+}
+
+#[test]
+fn test_gptdisk_external_modification() {
+    let tempdisk = fixture_disk();
+    let open = |force| {
+        gpt::GptConfig::new()
+            .writable(true)
+            .force_write(force)
+            .open(tempdisk.path())
+            .unwrap()
+    };
+    let mut gdisk = open(false);
+    let mut forced = open(true);
+
+    // Another writer updates the table meanwhile.
+    let mut other = open(false);
+    other.update_guid(None).unwrap();
+    other
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().flags = 1)
+        .unwrap();
+    other.write_partition_entry(1).unwrap();
+    // Own writes do not count as external modifications.
+    other.write_partition_entry(1).unwrap();
+
+    let err = gdisk.write_partition_entry(1).unwrap_err();
+    assert!(matches!(err, gpt::Error::ExternalModification(_)));
+    forced.write_partition_entry(1).unwrap();
+}
+
+#[test]
+fn test_gptdisk_write_inplace() {
+    let tempdisk = fixture_disk();
+    let open = || {
+        gpt::GptConfig::new()
+            .writable(true)
+            .open(tempdisk.path())
+            .unwrap()
+    };
+    let mut gdisk = open();
+    for name in &["first", "second"] {
+        for p in gdisk.partitions_mut() {
+            p.name = name.to_string();
+        }
+        assert_eq!(gdisk.changed_partitions(), vec![1]);
+        gdisk.write_inplace().unwrap();
+        assert!(gdisk.changed_partitions().is_empty());
+        assert_eq!(open().partition(1).unwrap().name, *name);
+    }
+
+    // Writes through another handle are still detected.
+    let mut other = open();
+    other.update_guid(None).unwrap();
+    other.write_inplace().unwrap();
+    let err = gdisk.write_inplace().unwrap_err();
+    assert!(matches!(err, gpt::Error::ExternalModification(_)));
+}
+
+#[test]
+fn test_gptdisk_pending_changes() {
+    use gpt::partition::PartitionChange;
+
+    let image = fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open_from_device(std::io::Cursor::new(image))
+        .unwrap();
+    assert!(gdisk.pending_changes().unwrap().is_empty());
+
+    let old = gdisk.partition(1).unwrap().clone();
+    gdisk.update_guid(None).unwrap();
+    gdisk
+        .with_partitions_mut(|pp| {
+            let p1 = pp.get_mut(&1).unwrap();
+            p1.last_lba = 50;
+            p1.name = "shrunk".to_string();
+            let mut p2 = p1.clone();
+            p2.part_guid = uuid::Uuid::new_v4();
+            p2.first_lba = 51;
+            p2.last_lba = 62;
+            pp.insert(2, p2);
+        })
+        .unwrap();
+    let changes = gdisk.pending_changes().unwrap();
+    assert_eq!(
+        changes.header_fields,
+        vec![gpt::header::FieldGroup::DiskGuid]
+    );
+    assert_eq!(changes.partitions.len(), 2);
+    match &changes.partitions[0] {
+        PartitionChange::Modified { id: 1, old: o, new } => {
+            assert_eq!(o, &old);
+            assert_eq!(new.last_lba, 50);
+        }
+        c => panic!("unexpected change: {}", c),
+    }
+    assert!(matches!(&changes.partitions[1], PartitionChange::Added(2, p) if p.first_lba == 51));
+    let summary = changes.to_string();
+    assert!(summary
+        .contains("modify partition 1: range 34 - 62 -> 34 - 50 name \"primary\" -> \"shrunk\""));
+    assert!(summary.contains("add partition 2"));
+
+    gdisk.write_inplace().unwrap();
+    assert!(gdisk.pending_changes().unwrap().is_empty());
+    gdisk.remove_partition(2, false).unwrap();
+    let changes = gdisk.pending_changes().unwrap();
+    assert_eq!(changes.partitions[0].id(), 2);
+    assert!(matches!(
+        changes.partitions[0],
+        PartitionChange::Removed(..)
+    ));
+}
+
+#[test]
+fn test_gptdisk_zero_partition() {
+    let tempdisk = fixture_disk();
+    fs::OpenOptions::new()
+        .write(true)
+        .open(tempdisk.path())
+        .and_then(|mut f| {
+            f.seek(SeekFrom::Start(34 * 512))?;
+            f.write_all(&[0xAA; 29 * 512])
+        })
+        .unwrap();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk.zero_partition(1, 1024).unwrap();
+    gdisk.zero_partition(2, 1024).unwrap_err();
+    // Regular files cannot be discarded.
+    let err = gdisk.discard_partition(1).unwrap_err();
+    assert_eq!(
+        std::io::Error::from(err).kind(),
+        std::io::ErrorKind::Unsupported
+    );
+
+    let data = fs::read(tempdisk.path()).unwrap();
+    let part = &data[34 * 512..63 * 512];
+    assert!(part[..1024].iter().all(|b| *b == 0));
+    assert!(part[1024..part.len() - 1024].iter().all(|b| *b == 0xAA));
+    assert!(part[part.len() - 1024..].iter().all(|b| *b == 0));
+    // The backup partition array right after the partition is untouched.
+    gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+
+    gdisk.zero_partition(1, u64::MAX).unwrap();
+    let data = fs::read(tempdisk.path()).unwrap();
+    assert!(data[34 * 512..63 * 512].iter().all(|b| *b == 0));
+
+    let mut gdisk = gpt::GptDisk::new_in_memory(1 << 30, disk::DEFAULT_SECTOR_SIZE).unwrap();
+    let linux_fs = gpt::partition_types::Type::from_name("Linux Filesystem Data").unwrap();
+    let h = gdisk.add_partition("p", 1 << 20, linux_fs, 0).unwrap();
+    gdisk.discard_partition(h.id()).unwrap();
+}
+
+#[test]
+fn test_gptdisk_open_partition() {
+    let tempdisk = fixture_disk();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk.open_partition(2).unwrap_err();
+    let mut part = gdisk.open_partition(1).unwrap();
+    assert_eq!(part.size(), 29 * 512);
+    let mut data = vec![0x55; 29 * 512 + 100];
+    let err = part.write_all(&data).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::WriteZero);
+    assert_eq!(part.stream_position().unwrap(), 29 * 512);
+    part.seek(SeekFrom::Start(10)).unwrap();
+    part.write_all(b"hello").unwrap();
+    part.seek(SeekFrom::Start(0)).unwrap();
+    data.clear();
+    part.read_to_end(&mut data).unwrap();
+    assert_eq!(data.len(), 29 * 512);
+    assert_eq!(&data[8..17], b"\x55\x55hello\x55\x55");
+
+    // The backup partition array right after the partition is untouched.
+    let mut gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    let err = gdisk.open_partition(1).unwrap().write(b"x").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn test_clone_layout() {
+    let src = gpt::GptConfig::new()
+        .open(path::Path::new("tests/fixtures/gpt-linux-disk-01.img"))
+        .unwrap();
+    let lb_size = disk::DEFAULT_SECTOR_SIZE;
+    let mut dst = gpt::GptDisk::new_in_memory(4 * 1024 * 1024, lb_size).unwrap();
+    let esp = gpt::partition_types::Type::from_name("EFI System").unwrap();
+    dst.add_partition("esp", 4096, esp, 0).unwrap();
+
+    gpt::clone_layout(&src, &mut dst, false).unwrap();
+    assert_eq!(dst.partitions(), src.partitions());
+    assert_eq!(dst.guid(), src.guid());
+    assert!(src.layout_eq(&dst));
+
+    gpt::clone_layout(&src, &mut dst, true).unwrap();
+    assert!(src.layout_eq(&dst));
+    assert_ne!(dst.guid(), src.guid());
+    assert_ne!(
+        dst.partition(1).unwrap().part_guid,
+        src.partition(1).unwrap().part_guid
+    );
+    assert!(gpt::find_cross_disk_duplicates(&[&dst]).is_empty());
+    let mut image = std::io::Cursor::new(vec![0u8; 4 * 1024 * 1024]);
+    dst.write_into(&mut image).unwrap();
+
+    // Partition 1 does not fit before the backup array of a smaller disk.
+    let mut small = gpt::GptDisk::new_in_memory(80 * 512, lb_size).unwrap();
+    gpt::clone_layout(&src, &mut small, false).unwrap_err();
+    assert!(small.partitions().is_empty());
+    let mut native =
+        gpt::GptDisk::new_in_memory(1024 * 1024, disk::LogicalBlockSize::Lb4096).unwrap();
+    gpt::clone_layout(&src, &mut native, false).unwrap_err();
+}
+
+#[test]
+fn test_gptconfig_exclusive() {
+    let tempdisk = fixture_disk();
+
+    // Regular files are never busy.
+    let gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .exclusive(true)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.partitions().len(), 1);
+}
+
+#[test]
+fn test_find_cross_disk_duplicates() {
+    let diskpath = path::Path::new("tests/fixtures/gpt-linux-disk-01.img");
+    let d1 = gpt::GptConfig::new().open(diskpath).unwrap();
+    let d2 = gpt::GptConfig::new().open(diskpath).unwrap();
+    let guid = *d1.guid();
+    let part_guid = d1.partitions()[&1].part_guid;
+
+    let dups = gpt::find_cross_disk_duplicates(&[&d1, &d2]);
+    assert_eq!(
+        dups,
+        vec![
+            gpt::CrossDiskDuplicate::DiskGuid(guid, vec![0, 1]),
+            gpt::CrossDiskDuplicate::PartitionGuid(part_guid, vec![(0, 1), (1, 1)]),
+        ]
+    );
+    assert!(gpt::find_cross_disk_duplicates(&[&d1]).is_empty());
+}
+
+#[test]
+fn test_gptdisk_write_image_to_partition() {
+    let mut tempdisk = NamedTempFile::new().expect("failed to create tempfile disk");
+    fs::copy("tests/fixtures/gpt-linux-disk-01.img", tempdisk.path()).unwrap();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    let start = gdisk.partitions()[&1]
+        .bytes_start(disk::LogicalBlockSize::Lb512)
+        .unwrap();
+
+    let image: Vec<u8> = (0..29 * 512).map(|i| (i % 251) as u8).collect();
+    let mut reports = vec![];
+    let written = gdisk
+        .write_image_to_partition(1, &mut image.as_slice(), |n| reports.push(n))
+        .unwrap();
+    assert_eq!(written, image.len() as u64);
+    assert_eq!(reports.last(), Some(&written));
+
+    let mut readback = vec![0u8; image.len()];
+    tempdisk.seek(SeekFrom::Start(start)).unwrap();
+    tempdisk.read_exact(&mut readback).unwrap();
+    assert_eq!(readback, image);
+
+    // One byte too many for the 29 sectors partition.
+    let too_big = vec![0u8; image.len() + 1];
+    gdisk
+        .write_image_to_partition(1, &mut too_big.as_slice(), |_| {})
+        .unwrap_err();
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_gptdisk_backup_restore_partition() {
+    let tempdisk = fixture_disk();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    let image: Vec<u8> = (0..29 * 512).map(|i| (i % 253) as u8).collect();
+    gdisk
+        .write_image_to_partition(1, &mut image.as_slice(), |_| {})
+        .unwrap();
+
+    let mut backup = vec![];
+    assert_eq!(gdisk.backup_partition(1, &mut backup).unwrap(), 29 * 512);
+    assert!(backup.len() < image.len());
+
+    gdisk.wipe_signatures(1).unwrap();
+    gdisk.restore_partition(1, backup.as_slice()).unwrap();
+    let mut again = vec![];
+    gdisk.backup_partition(1, &mut again).unwrap();
+    assert_eq!(again, backup);
+
+    // Corrupt the trailing checksum.
+    let last = backup.len() - 1;
+    backup[last] ^= 0xFF;
+    gdisk.restore_partition(1, backup.as_slice()).unwrap_err();
+}
+
+#[test]
+fn test_gptconfig_verify_writes() {
+    let tempdisk = fixture_disk();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .verify_writes(true)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().flags = 1 << 2)
+        .unwrap();
+    gdisk.write_partition_entry(1).unwrap();
+    gdisk.write().unwrap();
+}
+
+#[test]
+fn test_gptconfig_quirks() {
+    use gpt::quirks::Quirks;
+
+    let tempdisk = fixture_disk();
+    let gdisk = gpt::GptConfig::new()
+        .quirks(Quirks::all())
+        .open(tempdisk.path())
+        .unwrap();
+    assert!(gdisk.quirks().is_empty());
+
+    // Grown image, with the backup header left behind.
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .open(tempdisk.path())
+        .unwrap();
+    f.set_len(128 * 512).unwrap();
+    gpt::GptConfig::new().open(tempdisk.path()).unwrap_err();
+    let gdisk = gpt::GptConfig::new()
+        .quirks(Quirks::MISPLACED_BACKUP)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.quirks(), Quirks::MISPLACED_BACKUP);
+    assert_eq!(gdisk.backup_header().unwrap().current_lba, 95);
+
+    // Entries CRC32 over the single used entry, header CRC32 left zero.
+    let mut entry = [0u8; 128];
+    let mut rf = fs::File::open(tempdisk.path()).unwrap();
+    rf.seek(SeekFrom::Start(2 * 512)).unwrap();
+    rf.read_exact(&mut entry).unwrap();
+    let crc = crc32fast::hash(&entry);
+    f.seek(SeekFrom::Start(512 + 16)).unwrap();
+    f.write_all(&[0; 4]).unwrap();
+    f.seek(SeekFrom::Start(512 + 88)).unwrap();
+    f.write_all(&crc.to_le_bytes()).unwrap();
+    gpt::GptConfig::new()
+        .quirks(Quirks::MISPLACED_BACKUP | Quirks::ZERO_HEADER_CRC)
+        .open(tempdisk.path())
+        .unwrap_err();
+    let gdisk = gpt::GptConfig::new()
+        .quirks(Quirks::all())
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.quirks(), Quirks::all());
+    assert_eq!(gdisk.partitions().len(), 1);
+}
+
+#[test]
+fn test_gptdisk_add_bios_boot_partition() {
+    let tempdisk = temp_disk(4 * 1024 * 1024);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .open(tempdisk.path())
+        .unwrap();
+    let handle = gdisk.add_bios_boot_partition().unwrap();
+    let p = gdisk.partition_by_handle(&handle).unwrap();
+    assert_eq!(
+        p.part_type_guid.guid.to_string(),
+        "21686148-6449-6e6f-744e-656564454649"
+    );
+    assert_eq!(p.part_type_guid.description, "BIOS Boot Partition");
+    assert_eq!(p.last_lba - p.first_lba + 1, 2048);
+    gdisk.add_bios_boot_partition().unwrap_err();
+}
+
+#[test]
+fn test_gptdisk_add_windows_layout() {
+    let tempdisk = temp_disk(256 * 1024 * 1024);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk
+        .add_windows_layout(Some(200 * 1024 * 1024))
+        .unwrap_err();
+    assert!(gdisk.partitions().is_empty());
+
+    let handles = gdisk.add_windows_layout(Some(32 * 1024 * 1024)).unwrap();
+    let names: Vec<_> = handles
+        .iter()
+        .map(|h| gdisk.partition_by_handle(h).unwrap().name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "EFI system partition",
+            "Microsoft reserved partition",
+            "Basic data partition",
+            "Recovery"
+        ]
+    );
+    let recovery = gdisk.partition_by_handle(&handles[3]).unwrap();
+    let recovery_len = recovery.last_lba - recovery.first_lba + 1;
+    assert!((32 * 2048..33 * 2048).contains(&recovery_len));
+    assert_eq!(recovery.first_lba % gdisk.alignment(), 0);
+    assert_eq!(
+        recovery.last_lba,
+        gdisk.preview_headers().unwrap().0.last_usable
+    );
+    let findings = gdisk
+        .verify(&[gpt::validate::Profile::WindowsBoot])
+        .unwrap();
+    assert!(findings.is_empty(), "{:?}", findings);
+    gdisk.add_windows_layout(None).unwrap_err();
+}
+
+#[test]
+fn test_gptdisk_add_apple_partition() {
+    use gpt::partition::{PartitionType, APPLE_APFS, APPLE_HFS_PLUS};
+
+    let apfs = PartitionType::from_guid(uuid::Uuid::parse_str(APPLE_APFS).unwrap());
+    let hfs = PartitionType::from_guid(uuid::Uuid::parse_str(APPLE_HFS_PLUS).unwrap());
+    assert!(apfs.is_apple());
+    assert_eq!(apfs.description, "Apple APFS Container");
+
+    let tempdisk = temp_disk(512 * 1024 * 1024);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .open(tempdisk.path())
+        .unwrap();
+    let mib = 1024 * 1024;
+    let h1 = gdisk.add_apple_partition("mac", mib, apfs, 0).unwrap();
+    let h2 = gdisk.add_apple_partition("data", mib, hfs, 0).unwrap();
+    let p1 = gdisk.partition_by_handle(&h1).unwrap().clone();
+    let p2 = gdisk.partition_by_handle(&h2).unwrap();
+    assert_eq!(p1.first_lba, 2048);
+    assert_eq!(p2.first_lba, p1.last_lba + 1 + 128 * 2048);
+    gdisk
+        .add_apple_partition("too big", 256 * mib, p2.part_type_guid.clone(), 0)
+        .unwrap_err();
+}
+
+#[test]
+fn test_gptdisk_write_to() {
+    let gdisk = gpt::GptConfig::new()
+        .open(path::Path::new("tests/fixtures/gpt-linux-disk-01.img"))
+        .unwrap();
+    let mut small = std::io::Cursor::new(vec![]);
+    gdisk.write_to(&mut small, 80 * 512).unwrap_err();
+
+    let tempdisk = temp_disk(1024 * 1024);
+    let mut target = tempdisk.reopen().unwrap();
+    gdisk.write_to(&mut target, 1024 * 1024).unwrap();
+
+    let copy = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert!(copy.layout_eq(&gdisk));
+    assert_eq!(copy.backup_header().unwrap().current_lba, 2047);
+    assert_eq!(copy.primary_header().unwrap().last_usable, 2014);
+    gpt::mbr::ProtectiveMBR::from_disk(&mut target, disk::DEFAULT_SECTOR_SIZE).unwrap();
+    let mut mbr = [0u8; 512];
+    target.seek(SeekFrom::Start(0)).unwrap();
+    target.read_exact(&mut mbr).unwrap();
+    assert_eq!(mbr[446 + 4], 0xEE);
+}
+
+#[test]
+fn test_read_header_at() {
+    let lb_size = disk::DEFAULT_SECTOR_SIZE;
+    let mut f = fs::File::open("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+    let found: Vec<u64> = (0..96)
+        .filter(|lba| gpt::header::read_header_at(&mut f, *lba, lb_size).is_ok())
+        .collect();
+    assert_eq!(found, [1, 95]);
+    let h2 = gpt::header::read_header_at(&mut f, 95, lb_size).unwrap();
+    assert_eq!(h2.backup_lba, 1);
+    gpt::header::read_header_at(&mut f, 1000, lb_size).unwrap_err();
+    gpt::header::read_header_at(&mut f, u64::MAX, lb_size).unwrap_err();
+}
+
+#[test]
+fn test_gptdisk_partition_array_lbas() {
+    let tempdisk = temp_disk(1024 * 1024);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .alignment(512)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.partition_array_lbas().unwrap(), (2, 2015));
+    gdisk
+        .set_partition_array_lbas(Some((100, 2040)))
+        .unwrap_err();
+    gdisk.set_partition_array_lbas(Some((100, 1900))).unwrap();
+    let h = gdisk.add_partition("data", 4096, linux_fs(), 0).unwrap();
+    assert_eq!(gdisk.partition_by_handle(&h).unwrap().first_lba, 132);
+    gdisk
+        .set_partition_array_lbas(Some((200, 1900)))
+        .unwrap_err();
+    gdisk.write_partition_entry(h.id()).unwrap();
+
+    let gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert_eq!(gdisk.partition_array_lbas().unwrap(), (100, 1900));
+    let (h1, h2) = gdisk.preview_headers().unwrap();
+    assert_eq!((h1.part_start, h2.part_start), (100, 1900));
+    assert_eq!((h1.first_usable, h1.last_usable), (132, 1899));
+    assert_eq!(gdisk.partitions()[&1].name, "data");
+
+    // Recovering from a damaged primary array keeps it where it was recorded.
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .open(tempdisk.path())
+        .unwrap();
+    f.seek(SeekFrom::Start(100 * 512 + 56)).unwrap();
+    f.write_all(b"X").unwrap();
+    drop(f);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .readable_backup(true)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.partition_array_lbas().unwrap(), (100, 1900));
+    gdisk.repair_primary().unwrap();
+    let gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert_eq!(gdisk.primary_header().unwrap().part_start, 100);
+    assert_eq!(gdisk.partitions()[&1].name, "data");
+}
+
+#[test]
+fn test_gptdisk_reserved_regions() {
+    use gpt::partition::MapEntry;
+
+    let tempdisk = temp_disk(1024 * 1024);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .alignment(512)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk.reserve_region("firmware", 34, 99).unwrap();
+    gdisk.reserve_region("overlap", 90, 120).unwrap_err();
+    gdisk.reserve_region("outside", 10, 40).unwrap_err();
+    let h = gdisk.add_partition("data", 4096, linux_fs(), 0).unwrap();
+    let p = gdisk.partition_by_handle(&h).unwrap();
+    assert_eq!((p.first_lba, p.last_lba), (100, 107));
+
+    let map = gdisk.layout_map().unwrap();
+    assert_eq!(map.len(), 3);
+    assert_eq!(map[0].to_string(), "34 - 99\treserved (firmware)");
+    assert_eq!(
+        map[1],
+        MapEntry::Partition {
+            id: 1,
+            first_lba: 100,
+            last_lba: 107
+        }
+    );
+    assert_eq!(
+        map[2],
+        MapEntry::Free {
+            first_lba: 108,
+            last_lba: 2014
+        }
+    );
+
+    assert_eq!(gdisk.reserved_regions().len(), 1);
+    assert!(gdisk.release_region("firmware"));
+    assert!(!gdisk.release_region("firmware"));
+    assert_eq!(gdisk.layout_map().unwrap()[0].range(), (34, 99));
+}
+
+#[test]
+fn test_gptdisk_remove_required_partition() {
+    let tempdisk = fixture_disk();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().flags = 1)
+        .unwrap();
+    let err = gdisk.remove_partition(1, false).unwrap_err();
+    assert!(matches!(err, gpt::Error::RequiredPartition(1)));
+    assert_eq!(
+        std::io::Error::from(err).kind(),
+        std::io::ErrorKind::PermissionDenied
+    );
+    assert_eq!(gdisk.partitions().len(), 1);
+    assert_eq!(gdisk.remove_partition(1, true).unwrap().name, "primary");
+    assert!(gdisk.partitions().is_empty());
+    gdisk.remove_partition(1, true).unwrap_err();
+}
+
+#[test]
+fn test_gptdisk_write_check() {
+    let tempdisk = fixture_disk();
+    let open = |check| {
+        let mut gdisk = gpt::GptConfig::new()
+            .writable(true)
+            .write_check(check)
+            .open(tempdisk.path())
+            .unwrap();
+        // Duplicate partition 1, overlapping itself.
+        let mut pp = gdisk.partitions().clone();
+        pp.insert(2, pp[&1].clone());
+        gdisk.update_partitions(pp).map(|_| ())?;
+        Ok::<_, gpt::Error>(gdisk)
+    };
+
+    let err = open(gpt::validate::WriteCheck::Deny).unwrap_err();
+    let failed = match err {
+        gpt::Error::ValidationFailed(failed) => failed,
+        e => panic!("unexpected error: {}", e),
+    };
+    assert!(failed
+        .findings
+        .iter()
+        .any(|f| f.partitions == vec![1, 2] && f.severity == gpt::validate::Severity::Error));
+
+    open(gpt::validate::WriteCheck::Warn)
+        .unwrap()
+        .write()
+        .unwrap();
+}
+
+#[test]
+fn test_gptdisk_update_partitions_validation() {
+    let mut gdisk = gpt::GptConfig::new()
+        .open(path::Path::new("tests/fixtures/gpt-linux-disk-01.img"))
+        .unwrap();
+    let base = gdisk.partitions()[&1].clone();
+    let part = |first_lba, last_lba| {
+        let mut p = base.clone();
+        p.part_guid = uuid::Uuid::new_v4();
+        p.first_lba = first_lba;
+        p.last_lba = last_lba;
+        p
+    };
+    let failed = |gdisk: &mut gpt::GptDisk<_>, pp| match gdisk.update_partitions(pp) {
+        Err(gpt::Error::ValidationFailed(failed)) => failed.findings,
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("invalid table accepted"),
+    };
+
+    // Partition 3 is nested in partition 1, without touching partition 2.
+    let mut pp = std::collections::BTreeMap::new();
+    pp.insert(1, part(34, 50));
+    pp.insert(2, part(40, 42));
+    pp.insert(3, part(45, 47));
+    let findings = failed(&mut gdisk, pp);
+    let overlaps: Vec<&Vec<u32>> = findings.iter().map(|f| &f.partitions).collect();
+    assert_eq!(overlaps, vec![&vec![1, 2], &vec![1, 3]]);
+
+    let mut pp = std::collections::BTreeMap::new();
+    pp.insert(1, part(34, 40));
+    let mut p2 = pp[&1].clone();
+    p2.first_lba = 41;
+    p2.last_lba = 62;
+    pp.insert(2, p2);
+    let findings = failed(&mut gdisk, pp);
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].partitions, vec![1, 2]);
+    assert!(findings[0].message.starts_with("duplicate unique GUID"));
+
+    let mut pp = std::collections::BTreeMap::new();
+    pp.insert(1, part(34, 40));
+    pp.insert(2, part(41, 62));
+    gdisk.update_partitions(pp).unwrap();
+    assert_eq!(gdisk.partitions().len(), 2);
+}
+
+#[test]
+fn test_gptdisk_verify_integrity() {
+    use gpt::Error;
+
+    let tempdisk = fixture_disk();
+    let mut gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert!(gdisk.verify_integrity().unwrap().is_empty());
+    let h1 = gdisk.primary_header().unwrap().clone();
+    assert!(h1.is_valid(1));
+    assert!(matches!(
+        h1.check_integrity(95)[..],
+        [Error::CurrentLbaMismatch {
+            expected: 95,
+            found: 1
+        }]
+    ));
+
+    // Corrupt the primary partition array and the backup header.
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .open(tempdisk.path())
+        .unwrap();
+    f.seek(SeekFrom::Start(2 * 512 + 60)).unwrap();
+    f.write_all(b"X").unwrap();
+    f.seek(SeekFrom::Start(95 * 512 + 60)).unwrap();
+    f.write_all(b"X").unwrap();
+    f.sync_all().unwrap();
+
+    let errors = gdisk.verify_integrity().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert!(matches!(
+        errors[0],
+        (1, Error::PartitionArrayCrcMismatch { expected, .. }) if expected == h1.crc32_parts
+    ));
+    assert!(matches!(errors[1], (95, Error::HeaderCrcMismatch { .. })));
+}
+
+#[test]
+fn test_gptconfig_readable_backup() {
+    let tempdisk = NamedTempFile::new().expect("failed to create tempfile disk");
+    for offset in [512 + 60, 2 * 512 + 60] {
+        fs::copy("tests/fixtures/gpt-linux-disk-01.img", tempdisk.path()).unwrap();
+        let mut f = fs::OpenOptions::new()
+            .write(true)
+            .open(tempdisk.path())
+            .unwrap();
+        f.seek(SeekFrom::Start(offset)).unwrap();
+        f.write_all(b"X").unwrap();
+        f.sync_all().unwrap();
+        gpt::GptConfig::new().open(tempdisk.path()).unwrap_err();
+
+        let mut gdisk = gpt::GptConfig::new()
+            .writable(true)
+            .readable_backup(true)
+            .open(tempdisk.path())
+            .unwrap();
+        assert_eq!(gdisk.state(), gpt::DiskState::Degraded);
+        assert_eq!(gdisk.partitions()[&1].name, "primary");
+        gdisk.repair_primary().unwrap();
+        assert_eq!(gdisk.state(), gpt::DiskState::Healthy);
+        assert!(gdisk.verify_integrity().unwrap().is_empty());
+
+        let gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+        assert_eq!(gdisk.state(), gpt::DiskState::Healthy);
+        assert_eq!(gdisk.partitions()[&1].first_lba, 34);
+    }
+}
+
+#[test]
+fn test_gptdisk_expand() {
+    let tempdisk = fixture_disk();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.expand(true).unwrap(), None);
+
+    // The image is enlarged while open.
+    tempdisk.as_file().set_len(200 * 512).unwrap();
+    assert_eq!(gdisk.expand(true).unwrap(), Some(1));
+    assert_eq!(gdisk.partitions()[&1].last_lba, 166);
+    gdisk.write_inplace().unwrap();
+
+    // The protective MBR is resized along.
+    let gdisk = gpt::GptConfig::new()
+        .require_protective_mbr(true)
+        .open(tempdisk.path())
+        .unwrap();
+    let h2 = gdisk.backup_header().unwrap();
+    assert_eq!((h2.current_lba, h2.last_usable), (199, 166));
+    assert_eq!(gdisk.partitions()[&1].first_lba, 34);
+    assert_eq!(gdisk.partitions()[&1].last_lba, 166);
+
+    // Shrinking is refused.
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    tempdisk.as_file().set_len(150 * 512).unwrap();
+    gdisk.expand(false).unwrap_err();
+}
+
+#[test]
+fn test_image_create_sparse() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("disk.img");
+    let lb_size = disk::DEFAULT_SECTOR_SIZE;
+    gpt::image::create_sparse(&path, 1000, lb_size).unwrap_err();
+    assert!(!path.exists());
+
+    let mut gdisk = gpt::image::create_sparse(&path, 64 * 1024 * 1024, lb_size).unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().len(), 64 * 1024 * 1024);
+    let data = gpt::partition_types::Type::from_name("Linux Filesystem Data").unwrap();
+    gdisk.add_partition("data", 1024 * 1024, data, 0).unwrap();
+    gdisk.write().unwrap();
+    let gdisk = gpt::GptConfig::new()
+        .require_protective_mbr(true)
+        .open(&path)
+        .unwrap();
+    assert_eq!(gdisk.partitions()[&1].name, "data");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        assert!(fs::metadata(&path).unwrap().blocks() * 512 < 1024 * 1024);
+    }
+
+    // Existing files are left alone.
+    gpt::image::create_sparse(&path, 64 * 1024 * 1024, lb_size).unwrap_err();
+}
+
+#[test]
+fn test_gptdisk_recompute_checksums() {
+    let tempdisk = fixture_disk();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    let original = gdisk.primary_header().cloned().unwrap();
+    gdisk.recompute_checksums().unwrap();
+    assert_eq!(gdisk.primary_header(), Some(&original));
+
+    // Rename partition 1 by hand in both arrays ("primary" is UTF-16 at offset 56).
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .open(tempdisk.path())
+        .unwrap();
+    for lba in &[2, 63] {
+        f.seek(SeekFrom::Start(lba * 512 + 56)).unwrap();
+        f.write_all(b"P").unwrap();
+    }
+    drop(f);
+    gpt::GptConfig::new().open(tempdisk.path()).unwrap_err();
+
+    gdisk.recompute_checksums().unwrap();
+    let guid = uuid::Uuid::new_v4();
+    gdisk.primary_header_mut().unwrap().set_disk_guid(guid);
+    gdisk.backup_header_mut().unwrap().set_disk_guid(guid);
+    gdisk.write_headers().unwrap();
+    // Header CRC32 fields were computed before the change.
+    gpt::GptConfig::new().open(tempdisk.path()).unwrap_err();
+    assert!(gdisk.primary_header().unwrap().needs_checksum().unwrap());
+    gdisk.recompute_checksums().unwrap();
+    assert!(!gdisk.primary_header().unwrap().needs_checksum().unwrap());
+    gdisk.write_headers().unwrap();
+
+    let gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert_eq!(gdisk.partitions()[&1].name, "Primary");
+    assert_eq!(gdisk.guid(), &guid);
+}
+
+#[test]
+fn test_gptdisk_shrink_to() {
+    let tempdisk = fixture_disk();
+    let gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert_eq!(gdisk.minimum_disk_size().unwrap(), 96 * 512);
+
+    tempdisk.as_file().set_len(4 * 1024 * 1024).unwrap();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .overwrite(true)
+        .open(tempdisk.path())
+        .unwrap();
+    let data = gpt::partition_types::Type::from_name("Linux Filesystem Data").unwrap();
+    gdisk.add_partition("data", 1024 * 1024, data, 0).unwrap();
+    // Partition at LBAs 2048-4095, then 32 LBAs of array and the header.
+    let minimum = gdisk.minimum_disk_size().unwrap();
+    assert_eq!(minimum, 4129 * 512);
+    gdisk.shrink_to(minimum - 512).unwrap_err();
+    gdisk.shrink_to(minimum + 1).unwrap_err();
+    gdisk.shrink_to(8 * 1024 * 1024).unwrap_err();
+    gdisk.shrink_to(minimum).unwrap();
+    assert_eq!(gdisk.geometry().unwrap().last_usable, 4095);
+    gdisk.write_initial_layout().unwrap();
+
+    tempdisk.as_file().set_len(minimum).unwrap();
+    let gdisk = gpt::GptConfig::new()
+        .require_protective_mbr(true)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.backup_header().unwrap().current_lba, 4128);
+    assert_eq!(gdisk.partitions()[&1].last_lba, 4095);
+
+    // Shrinking an existing table resizes its protective MBR too.
+    let tempdisk = fixture_disk();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    tempdisk.as_file().set_len(200 * 512).unwrap();
+    gdisk.expand(false).unwrap();
+    gdisk.write_inplace().unwrap();
+    gdisk.shrink_to(150 * 512).unwrap();
+    gdisk.write_inplace().unwrap();
+    tempdisk.as_file().set_len(150 * 512).unwrap();
+    let gdisk = gpt::GptConfig::new()
+        .require_protective_mbr(true)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.backup_header().unwrap().current_lba, 149);
+}
+
+#[test]
+fn test_gptdisk_rewrite_backup_at_end() {
+    let tempdisk = fixture_disk();
+    tempdisk.as_file().set_len(200 * 512).unwrap();
+    gpt::GptConfig::new().open(tempdisk.path()).unwrap_err();
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .quirks(gpt::quirks::Quirks::MISPLACED_BACKUP)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.backup_header().unwrap().current_lba, 95);
+    gdisk.rewrite_backup_at_end().unwrap();
+    assert!(gdisk.verify_integrity().unwrap().is_empty());
+
+    let gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    let h1 = gdisk.primary_header().unwrap();
+    assert_eq!(h1.backup_lba, 199);
+    assert_eq!(h1.last_usable, 166);
+    assert_eq!(gdisk.backup_header().unwrap().part_start, 167);
+    assert_eq!(gdisk.partitions()[&1].name, "primary");
+}
+
+#[test]
+fn test_gptdisk_table_backup() {
+    let tempdisk = fixture_disk();
+    let backup = NamedTempFile::new().expect("failed to create tempfile backup");
+    let mut gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    gdisk.export_backup(backup.path()).unwrap();
+    // MBR, both headers and both arrays of 128 entries.
+    assert_eq!(
+        fs::metadata(backup.path()).unwrap().len(),
+        3 * 512 + 2 * 128 * 128
+    );
+
+    // Wipe all table structures, keeping the disk size.
+    let zeros = vec![0u8; 96 * 512];
+    fs::write(tempdisk.path(), &zeros).unwrap();
+    gpt::GptConfig::new().open(tempdisk.path()).unwrap_err();
+    let cfg = gpt::GptConfig::new().writable(false);
+    let err = cfg.restore_from_backup(backup.path(), tempdisk.path());
+    assert!(matches!(err, Err(gpt::Error::NotWritable)));
+
+    let mut restored = gpt::GptConfig::new()
+        .writable(true)
+        .restore_from_backup(backup.path(), tempdisk.path())
+        .unwrap();
+    assert_eq!(restored.partitions(), gdisk.partitions());
+    assert_eq!(restored.guid(), gdisk.guid());
+    assert!(restored.verify_integrity().unwrap().is_empty());
+
+    // On a larger target, the backup table is moved to the end.
+    let larger = temp_disk(200 * 512);
+    let restored = gpt::GptConfig::new()
+        .writable(true)
+        .restore_from_backup(backup.path(), larger.path())
+        .unwrap();
+    assert_eq!(restored.partitions(), gdisk.partitions());
+    assert_eq!(restored.primary_header().unwrap().backup_lba, 199);
+    assert_eq!(restored.backup_header().unwrap().part_start, 167);
+    let pmbr = gpt::mbr::ProtectiveMBR::from_disk(
+        &mut fs::File::open(larger.path()).unwrap(),
+        disk::DEFAULT_SECTOR_SIZE,
+    )
+    .unwrap();
+    pmbr.validate(200).unwrap();
+
+    // Smaller targets are refused.
+    let smaller = temp_disk(64 * 512);
+    let err = gpt::GptConfig::new()
+        .writable(true)
+        .restore_from_backup(backup.path(), smaller.path());
+    assert!(matches!(err, Err(gpt::Error::TooShort)));
+
+    // A corrupted primary array falls back to the appended backup one,
+    // and fails with both corrupted.
+    let mut data = fs::read(backup.path()).unwrap();
+    data[3 * 512] ^= 0xFF;
+    fs::write(backup.path(), &data).unwrap();
+    gpt::GptConfig::new()
+        .writable(true)
+        .restore_from_backup(backup.path(), tempdisk.path())
+        .unwrap();
+    data[3 * 512 + 128 * 128] ^= 0xFF;
+    fs::write(backup.path(), &data).unwrap();
+    let err = gpt::GptConfig::new()
+        .writable(true)
+        .restore_from_backup(backup.path(), tempdisk.path());
+    assert!(matches!(
+        err,
+        Err(gpt::Error::PartitionArrayCrcMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_gptdisk_table_backup_block_size() {
+    let tempdisk = temp_disk(256 * 4096);
+    let backup = NamedTempFile::new().expect("failed to create tempfile backup");
+    let mut gdisk = gpt::mkgpt(tempdisk.path(), disk::LogicalBlockSize::Lb4096).unwrap();
+    gdisk.export_backup(backup.path()).unwrap();
+
+    // A backup of a 4K-native disk does not fit 512-byte sectors.
+    let target = temp_disk(256 * 4096);
+    let err = gpt::GptConfig::new()
+        .writable(true)
+        .restore_from_backup(backup.path(), target.path());
+    assert!(matches!(err, Err(gpt::Error::Invalid(_))));
+    let data = fs::read(target.path()).unwrap();
+    assert!(data.iter().all(|b| *b == 0));
+
+    gpt::GptConfig::new()
+        .writable(true)
+        .logical_block_size(disk::LogicalBlockSize::Lb4096)
+        .restore_from_backup(backup.path(), target.path())
+        .unwrap();
+}
+
+#[test]
+fn test_gptdisk_zap() {
+    let tempdisk = fixture_disk();
+    let before = fs::read(tempdisk.path()).unwrap();
+    let mut gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert!(matches!(gdisk.zap(), Err(gpt::Error::NotWritable)));
+
+    // Corrupt the primary header, the backup table is used and both are zapped.
+    let mut data = before.clone();
+    data[512 + 24] ^= 0xFF;
+    fs::write(tempdisk.path(), &data).unwrap();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .readable_backup(true)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.state(), gpt::DiskState::Degraded);
+    gdisk.zap().unwrap();
+    assert!(gdisk.partitions().is_empty());
+    assert!(gdisk.primary_header().is_none());
+
+    let data = fs::read(tempdisk.path()).unwrap();
+    assert!(data[..34 * 512].iter().all(|b| *b == 0));
+    assert!(data[63 * 512..].iter().all(|b| *b == 0));
+    // Partition contents are left alone.
+    assert_eq!(data[34 * 512..63 * 512], before[34 * 512..63 * 512]);
+    gpt::GptConfig::new().open(tempdisk.path()).unwrap_err();
+
+    // A fresh table can then be written.
+    gdisk.write_initial_layout().unwrap();
+    let gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert!(gdisk.partitions().is_empty());
+}
+
+#[test]
+fn test_gptdisk_write_initial_layout_rejected() {
+    let tempdisk = temp_disk(4 * 1024 * 1024);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .write_check(gpt::validate::WriteCheck::Deny)
+        .open(tempdisk.path())
+        .unwrap();
+    let data = gpt::partition_types::Type::from_name("Linux Filesystem Data").unwrap();
+    gdisk.add_partition("data", 1024 * 1024, data, 0).unwrap();
+    for p in gdisk.partitions_mut() {
+        p.part_guid = uuid::Uuid::nil();
+    }
+    let err = gdisk.write_initial_layout().unwrap_err();
+    assert!(matches!(err, gpt::Error::ValidationFailed(_)));
+    // Not even the protective MBR was written.
+    let data = fs::read(tempdisk.path()).unwrap();
+    assert!(data.iter().all(|b| *b == 0));
+}
+
+#[test]
+fn test_mkgpt() {
+    let tempdisk = temp_disk(1024 * 1024);
+    let mut gdisk = gpt::mkgpt(tempdisk.path(), disk::DEFAULT_SECTOR_SIZE).unwrap();
+    assert!(gdisk.partitions().is_empty());
+    assert!(gdisk.verify_integrity().unwrap().is_empty());
+    assert_eq!(gdisk.backup_header().unwrap().current_lba, 2047);
+    let pmbr = gpt::mbr::ProtectiveMBR::from_disk(
+        &mut fs::File::open(tempdisk.path()).unwrap(),
+        disk::DEFAULT_SECTOR_SIZE,
+    )
+    .unwrap();
+    pmbr.validate(2048).unwrap();
+
+    // Existing tables are not clobbered.
+    gpt::mkgpt(tempdisk.path(), disk::DEFAULT_SECTOR_SIZE).unwrap_err();
+}
+
+#[test]
+fn test_gptdisk_native_sector_sizes() {
+    use std::convert::TryFrom;
+
+    let linux_fs = gpt::partition_types::Type::from_name("Linux Filesystem Data").unwrap();
+    for &(lb, array_lbas) in &[(4096u64, 4u64), (2048, 8), (520, 32)] {
+        let lb_size = disk::LogicalBlockSize::try_from(lb).unwrap();
+        let tempdisk = temp_disk(4096 * lb);
+        let mut gdisk = gpt::mkgpt(tempdisk.path(), lb_size).unwrap();
+        let id = gdisk
+            .add_partition("data", 1024 * 1024, linux_fs, 0)
+            .unwrap()
+            .id();
+        let first_lba = gdisk.partitions()[&id].first_lba;
+        gdisk.write().unwrap();
+
+        let mut gdisk = gpt::GptConfig::new()
+            .logical_block_size(lb_size)
+            .open(tempdisk.path())
+            .unwrap();
+        let (h1, h2) = (
+            gdisk.primary_header().unwrap(),
+            gdisk.backup_header().unwrap(),
+        );
+        assert_eq!((h1.backup_lba, h2.current_lba), (4095, 4095));
+        assert_eq!(h1.first_usable, 2 + array_lbas);
+        assert_eq!(h2.part_start, 4095 - array_lbas);
+        assert_eq!(h1.last_usable, h2.part_start - 1);
+        assert_eq!(gdisk.partitions()[&1].first_lba, first_lba);
+        assert!(gdisk.verify_integrity().unwrap().is_empty());
+    }
+}
+
+#[test]
+fn test_gptconfig_detect_block_size() {
+    let tempdisk = temp_disk(4096 * 4096);
+    gpt::mkgpt(tempdisk.path(), disk::LogicalBlockSize::Lb4096).unwrap();
+
+    gpt::GptConfig::new().open(tempdisk.path()).unwrap_err();
+    let gdisk = gpt::GptConfig::new()
+        .detect_block_size(true)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(*gdisk.logical_block_size(), disk::LogicalBlockSize::Lb4096);
+    assert_eq!(gdisk.backup_header().unwrap().current_lba, 4095);
+
+    let gdisk = gpt::GptConfig::new()
+        .detect_block_size(true)
+        .open(path::Path::new("tests/fixtures/gpt-linux-disk-01.img"))
+        .unwrap();
+    assert_eq!(*gdisk.logical_block_size(), disk::LogicalBlockSize::Lb512);
+}
+
+#[test]
+fn test_gptdisk_new_in_memory() {
+    let size = 64 * 1024 * 1024;
+    let lb_size = disk::DEFAULT_SECTOR_SIZE;
+    let linux_fs = gpt::partition_types::Type::from_name("Linux Filesystem Data").unwrap();
+    let mut gdisk = gpt::GptDisk::new_in_memory(size, lb_size).unwrap();
+    let h = gdisk
+        .add_partition("root", 16 * 1024 * 1024, linux_fs, 0)
+        .unwrap();
+    let guid = *gdisk.guid();
+
+    let tempdisk = temp_disk(size);
+    gdisk.write_into(&mut tempdisk.as_file()).unwrap();
+    let mut written = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert_eq!(written.guid(), &guid);
+    assert_eq!(written.partitions()[&h.id()].name, "root");
+    assert_eq!(written.primary_header().unwrap().backup_lba, size / 512 - 1);
+    assert!(written.verify_integrity().unwrap().is_empty());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_gptdisk_serde_roundtrip() {
+    use gpt::partition::PartitionAttributes;
+    use std::collections::BTreeMap;
+
+    let diskpath = path::Path::new("tests/fixtures/gpt-linux-disk-01.img");
+    let gdisk = gpt::GptConfig::new().open(diskpath).unwrap();
+    let json = serde_json::to_string(gdisk.partitions()).unwrap();
+    assert!(json.contains(r#""name":"primary""#));
+    let pp: BTreeMap<u32, gpt::partition::Partition> = serde_json::from_str(&json).unwrap();
+    assert_eq!(&pp, gdisk.partitions());
+
+    let h1 = gdisk.primary_header().unwrap();
+    let json = serde_json::to_string(h1).unwrap();
+    let h: gpt::header::Header = serde_json::from_str(&json).unwrap();
+    assert_eq!(&h, h1);
+
+    let attrs = PartitionAttributes::PLATFORM | PartitionAttributes::BOOTABLE;
+    let json = serde_json::to_string(&attrs).unwrap();
+    let decoded: PartitionAttributes = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, attrs);
+}
+
+#[test]
+fn test_gpt_error_kinds() {
+    let tempdisk = fixture_disk();
+    let mut gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert!(matches!(
+        gdisk.write_protective_mbr(),
+        Err(gpt::Error::NotWritable)
+    ));
+    assert!(matches!(
+        gdisk.remove_partition(7, false),
+        Err(gpt::Error::UnknownPartition)
+    ));
+
+    // Errors of lower-level modules keep their kind through `gpt::Error`.
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .open(tempdisk.path())
+        .unwrap();
+    f.seek(SeekFrom::Start(512 + 60)).unwrap();
+    f.write_all(b"X").unwrap();
+    f.seek(SeekFrom::Start(95 * 512 + 60)).unwrap();
+    f.write_all(b"X").unwrap();
+    f.sync_all().unwrap();
+    let err = gpt::GptConfig::new().open(tempdisk.path()).unwrap_err();
+    assert!(
+        matches!(err, gpt::Error::HeaderCrcMismatch { .. }),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_gptconfig_disk_size() {
+    let tempdisk = temp_disk(64 * 1024);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .disk_size(16 * 1024 * 1024)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk.update_partitions(Default::default()).unwrap();
+    let (h1, h2) = gdisk.preview_headers().unwrap();
+    assert_eq!(h1.backup_lba, 32767);
+    assert_eq!(h1.last_usable, 32767 - 33);
+    assert_eq!(h2.part_start, 32767 - 32);
+    let handle = gdisk
+        .add_partition("big", 8 * 1024 * 1024, linux_fs(), 0)
+        .unwrap();
+    let id = gdisk.resolve(&handle).unwrap();
+    assert!(gdisk.partitions()[&id].last_lba > 128);
+    assert_eq!(tempdisk.as_file().metadata().unwrap().len(), 64 * 1024);
+}
+
+#[test]
+fn test_gptconfig_open_from_device() {
+    let image = fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open_from_device(std::io::Cursor::new(image))
+        .unwrap();
+    assert_eq!(gdisk.partitions()[&1].name, "primary");
+    gdisk
+        .with_partitions_mut(|pp| pp.get_mut(&1).unwrap().name = "renamed".to_string())
+        .unwrap();
+    gdisk.write_partition_entry(1).unwrap();
+    let image = gdisk.write().unwrap().into_inner();
+
+    let gdisk = gpt::GptConfig::new()
+        .open_from_device(std::io::Cursor::new(image))
+        .unwrap();
+    assert_eq!(gdisk.partitions()[&1].name, "renamed");
+    assert_eq!(gdisk.alignment(), 2048);
+}
+
+#[test]
+fn test_gptdisk_write_partitions() {
+    let tempdisk = temp_disk(1024 * 1024);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .alignment(512)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk.update_partitions(Default::default()).unwrap();
+    gdisk.add_partition("first", 4096, linux_fs(), 0).unwrap();
+    gdisk.add_partition("second", 8192, linux_fs(), 0).unwrap();
+    let expected = gdisk.partitions().clone();
+    gdisk.write().unwrap();
+
+    let gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert_eq!(gdisk.partitions(), &expected);
+    let (h1, h2) = (
+        gdisk.primary_header().unwrap(),
+        gdisk.backup_header().unwrap(),
+    );
+    assert_eq!(h1.crc32_parts, h2.crc32_parts);
+}
+
+#[test]
+fn test_gptdisk_sort_partitions() {
+    let mut gdisk =
+        gpt::GptDisk::new_in_memory(8 * 1024 * 1024, disk::LogicalBlockSize::Lb512).unwrap();
+    let linux_fs = gpt::partition_types::Type::from_name("Linux Filesystem Data").unwrap();
+    let mib = 1024 * 1024;
+    let sizes = [2 * mib, mib, mib];
+    let handles: Vec<_> = sizes
+        .iter()
+        .map(|size| gdisk.add_partition("p", *size, linux_fs, 0).unwrap())
+        .collect();
+    gdisk.swap_partition_numbers(1, 3).unwrap();
+    gdisk.swap_partition_numbers(2, 7).unwrap();
+    assert_eq!(gdisk.resolve(&handles[0]), Some(3));
+    assert_eq!(gdisk.resolve(&handles[1]), Some(7));
+    assert_eq!(gdisk.resolve(&handles[2]), Some(1));
+    assert_eq!(gdisk.partition(7).unwrap().number(), 7);
+    gdisk.swap_partition_numbers(2, 4).unwrap_err();
+    gdisk.swap_partition_numbers(0, 1).unwrap_err();
+    gdisk.swap_partition_numbers(1, 129).unwrap_err();
+
+    gdisk.sort_partitions().unwrap();
+    let numbers: Vec<_> = handles.iter().map(|h| gdisk.resolve(h)).collect();
+    assert_eq!(numbers, vec![Some(1), Some(2), Some(3)]);
+    let starts: Vec<_> = gdisk.partitions().values().map(|p| p.first_lba).collect();
+    let mut sorted = starts.clone();
+    sorted.sort_unstable();
+    assert_eq!(starts, sorted);
+}
+
+#[test]
+fn test_gptdisk_partition_entries() {
+    let tempdisk = temp_disk(1024 * 1024);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .alignment(512)
+        .partition_entries(256, 128)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk.update_partitions(Default::default()).unwrap();
+    for _ in 0..200 {
+        gdisk.add_partition("p", 4096, linux_fs(), 0).unwrap();
+    }
+    gdisk.write().unwrap();
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    let h1 = gdisk.primary_header().unwrap();
+    assert_eq!(
+        (h1.num_parts, h1.part_size, h1.first_usable),
+        (256, 128, 66)
+    );
+    assert_eq!(gdisk.partitions().len(), 200);
+    assert_eq!(gdisk.partitions()[&1].first_lba, 66);
+    assert_eq!(gdisk.geometry().unwrap(), {
+        let h2 = gdisk.backup_header().unwrap();
+        gpt::header::TableGeometry::from_headers(h1, h2)
+    });
+
+    gdisk.set_partition_entries(512, 64).unwrap_err();
+    gdisk.set_partition_entries(64, 128).unwrap_err();
+    // Partition 200 needs at least 200 entries.
+    gdisk.set_partition_entries(128, 128).unwrap_err();
+    // 256 LBAs of entries would overlap partition 1.
+    gdisk.set_partition_entries(512, 256).unwrap_err();
+    assert_eq!(gdisk.geometry().unwrap().num_parts, 256);
+    gdisk.set_partition_entries(200, 128).unwrap();
+    gdisk.write().unwrap();
+
+    let gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    let h1 = gdisk.primary_header().unwrap();
+    assert_eq!(
+        (h1.num_parts, h1.part_size, h1.first_usable),
+        (200, 128, 52)
+    );
+    assert_eq!(gdisk.partitions().len(), 200);
+}
+
+#[test]
+fn test_gptdisk_add_partition_custom_type() {
+    use gpt::partition_types::Type;
+
+    let tempdisk = temp_disk(4 * 1024 * 1024);
+    let guid = uuid::Uuid::parse_str("5B2E6D5A-0C1F-4E5B-9A8D-7C6B5A493827").unwrap();
+    let vendor = Type::custom(guid, "Vendor", "Calibration Data");
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk.update_partitions(Default::default()).unwrap();
+    let h = gdisk
+        .add_partition("calib", 1024 * 1024, vendor, 0)
+        .unwrap();
+    gdisk
+        .add_partition(
+            "swap",
+            1024 * 1024,
+            Type::from_name("Linux swap").unwrap(),
+            0,
+        )
+        .unwrap();
+    assert_eq!(
+        gdisk.partitions()[&h.id()].part_type_guid.description,
+        "Calibration Data"
+    );
+    gdisk.write().unwrap();
+
+    let gdisk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+    assert!(gdisk.partitions()[&1].part_type_guid.os.is_empty());
+    let gdisk = gpt::GptConfig::new()
+        .custom_type(vendor)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.partitions()[&1].part_type_guid.os, "Vendor");
+    assert_eq!(
+        gdisk.partitions()[&2].part_type_guid.description,
+        "Swap Partition"
+    );
+}
+
+#[test]
+fn test_gptdisk_add_partition_allocation() {
+    use gpt::partition::Allocation;
+
+    let tempdisk = temp_disk(16 * 1024 * 1024);
+    let mib = 1024 * 1024;
+    let place = |strategy| {
+        let mut gdisk = gpt::GptConfig::new()
+            .writable(true)
+            .initialized(false)
+            .allocation(strategy)
+            .open(tempdisk.path())
+            .unwrap();
+        // Partitions are aligned to 1 MiB by default.
+        assert_eq!(gdisk.alignment(), 2048);
+        let sizes = [4 * mib, mib, 2 * mib, mib];
+        let handles: Vec<_> = sizes
+            .iter()
+            .map(|size| gdisk.add_partition("p", *size, linux_fs(), 0).unwrap())
+            .collect();
+        // Free 4 MiB at LBA 2048 and 2 MiB at LBA 12288.
+        gdisk.remove_partition(handles[0].id(), false).unwrap();
+        gdisk.remove_partition(handles[2].id(), false).unwrap();
+        let h = gdisk.add_partition("new", mib, linux_fs(), 0).unwrap();
+        gdisk.partition_by_handle(&h).unwrap().first_lba
+    };
+    assert_eq!(place(Allocation::FirstFit), 2048);
+    assert_eq!(place(Allocation::BestFit), 12288);
+
+    gpt::GptConfig::new()
+        .alignment(1000)
+        .open(tempdisk.path())
+        .unwrap_err();
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .alignment(gpt::partition::Alignment::Sectors(24))
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.alignment(), 24);
+    gdisk.update_partitions(Default::default()).unwrap();
+    let h = gdisk.add_partition("p", mib, linux_fs(), 0).unwrap();
+    let p = gdisk.partition_by_handle(&h).unwrap();
+    assert_eq!(p.first_lba, 48);
+    assert!(p.is_aligned(gdisk.alignment()));
+    assert!(!p.is_aligned(2048));
+}
+
+#[test]
+fn test_gptdisk_resize_partition() {
+    let tempdisk = temp_disk(16 * 1024 * 1024);
+    let mib = 1024 * 1024;
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .open(tempdisk.path())
+        .unwrap();
+    let a = gdisk.add_partition("a", mib, linux_fs(), 0).unwrap();
+    let b = gdisk.add_partition("b", mib, linux_fs(), 0).unwrap();
+
+    // Growing into the neighbor fails, shrinking and growing back works.
+    gdisk.resize_partition(a.id(), 2 * mib).unwrap_err();
+    assert_eq!(gdisk.resize_partition(a.id(), 512).unwrap().last_lba, 2048);
+    assert_eq!(gdisk.resize_partition(a.id(), mib).unwrap().last_lba, 4095);
+    // The last partition can grow up to the end of the usable area.
+    let last_usable = gdisk.geometry().unwrap().last_usable;
+    let max = (last_usable - 4096 + 1) * 512;
+    gdisk.resize_partition(b.id(), max + 512).unwrap_err();
+    assert_eq!(
+        gdisk.resize_partition(b.id(), max).unwrap().last_lba,
+        last_usable
+    );
+
+    let guid = gdisk.partition_by_handle(&a).unwrap().part_guid;
+    let (id, removed) = gdisk.remove_partition_by_guid(&guid, false).unwrap();
+    assert_eq!((id, removed.name.as_str()), (a.id(), "a"));
+    gdisk.remove_partition_by_guid(&guid, false).unwrap_err();
+}
+
+#[test]
+fn test_gptdisk_find_free_sectors() {
+    let tempdisk = temp_disk(1024 * 1024);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .alignment(512)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.find_free_sectors().unwrap(), vec![(34, 1981)]);
+    gdisk.add_partition("data", 4096, linux_fs(), 0).unwrap();
+    gdisk.reserve_region("firmware", 100, 199).unwrap();
+    assert_eq!(
+        gdisk.find_free_sectors().unwrap(),
+        vec![(42, 58), (200, 1815)]
+    );
+}
+
+#[test]
+fn test_gptdisk_allocation_geometry() {
+    let mut image = fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+    image.resize(200 * 512, 0);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .alignment(512)
+        .quirks(gpt::quirks::Quirks::MISPLACED_BACKUP)
+        .open_from_device(std::io::Cursor::new(image))
+        .unwrap();
+    let linux_fs = gdisk.partitions()[&1].part_type_guid.clone();
+    // The usable area found on disk is full, but `write()` moves the
+    // backup header to the end of the disk.
+    let id = gdisk.add_partition("data", 4096, linux_fs, 0).unwrap();
+    assert_eq!(gdisk.partitions()[&id.id()].first_lba, 63);
+    assert_eq!(gdisk.find_free_sectors().unwrap(), vec![(71, 96)]);
+
+    // Moving the backup array shrinks the free space.
+    gdisk.set_partition_array_lbas(Some((2, 150))).unwrap();
+    assert_eq!(gdisk.find_free_sectors().unwrap(), vec![(71, 79)]);
+}
+
+#[test]
+fn test_gptdisk_write_protective_mbr() {
+    let tempdisk = temp_disk(1024 * 1024);
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk.update_partitions(Default::default()).unwrap();
+    gdisk.write().unwrap();
+    let strict = || {
+        gpt::GptConfig::new()
+            .writable(true)
+            .require_protective_mbr(true)
+            .open(tempdisk.path())
+    };
+    strict().unwrap_err();
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk.write_protective_mbr().unwrap();
+    assert!(strict().unwrap().partitions().is_empty());
+    // CHS addresses of LBA 1 and LBA 2047 (head, sector, cylinder).
+    let lba0 = std::fs::read(tempdisk.path()).unwrap();
+    assert_eq!(lba0[447..450], [0, 2, 0]);
+    assert_eq!(lba0[451..454], [32, 32, 0]);
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .chs_mode(gpt::mbr::ChsMode::Zeroed)
+        .open(tempdisk.path())
+        .unwrap();
+    gdisk.write_protective_mbr().unwrap();
+    let lba0 = std::fs::read(tempdisk.path()).unwrap();
+    assert_eq!(lba0[447..450], [0; 3]);
+    assert_eq!(lba0[451..454], [0; 3]);
+    assert!(strict().unwrap().partitions().is_empty());
+}
+
+#[test]
+fn test_gptdisk_observer() {
+    use gpt::observer::{Region, WriteEvent};
+    use std::sync::{Arc, Mutex};
+
+    let image = fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open_from_device(std::io::Cursor::new(image))
+        .unwrap();
+    let events = Arc::new(Mutex::new(vec![]));
+    let recorded = events.clone();
+    gdisk.set_observer(move |e: &WriteEvent| recorded.lock().unwrap().push(e.clone()));
+
+    gdisk.write_inplace().unwrap();
+    let event = |region, offset, len| WriteEvent {
+        region,
+        offset,
+        len,
+    };
+    assert_eq!(
+        std::mem::take(&mut *events.lock().unwrap()),
+        [
+            event(Region::PartitionArray, 63 * 512, 128 * 128),
+            event(Region::Header, 95 * 512, 92),
+            event(Region::PartitionArray, 2 * 512, 128 * 128),
+            event(Region::Header, 512, 92),
+        ]
+    );
+    assert_eq!(
+        event(Region::Header, 512, 92).to_string(),
+        "header write of 92 bytes at 0x200"
+    );
+
+    gdisk.write_protective_mbr().unwrap();
+    gdisk.wipe_signatures(1).unwrap();
+    let written = std::mem::take(&mut *events.lock().unwrap());
+    assert_eq!(written[0], event(Region::Mbr, 446, 66));
+    assert!(written.len() > 1);
+    assert!(written[1..]
+        .iter()
+        .all(|e| e.region == Region::PartitionData(1)
+            && e.offset >= 34 * 512
+            && e.offset + e.len <= 63 * 512));
+
+    assert!(gdisk.take_observer().is_some());
+    gdisk.write_inplace().unwrap();
+    assert!(events.lock().unwrap().is_empty());
+}
+
+/// In-memory disk counting calls to `sync_data()`.
+#[derive(Debug)]
+struct SyncCounter {
+    inner: std::io::Cursor<Vec<u8>>,
+    syncs: usize,
+}
+
+impl Read for SyncCounter {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Write for SyncCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Seek for SyncCounter {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl disk::DiskDevice for SyncCounter {
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.inner.get_ref().len() as u64)
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        self.syncs += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_gptconfig_sync_on_write() {
+    let device = || SyncCounter {
+        inner: std::io::Cursor::new(fs::read("tests/fixtures/gpt-linux-disk-01.img").unwrap()),
+        syncs: 0,
+    };
+    let gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open_from_device(device())
+        .unwrap();
+    assert_eq!(gdisk.write().unwrap().syncs, 0);
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .sync_on_write(true)
+        .open_from_device(device())
+        .unwrap();
+    gdisk.write_protective_mbr().unwrap();
+    gdisk.wipe_signatures(1).unwrap();
+    gdisk.sync().unwrap();
+    assert_eq!(gdisk.write().unwrap().syncs, 4);
+
+    // Verified writes are synced once before being read back.
+    let gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .sync_on_write(true)
+        .verify_writes(true)
+        .open_from_device(device())
+        .unwrap();
+    assert_eq!(gdisk.write().unwrap().syncs, 1);
+}
@@ -1,9 +1,6 @@
-
-use tempfile;
-
 use gpt::{disk, mbr};
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom, Write};
 
 #[test]
 fn test_mbr_partrecord() {
@@ -104,3 +101,293 @@ fn test_mbr_disksig() {
     let s2 = mbr::read_disk_signature(&mut tempdisk).unwrap();
     assert_eq!(s1.to_vec(), s2.to_vec());
 }
+
+fn write_record(disk: &mut File, offset: u64, os_type: u8, start: u32, size: u32) {
+    let mut rec = [0u8; 16];
+    rec[4] = os_type;
+    rec[8..12].copy_from_slice(&start.to_le_bytes());
+    rec[12..16].copy_from_slice(&size.to_le_bytes());
+    disk.seek(SeekFrom::Start(offset)).unwrap();
+    disk.write_all(&rec).unwrap();
+    disk.seek(SeekFrom::Start(offset - offset % 512 + 510))
+        .unwrap();
+    disk.write_all(&[0x55, 0xAA]).unwrap();
+}
+
+#[test]
+fn test_mbr_logical_partitions() {
+    let mut tempdisk = tempfile::tempfile().unwrap();
+    tempdisk.set_len(512 * 256).unwrap();
+    // One primary partition, plus an extended one spanning LBA 100-199.
+    write_record(&mut tempdisk, 446, 0x83, 2, 90);
+    write_record(&mut tempdisk, 446 + 16, 0x0F, 100, 100);
+    // First EBR: logical partition at 102-141, link to the next EBR.
+    write_record(&mut tempdisk, 100 * 512 + 446, 0x83, 2, 40);
+    write_record(&mut tempdisk, 100 * 512 + 462, 0x05, 50, 50);
+    // Second EBR: logical partition at 151-199, end of chain.
+    write_record(&mut tempdisk, 150 * 512 + 446, 0x82, 1, 49);
+
+    let logical =
+        mbr::read_logical_partitions(&mut tempdisk, disk::LogicalBlockSize::Lb512).unwrap();
+    assert_eq!(logical.len(), 2);
+    assert_eq!(logical[0].ebr_lba, 100);
+    assert_eq!(logical[0].record.os_type, 0x83);
+    assert_eq!(logical[0].first_lba(), 102);
+    assert_eq!(logical[0].last_lba(), 141);
+    assert_eq!(logical[1].ebr_lba, 150);
+    assert_eq!(logical[1].record.os_type, 0x82);
+    assert_eq!(logical[1].first_lba(), 151);
+    assert_eq!(logical[1].last_lba(), 199);
+
+    // A link pointing back to an already visited EBR is rejected, and
+    // the position is restored all the same.
+    write_record(&mut tempdisk, 150 * 512 + 462, 0x05, 50, 50);
+    tempdisk.seek(SeekFrom::Start(7)).unwrap();
+    mbr::read_logical_partitions(&mut tempdisk, disk::LogicalBlockSize::Lb512).unwrap_err();
+    assert_eq!(tempdisk.stream_position().unwrap(), 7);
+}
+
+#[test]
+fn test_mbr_no_logical_partitions() {
+    let mut diskf = File::open("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+    let logical = mbr::read_logical_partitions(&mut diskf, disk::LogicalBlockSize::Lb512).unwrap();
+    assert!(logical.is_empty());
+}
+
+#[test]
+fn test_mbr_table_rw_roundtrip() {
+    let mut tempdisk = tempfile::tempfile().unwrap();
+    let mut m0 = mbr::MbrTable::new();
+    m0.set_partition(0, mbr::PartRecord::new(0x0C, 2048, 4096))
+        .unwrap();
+    m0.set_partition(1, mbr::PartRecord::new(0x83, 6144, 8192))
+        .unwrap();
+    m0.set_bootable(0, true).unwrap();
+    m0.set_disk_signature([0xDE, 0xAD, 0xBE, 0xEF]);
+    // Overlapping records and out-of-range slots are rejected.
+    m0.set_partition(2, mbr::PartRecord::new(0x83, 8000, 100))
+        .unwrap_err();
+    m0.set_partition(4, mbr::PartRecord::zero()).unwrap_err();
+    m0.overwrite_lba0(&mut tempdisk).unwrap();
+
+    let m1 = mbr::MbrTable::from_disk(&mut tempdisk, disk::LogicalBlockSize::Lb512).unwrap();
+    assert_eq!(m0.as_bytes().unwrap(), m1.as_bytes().unwrap());
+    let pp = m1.partitions();
+    assert!(pp[0].is_bootable());
+    assert_eq!(pp[0].os_type, 0x0C);
+    assert_eq!(pp[0].lb_start, 2048);
+    assert!(!pp[1].is_bootable());
+    assert_eq!(pp[1].lb_size, 8192);
+    assert!(pp[2].is_empty());
+    assert_eq!(m1.disk_signature(), &[0xDE, 0xAD, 0xBE, 0xEF]);
+}
+
+#[test]
+fn test_mbr_display() {
+    let pmbr = mbr::ProtectiveMBR::with_lb_size(95);
+    let out = pmbr.to_string();
+    assert!(out.starts_with("Protective MBR\n"));
+    assert!(out.contains("Boot signature:\t\t55AA (valid)"));
+    assert!(out.contains("Partition 1:\t\t0xee (GPT protective), LBA 1 - 95"));
+    assert!(out.contains("Partition 4:\t\tempty"));
+
+    let mut table = mbr::MbrTable::new();
+    table
+        .set_partition(0, mbr::PartRecord::new(0x83, 2048, 2048))
+        .unwrap();
+    table.set_bootable(0, true).unwrap();
+    assert!(table
+        .to_string()
+        .contains("Partition 1:\t\t0x83 (Linux), LBA 2048 - 4095, bootable"));
+}
+
+#[test]
+fn test_mbr_protective_validate() {
+    let m0 = mbr::ProtectiveMBR::for_disk(96);
+    assert_eq!(m0.partitions()[0].lb_size, 95);
+    m0.validate(96).unwrap();
+    m0.validate(200).unwrap_err();
+    // Large disks are capped, and a capped record always validates.
+    let big = mbr::ProtectiveMBR::for_disk(1 << 40);
+    assert_eq!(big.partitions()[0].lb_size, u32::MAX);
+    big.validate(1 << 40).unwrap();
+    mbr::ProtectiveMBR::with_lb_size(u32::MAX)
+        .validate(96)
+        .unwrap();
+
+    let legacy = mbr::MbrTable::new().as_bytes().unwrap();
+    let m1 = mbr::ProtectiveMBR::from_bytes(&legacy, disk::LogicalBlockSize::Lb512).unwrap();
+    m1.validate(96).unwrap_err();
+}
+
+#[test]
+fn test_mbr_hybrid() {
+    let mut tempdisk = tempfile::NamedTempFile::new().unwrap();
+    let mut fixture = File::open("tests/fixtures/gpt-linux-disk-01.img").unwrap();
+    std::io::copy(&mut fixture, &mut tempdisk).unwrap();
+    let disk = gpt::GptConfig::new().open(tempdisk.path()).unwrap();
+
+    let mut hybrid = mbr::HybridMBR::new(96);
+    hybrid.sync(disk.partitions(), &[(1, 0x83)]).unwrap();
+    hybrid.set_bootable(1, true).unwrap();
+    hybrid.set_bootable(2, true).unwrap_err();
+    hybrid.sync(disk.partitions(), &[(2, 0x83)]).unwrap_err();
+    hybrid.sync(disk.partitions(), &[(1, 0x83); 4]).unwrap_err();
+    // Re-syncing keeps the bootable flag of partitions still mirrored.
+    hybrid.sync(disk.partitions(), &[(1, 0x83)]).unwrap();
+    assert_eq!(hybrid.mirrored(), &[1]);
+    hybrid.update_conservative(tempdisk.as_file_mut()).unwrap();
+
+    let m1 =
+        mbr::MbrTable::from_disk(tempdisk.as_file_mut(), disk::LogicalBlockSize::Lb512).unwrap();
+    let pp = m1.partitions();
+    assert_eq!(pp[0].os_type, 0xEE);
+    assert_eq!((pp[0].lb_start, pp[0].lb_size), (1, 33));
+    assert_eq!(pp[1].os_type, 0x83);
+    assert_eq!((pp[1].lb_start, pp[1].lb_size), (34, 29));
+    assert!(pp[1].is_bootable());
+    assert!(pp[2].is_empty());
+    let pmbr = mbr::ProtectiveMBR::from_disk(tempdisk.as_file_mut(), disk::LogicalBlockSize::Lb512)
+        .unwrap();
+    pmbr.validate(96).unwrap();
+    gpt::GptConfig::new()
+        .require_protective_mbr(true)
+        .open(tempdisk.path())
+        .unwrap();
+
+    // Expanding the table leaves a hybrid MBR alone.
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .open(tempdisk.path())
+        .unwrap();
+    tempdisk.as_file().set_len(200 * 512).unwrap();
+    gdisk.expand(false).unwrap();
+    gdisk.write_inplace().unwrap();
+    let m2 =
+        mbr::MbrTable::from_disk(tempdisk.as_file_mut(), disk::LogicalBlockSize::Lb512).unwrap();
+    assert_eq!(m2.partitions(), m1.partitions());
+
+    // Mirroring nothing falls back to a protective record for the whole disk.
+    hybrid.sync(disk.partitions(), &[]).unwrap();
+    assert_eq!(hybrid.table().partitions()[0].lb_size, 95);
+    assert!(hybrid.table().partitions()[1].is_empty());
+}
+
+#[test]
+fn test_mbr_chs() {
+    assert_eq!(mbr::lba_to_chs(0), [0, 1, 0]);
+    assert_eq!(mbr::lba_to_chs(63), [1, 1, 0]);
+    // Cylinder 1000 (0x3E8) spills into the top bits of the sector byte.
+    assert_eq!(mbr::lba_to_chs(1000 * 255 * 63), [0, 0xC1, 0xE8]);
+    assert_eq!(mbr::lba_to_chs(1 << 40), [0xFE, 0xFF, 0xFF]);
+
+    let mut record = mbr::PartRecord::new(0x83, 2048, 4096);
+    assert_eq!(record.as_bytes().unwrap()[1..4], [0; 3]);
+    record.set_chs(mbr::ChsMode::Synthesized);
+    let bytes = record.as_bytes().unwrap();
+    assert_eq!(bytes[1..4], [32, 33, 0]);
+    assert_eq!(bytes[5..8], [97, 33, 0]);
+    record.set_chs(mbr::ChsMode::Zeroed);
+    assert_eq!(record, mbr::PartRecord::new(0x83, 2048, 4096));
+
+    let mut pmbr = mbr::ProtectiveMBR::for_disk(1 << 40);
+    pmbr.set_chs(mbr::ChsMode::Synthesized);
+    let bytes = pmbr.as_bytes().unwrap();
+    assert_eq!(bytes[447..450], [0, 2, 0]);
+    assert_eq!(bytes[451..454], [0xFE, 0xFF, 0xFF]);
+    pmbr.validate(1 << 40).unwrap();
+
+    let hybrid = mbr::HybridMBR::new(96).chs_mode(mbr::ChsMode::Zeroed);
+    assert_eq!(
+        hybrid.table().partitions()[0],
+        mbr::PartRecord::new(0xEE, 1, 95)
+    );
+}
+
+#[test]
+fn test_mbr_to_gpt() {
+    let mut tempdisk = tempfile::NamedTempFile::new().unwrap();
+    tempdisk.as_file().set_len(512 * 8192).unwrap();
+    let f = tempdisk.as_file_mut();
+    write_record(f, 446, 0x83, 2048, 2048);
+    f.seek(SeekFrom::Start(446)).unwrap();
+    f.write_all(&[0x80]).unwrap();
+    write_record(f, 446 + 32, 0x05, 4096, 2048);
+    write_record(f, 4096 * 512 + 446, 0x82, 1, 1023);
+    write_record(f, 4096 * 512 + 462, 0x05, 1024, 1024);
+    write_record(f, 5120 * 512 + 446, 0x9F, 1, 1023);
+
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .overwrite(true)
+        .open(tempdisk.path())
+        .unwrap();
+    gpt::convert::mbr_to_gpt(&mut gdisk).unwrap();
+    let pp = gdisk.partitions();
+    assert_eq!(pp.keys().copied().collect::<Vec<_>>(), [1, 5, 6]);
+    assert_eq!((pp[&1].first_lba, pp[&1].last_lba), (2048, 4095));
+    assert_eq!(pp[&1].part_type_guid.description, "Linux Filesystem Data");
+    assert_eq!(
+        pp[&1].flags,
+        gpt::partition::PartitionAttributes::BOOTABLE.bits()
+    );
+    assert_eq!((pp[&5].first_lba, pp[&5].last_lba), (4097, 5119));
+    assert_eq!(pp[&5].part_type_guid.description, "Swap Partition");
+    // Unknown types fall back to basic data.
+    assert_eq!((pp[&6].first_lba, pp[&6].last_lba), (5121, 6143));
+    assert_eq!(pp[&6].part_type_guid.description, "Basic Data Partition");
+
+    gdisk.write_initial_layout().unwrap();
+    let gdisk = gpt::GptConfig::new()
+        .require_protective_mbr(true)
+        .open(tempdisk.path())
+        .unwrap();
+    assert_eq!(gdisk.partitions().len(), 3);
+    // Converting again is refused.
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .overwrite(true)
+        .open(tempdisk.path())
+        .unwrap();
+    gpt::convert::mbr_to_gpt(&mut gdisk).unwrap_err();
+}
+
+#[test]
+fn test_gpt_to_mbr() {
+    let disk = gpt::GptConfig::new()
+        .open(std::path::Path::new("tests/fixtures/gpt-linux-disk-01.img"))
+        .unwrap();
+    let table = gpt::convert::gpt_to_mbr(&disk).unwrap();
+    let pp = table.partitions();
+    assert_eq!(
+        (pp[0].os_type, pp[0].lb_start, pp[0].lb_size),
+        (0x83, 34, 29)
+    );
+    assert!(!pp[0].is_bootable());
+    assert!(pp[1..].iter().all(|p| p.is_empty()));
+
+    let tempdisk = tempfile::NamedTempFile::new().unwrap();
+    tempdisk.as_file().set_len(8 * 1024 * 1024).unwrap();
+    let mut gdisk = gpt::GptConfig::new()
+        .writable(true)
+        .initialized(false)
+        .open(tempdisk.path())
+        .unwrap();
+    let esp = gpt::partition_types::Type::from_name("EFI System").unwrap();
+    gdisk.add_partition("esp", 64 * 1024, esp, 4).unwrap();
+    let table = gpt::convert::gpt_to_mbr(&gdisk).unwrap();
+    assert_eq!(table.partitions()[0].os_type, 0xEF);
+    assert!(table.partitions()[0].is_bootable());
+
+    // Unknown types and more than four partitions cannot be represented.
+    let custom = gpt::partition::PartitionType::from_guid(uuid::Uuid::new_v4());
+    gdisk.add_partition("custom", 64 * 1024, custom, 0).unwrap();
+    gpt::convert::gpt_to_mbr(&gdisk).unwrap_err();
+    gdisk.remove_partition(2, false).unwrap();
+    for _ in 0..4 {
+        gdisk.add_partition("data", 64 * 1024, esp, 0).unwrap();
+    }
+    gpt::convert::gpt_to_mbr(&gdisk).unwrap_err();
+}
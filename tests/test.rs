@@ -1,9 +1,3 @@
-
-
-use simplelog;
-
-use uuid;
-
 use gpt::disk;
 use gpt::header::{read_header, write_header, Header};
 use gpt::partition::{read_partitions, Partition, PartitionType};
@@ -30,20 +24,20 @@ fn test_read_header() {
         num_parts: 128,
         part_size: 128,
         crc32_parts: 151952294,
+        crc32_stale: false,
     };
 
-    let expected_partition = Partition {
-        part_type_guid: PartitionType {
-            guid: uuid::Uuid::parse_str("0FC63DAF-8483-4772-8E79-3D69D8477DE4").unwrap(),
-            os: "Linux".to_string(),
-            description: "Linux Filesystem Data".to_string(),
-        },
-        part_guid: uuid::Uuid::from_str("6fcc8240-3985-4840-901f-a05e7fd9b69d").unwrap(),
-        first_lba: 34,
-        last_lba: 62,
-        flags: 0,
-        name: "primary".to_string(),
+    let mut expected_partition = Partition::zero();
+    expected_partition.part_type_guid = PartitionType {
+        guid: uuid::Uuid::parse_str("0FC63DAF-8483-4772-8E79-3D69D8477DE4").unwrap(),
+        os: "Linux".to_string(),
+        description: "Linux Filesystem Data".to_string(),
     };
+    expected_partition.part_guid =
+        uuid::Uuid::from_str("6fcc8240-3985-4840-901f-a05e7fd9b69d").unwrap();
+    expected_partition.first_lba = 34;
+    expected_partition.last_lba = 62;
+    expected_partition.name = "primary".to_string();
 
     let diskpath = Path::new("tests/fixtures/gpt-linux-disk-01.img");
     let h = read_header(diskpath, disk::DEFAULT_SECTOR_SIZE).unwrap();
@@ -53,7 +47,7 @@ fn test_read_header() {
 
     let p = read_partitions(diskpath, &h, disk::DEFAULT_SECTOR_SIZE).unwrap();
     println!("Partitions: {:?}", p);
-    assert_eq!(p[0], expected_partition);
+    assert_eq!(p[&1], expected_partition);
 }
 
 #[test]
@@ -78,18 +72,16 @@ fn test_write_header() {
     let h = read_header(tempdisk.path(), disk::DEFAULT_SECTOR_SIZE).unwrap();
     println!("header: {:#?}", h);
 
-    let p = Partition {
-        part_type_guid: PartitionType {
-            guid: uuid::Uuid::parse_str("0FC63DAF-8483-4772-8E79-3D69D8477DE4").unwrap(),
-            os: "Linux".to_string(),
-            description: "Linux Filesystem Data".to_string(),
-        },
-        part_guid: uuid::Uuid::new_v4(),
-        first_lba: 36,
-        last_lba: 40,
-        flags: 0,
-        name: "gpt test".to_string(),
+    let mut p = Partition::zero();
+    p.part_type_guid = PartitionType {
+        guid: uuid::Uuid::parse_str("0FC63DAF-8483-4772-8E79-3D69D8477DE4").unwrap(),
+        os: "Linux".to_string(),
+        description: "Linux Filesystem Data".to_string(),
     };
+    p.part_guid = uuid::Uuid::new_v4();
+    p.first_lba = 36;
+    p.last_lba = 40;
+    p.name = "gpt test".to_string();
     p.write(tempdisk.path(), &h, disk::DEFAULT_SECTOR_SIZE)
         .unwrap();
 }